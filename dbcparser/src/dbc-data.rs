@@ -97,7 +97,11 @@ pub trait SigCodeGen<T> {
     /// # Errors
     /// Returns an error if writing to the output fails.
     fn gen_can_std_frame(&self, code: T, msg: &Message) -> io::Result<()>;
-    //fn gen_can_mux_frame(&self, code: T, msg: &Message) -> io::Result<()>;
+    /// Generate code to check/select a multiplexed signal within a CAN frame.
+    ///
+    /// # Errors
+    /// Returns an error if message has no multiplexor or writing to the output fails.
+    fn gen_can_mux_frame(&self, code: T, msg: &Message) -> io::Result<()>;
     /// Generate the signal trait.
     ///
     /// # Errors
@@ -564,6 +568,62 @@ impl DbcObject {
         })
     }
 
+    /// Lookup a message attribute value (`BA_ "name" BO_ id value;`), falls back on
+    /// attribute default value (`BA_DEF_DEF_`) when message does not define it.
+    #[must_use]
+    pub fn message_attribute_value(
+        &self,
+        message_id: MessageId,
+        attribute_name: &str,
+    ) -> Option<&AttributeValue> {
+        let value = self.attribute_values.iter().find_map(|x| match &x.attribute_value {
+            AttributeValuedForObjectType::MessageDefinitionAttributeValue(
+                x_message_id,
+                Some(value),
+            ) if x_message_id.0 == message_id.0 && x.attribute_name == attribute_name => {
+                Some(value)
+            },
+            _ => None,
+        });
+
+        value.or_else(|| {
+            self.attribute_defaults
+                .iter()
+                .find(|x| x.attribute_name == attribute_name)
+                .map(|x| &x.attribute_value)
+        })
+    }
+
+    /// Lookup a message ENUM attribute label. Numeric values are resolved through
+    /// `BA_DEF_ BO_ "name" ENUM "label0","label1",...;` definition.
+    #[must_use]
+    pub fn message_attribute_enum(
+        &self,
+        message_id: MessageId,
+        attribute_name: &str,
+    ) -> Option<String> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let index = match self.message_attribute_value(message_id, attribute_name)? {
+            AttributeValue::AttributeValueCharString(label) => return Some(label.clone()),
+            AttributeValue::AttributeValueF64(value) => *value as usize,
+            AttributeValue::AttributeValueU64(value) => usize::try_from(*value).ok()?,
+            AttributeValue::AttributeValueI64(value) => usize::try_from(*value).ok()?,
+        };
+
+        let quoted = format!("\"{attribute_name}\"");
+        self.attribute_definitions.iter().find_map(|x| match x {
+            AttributeDefinition::Message(definition) => {
+                let labels = definition.trim().strip_prefix(quoted.as_str())?;
+                let labels = labels.trim().strip_prefix("ENUM")?;
+                labels
+                    .split(',')
+                    .nth(index)
+                    .map(|label| label.trim().trim_matches('"').to_string())
+            },
+            _ => None,
+        })
+    }
+
     /// Lookup the message multiplexor switch signal for a given message.
     /// This does not work for extended multiplexed messages; if multiple multiplexors
     /// are defined for a message an `Error` is returned.
@@ -13,11 +13,11 @@
  */
 use crate::data::{
     ByteOrder, DbcObject, Message, MessageId, MsgCodeGen, MultiplexIndicator, SigCodeGen, Signal,
-    Transmitter, ValDescription, ValueType,
+    SignalExtendedValueType, Transmitter, ValDescription, ValueType,
 };
 use heck::{ToSnakeCase, ToUpperCamelCase};
 
-use sockcan::prelude::get_time;
+use sockcan::prelude::{can_fd_len, get_time, j1939_canid_pgn};
 use std::fs::File;
 use std::io::{self, Error, Write};
 
@@ -34,6 +34,7 @@ pub struct DbcCodeGen {
     dbcfd: DbcObject,
     range_check: bool,
     serde_json: bool,
+    thread_safe: bool,
 }
 
 pub struct DbcParser {
@@ -42,6 +43,7 @@ pub struct DbcParser {
     outfile: Option<String>,
     range_check: bool,
     serde_json: bool,
+    thread_safe: bool,
     header: Option<&'static str>,
     whitelist: Option<Vec<u32>>,
     blacklist: Option<Vec<u32>>,
@@ -84,14 +86,14 @@ impl ValDescription {
     fn get_data_value(&self, data: &str) -> String {
         match data {
             "bool" => format!("{}", (self.a as i64) == 1),
-            "f64" => format!("{}_f64", self.a),
+            "f32" | "f64" => format!("{}_{}", self.a, data),
             _ => format!("{}_{}", self.a as i64, data),
         }
     }
 }
 
 impl Message {
-    fn get_type_kamel(&self) -> String {
+    pub(crate) fn get_type_kamel(&self) -> String {
         if KEYWORDS.contains(&self.name.to_lowercase().as_str())
             || !self.name.starts_with(|c: char| c.is_ascii_alphabetic())
         {
@@ -100,10 +102,98 @@ impl Message {
             self.name.to_upper_camel_case()
         }
     }
+
+    /// Returns message simple multiplexor switch if any.
+    pub(crate) fn get_multiplexor(&self) -> Option<&Signal> {
+        self.signals
+            .iter()
+            .find(|signal| signal.multiplexer_indicator == MultiplexIndicator::Multiplexor)
+    }
+
+    /// Checks message size is a valid classic (0..=8) or CAN FD (12..=64) payload length.
+    pub(crate) fn check_size(&self) -> io::Result<()> {
+        let size = usize::try_from(self.size).unwrap_or(usize::MAX);
+        if can_fd_len(size) == Some(size) {
+            Ok(())
+        } else {
+            Err(Error::other(format!(
+                "message:{} size:{} is not a valid CAN/CAN-FD length",
+                self.name, self.size
+            )))
+        }
+    }
+
+    /// Returns CAN FD bit rate switch flag, None for classic CAN messages. A message is
+    /// CAN FD when bigger than 8 bytes or when `VFrameFormat` attribute says so.
+    pub(crate) fn get_fd_brs(&self, dbcfd: &DbcObject) -> Option<bool> {
+        let is_fd = self.size > 8
+            || dbcfd
+                .message_attribute_enum(self.id, "VFrameFormat")
+                .is_some_and(|format| format.ends_with("CAN_FD"));
+        if !is_fd {
+            return None;
+        }
+        let brs = dbcfd.message_attribute_enum(self.id, "CANFD_BRS");
+        Some(brs.as_deref() == Some("1"))
+    }
+
+    /// Returns J1939 PGN when `VFrameFormat` attribute flags message as `J1939PG`. Received
+    /// priority and source address vary at runtime, such messages are matched on PGN.
+    pub(crate) fn get_j1939_pgn(&self, dbcfd: &DbcObject) -> Option<u32> {
+        let format = dbcfd.message_attribute_enum(self.id, "VFrameFormat")?;
+        if format != "J1939PG" {
+            return None;
+        }
+        j1939_canid_pgn(self.id.to_u32() | 0x8000_0000)
+    }
+
+    // set_values condition selecting a multiplexed signal from its multiplexor chain arguments
+    fn gen_mux_check(&self, signal: &Signal, dbcfd: &DbcObject) -> io::Result<Option<String>> {
+        let mut checks = Vec::new();
+        let mut current = signal;
+        while let Some(selector) = current.get_mux_selector(self, dbcfd)? {
+            if checks.len() >= self.signals.len() {
+                return Err(Error::other(format!(
+                    "signal:{} multiplexor chain loops in message:{}",
+                    signal.name, self.name
+                )));
+            }
+            checks.push(format!(
+                "matches!({} as u64, {})",
+                selector.multiplexor.get_type_snake(),
+                selector.gen_pattern()
+            ));
+            current = selector.multiplexor;
+        }
+
+        if checks.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(checks.join(" && ")))
+        }
+    }
+}
+
+/// Multiplexor switch selecting a signal, with accepted multiplexor raw value ranges.
+pub(crate) struct MuxSelector<'a> {
+    pub(crate) multiplexor: &'a Signal,
+    pub(crate) ranges: Vec<(u64, u64)>,
+}
+
+impl MuxSelector<'_> {
+    // rust pattern matching accepted values (eg: "5 | 16..=24")
+    fn gen_pattern(&self) -> String {
+        let patterns: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|(min, max)| if min == max { format!("{min}") } else { format!("{min}..={max}") })
+            .collect();
+        patterns.join(" | ")
+    }
 }
 
 impl Signal {
-    fn le_start_end_bit(&self, msg: &Message) -> io::Result<(u64, u64)> {
+    pub(crate) fn le_start_end_bit(&self, msg: &Message) -> io::Result<(u64, u64)> {
         let msg_bits = msg.size.checked_mul(8).unwrap();
         let start_bit = self.start_bit;
         let end_bit = self.start_bit + self.size;
@@ -125,7 +215,7 @@ impl Signal {
         Ok((start_bit, end_bit))
     }
 
-    fn be_start_end_bit(self: &Signal, msg: &Message) -> io::Result<(u64, u64)> {
+    pub(crate) fn be_start_end_bit(self: &Signal, msg: &Message) -> io::Result<(u64, u64)> {
         let result = || -> Option<(u64, u64, u64)> {
             let x = self.start_bit.checked_div(8)?;
             let x = x.checked_mul(8)?;
@@ -162,6 +252,89 @@ impl Signal {
         Ok((start_bit, end_bit))
     }
 
+    /// Returns multiplexor value selecting this signal, None when signal is not multiplexed.
+    pub(crate) fn get_mux_value(&self) -> Option<u64> {
+        match self.multiplexer_indicator {
+            MultiplexIndicator::MultiplexedSignal(value)
+            | MultiplexIndicator::MultiplexorAndMultiplexedSignal(value) => Some(value),
+            MultiplexIndicator::Plain | MultiplexIndicator::Multiplexor => None,
+        }
+    }
+
+    /// Returns the multiplexor selecting this signal. Extended multiplexing (`SG_MUL_VAL_`)
+    /// takes precedence over simple `mN` indicator.
+    pub(crate) fn get_mux_selector<'a>(
+        &self,
+        msg: &'a Message,
+        dbcfd: &DbcObject,
+    ) -> io::Result<Option<MuxSelector<'a>>> {
+        let extended = dbcfd
+            .extended_multiplex
+            .iter()
+            .find(|ext_mp| ext_mp.message_id.0 == msg.id.0 && ext_mp.signal_name == self.name);
+
+        if let Some(ext_mp) = extended {
+            let Some(multiplexor) =
+                msg.signals.iter().find(|signal| signal.name == ext_mp.multiplexor_signal_name)
+            else {
+                return Err(Error::other(format!(
+                    "signal:{} multiplexor:{} not found in message:{}",
+                    self.name, ext_mp.multiplexor_signal_name, msg.name
+                )));
+            };
+            let ranges =
+                ext_mp.mappings.iter().map(|mapping| (mapping.min_value, mapping.max_value));
+            return Ok(Some(MuxSelector { multiplexor, ranges: ranges.collect() }));
+        }
+
+        match (self.get_mux_value(), msg.get_multiplexor()) {
+            (None, _) => Ok(None),
+            (Some(value), Some(multiplexor)) => {
+                Ok(Some(MuxSelector { multiplexor, ranges: vec![(value, value)] }))
+            },
+            (Some(_), None) => Err(Error::other(format!(
+                "signal:{} is multiplexed but message:{} has no multiplexor",
+                self.name, msg.name
+            ))),
+        }
+    }
+
+    // raw (unsigned/unscaled) signal read from a `data` byte slice
+    fn gen_read_raw(&self, msg: &Message, data: &str) -> io::Result<String> {
+        let code = match self.byte_order {
+            ByteOrder::LittleEndian => {
+                let (start_bit, end_bit) = self.le_start_end_bit(msg)?;
+                format!(
+                    "{data}.view_bits::<Lsb0>()[{start_bit}..{end_bit}].load_le::<{typ}>()",
+                    typ = self.get_data_usize(),
+                )
+            },
+            ByteOrder::BigEndian => {
+                let (start_bit, end_bit) = self.be_start_end_bit(msg)?;
+                format!(
+                    "{data}.view_bits::<Msb0>()[{start_bit}..{end_bit}].load_be::<{typ}>()",
+                    typ = self.get_data_usize(),
+                )
+            },
+        };
+        Ok(code)
+    }
+
+    // raw (unsigned/unscaled) signal write into a `data` byte slice
+    fn gen_write_raw(&self, msg: &Message, data: &str, value: &str) -> io::Result<String> {
+        let code = match self.byte_order {
+            ByteOrder::LittleEndian => {
+                let (start_bit, end_bit) = self.le_start_end_bit(msg)?;
+                format!("{data}.view_bits_mut::<Lsb0>()[{start_bit}..{end_bit}].store_le({value});")
+            },
+            ByteOrder::BigEndian => {
+                let (start_bit, end_bit) = self.be_start_end_bit(msg)?;
+                format!("{data}.view_bits_mut::<Msb0>()[{start_bit}..{end_bit}].store_be({value});")
+            },
+        };
+        Ok(code)
+    }
+
     fn get_data_usize(&self) -> String {
         let size = match self.size {
             n if n <= 8 => "u8",
@@ -183,16 +356,39 @@ impl Signal {
     }
 
     #[inline]
-    fn has_scaling(&self) -> bool {
+    pub(crate) fn has_scaling(&self) -> bool {
         const EPS: f64 = 1e-12;
         self.offset.abs() > EPS || (self.factor - 1.0).abs() > EPS
     }
 
-    fn get_data_type(&self) -> String {
-        if self.size == 1 {
+    /// Returns IEEE float type when `SIG_VALTYPE_` defines signal as float/double.
+    pub(crate) fn get_float_type(
+        &self,
+        msg: &Message,
+        dbcfd: &DbcObject,
+    ) -> io::Result<Option<&'static str>> {
+        let (float, size) = match dbcfd.extended_value_type_for_signal(msg.id, self.name.as_str()) {
+            Some(SignalExtendedValueType::IEEEfloat32Bit) => ("f32", 32),
+            Some(SignalExtendedValueType::IEEEdouble64bit) => ("f64", 64),
+            _ => return Ok(None),
+        };
+        if self.size != size {
+            return Err(Error::other(format!(
+                "signal:{} is {} but size is {} bits",
+                self.name, float, self.size
+            )));
+        }
+        Ok(Some(float))
+    }
+
+    fn get_data_type(&self, code: &DbcCodeGen, msg: &Message) -> io::Result<String> {
+        let float = self.get_float_type(msg, &code.dbcfd)?;
+        let typ = if self.size == 1 {
             "bool".into()
         } else if self.has_scaling() {
             "f64".into()
+        } else if let Some(float) = float {
+            float.into()
         } else {
             let size = match self.size {
                 n if n <= 8 => "8",
@@ -204,10 +400,11 @@ impl Signal {
                 ValueType::Signed => format!("i{size}"),
                 ValueType::Unsigned => format!("u{size}"),
             }
-        }
+        };
+        Ok(typ)
     }
 
-    fn get_type_kamel(&self) -> String {
+    pub(crate) fn get_type_kamel(&self) -> String {
         if KEYWORDS.contains(&self.name.to_lowercase().as_str())
             || !self.name.starts_with(|c: char| c.is_ascii_alphabetic())
         {
@@ -234,11 +431,18 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         code_output!(
             code,
             IDT1,
-            "/// {}::{} public api (CanDbcSignal trait)",
+            "/// {}::{} public api ({} trait)",
             msg.get_type_kamel(),
+            self.get_type_kamel(),
+            code.api("CanDbcSignal")
+        )?;
+        code_output!(
+            code,
+            IDT1,
+            "impl {} for {} {{\n",
+            code.api("CanDbcSignal"),
             self.get_type_kamel()
         )?;
-        code_output!(code, IDT1, "impl CanDbcSignal for {} {{\n", self.get_type_kamel())?;
         code_output!(code, IDT2, "fn get_name(&self) -> &'static str {")?;
         code_output!(code, IDT3, "self.name")?;
         code_output!(code, IDT2, "}\n")?;
@@ -258,34 +462,25 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         //signal update
         code_output!(code, IDT2, "fn update(&mut self, frame: &CanMsgData) -> i32 {")?;
 
-        let read_fn = match self.byte_order {
-            ByteOrder::LittleEndian => {
-                let (start_bit, end_bit) = self.le_start_end_bit(msg)?;
-
-                format!(
-                    "frame.data.view_bits::<Lsb0>()[{start}..{end}].load_le::<{typ}>()",
-                    typ = self.get_data_usize(),
-                    start = start_bit,
-                    end = end_bit,
-                )
-            },
-            ByteOrder::BigEndian => {
-                let (start_bit, end_bit) = self.be_start_end_bit(msg)?;
-
-                format!(
-                    "frame.data.view_bits::<Msb0>()[{start}..{end}].load_be::<{typ}>()",
-                    typ = self.get_data_usize(),
-                    start = start_bit,
-                    end = end_bit
-                )
-            },
-        };
+        let read_fn = self.gen_read_raw(msg, "frame.data")?;
+        let float = self.get_float_type(msg, &code.dbcfd)?;
 
         code_output!(code, IDT3, "match frame.opcode {")?;
+        if self.get_mux_selector(msg, &code.dbcfd)?.is_some() {
+            code_output!(
+                code,
+                IDT4,
+                "CanBcmOpCode::RxChanged if !Self::is_active(frame.data) => {"
+            )?;
+            code_output!(code, IDT5, "self.status= CanDataStatus::Inactive;")?;
+            code_output!(code, IDT4, "},")?;
+        }
         code_output!(code, IDT4, "CanBcmOpCode::RxChanged => {")?;
         code_output!(code, IDT5, "let value = {};", read_fn)?;
 
-        if self.value_type == ValueType::Signed {
+        if let Some(float) = float {
+            code_output!(code, IDT5, "let value = {}::from_bits(value);", float)?;
+        } else if self.value_type == ValueType::Signed {
             code_output!(
                 code,
                 IDT5,
@@ -329,7 +524,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         code_output!(code, IDT3, "match &self.callback {")?;
         code_output!(code, IDT4, "None => 0,")?;
         code_output!(code, IDT4, "Some(callback) => {")?;
-        code_output!(code, IDT5, "match callback.try_borrow() {")?;
+        code_output!(code, IDT5, "match callback.{}() {{", code.borrow())?;
         code_output!(
             code,
             IDT6,
@@ -347,7 +542,12 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
             IDT2,
             "fn set_value(&mut self, value:CanDbcType, data:&mut [u8]) -> Result<(),CanError> {"
         )?;
-        code_output!(code, IDT3, "let value:{}= match value.cast() {{", self.get_data_type())?;
+        code_output!(
+            code,
+            IDT3,
+            "let value:{}= match value.cast() {{",
+            self.get_data_type(code, msg)?
+        )?;
         code_output!(code, IDT4, "Ok(val) => val,")?;
         code_output!(code, IDT4, "Err(error) => return Err(error)")?;
         code_output!(code, IDT3, "};")?;
@@ -361,7 +561,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
             code,
             IDT3,
             "CanDbcType::{}(self.get_typed_value())",
-            self.get_data_type().to_upper_camel_case()
+            self.get_data_type(code, msg)?.to_upper_camel_case()
         )?;
         code_output!(code, IDT2, "}\n")?;
 
@@ -382,8 +582,13 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         code_output!(code, IDT2, "}\n")?;
 
         // set signal notification callback
-        code_output!(code, IDT2, "fn set_callback(&mut self, callback: Box<dyn CanSigCtrl>)  {")?;
-        code_output!(code, IDT3, "self.callback= Some(RefCell::new(callback));")?;
+        code_output!(
+            code,
+            IDT2,
+            "fn set_callback(&mut self, callback: Box<dyn {}>)  {{",
+            code.api("CanSigCtrl")
+        )?;
+        code_output!(code, IDT3, "self.callback= Some({}::new(callback));", code.cell())?;
         code_output!(code, IDT2, "}\n")?;
 
         code_output!(
@@ -397,12 +602,12 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         Ok(())
     }
 
-    fn gen_dbc_min_max(&self, code: &DbcCodeGen, _msg: &Message) -> io::Result<()> {
+    fn gen_dbc_min_max(&self, code: &DbcCodeGen, msg: &Message) -> io::Result<()> {
         if self.size == 1 {
             return Ok(());
         }
 
-        let typ = self.get_data_type();
+        let typ = self.get_data_type(code, msg)?;
         code_output!(
             code,
             IDT2,
@@ -442,7 +647,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
             for variant in variants {
                 code_output!(code, IDT2, "{},", variant.get_type_kamel())?;
             }
-            code_output!(code, IDT2, "_Other({}),", self.get_data_type())?;
+            code_output!(code, IDT2, "_Other({}),", self.get_data_type(code, msg)?)?;
             code_output!(code, IDT1, "}\n")?;
 
             code_output!(
@@ -450,14 +655,14 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                 IDT1,
                 "impl From<Dbc{}> for {} {{",
                 self.get_type_kamel(),
-                self.get_data_type()
+                self.get_data_type(code, msg)?
             )?;
             code_output!(
                 code,
                 IDT2,
                 "fn from (val: Dbc{}) -> {} {{",
                 self.get_type_kamel(),
-                self.get_data_type()
+                self.get_data_type(code, msg)?
             )?;
             code_output!(code, IDT3, "match val {")?;
             for variant in variants {
@@ -468,8 +673,8 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                         "Dbc{}::{} => panic! (\"(Hoops) impossible conversion {} -> {}\"),",
                         self.get_type_kamel(),
                         variant.get_type_kamel(),
-                        variant.get_data_value(&self.get_data_type()),
-                        self.get_data_type()
+                        variant.get_data_value(&self.get_data_type(code, msg)?),
+                        self.get_data_type(code, msg)?
                     )?;
                 } else {
                     code_output!(
@@ -478,7 +683,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                         "Dbc{}::{} => {},",
                         self.get_type_kamel(),
                         variant.get_type_kamel(),
-                        variant.get_data_value(&self.get_data_type())
+                        variant.get_data_value(&self.get_data_type(code, msg)?)
                     )?;
                 }
             }
@@ -517,26 +722,39 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         if code.serde_json {
             code_output!(code, IDT2, "#[serde(skip)]")?;
         }
-        code_output!(code, IDT2, "callback: Option<RefCell<Box<dyn CanSigCtrl>>>,")?;
+        code_output!(
+            code,
+            IDT2,
+            "callback: Option<{}<Box<dyn {}>>>,",
+            code.cell(),
+            code.api("CanSigCtrl")
+        )?;
         code_output!(code, IDT2, "status: CanDataStatus,")?;
         code_output!(code, IDT2, "name: &'static str,")?;
         code_output!(code, IDT2, "stamp: u64,")?;
-        code_output!(code, IDT2, "value: {},", self.get_data_type())?;
+        code_output!(code, IDT2, "value: {},", self.get_data_type(code, msg)?)?;
         code_output!(code, IDT1, "}\n")?;
 
         self.gen_signal_enum(code, msg)?;
 
         // start signal implementation
         code_output!(code, IDT1, "impl {}  {{", self.get_type_kamel())?;
-        code_output!(code, IDT2, "pub fn new() -> Rc<RefCell<Box<dyn CanDbcSignal>>> {")?;
-        code_output!(code, IDT3, "Rc::new(RefCell::new(Box::new({} {{", self.get_type_kamel())?;
+        code_output!(code, IDT2, "pub fn new() -> {} {{", code.shared("CanDbcSignal"))?;
+        code_output!(
+            code,
+            IDT3,
+            "{}::new({}::new(Box::new({} {{",
+            code.rc(),
+            code.cell(),
+            self.get_type_kamel()
+        )?;
         code_output!(code, IDT4, "status: CanDataStatus::Unset,")?;
         //code_output!(code, IDT4, "uid: DbcSignal::{},",)?;
         code_output!(code, IDT4, "name:\"{}\",", self.get_type_kamel())?;
         if self.size == 1 {
             code_output!(code, IDT4, "value: false,")?;
         } else {
-            code_output!(code, IDT4, "value: 0_{},", self.get_data_type())?;
+            code_output!(code, IDT4, "value: 0_{},", self.get_data_type(code, msg)?)?;
         }
 
         code_output!(code, IDT4, "stamp: 0,")?;
@@ -548,7 +766,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         if self.size == 1 {
             code_output!(code, IDT3, "self.value= false;")?;
         } else {
-            code_output!(code, IDT3, "self.value= 0_{};", self.get_data_type())?;
+            code_output!(code, IDT3, "self.value= 0_{};", self.get_data_type(code, msg)?)?;
         }
 
         code_output!(code, IDT2, "}\n")?;
@@ -563,7 +781,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
             )?;
 
             // float is not compatible with match
-            if self.get_data_type() == "f64" {
+            if matches!(self.get_data_type(code, msg)?.as_str(), "f32" | "f64") {
                 code_output!(
                     code,
                     IDT4,
@@ -579,10 +797,10 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                         code,
                         IDT4,
                         "// WARNING {} => Err(CanError::new(\"not-in-range\",\"({}) !!! {}({}) not in [{}..{}] range\")),",
-                        variant.get_data_value(&self.get_data_type()),
+                        variant.get_data_value(&self.get_data_type(code, msg)?),
                         variant.get_type_kamel(),
                         variant.a,
-                        self.get_data_type(),
+                        self.get_data_type(code, msg)?,
                         self.min,
                         self.max
                     )?;
@@ -592,7 +810,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                             code,
                             IDT4,
                             "{} => Dbc{}::{},",
-                            variant.get_data_value(&self.get_data_type()),
+                            variant.get_data_value(&self.get_data_type(code, msg)?),
                             self.get_type_kamel(),
                             variant.get_type_kamel()
                         )?;
@@ -630,7 +848,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                         variant.get_type_kamel(),
                         variant.get_type_kamel(),
                         variant.a,
-                        self.get_data_type(),
+                        self.get_data_type(code, msg)?,
                         self.min,
                         self.max
                     )?;
@@ -641,7 +859,7 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                         "Dbc{}::{} => self.set_typed_value({}, data),",
                         self.get_type_kamel(),
                         variant.get_type_kamel(),
-                        variant.get_data_value(&self.get_data_type())
+                        variant.get_data_value(&self.get_data_type(code, msg)?)
                     )?;
                 }
             }
@@ -656,7 +874,12 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         }
 
         // signal get typed_value
-        code_output!(code, IDT2, "fn get_typed_value(&self) -> {} {{", self.get_data_type())?;
+        code_output!(
+            code,
+            IDT2,
+            "fn get_typed_value(&self) -> {} {{",
+            self.get_data_type(code, msg)?
+        )?;
         code_output!(code, IDT3, "self.value")?;
         code_output!(code, IDT2, "}\n")?;
 
@@ -665,9 +888,10 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
             code,
             IDT2,
             "fn set_typed_value(&mut self, value:{}, data:&mut [u8]) -> Result<(),CanError> {{",
-            self.get_data_type()
+            self.get_data_type(code, msg)?
         )?;
 
+        let float = self.get_float_type(msg, &code.dbcfd)?;
         if self.size == 1 {
             code_output!(code, IDT3, "let value = value as u8;")?;
         } else if code.range_check && self.has_scaling() {
@@ -676,9 +900,9 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                 IDT3,
                 "if value < {}_{} || {}_{} < value {{",
                 self.min,
-                self.get_data_type(),
+                self.get_data_type(code, msg)?,
                 self.max,
-                self.get_data_type()
+                self.get_data_type(code, msg)?
             )?;
             code_output!(code,IDT4,
                     "return Err(CanError::new(\"invalid-signal-value\",format!(\"value={{}} not in [{}..{}]\",value)));", self.min, self.max)?;
@@ -689,11 +913,14 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
                 code,
                 IDT3,
                 "let value = ((value - offset) / factor) as {};",
-                self.get_data_usize()
+                float.map_or_else(|| self.get_data_usize(), str::to_string)
             )?;
         }
 
-        if self.value_type == ValueType::Signed {
+        // IEEE float signals store raw bits
+        if float.is_some() {
+            code_output!(code, IDT3, "let value = value.to_bits();")?;
+        } else if self.value_type == ValueType::Signed {
             code_output!(
                 code,
                 IDT3,
@@ -702,27 +929,11 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
             )?;
         }
 
-        match self.byte_order {
-            ByteOrder::LittleEndian => {
-                let (start_bit, end_bit) = self.le_start_end_bit(msg)?;
-                code_output!(
-                    code,
-                    IDT3,
-                    "data.view_bits_mut::<Lsb0>()[{}..{}].store_le(value);",
-                    start_bit,
-                    end_bit
-                )?;
-            },
-            ByteOrder::BigEndian => {
-                let (start_bit, end_bit) = self.be_start_end_bit(msg)?;
-                code_output!(
-                    code,
-                    IDT3,
-                    "data.view_bits_mut::<Msb0>()[{}..{}].store_be(value);",
-                    start_bit,
-                    end_bit
-                )?;
-            },
+        code_output!(code, IDT3, self.gen_write_raw(msg, "data", "value")?)?;
+
+        // multiplexed signal is meaningless without its multiplexor value
+        if self.get_mux_selector(msg, &code.dbcfd)?.is_some() {
+            code_output!(code, IDT3, "Self::set_active(data);")?;
         }
 
         code_output!(code, IDT3, "Ok(())")?;
@@ -744,14 +955,70 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
         Ok(())
     }
 
+    fn gen_can_mux_frame(&self, code: &DbcCodeGen, msg: &Message) -> io::Result<()> {
+        let Some(selector) = self.get_mux_selector(msg, &code.dbcfd)? else {
+            return Err(Error::other(format!("signal:{} is not multiplexed", self.name)));
+        };
+        let multiplexor = selector.multiplexor;
+        let pattern = selector.gen_pattern();
+
+        // nested multiplexor is itself only valid when its own multiplexor matches
+        let nested = multiplexor.get_mux_selector(msg, &code.dbcfd)?.is_some();
+
+        code_output!(code, IDT1, "impl {} {{", self.get_type_kamel())?;
+        code_output!(
+            code,
+            IDT2,
+            "// signal is only valid when {} multiplexor matches {}",
+            multiplexor.get_type_kamel(),
+            pattern
+        )?;
+        code_output!(code, IDT2, "fn is_active(data: &[u8]) -> bool {")?;
+        if nested {
+            code_output!(
+                code,
+                IDT3,
+                "matches!({} as u64, {}) && {}::is_active(data)",
+                multiplexor.gen_read_raw(msg, "data")?,
+                pattern,
+                multiplexor.get_type_kamel()
+            )?;
+        } else {
+            code_output!(
+                code,
+                IDT3,
+                "matches!({} as u64, {})",
+                multiplexor.gen_read_raw(msg, "data")?,
+                pattern
+            )?;
+        }
+        code_output!(code, IDT2, "}\n")?;
+
+        // keep multiplexor value when already in range, else select first range value
+        code_output!(code, IDT2, "fn set_active(data: &mut [u8]) {")?;
+        code_output!(
+            code,
+            IDT3,
+            "if !matches!({} as u64, {}) {{",
+            multiplexor.gen_read_raw(msg, "data")?,
+            pattern
+        )?;
+        let mux_value = format!("{}_{}", selector.ranges[0].0, multiplexor.get_data_usize());
+        code_output!(code, IDT4, multiplexor.gen_write_raw(msg, "data", &mux_value)?)?;
+        code_output!(code, IDT3, "}")?;
+        if nested {
+            code_output!(code, IDT3, "{}::set_active(data);", multiplexor.get_type_kamel())?;
+        }
+        code_output!(code, IDT2, "}")?;
+        code_output!(code, IDT1, "}\n")?;
+        Ok(())
+    }
+
     fn gen_can_any_frame(&self, code: &DbcCodeGen, msg: &Message) -> io::Result<()> {
-        match self.multiplexer_indicator {
-            MultiplexIndicator::Plain => self.gen_can_std_frame(code, msg)?,
-            MultiplexIndicator::Multiplexor
-            | MultiplexIndicator::MultiplexedSignal(_)
-            | MultiplexIndicator::MultiplexorAndMultiplexedSignal(_) => {
-                // (optional) any shared handling for multiplexed cases
-            },
+        if self.get_mux_selector(msg, &code.dbcfd)?.is_some() {
+            self.gen_can_mux_frame(code, msg)?;
+        } else {
+            self.gen_can_std_frame(code, msg)?;
         }
 
         // fmt display for signal
@@ -796,11 +1063,18 @@ impl SigCodeGen<&DbcCodeGen> for Signal {
 impl MsgCodeGen<&DbcCodeGen> for Message {
     fn gen_can_dbc_impl(&self, code: &DbcCodeGen) -> io::Result<()> {
         code_output!(code, IDT1, "pub struct DbcMessage {")?;
-        code_output!(code, IDT2, "callback: Option<RefCell<Box<dyn CanMsgCtrl>>>,")?;
         code_output!(
             code,
             IDT2,
-            "signals: [Rc<RefCell<Box<dyn CanDbcSignal>>>;{}],",
+            "callback: Option<{}<Box<dyn {}>>>,",
+            code.cell(),
+            code.api("CanMsgCtrl")
+        )?;
+        code_output!(
+            code,
+            IDT2,
+            "signals: [{};{}],",
+            code.shared("CanDbcSignal"),
             self.signals.len()
         )?;
         code_output!(code, IDT2, "name: &'static str,")?;
@@ -808,14 +1082,22 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         code_output!(code, IDT2, "listeners: i32,")?;
         code_output!(code, IDT2, "stamp: u64,")?;
         code_output!(code, IDT2, "id: u32,")?;
+        code_output!(code, IDT2, "canid: u32,")?;
         code_output!(code, IDT1, "}\n")?;
 
         code_output!(code, IDT1, "impl DbcMessage {")?;
 
         // instantiate an empty message
-        code_output!(code, IDT2, "pub fn new() -> Rc<RefCell<Box <dyn CanDbcMessage>>> {")?;
-        code_output!(code, IDT3, "Rc::new(RefCell::new(Box::new (DbcMessage {")?;
+        code_output!(code, IDT2, "pub fn new() -> {} {{", code.shared("CanDbcMessage"))?;
+        code_output!(
+            code,
+            IDT3,
+            "{}::new({}::new(Box::new (DbcMessage {{",
+            code.rc(),
+            code.cell()
+        )?;
         code_output!(code, IDT4, "id: {},", self.id.to_u32())?;
+        code_output!(code, IDT4, "canid: {},", self.id.to_u32())?;
         code_output!(code, IDT4, "name: \"{}\",", self.get_type_kamel())?;
         code_output!(code, IDT4, "status: CanBcmOpCode::Unknown,")?;
         code_output!(code, IDT4, "listeners: 0,")?;
@@ -833,8 +1115,10 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         let args: Vec<String> = self
             .signals
             .iter()
-            .map(|signal| format!("{}: {}", signal.get_type_snake(), signal.get_data_type()))
-            .collect();
+            .map(|signal| {
+                Ok(format!("{}: {}", signal.get_type_snake(), signal.get_data_type(code, self)?))
+            })
+            .collect::<io::Result<_>>()?;
 
         code_output!(
             code,
@@ -844,18 +1128,26 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         )?;
 
         for idx in 0..self.signals.len() {
+            // only write multiplexed signals selected by multiplexor value(s)
+            let mux_check = self.gen_mux_check(&self.signals[idx], &code.dbcfd)?;
+            if let Some(check) = &mux_check {
+                code_output!(code, IDT3, "if {} {{", check)?;
+            }
+
             code_output!(
                 code,
                 IDT3,
-                "match Rc::clone (&self.signals[{}]).try_borrow_mut() {{",
-                idx
+                "match {}::clone (&self.signals[{}]).{}() {{",
+                code.rc(),
+                idx,
+                code.borrow_mut()
             )?;
 
             code_output!(
                 code,
                 IDT4,
                 "Ok(mut signal) => signal.set_value(CanDbcType::{}({}), frame)?,",
-                self.signals[idx].get_data_type().to_upper_camel_case(),
+                self.signals[idx].get_data_type(code, self)?.to_upper_camel_case(),
                 self.signals[idx].get_type_snake()
             )?;
             code_output!(
@@ -863,10 +1155,13 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
                 IDT4,
                 "Err(_) => return Err(CanError::new(\"signal-set-values-fail\",\"Internal error {}:{}\")),",
                 self.signals[idx].get_type_snake(),
-                self.signals[idx].get_data_type().to_upper_camel_case()
+                self.signals[idx].get_data_type(code, self)?.to_upper_camel_case()
             )?;
 
             code_output!(code, IDT3, "}")?;
+            if mux_check.is_some() {
+                code_output!(code, IDT3, "}")?;
+            }
         }
         code_output!(code, IDT3, "Ok(self)")?;
         code_output!(code, IDT2, "}")?;
@@ -875,9 +1170,10 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         Ok(())
     }
 
+    #[allow(clippy::too_many_lines)]
     fn gen_can_dbc_message(&self, code: &DbcCodeGen) -> io::Result<()> {
         // build message signal:type list
-        code_output!(code, IDT1, "impl CanDbcMessage for DbcMessage {")?;
+        code_output!(code, IDT1, "impl {} for DbcMessage {{", code.api("CanDbcMessage"))?;
         code_output!(code, IDT2, "fn reset(&mut self) -> Result<(), CanError> {")?;
         code_output!(code, IDT3, "self.status=CanBcmOpCode::Unknown;")?;
         code_output!(code, IDT3, "self.stamp=0;")?;
@@ -885,8 +1181,10 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
             code_output!(
                 code,
                 IDT3,
-                "match Rc::clone (&self.signals[{}]).try_borrow_mut() {{",
-                idx
+                "match {}::clone (&self.signals[{}]).{}() {{",
+                code.rc(),
+                idx,
+                code.borrow_mut()
             )?;
 
             code_output!(code, IDT4, "Ok(mut signal) => signal.reset(),",)?;
@@ -895,7 +1193,7 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
                 IDT4,
                 "Err(_) => return Err(CanError::new(\"signal-reset-fail\",\"Internal error {}:{}\")),",
                 self.signals[idx].get_type_snake(),
-                self.signals[idx].get_data_type().to_upper_camel_case()
+                self.signals[idx].get_data_type(code, self)?.to_upper_camel_case()
             )?;
 
             code_output!(code, IDT3, "}")?;
@@ -909,15 +1207,32 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
             IDT2,
             "fn update(&mut self, frame: &CanMsgData) -> Result<(), CanError> {"
         )?;
+        code_output!(
+            code,
+            IDT3,
+            "if matches!(frame.opcode, CanBcmOpCode::RxChanged) && frame.data.len() < {} {{",
+            self.size
+        )?;
+        code_output!(
+            code,
+            IDT4,
+            "return Err(CanError::new(\"invalid-message-len\", format!(\"{}: len={{}} expected={}\", frame.data.len())));",
+            self.get_type_kamel(),
+            self.size
+        )?;
+        code_output!(code, IDT3, "}")?;
         code_output!(code, IDT3, "self.stamp= frame.stamp;")?;
         code_output!(code, IDT3, "self.status= frame.opcode;")?;
+        code_output!(code, IDT3, "self.canid= frame.canid;")?;
         code_output!(code, IDT3, "self.listeners= 0;")?;
         for idx in 0..self.signals.len() {
             code_output!(
                 code,
                 IDT3,
-                "match Rc::clone (&self.signals[{}]).try_borrow_mut() {{",
-                idx
+                "match {}::clone (&self.signals[{}]).{}() {{",
+                code.rc(),
+                idx,
+                code.borrow_mut()
             )?;
 
             code_output!(code, IDT4, "Ok(mut signal) => self.listeners += signal.update(frame),",)?;
@@ -926,7 +1241,7 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
                 IDT4,
                 "Err(_) => return Err(CanError::new(\"signal-update-fail\",\"Internal error {}:{}\")),",
                 self.signals[idx].get_type_snake(),
-                self.signals[idx].get_data_type().to_upper_camel_case()
+                self.signals[idx].get_data_type(code, self)?.to_upper_camel_case()
             )?;
 
             code_output!(code, IDT3, "}")?;
@@ -934,7 +1249,7 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         code_output!(code, IDT3, "match &self.callback {")?;
         code_output!(code, IDT4, "None => {},")?;
         code_output!(code, IDT4, "Some(callback) => {")?;
-        code_output!(code, IDT5, "match callback.try_borrow() {")?;
+        code_output!(code, IDT5, "match callback.{}() {{", code.borrow())?;
         code_output!(
             code,
             IDT6,
@@ -949,11 +1264,7 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         code_output!(code, IDT2, "}\n")?;
 
         // get message signals collection
-        code_output!(
-            code,
-            IDT2,
-            "fn get_signals(&self) -> &[Rc<RefCell<Box<dyn CanDbcSignal>>>] {"
-        )?;
+        code_output!(code, IDT2, "fn get_signals(&self) -> &[{}] {{", code.shared("CanDbcSignal"))?;
         code_output!(code, IDT3, "&self.signals")?;
         code_output!(code, IDT2, "}\n")?;
 
@@ -963,8 +1274,13 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         code_output!(code, IDT2, "}\n")?;
 
         // set message notification callback
-        code_output!(code, IDT2, "fn set_callback(&mut self, callback: Box<dyn CanMsgCtrl>)  {")?;
-        code_output!(code, IDT3, "self.callback= Some(RefCell::new(callback));")?;
+        code_output!(
+            code,
+            IDT2,
+            "fn set_callback(&mut self, callback: Box<dyn {}>)  {{",
+            code.api("CanMsgCtrl")
+        )?;
+        code_output!(code, IDT3, "self.callback= Some({}::new(callback));", code.cell())?;
         code_output!(code, IDT2, "}\n")?;
 
         // get message name
@@ -987,20 +1303,55 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         code_output!(code, IDT3, "self.id")?;
         code_output!(code, IDT2, "}\n")?;
 
+        // get message size
+        code_output!(code, IDT2, "fn get_len(&self) -> u8 {")?;
+        code_output!(code, IDT3, "{}", self.size)?;
+        code_output!(code, IDT2, "}\n")?;
+
+        // get message CAN FD flags
+        code_output!(code, IDT2, "fn get_fd_flags(&self) -> Option<CanFdFlags> {")?;
+        match self.get_fd_brs(&code.dbcfd) {
+            None => code_output!(code, IDT3, "None")?,
+            Some(true) => code_output!(code, IDT3, "Some(CanFdFlags::BRS)")?,
+            Some(false) => code_output!(code, IDT3, "Some(CanFdFlags::empty())")?,
+        }
+        code_output!(code, IDT2, "}\n")?;
+
+        // J1939 messages match on PGN, source address comes from last received canid
+        if let Some(pgn) = self.get_j1939_pgn(&code.dbcfd) {
+            code_output!(code, IDT2, "fn get_pgn(&self) -> Option<u32> {")?;
+            code_output!(code, IDT3, "Some({})", pgn)?;
+            code_output!(code, IDT2, "}\n")?;
+            code_output!(code, IDT2, "fn get_src(&self) -> Option<u8> {")?;
+            code_output!(code, IDT3, "u8::try_from(self.canid & 0xFF).ok()")?;
+            code_output!(code, IDT2, "}\n")?;
+        }
+
         // get message as_any
         code_output!(code, IDT2, "fn as_any(&mut self) -> &mut dyn Any {")?;
         code_output!(code, IDT3, "self")?;
         code_output!(code, IDT2, "}\n")?;
 
-        code_output!(code, IDT1, "}} // end {} impl for CanDbcMessage", self.get_type_kamel())?;
+        code_output!(
+            code,
+            IDT1,
+            "}} // end {} impl for {}",
+            self.get_type_kamel(),
+            code.api("CanDbcMessage")
+        )?;
         Ok(())
     }
 
     fn gen_code_message(&self, code: &DbcCodeGen) -> io::Result<()> {
+        self.check_size()?;
+
         // message header
         code_output!(code, IDT0, "/// {} Message", self.name)?;
         code_output!(code, IDT0, "/// - ID: {0} (0x{0:x})", self.id.0)?;
         code_output!(code, IDT0, "/// - Size: {} bytes", self.size)?;
+        if let Some(brs) = self.get_fd_brs(&code.dbcfd) {
+            code_output!(code, IDT0, "/// - CAN FD frame (BRS: {})", brs)?;
+        }
         if let Transmitter::NodeName(transmitter) = &self.transmitter {
             code_output!(code, IDT0, "/// - Transmitter: {}", transmitter)?;
         }
@@ -1016,8 +1367,12 @@ impl MsgCodeGen<&DbcCodeGen> for Message {
         code_output!(code, IDT1, "use sockcan::prelude::*;")?;
         code_output!(code, IDT1, "use bitvec::prelude::*;")?;
         code_output!(code, IDT1, "use std::any::Any;")?;
-        code_output!(code, IDT1, "use std::cell::{RefCell};")?;
-        code_output!(code, IDT1, "use std::rc::Rc;\n")?;
+        if code.thread_safe {
+            code_output!(code, IDT1, "use std::sync::{Arc, Mutex};\n")?;
+        } else {
+            code_output!(code, IDT1, "use std::cell::{RefCell};")?;
+            code_output!(code, IDT1, "use std::rc::Rc;\n")?;
+        }
         code_output!(code, IDT1, "use std::fmt;\n")?;
         if code.serde_json {
             code_output!(code, IDT1, "use serde::{Deserialize, Serialize};")?;
@@ -1083,6 +1438,60 @@ impl DbcCodeGen {
     {
         Self::write(self, indent, text)
     }
+
+    // thread safe pools use Arc<Mutex<..>> storage and Send + Sync traits
+    fn rc(&self) -> &'static str {
+        if self.thread_safe {
+            "Arc"
+        } else {
+            "Rc"
+        }
+    }
+
+    fn cell(&self) -> &'static str {
+        if self.thread_safe {
+            "Mutex"
+        } else {
+            "RefCell"
+        }
+    }
+
+    fn guard(&self) -> &'static str {
+        if self.thread_safe {
+            "MutexGuard"
+        } else {
+            "RefMut"
+        }
+    }
+
+    // mutex lock waits for other threads, only a poisoned lock returns an error
+    fn borrow(&self) -> &'static str {
+        if self.thread_safe {
+            "lock"
+        } else {
+            "try_borrow"
+        }
+    }
+
+    fn borrow_mut(&self) -> &'static str {
+        if self.thread_safe {
+            "lock"
+        } else {
+            "try_borrow_mut"
+        }
+    }
+
+    fn api(&self, name: &str) -> String {
+        if self.thread_safe {
+            format!("{name}Sync")
+        } else {
+            name.to_owned()
+        }
+    }
+
+    fn shared(&self, name: &str) -> String {
+        format!("{}<{}<Box<dyn {}>>>", self.rc(), self.cell(), self.api(name))
+    }
 }
 
 impl DbcParser {
@@ -1092,6 +1501,7 @@ impl DbcParser {
             uid,
             range_check: true,
             serde_json: true,
+            thread_safe: false,
             infile: None,
             outfile: None,
             header: None,
@@ -1135,6 +1545,13 @@ impl DbcParser {
         self
     }
 
+    /// Generates a `Send + Sync` pool (`Arc<Mutex<..>>` storage, `CanDbcPoolSync` traits)
+    /// that can be shared between a reader and consumer threads.
+    pub fn thread_safe(&mut self, flag: bool) -> &mut Self {
+        self.thread_safe = flag;
+        self
+    }
+
     fn check_list(canid: MessageId, list: &[u32]) -> bool {
         list.binary_search(&canid.0).is_ok()
     }
@@ -1158,7 +1575,7 @@ impl DbcParser {
         };
 
         // sort message by canid
-        dbcfd.messages.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+        dbcfd.messages.sort_by_key(|a| a.id.0);
 
         if let Some(mut list) = self.whitelist.clone() {
             list.sort_unstable();
@@ -1171,7 +1588,7 @@ impl DbcParser {
         }
 
         // sort message by canid
-        dbcfd.messages.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+        dbcfd.messages.sort_by_key(|a| a.id.0);
 
         let outfd = match &self.outfile {
             Some(outfile) => {
@@ -1182,8 +1599,13 @@ impl DbcParser {
         };
 
         // open/create output file
-        let code =
-            DbcCodeGen { dbcfd, outfd, range_check: self.range_check, serde_json: self.serde_json };
+        let code = DbcCodeGen {
+            dbcfd,
+            outfd,
+            range_check: self.range_check,
+            serde_json: self.serde_json,
+            thread_safe: self.thread_safe,
+        };
 
         match self.header {
             None => {},
@@ -1235,8 +1657,12 @@ impl DbcParser {
         }
         code_output!(code, IDT0, "extern crate bitvec;")?;
         code_output!(code, IDT0, "use sockcan::prelude::*;")?;
-        code_output!(code, IDT0, "use std::cell::{RefCell,RefMut};")?;
-        code_output!(code, IDT0, "use std::rc::{Rc};")?;
+        if code.thread_safe {
+            code_output!(code, IDT0, "use std::sync::{Arc, Mutex, MutexGuard};")?;
+        } else {
+            code_output!(code, IDT0, "use std::cell::{RefCell,RefMut};")?;
+            code_output!(code, IDT0, "use std::rc::{Rc};")?;
+        }
         code_output!(code, IDT0, "")?;
 
         // output messages/signals
@@ -1256,7 +1682,8 @@ impl DbcParser {
         code_output!(
             code,
             IDT1,
-            "pool: [Rc<RefCell<Box<dyn CanDbcMessage>>>;{}],",
+            "pool: [{};{}],",
+            code.shared("CanDbcMessage"),
             &code.dbcfd.messages.len()
         )?;
         code_output!(code, IDT0, "}\n")?;
@@ -1265,6 +1692,16 @@ impl DbcParser {
 
         // extract canid from messages vector
         let canids: Vec<u32> = code.dbcfd.messages.iter().map(|msg| msg.id.to_u32()).collect();
+        let mut j1939: Vec<(u32, usize)> = code
+            .dbcfd
+            .messages
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, msg)| Some((msg.get_j1939_pgn(&code.dbcfd)?, idx)))
+            .collect();
+        // same PGN from several source addresses: exact canid first, then lowest canid
+        j1939.sort_by_key(|(pgn, _)| *pgn);
+        j1939.dedup_by_key(|(pgn, _)| *pgn);
 
         code_output!(code, IDT1, "pub fn new(uid: &'static str) -> Self {")?;
         code_output!(code, IDT2, "CanMsgPool {")?;
@@ -1283,11 +1720,12 @@ impl DbcParser {
         code_output!(code, IDT1, "}")?;
         code_output!(code, IDT0, "}\n")?;
 
-        code_output!(code, IDT0, "impl CanDbcPool for CanMsgPool {")?;
+        code_output!(code, IDT0, "impl {} for CanMsgPool {{", code.api("CanDbcPool"))?;
         code_output!(
             code,
             IDT1,
-            "fn get_messages(&self) -> &[Rc<RefCell<Box<dyn CanDbcMessage>>>] {"
+            "fn get_messages(&self) -> &[{}] {{",
+            code.shared("CanDbcMessage")
         )?;
 
         code_output!(code, IDT2, "&self.pool")?;
@@ -1299,16 +1737,28 @@ impl DbcParser {
         code_output!(
             code,
             IDT1,
-            "fn get_mut(&self, canid: u32) -> Result<RefMut<'_, Box<dyn CanDbcMessage>>, CanError> {"
-        )?;
-        code_output!(
-            code,
-            IDT2,
-            "let search= self.pool.binary_search_by(|msg| msg.borrow().get_id().cmp(&canid));",
+            "fn get_mut(&self, canid: u32) -> Result<{}<'_, Box<dyn {}>>, CanError> {{",
+            code.guard(),
+            code.api("CanDbcMessage")
         )?;
+        // pool and canids share the same order, search does not lock other messages
+        code_output!(code, IDT2, "let search= self.get_ids().binary_search(&canid);")?;
+        if !j1939.is_empty() {
+            // J1939 messages fallback on PGN, priority and source address vary at runtime
+            code_output!(
+                code,
+                IDT2,
+                "let search= search.or_else(|_| match j1939_canid_pgn(canid) {"
+            )?;
+            for (pgn, idx) in &j1939 {
+                code_output!(code, IDT3, "Some({}) => Ok({}),", pgn, idx)?;
+            }
+            code_output!(code, IDT3, "_ => Err(0),")?;
+            code_output!(code, IDT2, "});")?;
+        }
         code_output!(code, IDT2, "match search {")?;
         code_output!(code, IDT3, "Ok(idx) => {")?;
-        code_output!(code, IDT4, "match self.pool[idx].try_borrow_mut() {")?;
+        code_output!(code, IDT4, "match self.pool[idx].{}() {{", code.borrow_mut())?;
         code_output!(
             code,
             IDT5,
@@ -1324,7 +1774,9 @@ impl DbcParser {
         code_output!(
             code,
             IDT1,
-            "fn update(&self, data: &CanMsgData) -> Result<RefMut<'_, Box<dyn CanDbcMessage>>, CanError> {"
+            "fn update(&self, data: &CanMsgData) -> Result<{}<'_, Box<dyn {}>>, CanError> {{",
+            code.guard(),
+            code.api("CanDbcMessage")
         )?;
         code_output!(code, IDT2, "let mut msg= match self.get_mut(data.canid) {")?;
         code_output!(code, IDT3, "Err(error) => return Err(error),")?;
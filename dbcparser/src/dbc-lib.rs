@@ -25,9 +25,13 @@ pub mod parser;
 #[path = "dbc-gencode.rs"]
 pub mod gencode;
 
+#[path = "dbc-runtime.rs"]
+pub mod runtime;
+
 // --- Re-exports (optionnels) pour l'API publique
 pub use crate::data::*;
 pub use crate::gencode::*;
+pub use crate::runtime::*;
 // pub use crate::parser::{dbc_from_str /*, ...*/};
 
 /// Prélude pratique pour `use dbcparser::prelude::*;`
@@ -35,4 +39,5 @@ pub mod prelude {
     pub use crate::data::*;
     pub use crate::gencode::*;
     pub use crate::parser::*;
+    pub use crate::runtime::*;
 }
@@ -81,12 +81,13 @@ fn is_quote(chr: char) -> bool {
 /// # Examples
 /// ```rust
 /// # use nom::{IResult, error::Error as NomError};
-/// # fn ms0_demo(i: &str) -> IResult<&str, &str> { super::ms0::<_, NomError<&str>>(i) }
+/// # use dbcparser::parser::ms0;
+/// # fn ms0_demo(i: &str) -> IResult<&str, &str> { ms0::<_, NomError<&str>>(i) }
 /// assert_eq!(ms0_demo("   BO_"), Ok(("BO_", "   ")));
 /// assert_eq!(ms0_demo("\t BO_"), Ok(("\t BO_", ""))); // tab is NOT consumed
 /// ```
 #[allow(clippy::needless_pass_by_value)]
-fn ms0<T, E: ParseError<T>>(input: T) -> IResult<T, T, E>
+pub fn ms0<T, E: ParseError<T>>(input: T) -> IResult<T, T, E>
 where
     T: InputTakeAtPosition,
     <T as InputTakeAtPosition>::Item: AsChar + Clone,
@@ -122,13 +123,14 @@ where
 /// # Examples
 /// ```rust
 /// # use nom::{IResult, error::Error as NomError};
-/// # fn ms1_demo(i: &str) -> IResult<&str, &str> { super::ms1::<_, NomError<&str>>(i) }
+/// # use dbcparser::parser::ms1;
+/// # fn ms1_demo(i: &str) -> IResult<&str, &str> { ms1::<_, NomError<&str>>(i) }
 /// assert_eq!(ms1_demo("   BO_"), Ok(("BO_", "   ")));
 /// assert!(ms1_demo("BO_").is_err());          // requires at least one space
 /// assert!(ms1_demo("\t BO_").is_err());       // tab is not accepted
 /// ```
 #[allow(clippy::needless_pass_by_value)]
-fn ms1<T, E: ParseError<T>>(input: T) -> IResult<T, T, E>
+pub fn ms1<T, E: ParseError<T>>(input: T) -> IResult<T, T, E>
 where
     T: InputTakeAtPosition,
     <T as InputTakeAtPosition>::Item: AsChar + Clone,
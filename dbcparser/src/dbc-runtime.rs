@@ -0,0 +1,1112 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * Runtime (interpreted) counterpart of dbc-gencode: messages/signals are built from
+ * DbcObject at load time and expose the same CanDbcPool/CanDbcMessage/CanDbcSignal api
+ * as generated code.
+ */
+use crate::data::{
+    ByteOrder, DbcObject, Message, Signal, SignalExtendedValueType, ValDescription, ValueType,
+};
+
+use bitvec::prelude::*;
+use sockcan::prelude::*;
+use std::any::Any;
+use std::cell::{RefCell, RefMut};
+use std::fmt;
+use std::rc::Rc;
+
+// signal start/end bits within message payload
+fn signal_bits(signal: &Signal, msg: &Message) -> Result<(usize, usize), CanError> {
+    let bits = match signal.byte_order {
+        ByteOrder::LittleEndian => signal.le_start_end_bit(msg),
+        ByteOrder::BigEndian => signal.be_start_end_bit(msg),
+    };
+    match bits {
+        Ok((start, end)) => Ok((
+            usize::try_from(start).unwrap_or(usize::MAX),
+            usize::try_from(end).unwrap_or(usize::MAX),
+        )),
+        Err(error) => Err(CanError::new("dbc-signal-invalid", error.to_string())),
+    }
+}
+
+// multiplexor switch selecting a signal with its accepted raw value ranges
+struct DbcRtMux {
+    start: usize,
+    end: usize,
+    byte_order: ByteOrder,
+    ranges: Vec<(u64, u64)>,
+}
+
+impl DbcRtMux {
+    // multiplexor chain from signal to its top level multiplexor (nested multiplexing)
+    fn chain(signal: &Signal, msg: &Message, dbcfd: &DbcObject) -> Result<Vec<Self>, CanError> {
+        let mut chain = Vec::new();
+        let mut current = signal;
+        loop {
+            let selector = match current.get_mux_selector(msg, dbcfd) {
+                Ok(Some(selector)) => selector,
+                Ok(None) => break,
+                Err(error) => return Err(CanError::new("dbc-signal-invalid", error.to_string())),
+            };
+            if chain.len() >= msg.signals.len() {
+                return Err(CanError::new(
+                    "dbc-signal-invalid",
+                    format!(
+                        "signal:{} multiplexor chain loops in message:{}",
+                        signal.name, msg.name
+                    ),
+                ));
+            }
+            let (start, end) = signal_bits(selector.multiplexor, msg)?;
+            chain.push(DbcRtMux {
+                start,
+                end,
+                byte_order: selector.multiplexor.byte_order,
+                ranges: selector.ranges,
+            });
+            current = selector.multiplexor;
+        }
+        Ok(chain)
+    }
+
+    fn read_raw(&self, data: &[u8]) -> Option<u64> {
+        if self.end > data.len() * 8 {
+            return None;
+        }
+        let value = match self.byte_order {
+            ByteOrder::LittleEndian => data.view_bits::<Lsb0>()[self.start..self.end].load_le(),
+            ByteOrder::BigEndian => data.view_bits::<Msb0>()[self.start..self.end].load_be(),
+        };
+        Some(value)
+    }
+
+    fn is_active(&self, data: &[u8]) -> bool {
+        self.read_raw(data)
+            .is_some_and(|raw| self.ranges.iter().any(|(min, max)| (*min..=*max).contains(&raw)))
+    }
+
+    // keep multiplexor value when already in range, else select first range value
+    fn set_active(&self, data: &mut [u8]) {
+        if self.is_active(data) || self.end > data.len() * 8 {
+            return;
+        }
+        let value = self.ranges[0].0;
+        match self.byte_order {
+            ByteOrder::LittleEndian => {
+                data.view_bits_mut::<Lsb0>()[self.start..self.end].store_le(value);
+            },
+            ByteOrder::BigEndian => {
+                data.view_bits_mut::<Msb0>()[self.start..self.end].store_be(value);
+            },
+        }
+    }
+}
+
+pub struct DbcRtSignal {
+    callback: Option<RefCell<Box<dyn CanSigCtrl>>>,
+    status: CanDataStatus,
+    name: String,
+    stamp: u64,
+    value: CanDbcType,
+    start: usize,
+    end: usize,
+    byte_order: ByteOrder,
+    signed: bool,
+    float: bool,
+    factor: f64,
+    offset: f64,
+    min: f64,
+    max: f64,
+    range_check: bool,
+    unit: String,
+    values: Vec<ValDescription>,
+    mux: Vec<DbcRtMux>,
+}
+
+impl DbcRtSignal {
+    /// Builds a runtime signal from its DBC definition.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when signal bits do not fit within message size.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        signal: &Signal,
+        msg: &Message,
+        dbcfd: &DbcObject,
+        range_check: bool,
+    ) -> Result<Rc<RefCell<Box<dyn CanDbcSignal>>>, CanError> {
+        // raw values are handled as u64, sign extension needs 1..=64 bits
+        if signal.size == 0 || signal.size > 64 {
+            return Err(CanError::new(
+                "dbc-signal-invalid",
+                format!("signal:{} size {} is not within 1-64 bits", signal.name, signal.size),
+            ));
+        }
+        let (start, end) = signal_bits(signal, msg)?;
+
+        // IEEE float/double signals (SIG_VALTYPE_) store raw bits
+        let float = match dbcfd.extended_value_type_for_signal(msg.id, signal.name.as_str()) {
+            Some(SignalExtendedValueType::IEEEfloat32Bit) => Some(32),
+            Some(SignalExtendedValueType::IEEEdouble64bit) => Some(64),
+            _ => None,
+        };
+        if let Some(size) = float {
+            if signal.size != size {
+                return Err(CanError::new(
+                    "dbc-signal-invalid",
+                    format!(
+                        "signal:{} is {} bits float but size is {}",
+                        signal.name, size, signal.size
+                    ),
+                ));
+            }
+        }
+
+        let values = match dbcfd.value_descriptions_for_signal(msg.id, signal.name.as_str()) {
+            Some(values) => values.to_vec(),
+            None => Vec::new(),
+        };
+
+        let mut rtsig = DbcRtSignal {
+            callback: None,
+            status: CanDataStatus::Unset,
+            name: signal.get_type_kamel(),
+            stamp: 0,
+            value: CanDbcType::Bool(false),
+            start,
+            end,
+            byte_order: signal.byte_order,
+            signed: signal.value_type == ValueType::Signed,
+            float: float.is_some(),
+            factor: signal.factor,
+            offset: signal.offset,
+            min: signal.min,
+            max: signal.max,
+            range_check: range_check && signal.has_scaling(),
+            unit: signal.unit.clone(),
+            values,
+            mux: DbcRtMux::chain(signal, msg, dbcfd)?,
+        };
+        rtsig.value = rtsig.default_value(signal);
+        Ok(Rc::new(RefCell::new(Box::new(rtsig))))
+    }
+
+    // same typing rules as dbc-gencode get_data_type
+    fn default_value(&self, signal: &Signal) -> CanDbcType {
+        if signal.size == 1 {
+            CanDbcType::Bool(false)
+        } else if signal.has_scaling() {
+            CanDbcType::F64(0.0)
+        } else if self.float && signal.size == 32 {
+            CanDbcType::F32(0.0)
+        } else if self.float {
+            CanDbcType::F64(0.0)
+        } else {
+            match (self.signed, signal.size) {
+                (false, n) if n <= 8 => CanDbcType::U8(0),
+                (false, n) if n <= 16 => CanDbcType::U16(0),
+                (false, n) if n <= 32 => CanDbcType::U32(0),
+                (false, _) => CanDbcType::U64(0),
+                (true, n) if n <= 8 => CanDbcType::I8(0),
+                (true, n) if n <= 16 => CanDbcType::I16(0),
+                (true, n) if n <= 32 => CanDbcType::I32(0),
+                (true, _) => CanDbcType::I64(0),
+            }
+        }
+    }
+
+    fn get_size(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[must_use]
+    pub fn get_unit(&self) -> &str {
+        self.unit.as_str()
+    }
+
+    #[must_use]
+    pub fn get_min_max(&self) -> (f64, f64) {
+        (self.min, self.max)
+    }
+
+    /// Returns true when signal is not multiplexed or when its multiplexor chain matches `data`.
+    #[must_use]
+    pub fn is_active(&self, data: &[u8]) -> bool {
+        self.mux.iter().all(|mux| mux.is_active(data))
+    }
+
+    fn read_raw(&self, data: &[u8]) -> Option<u64> {
+        if self.end > data.len() * 8 {
+            return None;
+        }
+        let value = match self.byte_order {
+            ByteOrder::LittleEndian => data.view_bits::<Lsb0>()[self.start..self.end].load_le(),
+            ByteOrder::BigEndian => data.view_bits::<Msb0>()[self.start..self.end].load_be(),
+        };
+        Some(value)
+    }
+
+    fn write_raw(&self, value: u64, data: &mut [u8]) -> Result<(), CanError> {
+        if self.end > data.len() * 8 {
+            return Err(CanError::new(
+                "invalid-signal-buffer",
+                format!("signal:{} requires {} bits", self.name, self.end),
+            ));
+        }
+        match self.byte_order {
+            ByteOrder::LittleEndian => {
+                data.view_bits_mut::<Lsb0>()[self.start..self.end].store_le(value);
+            },
+            ByteOrder::BigEndian => {
+                data.view_bits_mut::<Msb0>()[self.start..self.end].store_be(value);
+            },
+        }
+        Ok(())
+    }
+
+    // extend sign of a `size` bits two's complement value
+    #[allow(clippy::cast_possible_wrap)]
+    fn sign_extend(&self, raw: u64) -> i64 {
+        let shift = 64 - self.get_size();
+        ((raw << shift) as i64) >> shift
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    fn decode(&self, raw: u64) -> CanDbcType {
+        let signed = self.sign_extend(raw);
+        match self.value {
+            CanDbcType::Bool(_) => CanDbcType::Bool(raw == 1),
+            CanDbcType::F32(_) => CanDbcType::F32(f32::from_bits(raw as u32)),
+            CanDbcType::F64(_) => {
+                let value = match (self.float, self.get_size()) {
+                    (true, 32) => f64::from(f32::from_bits(raw as u32)),
+                    (true, _) => f64::from_bits(raw),
+                    (false, _) if self.signed => signed as f64,
+                    (false, _) => raw as f64,
+                };
+                CanDbcType::F64(value * self.factor + self.offset)
+            },
+            CanDbcType::U8(_) => CanDbcType::U8(raw as u8),
+            CanDbcType::U16(_) => CanDbcType::U16(raw as u16),
+            CanDbcType::U32(_) => CanDbcType::U32(raw as u32),
+            CanDbcType::U64(_) => CanDbcType::U64(raw),
+            CanDbcType::I8(_) => CanDbcType::I8(signed as i8),
+            CanDbcType::I16(_) => CanDbcType::I16(signed as i16),
+            CanDbcType::I32(_) => CanDbcType::I32(signed as i32),
+            CanDbcType::I64(_) => CanDbcType::I64(signed),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+    fn encode(&self, value: CanDbcType) -> Result<u64, CanError> {
+        if std::mem::discriminant(&value) != std::mem::discriminant(&self.value) {
+            return Err(CanError::new(
+                "dbc-convert",
+                format!("signal:{} expected variant {:?}", self.name, self.value),
+            ));
+        }
+        let raw = match value {
+            CanDbcType::Bool(val) => u64::from(val),
+            CanDbcType::F64(val) => {
+                if self.range_check && (val < self.min || self.max < val) {
+                    return Err(CanError::new(
+                        "invalid-signal-value",
+                        format!("value={val} not in [{}..{}]", self.min, self.max),
+                    ));
+                }
+                let scaled = (val - self.offset) / self.factor;
+                if self.float && self.get_size() == 32 {
+                    u64::from((scaled as f32).to_bits())
+                } else if self.float {
+                    scaled.to_bits()
+                } else if self.signed {
+                    (scaled.round() as i64) as u64
+                } else {
+                    scaled.round() as u64
+                }
+            },
+            CanDbcType::F32(val) => u64::from(val.to_bits()),
+            CanDbcType::U8(val) => u64::from(val),
+            CanDbcType::U16(val) => u64::from(val),
+            CanDbcType::U32(val) => u64::from(val),
+            CanDbcType::U64(val) => val,
+            CanDbcType::I8(val) => i64::from(val) as u64,
+            CanDbcType::I16(val) => i64::from(val) as u64,
+            CanDbcType::I32(val) => i64::from(val) as u64,
+            CanDbcType::I64(val) => val as u64,
+        };
+
+        // keep only signal bits (bitvec store truncates, mask is for readability)
+        let mask = if self.get_size() >= 64 { u64::MAX } else { (1u64 << self.get_size()) - 1 };
+        Ok(raw & mask)
+    }
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn raw_value(&self) -> Option<i64> {
+        match self.value {
+            CanDbcType::Bool(val) => Some(i64::from(val)),
+            CanDbcType::U8(val) => Some(i64::from(val)),
+            CanDbcType::U16(val) => Some(i64::from(val)),
+            CanDbcType::U32(val) => Some(i64::from(val)),
+            CanDbcType::U64(val) => i64::try_from(val).ok(),
+            CanDbcType::I8(val) => Some(i64::from(val)),
+            CanDbcType::I16(val) => Some(i64::from(val)),
+            CanDbcType::I32(val) => Some(i64::from(val)),
+            CanDbcType::I64(val) => Some(val),
+            // float is not compatible with value descriptions (same as generated code)
+            CanDbcType::F32(_) | CanDbcType::F64(_) => None,
+        }
+    }
+
+    /// Returns DBC value description (VAL_) matching current value if any.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn get_as_def(&self) -> Option<&str> {
+        let value = self.raw_value()?;
+        self.values
+            .iter()
+            .find(|variant| variant.a as i64 == value)
+            .map(|variant| variant.b.as_str())
+    }
+
+    /// Encodes a DBC value description (VAL_) into `data`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when `label` is unknown or out of signal range.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn set_as_def(&mut self, label: &str, data: &mut [u8]) -> Result<(), CanError> {
+        let Some(variant) = self.values.iter().find(|variant| variant.b == label) else {
+            return Err(CanError::new("not-in-range", format!("({label}) unknown value")));
+        };
+        if variant.a > self.max || variant.a < self.min {
+            return Err(CanError::new(
+                "not-in-range",
+                format!("({label}) !!! {} not in [{}..{}] range", variant.a, self.min, self.max),
+            ));
+        }
+        let value = match self.value {
+            CanDbcType::Bool(_) => CanDbcType::Bool(variant.a as i64 == 1),
+            CanDbcType::F32(_) => CanDbcType::F32(variant.a as f32),
+            CanDbcType::F64(_) => CanDbcType::F64(variant.a),
+            CanDbcType::U8(_) => CanDbcType::U8(variant.a as u8),
+            CanDbcType::U16(_) => CanDbcType::U16(variant.a as u16),
+            CanDbcType::U32(_) => CanDbcType::U32(variant.a as u32),
+            CanDbcType::U64(_) => CanDbcType::U64(variant.a as u64),
+            CanDbcType::I8(_) => CanDbcType::I8(variant.a as i8),
+            CanDbcType::I16(_) => CanDbcType::I16(variant.a as i16),
+            CanDbcType::I32(_) => CanDbcType::I32(variant.a as i32),
+            CanDbcType::I64(_) => CanDbcType::I64(variant.a as i64),
+        };
+        self.set_value(value, data)
+    }
+}
+
+impl fmt::Display for DbcRtSignal {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = format!("{}:{}", self.name, self.value);
+        fmt.pad(&text)
+    }
+}
+
+#[allow(clippy::missing_fields_in_debug)]
+impl fmt::Debug for DbcRtSignal {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format
+            .debug_struct(&self.name)
+            .field("val", &self.value)
+            .field("stamp", &self.stamp)
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
+impl CanDbcSignal for DbcRtSignal {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_stamp(&self) -> u64 {
+        self.stamp
+    }
+
+    fn get_status(&self) -> CanDataStatus {
+        self.status
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn update(&mut self, frame: &CanMsgData) -> i32 {
+        match frame.opcode {
+            CanBcmOpCode::RxChanged if !self.is_active(frame.data) => {
+                self.status = CanDataStatus::Inactive;
+            },
+            CanBcmOpCode::RxChanged => match self.read_raw(frame.data) {
+                Some(raw) => {
+                    let value = self.decode(raw);
+                    if value == self.value {
+                        self.status = CanDataStatus::Unchanged;
+                    } else {
+                        self.value = value;
+                        self.status = CanDataStatus::Updated;
+                        self.stamp = frame.stamp;
+                    }
+                },
+                None => self.status = CanDataStatus::Error,
+            },
+            CanBcmOpCode::RxTimeout => {
+                self.status = CanDataStatus::Timeout;
+            },
+            _ => {
+                self.status = CanDataStatus::Error;
+            },
+        }
+        match &self.callback {
+            None => 0,
+            Some(callback) => match callback.try_borrow() {
+                Err(_) => {
+                    log::warn!("signal:{} fail to get callback reference", self.name);
+                    -1
+                },
+                Ok(cb_ref) => cb_ref.sig_notification(self),
+            },
+        }
+    }
+
+    fn set_value(&mut self, value: CanDbcType, data: &mut [u8]) -> Result<(), CanError> {
+        let raw = self.encode(value)?;
+        self.write_raw(raw, data)?;
+        // multiplexed signal is meaningless without its multiplexor value
+        for mux in &self.mux {
+            mux.set_active(data);
+        }
+        Ok(())
+    }
+
+    fn get_value(&self) -> CanDbcType {
+        self.value
+    }
+
+    fn to_json(&self) -> String {
+        let value = match self.value {
+            CanDbcType::F32(val) => format!("{val:?}"),
+            CanDbcType::F64(val) => format!("{val:?}"),
+            other => format!("{other}"),
+        };
+        format!(
+            "{{\"status\":\"{}\",\"name\":\"{}\",\"stamp\":{},\"value\":{}}}",
+            self.status, self.name, self.stamp, value
+        )
+    }
+
+    fn reset(&mut self) {
+        self.stamp = 0;
+        self.value = match self.value {
+            CanDbcType::Bool(_) => CanDbcType::Bool(false),
+            CanDbcType::F32(_) => CanDbcType::F32(0.0),
+            CanDbcType::F64(_) => CanDbcType::F64(0.0),
+            CanDbcType::U8(_) => CanDbcType::U8(0),
+            CanDbcType::U16(_) => CanDbcType::U16(0),
+            CanDbcType::U32(_) => CanDbcType::U32(0),
+            CanDbcType::U64(_) => CanDbcType::U64(0),
+            CanDbcType::I8(_) => CanDbcType::I8(0),
+            CanDbcType::I16(_) => CanDbcType::I16(0),
+            CanDbcType::I32(_) => CanDbcType::I32(0),
+            CanDbcType::I64(_) => CanDbcType::I64(0),
+        };
+        self.status = CanDataStatus::Unset;
+    }
+
+    fn set_callback(&mut self, callback: Box<dyn CanSigCtrl>) {
+        self.callback = Some(RefCell::new(callback));
+    }
+}
+
+pub struct DbcRtMessage {
+    callback: Option<RefCell<Box<dyn CanMsgCtrl>>>,
+    signals: Vec<Rc<RefCell<Box<dyn CanDbcSignal>>>>,
+    name: String,
+    status: CanBcmOpCode,
+    listeners: i32,
+    stamp: u64,
+    id: u32,
+    canid: u32,
+    pgn: Option<u32>,
+    size: usize,
+    fd_flags: Option<CanFdFlags>,
+}
+
+impl DbcRtMessage {
+    /// Builds a runtime message and its signals from DBC definition.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when one of the message signals is invalid.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        msg: &Message,
+        dbcfd: &DbcObject,
+        range_check: bool,
+    ) -> Result<Rc<RefCell<Box<dyn CanDbcMessage>>>, CanError> {
+        if let Err(error) = msg.check_size() {
+            return Err(CanError::new("dbc-message-invalid", error.to_string()));
+        }
+        let fd_flags =
+            msg.get_fd_brs(dbcfd).map(
+                |brs| {
+                    if brs {
+                        CanFdFlags::BRS
+                    } else {
+                        CanFdFlags::empty()
+                    }
+                },
+            );
+
+        let mut signals = Vec::with_capacity(msg.signals.len());
+        for signal in &msg.signals {
+            signals.push(DbcRtSignal::new(signal, msg, dbcfd, range_check)?);
+        }
+
+        Ok(Rc::new(RefCell::new(Box::new(DbcRtMessage {
+            id: msg.id.to_u32(),
+            canid: msg.id.to_u32(),
+            pgn: msg.get_j1939_pgn(dbcfd),
+            name: msg.get_type_kamel(),
+            status: CanBcmOpCode::Unknown,
+            listeners: 0,
+            stamp: 0,
+            callback: None,
+            size: usize::try_from(msg.size).unwrap_or(usize::MAX),
+            fd_flags,
+            signals,
+        }))))
+    }
+
+    /// Returns DBC message size in bytes.
+    #[must_use]
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /// Encodes one signal by name into `frame`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when signal is unknown or value invalid.
+    pub fn set_value(
+        &mut self,
+        name: &str,
+        value: CanDbcType,
+        frame: &mut [u8],
+    ) -> Result<&mut Self, CanError> {
+        let Some(signal) = self.signals.iter().find(|signal| signal.borrow().get_name() == name)
+        else {
+            return Err(CanError::new("signal-not-found", format!("signal:{name} not found")));
+        };
+        match Rc::clone(signal).try_borrow_mut() {
+            Ok(mut signal) => signal.set_value(value, frame)?,
+            Err(_) => {
+                return Err(CanError::new(
+                    "signal-set-values-fail",
+                    format!("Internal error {name}"),
+                ))
+            },
+        }
+        Ok(self)
+    }
+}
+
+impl CanDbcMessage for DbcRtMessage {
+    fn reset(&mut self) -> Result<(), CanError> {
+        self.status = CanBcmOpCode::Unknown;
+        self.stamp = 0;
+        for signal in &self.signals {
+            match Rc::clone(signal).try_borrow_mut() {
+                Ok(mut signal) => signal.reset(),
+                Err(_) => {
+                    return Err(CanError::new(
+                        "signal-reset-fail",
+                        format!("Internal error {}", self.name),
+                    ))
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, frame: &CanMsgData) -> Result<(), CanError> {
+        if matches!(frame.opcode, CanBcmOpCode::RxChanged) && frame.data.len() < self.size {
+            return Err(CanError::new(
+                "invalid-message-len",
+                format!("{}: len={} expected={}", self.name, frame.data.len(), self.size),
+            ));
+        }
+        self.stamp = frame.stamp;
+        self.status = frame.opcode;
+        self.canid = frame.canid;
+        self.listeners = 0;
+        for signal in &self.signals {
+            match Rc::clone(signal).try_borrow_mut() {
+                Ok(mut signal) => self.listeners += signal.update(frame),
+                Err(_) => {
+                    return Err(CanError::new(
+                        "signal-update-fail",
+                        format!("Internal error {}", self.name),
+                    ))
+                },
+            }
+        }
+        match &self.callback {
+            None => {},
+            Some(callback) => match callback.try_borrow() {
+                Err(_) => {
+                    return Err(CanError::new(
+                        "message-callback-fail",
+                        format!("{}: fail to get callback reference", self.name),
+                    ))
+                },
+                Ok(cb_ref) => cb_ref.msg_notification(self),
+            },
+        }
+        Ok(())
+    }
+
+    fn get_signals(&self) -> &[Rc<RefCell<Box<dyn CanDbcSignal>>>] {
+        &self.signals
+    }
+
+    fn get_listeners(&self) -> i32 {
+        self.listeners
+    }
+
+    fn set_callback(&mut self, callback: Box<dyn CanMsgCtrl>) {
+        self.callback = Some(RefCell::new(callback));
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_status(&self) -> CanBcmOpCode {
+        self.status
+    }
+
+    fn get_stamp(&self) -> u64 {
+        self.stamp
+    }
+
+    fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    fn get_len(&self) -> u8 {
+        u8::try_from(self.size).unwrap_or(u8::MAX)
+    }
+
+    fn get_fd_flags(&self) -> Option<CanFdFlags> {
+        self.fd_flags
+    }
+
+    fn get_pgn(&self) -> Option<u32> {
+        self.pgn
+    }
+
+    fn get_src(&self) -> Option<u8> {
+        self.pgn.and(u8::try_from(self.canid & 0xFF).ok())
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Runtime message pool, same api as generated `CanMsgPool`.
+pub struct DbcRtPool {
+    uid: &'static str,
+    ids: Vec<u32>,
+    pgns: Vec<(u32, usize)>,
+    pool: Vec<Rc<RefCell<Box<dyn CanDbcMessage>>>>,
+}
+
+impl DbcRtPool {
+    /// Builds a pool with every message from `dbcfd`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when one message/signal definition is invalid.
+    pub fn new(uid: &'static str, dbcfd: &DbcObject, range_check: bool) -> Result<Self, CanError> {
+        let mut messages: Vec<&Message> = dbcfd.messages.iter().collect();
+        messages.sort_by_key(|msg| msg.id.0);
+
+        let mut pool = Vec::with_capacity(messages.len());
+        for msg in &messages {
+            pool.push(DbcRtMessage::new(msg, dbcfd, range_check)?);
+        }
+        let ids = messages.iter().map(|msg| msg.id.to_u32()).collect();
+
+        // J1939 messages fallback on PGN, lowest canid wins (messages are sorted by canid)
+        let mut pgns: Vec<(u32, usize)> = messages
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, msg)| Some((msg.get_j1939_pgn(dbcfd)?, idx)))
+            .collect();
+        pgns.sort_by_key(|(pgn, _)| *pgn);
+        pgns.dedup_by_key(|(pgn, _)| *pgn);
+        Ok(DbcRtPool { uid, ids, pgns, pool })
+    }
+
+    #[must_use]
+    pub fn get_uid(&self) -> &'static str {
+        self.uid
+    }
+}
+
+impl CanDbcPool for DbcRtPool {
+    fn get_messages(&self) -> &[Rc<RefCell<Box<dyn CanDbcMessage>>>] {
+        &self.pool
+    }
+
+    fn get_ids(&self) -> &[u32] {
+        &self.ids
+    }
+
+    fn get_mut(&self, canid: u32) -> Result<RefMut<'_, Box<dyn CanDbcMessage>>, CanError> {
+        let search = self.ids.binary_search(&canid).or_else(|_| {
+            let pgn = j1939_canid_pgn(canid).ok_or(0usize)?;
+            self.pgns
+                .binary_search_by_key(&pgn, |(pgn, _)| *pgn)
+                .map(|found| self.pgns[found].1)
+        });
+        match search {
+            Ok(idx) => match self.pool[idx].try_borrow_mut() {
+                Err(_code) => Err(CanError::new("message-get_mut", "internal msg pool error")),
+                Ok(mut_ref) => Ok(mut_ref),
+            },
+            Err(_) => Err(CanError::new("fail-canid-search", format!("canid:{canid} not found"))),
+        }
+    }
+
+    fn update(&self, data: &CanMsgData) -> Result<RefMut<'_, Box<dyn CanDbcMessage>>, CanError> {
+        let mut msg = self.get_mut(data.canid)?;
+        msg.update(data)?;
+        Ok(msg)
+    }
+}
+
+/// Loads a DBC file at runtime, mirror of `DbcParser` for interpreted pools.
+pub struct DbcRuntime {
+    uid: &'static str,
+    infile: Option<String>,
+    range_check: bool,
+    whitelist: Option<Vec<u32>>,
+    blacklist: Option<Vec<u32>>,
+}
+
+impl DbcRuntime {
+    #[must_use]
+    pub fn new(uid: &'static str) -> Self {
+        DbcRuntime { uid, infile: None, range_check: true, whitelist: None, blacklist: None }
+    }
+
+    pub fn dbcfile(&mut self, dbcfile: &str) -> &mut Self {
+        self.infile = Some(dbcfile.to_owned());
+        self
+    }
+
+    pub fn whitelist(&mut self, canids: Vec<u32>) -> &mut Self {
+        self.whitelist = Some(canids);
+        self
+    }
+
+    pub fn blacklist(&mut self, canids: Vec<u32>) -> &mut Self {
+        self.blacklist = Some(canids);
+        self
+    }
+
+    pub fn range_check(&mut self, flag: bool) -> &mut Self {
+        self.range_check = flag;
+        self
+    }
+
+    /// Parses the DBC file and builds the runtime pool.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the DBC file cannot be read/parsed or when a
+    /// message/signal definition is invalid.
+    pub fn load(&self) -> Result<DbcRtPool, CanError> {
+        let Some(infile) = &self.infile else {
+            return Err(CanError::new("dbc-load-fail", "setting dbcpath is mandatory"));
+        };
+
+        let mut dbcfd = match DbcObject::from_file(infile.as_str()) {
+            Err(error) => return Err(CanError::new("dbc-load-fail", error.to_string())),
+            Ok(dbcfd) => dbcfd,
+        };
+
+        if let Some(list) = &self.whitelist {
+            dbcfd.messages.retain(|msg| list.contains(&msg.id.0));
+        }
+
+        if let Some(list) = &self.blacklist {
+            dbcfd.messages.retain(|msg| !list.contains(&msg.id.0));
+        }
+
+        DbcRtPool::new(self.uid, &dbcfd, self.range_check)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBC: &str = "VERSION \"\"\n\nNS_ :\n\nBS_:\n\nBU_: ECU\n\n\
+BO_ 291 Test: 8 ECU\n\
+ SG_ Speed : 0|16@1+ (0.1,0) [0|6553.5] \"km/h\" ECU\n\
+ SG_ Temp : 16|8@1- (1,-40) [-168|87] \"C\" ECU\n\
+ SG_ Gear : 31|4@0+ (1,0) [0|15] \"\" ECU\n\
+ SG_ Torque : 39|12@0- (1,0) [-2048|2047] \"Nm\" ECU\n\
+ SG_ Brake : 56|1@1+ (1,0) [0|1] \"\" ECU\n\
+\n\
+BO_ 292 Inverter: 8 ECU\n\
+ SG_ Torque : 0|32@1- (1,0) [-1000|1000] \"Nm\" ECU\n\
+ SG_ Current : 32|32@1- (0.5,10) [-1000|1000] \"A\" ECU\n\
+\n\
+BO_ 293 Battery: 64 ECU\n\
+ SG_ Cell : 496|16@1+ (0.5,0) [0|32767] \"V\" ECU\n\
+\n\
+VAL_ 291 Gear 0 \"Park\" 1 \"Drive\" ;\n\
+SIG_VALTYPE_ 292 Torque : 1;\n\
+SIG_VALTYPE_ 292 Current : 1;\n";
+
+    const J1939_DBC: &str = "VERSION \"\"\n\nNS_ :\n\nBS_:\n\nBU_: ECU\n\n\
+BO_ 2364540158 EEC1: 8 ECU\n\
+ SG_ EngineSpeed : 24|16@1+ (0.125,0) [0|8031.875] \"rpm\" ECU\n\
+\n\
+BO_ 2566834942 DM1: 8 ECU\n\
+ SG_ Lamps : 0|8@1+ (1,0) [0|255] \"\" ECU\n\
+\n\
+BA_DEF_ BO_ \"VFrameFormat\" ENUM \"StandardCAN\",\"ExtendedCAN\",\"reserved\",\"J1939PG\";\n\
+BA_DEF_DEF_ \"VFrameFormat\" \"J1939PG\";\n";
+
+    const MUX_DBC: &str = "VERSION \"\"\n\nNS_ :\n\nBS_:\n\nBU_: ECU\n\n\
+BO_ 300 Mux: 8 ECU\n\
+ SG_ Mode M : 0|8@1+ (1,0) [0|255] \"\" ECU\n\
+ SG_ Speed m0 : 8|8@1+ (1,0) [0|255] \"\" ECU\n\
+ SG_ Level m1M : 8|8@1+ (1,0) [0|255] \"\" ECU\n\
+ SG_ Pressure m2 : 16|8@1+ (1,0) [0|255] \"\" ECU\n\
+ SG_ Temp m3 : 16|8@1- (1,0) [-128|127] \"\" ECU\n\
+\n\
+SG_MUL_VAL_ 300 Pressure Level 2-2, 4-6;\n\
+SG_MUL_VAL_ 300 Temp Level 3-3;\n";
+
+    fn signal_value(pool: &DbcRtPool, name: &str) -> CanDbcType {
+        let msg = pool.get_mut(291).unwrap();
+        let signal = msg.get_signals().iter().find(|sig| sig.borrow().get_name() == name).unwrap();
+        let value = signal.borrow().get_value();
+        value
+    }
+
+    #[test]
+    fn runtime_decode_encode() {
+        let dbcfd = DbcObject::from_str(DBC).unwrap();
+        let pool = DbcRtPool::new("test", &dbcfd, true).unwrap();
+        assert_eq!(pool.get_ids(), &[291, 292, 293]);
+
+        let mut data = [0u8; 8];
+        {
+            let mut msg = pool.get_mut(291).unwrap();
+            let msg = msg.as_any().downcast_mut::<DbcRtMessage>().unwrap();
+            msg.set_value("Speed", CanDbcType::F64(123.4), &mut data).unwrap();
+            msg.set_value("Temp", CanDbcType::F64(-45.0), &mut data).unwrap();
+            msg.set_value("Gear", CanDbcType::U8(1), &mut data).unwrap();
+            msg.set_value("Torque", CanDbcType::I16(-300), &mut data).unwrap();
+            msg.set_value("Brake", CanDbcType::Bool(true), &mut data).unwrap();
+            assert!(msg.set_value("Gear", CanDbcType::U16(1), &mut data).is_err());
+        }
+        assert_eq!(data[0..2], 1234u16.to_le_bytes());
+        assert_eq!(data[2], (-5i8).to_ne_bytes()[0]);
+
+        let frame = CanMsgData {
+            canid: 291,
+            len: 8,
+            stamp: 10,
+            opcode: CanBcmOpCode::RxChanged,
+            data: &data,
+        };
+        pool.update(&frame).unwrap();
+
+        assert_eq!(signal_value(&pool, "Speed"), CanDbcType::F64(123.4));
+        assert_eq!(signal_value(&pool, "Temp"), CanDbcType::F64(-45.0));
+        assert_eq!(signal_value(&pool, "Gear"), CanDbcType::U8(1));
+        assert_eq!(signal_value(&pool, "Torque"), CanDbcType::I16(-300));
+        assert_eq!(signal_value(&pool, "Brake"), CanDbcType::Bool(true));
+
+        let mut msg = pool.get_mut(291).unwrap();
+        let signal = msg.get_signals()[2].clone();
+        let mut signal = signal.borrow_mut();
+        let gear = signal.as_any().downcast_mut::<DbcRtSignal>().unwrap();
+        assert_eq!(gear.get_as_def(), Some("Drive"));
+        assert!(matches!(msg.get_status(), CanBcmOpCode::RxChanged));
+        assert!(msg.as_any().is::<DbcRtMessage>());
+    }
+    #[test]
+    fn runtime_signal_size() {
+        let dbc = DBC.replace("SG_ Brake : 56|1@1+", "SG_ Brake : 56|0@1+");
+        let dbcfd = DbcObject::from_str(&dbc).unwrap();
+        let error = DbcRtPool::new("test", &dbcfd, false).err().unwrap();
+        assert_eq!(error.get_uid(), "dbc-signal-invalid");
+    }
+
+    #[test]
+    fn runtime_ieee_float() {
+        let dbcfd = DbcObject::from_str(DBC).unwrap();
+        let pool = DbcRtPool::new("test", &dbcfd, true).unwrap();
+
+        let mut data = [0u8; 8];
+        {
+            let mut msg = pool.get_mut(292).unwrap();
+            let msg = msg.as_any().downcast_mut::<DbcRtMessage>().unwrap();
+            msg.set_value("Torque", CanDbcType::F32(-12.5), &mut data).unwrap();
+            msg.set_value("Current", CanDbcType::F64(110.0), &mut data).unwrap();
+        }
+        assert_eq!(data[0..4], (-12.5f32).to_le_bytes());
+        assert_eq!(data[4..8], 200f32.to_le_bytes());
+
+        let frame = CanMsgData {
+            canid: 292,
+            len: 8,
+            stamp: 10,
+            opcode: CanBcmOpCode::RxChanged,
+            data: &data,
+        };
+        pool.update(&frame).unwrap();
+        let msg = pool.get_mut(292).unwrap();
+        assert_eq!(msg.get_signals()[0].borrow().get_value(), CanDbcType::F32(-12.5));
+        assert_eq!(msg.get_signals()[1].borrow().get_value(), CanDbcType::F64(110.0));
+    }
+
+    #[test]
+    fn runtime_can_fd() {
+        let dbcfd = DbcObject::from_str(DBC).unwrap();
+        let pool = DbcRtPool::new("test", &dbcfd, true).unwrap();
+
+        let mut data = [0u8; 64];
+        {
+            let mut msg = pool.get_mut(293).unwrap();
+            assert_eq!(msg.get_len(), 64);
+            assert!(msg.get_fd_flags().is_some());
+            let msg = msg.as_any().downcast_mut::<DbcRtMessage>().unwrap();
+            msg.set_value("Cell", CanDbcType::F64(1650.0), &mut data).unwrap();
+        }
+        assert_eq!(data[62..64], 3300u16.to_le_bytes());
+
+        let short = CanMsgData {
+            canid: 293,
+            len: 8,
+            stamp: 10,
+            opcode: CanBcmOpCode::RxChanged,
+            data: &data[0..8],
+        };
+        assert!(pool.update(&short).is_err());
+
+        let frame = CanMsgData {
+            canid: 293,
+            len: 64,
+            stamp: 20,
+            opcode: CanBcmOpCode::RxChanged,
+            data: &data,
+        };
+        pool.update(&frame).unwrap();
+        let msg = pool.get_mut(293).unwrap();
+        assert_eq!(msg.get_signals()[0].borrow().get_value(), CanDbcType::F64(1650.0));
+    }
+
+    #[test]
+    fn runtime_j1939_pgn() {
+        let dbcfd = DbcObject::from_str(J1939_DBC).unwrap();
+        let pool = DbcRtPool::new("test", &dbcfd, true).unwrap();
+        {
+            let msg = pool.get_mut(0x8CF0_04FE).unwrap();
+            assert_eq!(msg.get_pgn(), Some(0xF004));
+            assert_eq!(msg.get_src(), Some(0xFE));
+        }
+
+        // same PGN with an other priority and source address
+        let data = [0, 0, 0, 0x40, 0x1F, 0, 0, 0];
+        let frame = CanMsgData {
+            canid: 0x0CF0_0417,
+            len: 8,
+            stamp: 10,
+            opcode: CanBcmOpCode::RxChanged,
+            data: &data,
+        };
+        let msg = pool.update(&frame).unwrap();
+        assert_eq!(msg.get_name(), "Eec1");
+        assert_eq!(msg.get_src(), Some(0x17));
+        assert_eq!(msg.get_signals()[0].borrow().get_value(), CanDbcType::F64(1000.0));
+        drop(msg);
+
+        assert_eq!(pool.get_mut(0x18FE_CA00).unwrap().get_pgn(), Some(0xFECA));
+        assert!(pool.get_mut(0x0CF0_0517).is_err());
+        assert!(pool.get_mut(0x123).is_err());
+    }
+
+    #[test]
+    fn runtime_multiplex() {
+        let dbcfd = DbcObject::from_str(MUX_DBC).unwrap();
+        let pool = DbcRtPool::new("test", &dbcfd, true).unwrap();
+        let status =
+            |msg: &RefMut<'_, Box<dyn CanDbcMessage>>| -> Vec<(CanDataStatus, CanDbcType)> {
+                msg.get_signals()
+                    .iter()
+                    .map(|sig| (sig.borrow().get_status(), sig.borrow().get_value()))
+                    .collect()
+            };
+
+        // Mode=1 selects Level, Level=5 selects Pressure through SG_MUL_VAL_ 4-6 range
+        let data = [1u8, 5, 42, 0, 0, 0, 0, 0];
+        let frame = CanMsgData {
+            canid: 300,
+            len: 8,
+            stamp: 10,
+            opcode: CanBcmOpCode::RxChanged,
+            data: &data,
+        };
+        let msg = pool.update(&frame).unwrap();
+        let values = status(&msg);
+        assert_eq!(values[0], (CanDataStatus::Updated, CanDbcType::U8(1)));
+        assert_eq!(values[1].0, CanDataStatus::Inactive);
+        assert_eq!(values[2], (CanDataStatus::Updated, CanDbcType::U8(5)));
+        assert_eq!(values[3], (CanDataStatus::Updated, CanDbcType::U8(42)));
+        assert_eq!(values[4].0, CanDataStatus::Inactive);
+        drop(msg);
+
+        // Mode=0 selects Speed, nested Level/Pressure/Temp are all inactive
+        let data = [0u8, 7, 42, 0, 0, 0, 0, 0];
+        let frame = CanMsgData {
+            canid: 300,
+            len: 8,
+            stamp: 20,
+            opcode: CanBcmOpCode::RxChanged,
+            data: &data,
+        };
+        let msg = pool.update(&frame).unwrap();
+        let values = status(&msg);
+        assert_eq!(values[1], (CanDataStatus::Updated, CanDbcType::U8(7)));
+        assert_eq!(values[2].0, CanDataStatus::Inactive);
+        assert_eq!(values[3].0, CanDataStatus::Inactive);
+        assert_eq!(values[4].0, CanDataStatus::Inactive);
+        drop(msg);
+
+        // encoding a nested multiplexed signal selects its whole multiplexor chain
+        let mut data = [0u8; 8];
+        let mut msg = pool.get_mut(300).unwrap();
+        let msg = msg.as_any().downcast_mut::<DbcRtMessage>().unwrap();
+        msg.set_value("Temp", CanDbcType::I8(-3), &mut data).unwrap();
+        assert_eq!(data[0..3], [1, 3, (-3i8).to_ne_bytes()[0]]);
+    }
+}
@@ -0,0 +1,66 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+*/
+extern crate dbcparser;
+use dbcparser::prelude::*;
+
+const HEADER: &str = "
+// -----------------------------------------------------------------------
+//              <- DBC file Rust mapping ->
+// -----------------------------------------------------------------------
+//  Do not edit this file it will be regenerated automatically by cargo.
+//  Check:
+//   - build.rs at project root for dynamically mapping
+//   - examples/basic/etc/dbc/??? for static values
+//  Reference: iot.bzh/Redpesk canbus-rs code generator
+// -----------------------------------------------------------------------
+
+// Tell rustfmt (stable) to skip formatting this whole file
+#[rustfmt::skip]
+
+#[allow(
+    warnings,
+    clippy::all,
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::redundant_field_names,
+    clippy::similar_names
+)]
+    ";
+
+fn main() {
+    // generated parsers are type-checked and exercised by tests/dbc-gencode.rs
+    let dbc_infile = "./etc/dbc/extended_multiplex.dbc";
+    println!("cargo:rerun-if-changed={dbc_infile}");
+    DbcParser::new("DbcMux")
+        .dbcfile(dbc_infile)
+        .outfile("./src/__mux-dbcgen.rs")
+        .header(HEADER)
+        .generate()
+        .expect("Fail to parse dbc-file'\n");
+
+    let dbc_infile = "./etc/dbc/float.dbc";
+    println!("cargo:rerun-if-changed={dbc_infile}");
+    DbcParser::new("DbcFloat")
+        .dbcfile(dbc_infile)
+        .outfile("./src/__float-dbcgen.rs")
+        .header(HEADER)
+        .generate()
+        .expect("Fail to parse dbc-file'\n");
+
+    // thread safe variant only needs to compile and run from multiple threads
+    let dbc_infile = "./etc/dbc/simple.dbc";
+    println!("cargo:rerun-if-changed={dbc_infile}");
+    DbcParser::new("DbcSync")
+        .dbcfile(dbc_infile)
+        .outfile("./src/__sync-dbcgen.rs")
+        .header(HEADER)
+        .thread_safe(true)
+        .generate()
+        .expect("Fail to parse dbc-file'\n");
+}
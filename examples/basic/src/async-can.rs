@@ -0,0 +1,69 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * Read RAW and BCM sockets concurrently from a single tokio task.
+ */
+
+extern crate sockcan;
+use env_logger::Env;
+use sockcan::prelude::*;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), String> {
+    // Initialize logging backend for the `log` facade (idempotent).
+    let env = Env::default().default_filter_or("info");
+    let _ = env_logger::Builder::from_env(env).format_timestamp_millis().try_init();
+
+    const VCAN: &str = "vcan0";
+
+    let raw = match SockCanHandle::open_raw(VCAN, CanTimeStamp::CLASSIC) {
+        Err(error) => return Err(format!("fail opening candev {error}")),
+        Ok(value) => SockCanAsync::new(value).map_err(|error| error.to_string())?,
+    };
+
+    let bcm = match SockCanHandle::open_bcm(VCAN, CanTimeStamp::CLASSIC) {
+        Err(error) => return Err(format!("fail opening candev {error}")),
+        Ok(value) => SockCanAsync::new(value).map_err(|error| error.to_string())?,
+    };
+
+    // watch 0x118 changes with a 1s timeout
+    bcm.send_bcm(
+        SockBcmCmd::new(
+            CanBcmOpCode::RxSetup,
+            CanBcmFlag::RX_FILTER_ID | CanBcmFlag::SET_TIMER | CanBcmFlag::START_TIMER,
+            0x118,
+        )
+        .set_timers(0, 1000),
+    )
+    .await
+    .map_err(|error| format!("bcm-filter fail Error:{error}"))?;
+
+    loop {
+        tokio::select! {
+            msg = raw.recv() => match msg {
+                Ok(msg) => match msg.get_raw() {
+                    CanAnyFrame::RawStd(frame) => {
+                        log::info!("RAW canid:{:#04x} len:{}", frame.get_id(), frame.get_len());
+                    },
+                    CanAnyFrame::RawFd(frame) => {
+                        log::info!("RAW FD canid:{:#04x} len:{}", frame.get_id(), frame.get_len());
+                    },
+                    _ => log::info!("RAW invalid frame"),
+                },
+                Err(error) => return Err(format!("raw-recv fail Error:{error}")),
+            },
+            msg = bcm.recv_bcm() => match msg {
+                Ok(msg) => log::info!(
+                    "BCM opcode:{:?} canid:{:#04x}",
+                    msg.get_opcode(),
+                    msg.get_id().unwrap_or(0)
+                ),
+                Err(error) => return Err(format!("bcm-recv fail Error:{error}")),
+            },
+        }
+    }
+}
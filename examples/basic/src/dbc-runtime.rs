@@ -0,0 +1,68 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * Loads any DBC file at runtime (no code generation) and decodes an optional candump log.
+ */
+
+extern crate dbcparser;
+extern crate sockcan;
+use dbcparser::prelude::*;
+use env_logger::Env;
+use sockcan::prelude::*;
+
+const USAGE: &str = "can-dbc-runtime file.dbc [candump.log]";
+
+fn main() -> Result<(), String> {
+    // Initialize logging backend for the `log` facade (idempotent).
+    let env = Env::default().default_filter_or("info");
+    let _ = env_logger::Builder::from_env(env).format_timestamp_millis().try_init();
+
+    let mut args = std::env::args().skip(1);
+    let dbcfile = args.next().ok_or(USAGE)?;
+    let dumpfile = args.next();
+
+    let pool = DbcRuntime::new("Runtime")
+        .dbcfile(&dbcfile)
+        .load()
+        .map_err(|e| format!("fail loading {dbcfile}: {e}"))?;
+
+    for msg in pool.get_messages() {
+        let msg = msg.borrow();
+        let signals: Vec<String> = msg
+            .get_signals()
+            .iter()
+            .map(|signal| signal.borrow().get_name().to_owned())
+            .collect();
+        log::info!(
+            "canid:{:#04x} len:{} {} [{}]",
+            msg.get_id(),
+            msg.get_len(),
+            msg.get_name(),
+            signals.join(",")
+        );
+    }
+
+    let Some(dumpfile) = dumpfile else {
+        return Ok(());
+    };
+
+    let reader = CanDumpReader::open(&dumpfile).map_err(|e| e.to_string())?;
+    for record in reader {
+        let record = record.map_err(|e| e.to_string())?;
+        // frames not described within DBC are silently ignored
+        let Ok(msg) = pool.update(&CanMsgData::from(&record)) else {
+            continue;
+        };
+        for signal in msg.get_signals() {
+            let signal = signal.borrow();
+            if let CanDataStatus::Updated = signal.get_status() {
+                log::info!("{}.{}", msg.get_name(), signal.to_json());
+            }
+        }
+    }
+    Ok(())
+}
@@ -8,76 +8,21 @@
  */
 extern crate sockcan;
 use env_logger::Env;
-
-use regex::Regex;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::io::{BufRead, BufReader};
-use std::path::Path; // pour writeln!
-
-//use sockcan::{CanTimeStamp, SockCanHandle, SockCanMsg};
-
-#[derive(Debug, Clone)]
-struct ParsedCan {
-    ts: f64,       // timestamp absolu trouvé dans le dump
-    iface: String, // nom d’interface dans le dump (informative)
-    id: u32,       // identifiant CAN (hex)
-    data: Vec<u8>, // payload
-}
-
-fn parse_line(line: &str, re: &Regex) -> Option<ParsedCan> {
-    // captures: 1=ts  2=iface  3=id_hex  4=data_hex
-    let caps = re.captures(line)?;
-    let ts: f64 = caps.get(1)?.as_str().parse().ok()?;
-    let iface = caps.get(2)?.as_str().to_string();
-
-    // id en hex sans 0x, peut être 11-bit ou 29-bit; on retire un éventuel bit extended si présent dans ta source
-    let id = u32::from_str_radix(caps.get(3)?.as_str(), 16).ok()?;
-    // si besoin: id &= 0x1FFF_FFFF; // pour forcer 29 bits utiles
-
-    let data_hex = caps.get(4)?.as_str();
-    if data_hex.len() % 2 != 0 {
-        return None;
+use sockcan::prelude::*;
+
+const USAGE: &str = "load-can-dump dump.log|trace.asc|trace.blf|trace.pcapng iface [--no-timing] [--speed x] [--loop n] [--map src=dst] [--include id[:mask]] [--exclude id[:mask]]";
+
+// canid filter as hex id with optional hex mask (default exact match), as candump
+// an id written with more than 3 digits selects extended frames
+fn parse_filter(arg: &str) -> Result<(u32, u32), String> {
+    let (text, mask) = arg.split_once(':').unwrap_or((arg, "1FFFFFFF"));
+    let mut canid =
+        u32::from_str_radix(text, 16).map_err(|e| format!("invalid canid {arg}: {e}"))?;
+    if text.len() > 3 {
+        canid |= FilterMask::EFF_FLAG.bits();
     }
-    let mut data = Vec::with_capacity(data_hex.len() / 2);
-    for i in (0..data_hex.len()).step_by(2) {
-        let byte = u8::from_str_radix(&data_hex[i..i + 2], 16).ok()?;
-        data.push(byte);
-    }
-
-    Some(ParsedCan { ts, iface, id, data })
-}
-
-/// Charge et parse un fichier can-dump
-fn load_dump<P: AsRef<Path>>(path: P) -> Result<Vec<ParsedCan>, String> {
-    let file = File::open(path.as_ref()).map_err(|e| format!("open dump failed: {e}"))?;
-    let reader = BufReader::new(file);
-
-    // Exemple de lignes visées :
-    // (1597243671.759299) elmcan 118#5906221800000000
-    let re = Regex::new(
-        r"^\((?P<ts>\d+\.\d+)\)\s+(?P<iface>\S+)\s+(?P<id>[0-9A-Fa-f]+)#(?P<data>[0-9A-Fa-f]+)\s*$",
-    )
-    .map_err(|e| format!("regex build failed: {e}"))?;
-
-    let mut out = Vec::new();
-    for (lineno, line) in reader.lines().enumerate() {
-        let line = line.map_err(|e| format!("read line {lineno}: {e}"))?;
-        if line.trim().is_empty() {
-            continue;
-        }
-        if let Some(parsed) = parse_line(&line, &re) {
-            out.push(parsed);
-        } else {
-            return Err(format!("parse error at line {}: {}", lineno + 1, line));
-        }
-    }
-    Ok(out)
-}
-
-fn to_hex(bytes: &[u8]) -> String {
-    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join("")
+    let mask = u32::from_str_radix(mask, 16).map_err(|e| format!("invalid mask {arg}: {e}"))?;
+    Ok((canid, mask))
 }
 
 fn main() -> Result<(), String> {
@@ -86,37 +31,79 @@ fn main() -> Result<(), String> {
     let _ = env_logger::Builder::from_env(env).format_timestamp_millis().try_init();
 
     let mut args = std::env::args().skip(1);
-    let dump_path = args.next().ok_or("missing dump path (e.g., dump.log)")?;
-
-    let iface = args.next().ok_or("missing can iface (e.g., vcan0)")?;
-
-    let _no_timing = args.any(|a| a == "--no-timing");
-
-    let records = load_dump(&dump_path)?;
-    println!("loaded {} frames from {}", records.len(), dump_path);
-
-    log::info!("replay done on {}", iface);
-
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("replay.log")
-        .map_err(|e| e.to_string())?;
+    let dump_path = args.next().ok_or(USAGE)?;
+    let iface = args.next().ok_or(USAGE)?;
+
+    let mut replay = CanReplay::new();
+    let mut outputs = vec![iface.clone()];
+    let mut mapped = false;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {arg}\n{USAGE}"));
+        match arg.as_str() {
+            "--no-timing" => {
+                replay.set_speed(0.0);
+            },
+            "--speed" => {
+                let speed = value()?.parse::<f64>().map_err(|e| e.to_string())?;
+                replay.set_speed(speed);
+            },
+            "--loop" => {
+                let loops = value()?.parse::<u32>().map_err(|e| e.to_string())?;
+                replay.set_loops(loops);
+            },
+            "--map" => {
+                let map = value()?;
+                let (source, target) = map.split_once('=').ok_or(format!("invalid map {map}"))?;
+                replay.map_iface(source, target);
+                outputs.push(target.to_string());
+                mapped = true;
+            },
+            "--include" => {
+                let (canid, mask) = parse_filter(&value()?)?;
+                replay.add_include(canid, mask);
+            },
+            "--exclude" => {
+                let (canid, mask) = parse_filter(&value()?)?;
+                replay.add_exclude(canid, mask);
+            },
+            _ => return Err(format!("unknown option {arg}\n{USAGE}")),
+        }
+    }
 
-    for rec in records.iter() {
-        let secs = rec.ts.trunc() as i64;
-        let micros = ((rec.ts - secs as f64) * 1_000_000.0).round() as u32;
-        let msg = format!(
-            "({0}.{1:06}) {2} {3:X}#{4}",
-            secs,
-            micros,
-            rec.iface,
-            rec.id,
-            to_hex(&rec.data)
-        );
+    // without explicit mapping every recorded interface goes to iface
+    if !mapped {
+        replay.map_iface("*", &iface);
+    }
+    outputs.sort();
+    outputs.dedup();
+    for output in &outputs {
+        replay
+            .open_output(output)
+            .map_err(|e| format!("fail opening candev {output}: {e}"))?;
+    }
 
-        println!("{}", msg);
-        let _ = writeln!(file, "{}", msg);
+    let extension = std::path::Path::new(&dump_path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    let stats = match extension.as_deref() {
+        Some("asc") => CanAscReader::open(&dump_path)
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+            .and_then(|records| replay.play(&records)),
+        Some("blf") => CanBlfReader::open(&dump_path)
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+            .and_then(|records| replay.play(&records)),
+        Some("pcap" | "pcapng") => CanPcapReader::open(&dump_path)
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+            .and_then(|records| replay.play(&records)),
+        _ => replay.play_file(&dump_path),
     }
+    .map_err(|e| e.to_string())?;
+    log::info!(
+        "replay {dump_path} done: sent={} filtered={} unmapped={} loops={}",
+        stats.sent,
+        stats.filtered,
+        stats.unmapped,
+        stats.loops
+    );
     Ok(())
 }
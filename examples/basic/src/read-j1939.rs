@@ -45,6 +45,15 @@ fn main() -> Result<(), String> {
         sock.set_callback(Box::new(filters));
     }
 
+    // optional canboat database to decode fields (https://github.com/canboat/canboat pgns.json)
+    let n2k = match std::env::args().nth(1) {
+        None => None,
+        Some(path) => match N2kDecoder::from_file(&path) {
+            Err(error) => return Err(format!("fail loading {path} {error}")),
+            Ok(value) => Some(value),
+        },
+    };
+
     // choose blocking/non blocking mode [default blocking]
     // sock.set_blocking(true).expect("Fail to set block mode");
     println!("sockj1939 waiting for packet");
@@ -54,6 +63,19 @@ fn main() -> Result<(), String> {
 
         let frame = sock.get_j1939_frame();
         match frame.get_opcode() {
+            SockCanOpCode::RxRead(_data) if n2k.is_some() => {
+                match n2k.as_ref().map(|n2k| n2k.decode_msg(&frame)) {
+                    Some(Ok(msg)) => println!(
+                        "({:4}) N2K {}:{} {}",
+                        count,
+                        msg.pgn,
+                        msg.id,
+                        msg.fields.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ")
+                    ),
+                    Some(Err(error)) => log::info!("{error}"),
+                    None => {},
+                }
+            },
             SockCanOpCode::RxRead(_data) => println!(
                 "({:4}) J1939 pgn:{:#04x} stamp:{} len:{} data:{}",
                 count,
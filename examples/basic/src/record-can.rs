@@ -0,0 +1,63 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ */
+extern crate sockcan;
+use env_logger::Env;
+use sockcan::prelude::*;
+use std::time::Duration;
+
+const USAGE: &str =
+    "record-can iface trace.pcapng [--hardware] [--rotate-size bytes] [--rotate-time secs] [--duration secs]";
+
+fn main() -> Result<(), String> {
+    // Initialize logging backend for the `log` facade (idempotent).
+    let env = Env::default().default_filter_or("info");
+    let _ = env_logger::Builder::from_env(env).format_timestamp_millis().try_init();
+
+    let mut args = std::env::args().skip(1);
+    let iface = args.next().ok_or(USAGE)?;
+    let path = args.next().ok_or(USAGE)?;
+
+    let mut stamp = CanTimeStamp::NANOSEC;
+    let mut rotate_size = 0;
+    let mut rotate_time = None;
+    let mut duration = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {arg}\n{USAGE}"));
+        match arg.as_str() {
+            "--hardware" => stamp = CanTimeStamp::HARDWARE,
+            "--rotate-size" => rotate_size = value()?.parse::<u64>().map_err(|e| e.to_string())?,
+            "--rotate-time" => {
+                let secs = value()?.parse::<u64>().map_err(|e| e.to_string())?;
+                rotate_time = Some(Duration::from_secs(secs));
+            },
+            "--duration" => {
+                let secs = value()?.parse::<u64>().map_err(|e| e.to_string())?;
+                duration = Some(Duration::from_secs(secs));
+            },
+            _ => return Err(format!("unknown option {arg}\n{USAGE}")),
+        }
+    }
+
+    let mut recorder = CanPcapRecorder::open(&iface, stamp, &path)
+        .map_err(|e| format!("fail opening candev {iface}: {e}"))?;
+    recorder.set_rotate_size(rotate_size).set_rotate_time(rotate_time);
+
+    if let Some(duration) = duration {
+        let stopper = recorder.get_stopper();
+        std::thread::spawn(move || {
+            std::thread::sleep(duration);
+            stopper.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+    }
+
+    log::info!("recording {iface} into {path}");
+    let count = recorder.run().map_err(|e| e.to_string())?;
+    log::info!("recorded {count} frames into {:?}", recorder.get_files());
+    Ok(())
+}
@@ -0,0 +1,47 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * Simulated UDS ECU on vcan0 (requires can-isotp kernel module)
+ *   test with: isotpsend -s 7E0 -d 7E8 vcan0 <<< "22 F1 90" ; isotprecv -s 7E0 -d 7E8 vcan0
+ *
+ */
+
+extern crate sockcan;
+use env_logger::Env;
+use sockcan::prelude::*;
+
+fn main() -> Result<(), String> {
+    let env = Env::default().default_filter_or("info");
+    let _ = env_logger::Builder::from_env(env).format_timestamp_millis().try_init();
+
+    const VCAN: &str = "vcan0";
+
+    // ecu receives on 0x7E0 and answers on 0x7E8
+    let sock = match SockCanHandle::open_isotp(
+        VCAN,
+        0x7E8,
+        0x7E0,
+        &SockIsoTpOpts::new(),
+        CanTimeStamp::CLASSIC,
+    ) {
+        Err(error) => return Err(format!("fail opening candev {error}")),
+        Ok(value) => value,
+    };
+
+    let mut ecu = UdsServer::new(UdsIsoTp::new(sock));
+    ecu.add_did(0xF190, b"WVWZZZ1JZXW000001", UdsDidAccess::ReadOnly)
+        .add_did(0xF18C, b"SN-0001", UdsDidAccess::ReadWrite)
+        .add_dtc(0x12_3456, UdsDtc::STATUS_CONFIRMED | UdsDtc::STATUS_TEST_FAILED)
+        .add_routine(0xFF00, &[0x00])
+        .set_delay(UdsSid::RoutineControl as u8, 200);
+
+    log::info!("uds ecu ready on {VCAN} rx:0x7E0 tx:0x7E8");
+    if let Err(error) = ecu.serve() {
+        return Err(format!("uds ecu stopped {error}"));
+    }
+    Ok(())
+}
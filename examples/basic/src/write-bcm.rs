@@ -0,0 +1,77 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * Send a cyclic frame with BCM, check with: candump vcan0
+ */
+
+extern crate sockcan;
+use env_logger::Env;
+use sockcan::prelude::*;
+
+fn main() -> Result<(), String> {
+    // Initialize logging backend for the `log` facade (idempotent).
+    let env = Env::default().default_filter_or("info");
+    let _ = env_logger::Builder::from_env(env).format_timestamp_millis().try_init();
+
+    const VCAN: &str = "vcan0";
+
+    let sock = match SockCanHandle::open_bcm(VCAN, CanTimeStamp::CLASSIC) {
+        Err(error) => return Err(format!("fail opening candev {error}")),
+        Ok(value) => value,
+    };
+
+    // send 5 frames every 10ms then one frame every 500ms, notify when burst is over
+    let mut job = SockBcmTx::new(0x118);
+    job.set_count(5, 10)
+        .set_cycle(500)
+        .set_notify_expired(true)
+        .add_frame(CanAnyFrame::RawStd(CanFrameRaw::new(0x118, 2, 0, 0, [1, 2, 0, 0, 0, 0, 0, 0])));
+
+    match job.start(&sock) {
+        Err(error) => return Err(format!("bcm-tx fail Error:{error}")),
+        Ok(()) => log::info!("sockbcm cyclic job ready canid: 0x118"),
+    }
+
+    let mut count: u8 = 0;
+    loop {
+        let msg = sock.get_bcm_frame();
+        match msg.get_opcode() {
+            CanBcmOpCode::TxExpired => {
+                log::info!("BCM burst done canid:{:#04x}", msg.get_id().unwrap_or(0));
+                job.read(&sock).map_err(|error| format!("bcm-read fail Error:{error}"))?;
+            },
+            CanBcmOpCode::TxStatus => {
+                let (ival1, ival2) = msg.get_timers();
+                log::info!(
+                    "BCM status canid:{:#04x} count:{} ival1:{ival1}ms ival2:{ival2}ms frames:{}",
+                    msg.get_id().unwrap_or(0),
+                    msg.get_count(),
+                    msg.get_frames().len()
+                );
+
+                // update frame content without touching timers, then stop after a few updates
+                count += 1;
+                if count > 3 {
+                    job.stop(&sock).map_err(|error| format!("bcm-stop fail Error:{error}"))?;
+                    log::info!("BCM job stopped");
+                    return Ok(());
+                }
+                let mut update = SockBcmTx::new(0x118);
+                update.add_frame(CanAnyFrame::RawStd(CanFrameRaw::new(
+                    0x118,
+                    2,
+                    0,
+                    0,
+                    [1, 2 + count, 0, 0, 0, 0, 0, 0],
+                )));
+                update.update(&sock).map_err(|error| format!("bcm-update fail Error:{error}"))?;
+                job.read(&sock).map_err(|error| format!("bcm-read fail Error:{error}"))?;
+            },
+            _ => log::info!("unexpected bcm opcode:{:?}", msg.get_opcode()),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * Decodes frames with parsers generated by build.rs and checks runtime pool agrees.
+ */
+extern crate dbcparser;
+extern crate sockcan;
+
+include!("../src/__mux-dbcgen.rs");
+include!("../src/__float-dbcgen.rs");
+include!("../src/__sync-dbcgen.rs");
+
+use dbcparser::prelude::*;
+use sockcan::prelude::*;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn decode(pool: &dyn CanDbcPool, canid: u32, data: &[u8]) -> Vec<(String, String)> {
+    let len = u8::try_from(data.len()).unwrap();
+    let frame = CanMsgData { canid, len, stamp: 10, opcode: CanBcmOpCode::RxChanged, data };
+    let msg = pool.update(&frame).unwrap();
+    msg.get_signals()
+        .iter()
+        .map(|signal| {
+            let signal = signal.borrow();
+            (
+                signal.get_name().to_owned(),
+                format!("{}:{}", signal.get_status(), signal.get_value()),
+            )
+        })
+        .collect()
+}
+
+fn get<'a>(values: &'a [(String, String)], name: &str) -> &'a str {
+    values
+        .iter()
+        .find(|(signal, _)| signal == name)
+        .map(|(_, value)| value.as_str())
+        .unwrap()
+}
+
+#[test]
+fn gencode_extended_multiplex() {
+    let pool = DbcMux::CanMsgPool::new("mux");
+    let canid = pool.get_ids()[0];
+
+    // MUX_A=1, MUX_B=20 (SG_MUL_VAL_ 16-24 range), MUX_C=1 => nested MUX_D=0
+    let data = [1u8, 0xFB, 20, 7, 1, 0, 0xF7];
+    let values = decode(&pool, canid, &data);
+    assert_eq!(get(&values, "MuxA"), "Updated:1");
+    assert_eq!(get(&values, "MuxedA1"), "Updated:-5");
+    assert_eq!(get(&values, "MuxedA0").split(':').next(), Some("Inactive"));
+    assert_eq!(get(&values, "MuxedB5"), "Updated:7");
+    assert_eq!(get(&values, "MuxedB1").split(':').next(), Some("Inactive"));
+    assert_eq!(get(&values, "MuxedB2").split(':').next(), Some("Inactive"));
+    assert_eq!(get(&values, "MuxedC1MuxD"), "Unchanged:0");
+    assert_eq!(get(&values, "MuxedC0").split(':').next(), Some("Inactive"));
+    assert_eq!(get(&values, "MuxedD0"), "Updated:-9");
+    assert_eq!(get(&values, "MuxedD1").split(':').next(), Some("Inactive"));
+
+    // runtime pool interprets the same DBC identically
+    let runtime = DbcRuntime::new("mux")
+        .dbcfile("./etc/dbc/extended_multiplex.dbc")
+        .load()
+        .unwrap();
+    assert_eq!(decode(&runtime, canid, &data), values);
+
+    // MUX_C=0 deactivates the whole nested MUX_D branch
+    let data = [0u8, 3, 2, 0xFE, 0, 0x10, 0];
+    let values = decode(&pool, canid, &data);
+    assert_eq!(get(&values, "MuxedA0"), "Updated:3");
+    assert_eq!(get(&values, "MuxedB2"), "Updated:-2");
+    assert_eq!(get(&values, "MuxedC0"), "Updated:16");
+    assert_eq!(get(&values, "MuxedC1MuxD").split(':').next(), Some("Inactive"));
+    assert_eq!(get(&values, "MuxedD0").split(':').next(), Some("Inactive"));
+    assert_eq!(get(&values, "MuxedD1").split(':').next(), Some("Inactive"));
+    assert_eq!(decode(&runtime, canid, &data), values);
+}
+
+#[test]
+fn gencode_ieee_float() {
+    let pool = DbcFloat::CanMsgPool::new("float");
+
+    let mut data = [0u8; 8];
+    {
+        let mut msg = pool.get_mut(801).unwrap();
+        let msg = msg.as_any().downcast_mut::<DbcFloat::FloatSingle::DbcMessage>().unwrap();
+        msg.set_values(-12.5, 1013.25, &mut data).unwrap();
+    }
+    assert_eq!(data[0..4], (-12.5f32).to_le_bytes());
+    assert_eq!(data[4..8], 1013.25f32.to_be_bytes());
+    let values = decode(&pool, 801, &data);
+    assert_eq!(get(&values, "Temperature"), "Updated:-12.5");
+    assert_eq!(get(&values, "Pressure"), "Updated:1013.25");
+
+    let mut data = [0u8; 8];
+    {
+        let mut msg = pool.get_mut(802).unwrap();
+        let msg = msg.as_any().downcast_mut::<DbcFloat::FloatDouble::DbcMessage>().unwrap();
+        msg.set_values(-1.0e-300, &mut data).unwrap();
+    }
+    assert_eq!(data, (-1.0e-300f64).to_le_bytes());
+    let values = decode(&pool, 802, &data);
+    assert_eq!(get(&values, "Distance"), format!("Updated:{}", -1.0e-300f64));
+
+    // runtime pool decodes the same raw IEEE bits
+    let runtime = DbcRuntime::new("float").dbcfile("./etc/dbc/float.dbc").load().unwrap();
+    assert_eq!(decode(&runtime, 802, &data), values);
+}
+
+#[test]
+fn gencode_thread_safe() {
+    let pool = Arc::new(DbcSync::CanMsgPool::new("sync"));
+
+    // Current=-2A (raw -32), Voltage=12V (raw 12288)
+    let mut data = [0u8; 4];
+    data[0..2].copy_from_slice(&(-32i16).to_le_bytes());
+    data[2..4].copy_from_slice(&12288u16.to_le_bytes());
+
+    // reader thread waits for the message lock instead of failing
+    let guard = pool.get_mut(256).unwrap();
+    let reader = {
+        let pool = Arc::clone(&pool);
+        thread::spawn(move || {
+            let frame = CanMsgData {
+                canid: 256,
+                len: 4,
+                stamp: 10,
+                opcode: CanBcmOpCode::RxChanged,
+                data: &data,
+            };
+            pool.update(&frame).map(|msg| msg.get_stamp())
+        })
+    };
+    thread::sleep(Duration::from_millis(20));
+    drop(guard);
+    assert_eq!(reader.join().unwrap().unwrap(), 10);
+
+    let msg = pool.get_mut(256).unwrap();
+    let values: Vec<CanDbcType> = msg
+        .get_signals()
+        .iter()
+        .map(|signal| signal.lock().unwrap().get_value())
+        .collect();
+    assert!(values.contains(&CanDbcType::F64(-2.0)));
+    assert!(values
+        .iter()
+        .any(|value| matches!(value, CanDbcType::F64(volt) if (volt - 12.0).abs() < 0.01)));
+}
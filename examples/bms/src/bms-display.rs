@@ -58,17 +58,14 @@ fn register_pool_filters(
     rate_ms: u64,
     watchdog_ms: u64,
 ) -> Result<(), CanError> {
-    for &canid in pool.get_ids().iter() {
-        SockBcmCmd::new(
-            CanBcmOpCode::RxSetup,
-            CanBcmFlag::RX_FILTER_ID
-                | CanBcmFlag::SET_TIMER
-                | CanBcmFlag::START_TIMER
-                | CanBcmFlag::RX_ANNOUNCE_RESUME,
-            canid,
-        )
-        .set_timers(rate_ms, watchdog_ms)
-        .apply(sock)?;
+    // CAN FD messages are registered with FD_FRAME flag
+    pool.subscribe_bcm(
+        sock,
+        &(CanBcmFlag::SET_TIMER | CanBcmFlag::START_TIMER | CanBcmFlag::RX_ANNOUNCE_RESUME),
+        rate_ms,
+        watchdog_ms,
+    )?;
+    for &canid in pool.get_ids() {
         info!("Subscribed canid=0x{canid:03X} rate={}ms watchdog={}ms", rate_ms, watchdog_ms);
     }
     Ok(())
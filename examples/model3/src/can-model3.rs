@@ -53,19 +53,13 @@ fn main() -> Result<(), CanError> {
     // get canid list from dbc pool
     let pool = CanMsgPool::new("dbc-demo");
 
-    // register dbc defined canid
-    for canid in pool.get_ids() {
-        SockBcmCmd::new(
-            CanBcmOpCode::RxSetup,
-            CanBcmFlag::RX_FILTER_ID
-                | CanBcmFlag::SET_TIMER
-                | CanBcmFlag::START_TIMER
-                | CanBcmFlag::RX_ANNOUNCE_RESUME,
-            *canid,
-        )
-        .set_timers(rate, watchdog)
-        .apply(&sock)?;
-    }
+    // register dbc defined canid (CAN FD messages get FD_FRAME flag)
+    pool.subscribe_bcm(
+        &sock,
+        &(CanBcmFlag::SET_TIMER | CanBcmFlag::START_TIMER | CanBcmFlag::RX_ANNOUNCE_RESUME),
+        rate,
+        watchdog,
+    )?;
 
     // loop on message reception and decode messages
     let mut count = 0;
@@ -45,12 +45,14 @@ fn main() {
         .allowlist_function("bind")
         .allowlist_function("socket")
         .allowlist_function("setsockopt")
+        .allowlist_function("getsockname")
         .allowlist_function("ioctl")
         .allowlist_function("fcntl")
         .allowlist_function("recvfrom")
         .allowlist_function("recvmsg")
         .allowlist_function("read")
         .allowlist_function("send")
+        .allowlist_function("sendto")
         .allowlist_function("write")
         .allowlist_function("close")
         .allowlist_function("connect")
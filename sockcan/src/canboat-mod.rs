@@ -0,0 +1,469 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    https://github.com/canboat/canboat/blob/master/docs/canboat.json (pgns.json)
+ *    https://canboat.github.io/canboat/canboat.html
+ *
+ * NMEA2000 decoder interpreting canboat PGN database, usage:
+ *    let n2k = N2kDecoder::from_file("pgns.json")?;
+ *    let msg = n2k.decode_msg(&sock.get_j1939_frame())?;
+ *
+*/
+use crate::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Deserialize)]
+struct CanBoatEnumValue {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Value", default)]
+    value: u32,
+    #[serde(rename = "Bit", default)]
+    bit: u32,
+}
+
+#[derive(Deserialize)]
+struct CanBoatLookup {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "EnumValues", default)]
+    values: Vec<CanBoatEnumValue>,
+    #[serde(rename = "EnumBitValues", default)]
+    bits: Vec<CanBoatEnumValue>,
+}
+
+#[derive(Deserialize)]
+struct CanBoatField {
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(rename = "Name", default)]
+    name: String,
+    #[serde(rename = "BitLength", default)]
+    bit_len: u32,
+    #[serde(rename = "Resolution", default)]
+    resolution: Option<f64>,
+    #[serde(rename = "Offset", default)]
+    offset: Option<f64>,
+    #[serde(rename = "Signed", default)]
+    signed: bool,
+    #[serde(rename = "Unit", default)]
+    unit: Option<String>,
+    #[serde(rename = "FieldType", default)]
+    field_type: String,
+    #[serde(rename = "Match", default)]
+    match_value: Option<i64>,
+    #[serde(rename = "LookupEnumeration", default)]
+    lookup: Option<String>,
+    #[serde(rename = "LookupBitEnumeration", default)]
+    bit_lookup: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CanBoatPgn {
+    #[serde(rename = "PGN")]
+    pgn: u32,
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(rename = "Description", default)]
+    description: String,
+    #[serde(rename = "RepeatingFieldSet1Size", default)]
+    repeat_size: usize,
+    #[serde(rename = "RepeatingFieldSet1StartField", default)]
+    repeat_start: usize,
+    #[serde(rename = "RepeatingFieldSet1CountField", default)]
+    repeat_count: usize,
+    #[serde(rename = "Fields", default)]
+    fields: Vec<CanBoatField>,
+}
+
+#[derive(Deserialize)]
+struct CanBoatDb {
+    #[serde(rename = "PGNs")]
+    pgns: Vec<CanBoatPgn>,
+    #[serde(rename = "LookupEnumerations", default)]
+    lookups: Vec<CanBoatLookup>,
+    #[serde(rename = "LookupBitEnumerations", default)]
+    bit_lookups: Vec<CanBoatLookup>,
+}
+
+/// One decoded NMEA2000 field, lookup and string fields also get a `text` value
+pub struct N2kValue {
+    pub id: String,
+    pub name: String,
+    pub unit: Option<String>,
+    pub value: CanDbcType,
+    pub text: Option<String>,
+    pub status: CanDataStatus,
+}
+
+impl N2kValue {
+    #[must_use]
+    pub fn get_value(&self) -> CanDbcType {
+        self.value
+    }
+
+    #[must_use]
+    pub fn get_status(&self) -> CanDataStatus {
+        self.status
+    }
+}
+
+impl fmt::Display for N2kValue {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.status, &self.text) {
+            (CanDataStatus::Updated, Some(text)) => write!(format, "{}={text}", self.id),
+            (CanDataStatus::Updated, None) => {
+                write!(format, "{}={}{}", self.id, self.value, self.unit.as_deref().unwrap_or(""))
+            },
+            (status, _) => write!(format, "{}=({status})", self.id),
+        }
+    }
+}
+
+pub struct N2kMessage {
+    pub pgn: u32,
+    pub id: String,
+    pub description: String,
+    pub fields: Vec<N2kValue>,
+}
+
+impl N2kMessage {
+    #[must_use]
+    pub fn get_field(&self, id: &str) -> Option<&N2kValue> {
+        self.fields.iter().find(|field| field.id == id)
+    }
+}
+
+// little endian bit reader, NMEA2000 fields are not byte aligned
+struct N2kBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl N2kBits<'_> {
+    fn remaining(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.pos)
+    }
+
+    fn read(&mut self, len: usize) -> Option<u64> {
+        if len > 64 || len > self.remaining() {
+            return None;
+        }
+        let mut value = 0u64;
+        for idx in 0..len {
+            let bit = self.pos + idx;
+            if self.data[bit / 8] & (1 << (bit % 8)) != 0 {
+                value |= 1 << idx;
+            }
+        }
+        self.pos += len;
+        Some(value)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let start = self.pos.div_ceil(8);
+        let end = start.checked_add(len)?;
+        let bytes = self.data.get(start..end)?;
+        self.pos = end * 8;
+        Some(bytes)
+    }
+}
+
+fn n2k_text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches(['\0', '@', ' ', '\u{FFFD}'])
+        .to_string()
+}
+
+/// NMEA2000 decoder built from canboat `pgns.json` definitions
+pub struct N2kDecoder {
+    pgns: HashMap<u32, Vec<CanBoatPgn>>,
+    lookups: HashMap<String, HashMap<u32, String>>,
+    bit_lookups: HashMap<String, HashMap<u32, String>>,
+}
+
+impl N2kDecoder {
+    /// Loads canboat definitions from a JSON string.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when JSON does not follow canboat `pgns.json` format.
+    pub fn from_json(json: &str) -> Result<Self, CanError> {
+        let database: CanBoatDb = serde_json::from_str(json)
+            .map_err(|error| CanError::new("canboat-json-invalid", error.to_string()))?;
+
+        let mut pgns: HashMap<u32, Vec<CanBoatPgn>> = HashMap::new();
+        for pgn in database.pgns {
+            pgns.entry(pgn.pgn).or_default().push(pgn);
+        }
+        let lookups = database
+            .lookups
+            .into_iter()
+            .map(|lookup| {
+                (lookup.name, lookup.values.into_iter().map(|val| (val.value, val.name)).collect())
+            })
+            .collect();
+        let bit_lookups = database
+            .bit_lookups
+            .into_iter()
+            .map(|lookup| {
+                (lookup.name, lookup.bits.into_iter().map(|val| (val.bit, val.name)).collect())
+            })
+            .collect();
+
+        Ok(N2kDecoder { pgns, lookups, bit_lookups })
+    }
+
+    /// Loads canboat definitions from `pgns.json` file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be read or is not a valid canboat database.
+    pub fn from_file(path: &str) -> Result<Self, CanError> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json)
+    }
+
+    /// Returns sorted list of known PGNs (e.g. to build `SockJ1939Filters`)
+    #[must_use]
+    pub fn get_pgns(&self) -> Vec<u32> {
+        let mut pgns: Vec<u32> = self.pgns.keys().copied().collect();
+        pgns.sort_unstable();
+        pgns
+    }
+
+    /// Decodes reassembled J1939/NMEA2000 message.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when message has no J1939 info or PGN is not defined.
+    pub fn decode_msg(&self, msg: &SockJ1939Msg) -> Result<N2kMessage, CanError> {
+        let info = msg.get_info()?;
+        self.decode(info.pgn, msg.get_data())
+    }
+
+    /// Decodes `data` payload of `pgn`, when several definitions exist (proprietary PGNs)
+    /// the first one whose `Match` fields fit is used.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when PGN is unknown or no definition matches.
+    pub fn decode(&self, pgn: u32, data: &[u8]) -> Result<N2kMessage, CanError> {
+        let Some(definitions) = self.pgns.get(&pgn) else {
+            return Err(CanError::new("canboat-pgn-unknown", format!("pgn:{pgn} not defined")));
+        };
+        definitions
+            .iter()
+            .find_map(|definition| self.decode_pgn(definition, data))
+            .ok_or_else(|| {
+                CanError::new("canboat-pgn-nomatch", format!("pgn:{pgn} no matching definition"))
+            })
+    }
+
+    fn decode_pgn(&self, definition: &CanBoatPgn, data: &[u8]) -> Option<N2kMessage> {
+        let mut bits = N2kBits { data, pos: 0 };
+        let mut fields = Vec::new();
+        let fixed = match definition.repeat_start {
+            0 => definition.fields.len(),
+            start => (start - 1).min(definition.fields.len()),
+        };
+
+        for field in &definition.fields[..fixed] {
+            let value = self.decode_field(field, &mut bits);
+            if let (Some(expected), CanDbcType::I64(raw)) = (field.match_value, value.value) {
+                if expected != raw {
+                    return None;
+                }
+            }
+            fields.push(value);
+        }
+
+        // repeating field set, repetition count is given by a previous field
+        if definition.repeat_size > 0 && fixed < definition.fields.len() {
+            let count = definition
+                .repeat_count
+                .checked_sub(1)
+                .and_then(|idx| fields.get(idx))
+                .and_then(|field| match field.value {
+                    CanDbcType::I64(count) if field.status == CanDataStatus::Updated => {
+                        usize::try_from(count).ok()
+                    },
+                    _ => None,
+                })
+                .unwrap_or(usize::MAX); // no count field: repeat until end of data
+            let end = (fixed + definition.repeat_size).min(definition.fields.len());
+            for _ in 0..count {
+                if bits.remaining() < 8 {
+                    break;
+                }
+                for field in &definition.fields[fixed..end] {
+                    fields.push(self.decode_field(field, &mut bits));
+                }
+            }
+        }
+
+        Some(N2kMessage {
+            pgn: definition.pgn,
+            id: definition.id.clone(),
+            description: definition.description.clone(),
+            fields,
+        })
+    }
+
+    fn decode_field(&self, field: &CanBoatField, bits: &mut N2kBits) -> N2kValue {
+        let mut value = N2kValue {
+            id: field.id.clone(),
+            name: field.name.clone(),
+            unit: field.unit.clone(),
+            value: CanDbcType::I64(0),
+            text: None,
+            status: CanDataStatus::Updated,
+        };
+        let bit_len = usize::try_from(field.bit_len).unwrap_or(0);
+
+        match field.field_type.as_str() {
+            "STRING_FIX" => match bits.bytes(bit_len / 8) {
+                Some(bytes) => value.text = Some(n2k_text(bytes)),
+                None => value.status = CanDataStatus::Error,
+            },
+            // len[1] + chars, STRING_LAU adds an encoding byte (0:utf16 1:ascii)
+            "STRING_LZ" | "STRING_LAU" => {
+                let header = if field.field_type == "STRING_LZ" { 1 } else { 2 };
+                let len = bits.bytes(header).map(|head| usize::from(head[0]));
+                let len = if header == 2 { len.map(|len| len.saturating_sub(2)) } else { len };
+                let text = len.and_then(|len| bits.bytes(len));
+                match text {
+                    Some(bytes) => value.text = Some(n2k_text(bytes)),
+                    None => value.status = CanDataStatus::Error,
+                }
+            },
+            "RESERVED" | "SPARE" | "BINARY" if bit_len > 0 => {
+                value.status = CanDataStatus::Inactive;
+                if bits.read(bit_len).is_none() && bits.bytes(bit_len / 8).is_none() {
+                    value.status = CanDataStatus::Error;
+                }
+            },
+            "FLOAT" => match bits.read(32).and_then(|raw| u32::try_from(raw).ok()) {
+                Some(raw) => value.value = CanDbcType::F32(f32::from_bits(raw)),
+                None => value.status = CanDataStatus::Error,
+            },
+            // NUMBER, LOOKUP, BITLOOKUP, TIME, DATE, MMSI, PGN, ISO_NAME, DURATION, ...
+            _ if bit_len > 0 && bit_len <= 64 => match bits.read(bit_len) {
+                None => value.status = CanDataStatus::Error,
+                Some(raw) => self.decode_number(field, raw, bit_len, &mut value),
+            },
+            _ => value.status = CanDataStatus::Error, // variable len field not supported
+        }
+        value
+    }
+
+    fn decode_number(&self, field: &CanBoatField, raw: u64, bit_len: usize, value: &mut N2kValue) {
+        let max = if bit_len == 64 { u64::MAX } else { (1u64 << bit_len) - 1 };
+        let signed_max = max >> 1;
+
+        // all ones (or signed max) means data not available, small fields have no such value
+        let unset = bit_len > 1
+            && field.field_type != "BITLOOKUP"
+            && ((field.signed && raw == signed_max) || (!field.signed && raw == max));
+        if unset {
+            value.status = CanDataStatus::Unset;
+            return;
+        }
+
+        // sign extension within i128, 64 bits fields do not fit i64 before subtraction
+        let raw = if field.signed && raw > signed_max {
+            i64::try_from(i128::from(raw) - i128::from(max) - 1).unwrap_or(i64::MIN)
+        } else {
+            i64::try_from(raw).unwrap_or(i64::MAX)
+        };
+
+        let resolution = field.resolution.unwrap_or(1.0);
+        let offset = field.offset.unwrap_or(0.0);
+        #[allow(clippy::cast_precision_loss, clippy::float_cmp)]
+        if resolution == 1.0 && offset == 0.0 {
+            value.value = CanDbcType::I64(raw);
+        } else {
+            value.value = CanDbcType::F64(raw as f64 * resolution + offset);
+        }
+
+        let key = u32::try_from(raw).unwrap_or(u32::MAX);
+        if let Some(lookup) = field.lookup.as_ref().and_then(|name| self.lookups.get(name)) {
+            value.text = lookup.get(&key).cloned();
+        } else if let Some(lookup) =
+            field.bit_lookup.as_ref().and_then(|name| self.bit_lookups.get(name))
+        {
+            let names: Vec<&str> = (0..32)
+                .filter(|bit| key & (1 << bit) != 0)
+                .filter_map(|bit| lookup.get(&bit).map(String::as_str))
+                .collect();
+            value.text = Some(names.join(","));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGNS_JSON: &str = r#"{
+        "PGNs": [
+            {"PGN": 127250, "Id": "vesselHeading", "Description": "Vessel Heading", "Fields": [
+                {"Order": 1, "Id": "sid", "BitLength": 8, "FieldType": "NUMBER"},
+                {"Order": 2, "Id": "heading", "BitLength": 16, "Resolution": 0.0001, "Unit": "rad", "FieldType": "NUMBER"},
+                {"Order": 3, "Id": "deviation", "BitLength": 16, "Resolution": 0.0001, "Signed": true, "Unit": "rad", "FieldType": "NUMBER"},
+                {"Order": 4, "Id": "variation", "BitLength": 16, "Resolution": 0.0001, "Signed": true, "Unit": "rad", "FieldType": "NUMBER"},
+                {"Order": 5, "Id": "reference", "BitLength": 2, "FieldType": "LOOKUP", "LookupEnumeration": "DIRECTION_REFERENCE"},
+                {"Order": 6, "Id": "reserved", "BitLength": 6, "FieldType": "RESERVED"}
+            ]},
+            {"PGN": 129285, "Id": "navigationRouteWpInformation", "RepeatingFieldSet1Size": 2,
+             "RepeatingFieldSet1StartField": 3, "RepeatingFieldSet1CountField": 2, "Fields": [
+                {"Order": 1, "Id": "startRps", "BitLength": 16, "FieldType": "NUMBER"},
+                {"Order": 2, "Id": "nItems", "BitLength": 8, "FieldType": "NUMBER"},
+                {"Order": 3, "Id": "wpId", "BitLength": 16, "FieldType": "NUMBER"},
+                {"Order": 4, "Id": "wpName", "FieldType": "STRING_LAU"}
+            ]}
+        ],
+        "LookupEnumerations": [
+            {"Name": "DIRECTION_REFERENCE", "EnumValues": [{"Name": "True", "Value": 0}, {"Name": "Magnetic", "Value": 1}]}
+        ]
+    }"#;
+
+    #[test]
+    fn canboat_decode() {
+        let n2k = N2kDecoder::from_json(PGNS_JSON).unwrap();
+        assert_eq!(n2k.get_pgns(), vec![127_250, 129_285]);
+
+        let msg = n2k.decode(127_250, &[0x01, 0xE0, 0x2E, 0xFF, 0x7F, 0x9C, 0xFF, 0xFD]).unwrap();
+        assert_eq!(msg.id, "vesselHeading");
+        let heading = msg.get_field("heading").unwrap();
+        assert!(matches!(heading.value, CanDbcType::F64(val) if (val - 1.2).abs() < 1e-9));
+        assert_eq!(msg.get_field("deviation").unwrap().status, CanDataStatus::Unset);
+        let variation = msg.get_field("variation").unwrap();
+        assert!(matches!(variation.value, CanDbcType::F64(val) if (val + 0.01).abs() < 1e-9));
+        assert_eq!(msg.get_field("reference").unwrap().text.as_deref(), Some("Magnetic"));
+
+        let data = [0x00, 0x00, 0x02, 0x01, 0x00, 0x04, 0x01, b'A', b'B', 0x02, 0x00, 0x02, 0x01];
+        let msg = n2k.decode(129_285, &data).unwrap();
+        let names: Vec<String> = msg.fields.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            names,
+            vec!["startRps=0", "nItems=2", "wpId=1", "wpName=AB", "wpId=2", "wpName="]
+        );
+        assert!(n2k.decode(60_928, &[0; 8]).is_err());
+    }
+
+    #[test]
+    fn canboat_signed_64bits() {
+        let json = r#"{"PGNs": [{"PGN": 130000, "Id": "wide", "Fields": [
+            {"Order": 1, "Id": "value", "BitLength": 64, "Signed": true, "FieldType": "NUMBER"}
+        ]}]}"#;
+        let n2k = N2kDecoder::from_json(json).unwrap();
+        let value = |data: i64| n2k.decode(130_000, &data.to_le_bytes()).unwrap().fields[0].value;
+        assert_eq!(value(-2), CanDbcType::I64(-2));
+        assert_eq!(value(i64::MIN), CanDbcType::I64(i64::MIN));
+        assert_eq!(value(42), CanDbcType::I64(42));
+    }
+}
@@ -0,0 +1,543 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    can-utils candump -l log format (lib.c parse_canframe/sprint_canframe)
+ *    (1621945396.763904) elmcan 301#0000 R
+ *
+*/
+use crate::prelude::*;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::str::FromStr;
+
+const CANDUMP_SFF_DIGITS: usize = 3;
+const CANDUMP_EFF_DIGITS: usize = 8;
+
+/// Frame direction as logged by `candump -l -x` (trailing R/T column).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CanLogDir {
+    Rx,
+    Tx,
+}
+
+/// One candump log line: timestamp (us), interface name, frame and optional direction.
+#[derive(Clone)]
+pub struct CanLogRecord {
+    pub stamp: u64,
+    pub iface: String,
+    pub frame: CanAnyFrame,
+    pub dir: Option<CanLogDir>,
+    line: usize,
+}
+
+impl CanLogRecord {
+    #[must_use]
+    pub fn new(stamp: u64, iface: &str, frame: CanAnyFrame) -> Self {
+        CanLogRecord { stamp, iface: iface.to_owned(), frame, dir: None, line: 0 }
+    }
+
+    pub fn set_dir(&mut self, dir: CanLogDir) -> &mut Self {
+        self.dir = Some(dir);
+        self
+    }
+
+    #[must_use]
+    pub fn get_stamp(&self) -> u64 {
+        self.stamp
+    }
+
+    #[must_use]
+    pub fn get_iface(&self) -> &str {
+        &self.iface
+    }
+
+    #[must_use]
+    pub fn get_frame(&self) -> &CanAnyFrame {
+        &self.frame
+    }
+
+    #[must_use]
+    pub fn get_dir(&self) -> Option<CanLogDir> {
+        self.dir
+    }
+
+    /// Line number within source log (0 when record was not read from a file).
+    #[must_use]
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    pub(crate) fn set_line(&mut self, line: usize) -> &mut Self {
+        self.line = line;
+        self
+    }
+
+    /// Raw kernel `can_id` including EFF/RTR/ERR flags.
+    #[must_use]
+    pub fn get_canid(&self) -> u32 {
+        candump_raw_canid(&self.frame).unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn is_fd(&self) -> bool {
+        matches!(self.frame, CanAnyFrame::RawFd(_))
+    }
+
+    #[must_use]
+    pub fn is_rtr(&self) -> bool {
+        self.get_canid() & FilterMask::RTR_FLAG.bits() != 0
+    }
+
+    /// True for CAN error frames (`CAN_ERR_FLAG`), data holds error class details.
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        self.get_canid() & FilterMask::ERR_FLAG.bits() != 0
+    }
+}
+
+// feeds recorded frames to a CanDbcPool as if received from a BCM socket
+impl<'a> From<&'a CanLogRecord> for CanMsgData<'a> {
+    fn from(record: &'a CanLogRecord) -> Self {
+        let canid = record.get_canid();
+        let len = record.get_frame().get_len().unwrap_or(0);
+        let data = record.get_frame().get_data().unwrap_or(&[]);
+        CanMsgData {
+            canid: canid & (FilterMask::EFF_FLAG.bits() | FilterMask::EFF_MASK.bits()),
+            len,
+            stamp: record.stamp,
+            opcode: CanBcmOpCode::RxChanged,
+            data: &data[..usize::from(len).min(data.len())],
+        }
+    }
+}
+
+fn candump_raw_canid(frame: &CanAnyFrame) -> Option<u32> {
+    match frame {
+        CanAnyFrame::RawStd(frame) => Some(frame.0.can_id),
+        CanAnyFrame::RawFd(frame) => Some(frame.0.can_id),
+        _ => None,
+    }
+}
+
+fn candump_hex(text: &str, max: usize) -> Result<Vec<u8>, String> {
+    // can-utils accepts '.' as optional byte separator
+    let digits: Vec<u8> = text.bytes().filter(|byte| *byte != b'.').collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits '{text}'"));
+    }
+    let mut data = Vec::with_capacity(digits.len() / 2);
+    for pair in digits.chunks(2) {
+        let hex = std::str::from_utf8(pair).unwrap_or_default();
+        match u8::from_str_radix(hex, 16) {
+            Ok(byte) => data.push(byte),
+            Err(_) => return Err(format!("invalid hex byte '{hex}'")),
+        }
+    }
+    if data.len() > max {
+        return Err(format!("payload {} > {max} bytes", data.len()));
+    }
+    Ok(data)
+}
+
+fn candump_canid(text: &str) -> Result<u32, String> {
+    if text.is_empty() || text.len() > CANDUMP_EFF_DIGITS {
+        return Err(format!("invalid canid '{text}'"));
+    }
+    let Ok(value) = u32::from_str_radix(text, 16) else {
+        return Err(format!("invalid canid '{text}'"));
+    };
+
+    if text.len() <= CANDUMP_SFF_DIGITS {
+        if value > FilterMask::SFF_MASK.bits() {
+            return Err(format!("standard canid '{text}' > 0x7FF"));
+        }
+        Ok(value)
+    } else if value & FilterMask::ERR_FLAG.bits() != 0 {
+        // 8 digits with error flag are error frames, not extended ones
+        Ok(value & (FilterMask::ERR_FLAG.bits() | FilterMask::ERR_MASK.bits()))
+    } else {
+        Ok((value & FilterMask::EFF_MASK.bits()) | FilterMask::EFF_FLAG.bits())
+    }
+}
+
+fn candump_frame(text: &str) -> Result<CanAnyFrame, String> {
+    let Some((ident, payload)) = text.split_once('#') else {
+        return Err(format!("missing '#' in frame '{text}'"));
+    };
+    let mut canid = candump_canid(ident)?;
+
+    // CAN FD: ID##<flags><data>
+    if let Some(payload) = payload.strip_prefix('#') {
+        let mut chars = payload.chars();
+        let Some(flags) = chars.next().and_then(|flag| flag.to_digit(16)) else {
+            return Err(format!("missing CAN FD flags in '{text}'"));
+        };
+        let data = candump_hex(chars.as_str(), 64)?;
+        // CAN FD payload is 0-8, 12, 16, 20, 24, 32, 48 or 64 bytes
+        if data.len() > 8 && ![12, 16, 20, 24, 32, 48, 64].contains(&data.len()) {
+            return Err(format!("invalid CAN FD length {} in '{text}'", data.len()));
+        }
+        let mut buffer = [0u8; 64];
+        buffer[..data.len()].copy_from_slice(&data);
+        #[allow(clippy::cast_possible_truncation)]
+        let frame = CanFdFrameRaw::new(canid, data.len() as u8, flags as u8, 0, 0, buffer);
+        return Ok(CanAnyFrame::RawFd(frame));
+    }
+
+    // classic CAN: ID#<data>[_<len8_dlc>] or ID#R[<len>][_<len8_dlc>]
+    let (body, len8_dlc) = match payload.split_once('_') {
+        Some((body, dlc)) => match u8::from_str_radix(dlc, 16) {
+            Ok(dlc) if (9..=15).contains(&dlc) => (body, dlc),
+            _ => return Err(format!("invalid len8_dlc '{dlc}'")),
+        },
+        None => (payload, 0),
+    };
+
+    let mut buffer = [0u8; 8];
+    let len = if let Some(len) = body.strip_prefix(['R', 'r']) {
+        canid |= FilterMask::RTR_FLAG.bits();
+        match len {
+            "" => 0,
+            _ => match len.parse::<u8>() {
+                Ok(len) if len <= 8 => len,
+                _ => return Err(format!("invalid remote frame length '{len}'")),
+            },
+        }
+    } else {
+        let data = candump_hex(body, 8)?;
+        buffer[..data.len()].copy_from_slice(&data);
+        u8::try_from(data.len()).unwrap_or(8)
+    };
+
+    if len8_dlc != 0 && len != 8 {
+        return Err(format!("len8_dlc requires 8 bytes payload in '{text}'"));
+    }
+    let mut frame = CanFrameRaw::new(canid, len, 0, 0, buffer);
+    frame.0.len8_dlc = len8_dlc;
+    Ok(CanAnyFrame::RawStd(frame))
+}
+
+// parses 'sec[.fraction]' into microseconds whatever the logged precision
+pub(crate) fn log_parse_stamp(stamp: &str) -> Option<u64> {
+    let (secs, frac) = stamp.split_once('.').unwrap_or((stamp, ""));
+    let secs = secs.parse::<u64>().ok()?;
+    if !frac.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let micros = frac
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(6)
+        .fold(0u64, |acc, digit| acc * 10 + u64::from(digit - b'0'));
+    Some(secs.saturating_mul(1_000_000).saturating_add(micros))
+}
+
+fn candump_stamp(text: &str) -> Result<u64, String> {
+    text.strip_prefix('(')
+        .and_then(|stamp| stamp.strip_suffix(')'))
+        .and_then(log_parse_stamp)
+        .ok_or(format!("invalid timestamp '{text}'"))
+}
+
+fn candump_record(line: &str) -> Result<CanLogRecord, String> {
+    let mut tokens = line.split_whitespace();
+    let (Some(stamp), Some(iface), Some(frame)) = (tokens.next(), tokens.next(), tokens.next())
+    else {
+        return Err(format!("truncated line '{line}'"));
+    };
+    let mut record = CanLogRecord::new(candump_stamp(stamp)?, iface, candump_frame(frame)?);
+    match tokens.next() {
+        None => {},
+        Some("R") => {
+            record.set_dir(CanLogDir::Rx);
+        },
+        Some("T") => {
+            record.set_dir(CanLogDir::Tx);
+        },
+        Some(extra) => return Err(format!("unexpected trailing '{extra}'")),
+    }
+    Ok(record)
+}
+
+impl FromStr for CanLogRecord {
+    type Err = CanError;
+
+    /// Parses one candump log line `(sec.usec) iface ID#DATA [R|T]`.
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        candump_record(line).map_err(|error| CanError::new("candump-invalid-line", error))
+    }
+}
+
+impl fmt::Display for CanLogRecord {
+    /// Formats record back to candump log format, including direction when known.
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            format,
+            "({}.{:06}) {} ",
+            self.stamp / 1_000_000,
+            self.stamp % 1_000_000,
+            self.iface
+        )?;
+
+        let Some(canid) = candump_raw_canid(&self.frame) else {
+            return Err(fmt::Error);
+        };
+        if canid & FilterMask::ERR_FLAG.bits() != 0 {
+            write!(
+                format,
+                "{:08X}#",
+                canid & (FilterMask::ERR_FLAG.bits() | FilterMask::ERR_MASK.bits())
+            )?;
+        } else if canid & FilterMask::EFF_FLAG.bits() != 0 {
+            write!(format, "{:08X}#", canid & FilterMask::EFF_MASK.bits())?;
+        } else {
+            write!(format, "{:03X}#", canid & FilterMask::SFF_MASK.bits())?;
+        }
+
+        match &self.frame {
+            CanAnyFrame::RawFd(frame) => {
+                write!(format, "#{:X}", frame.get_flag() & 0x0F)?;
+                for byte in &frame.get_data()[..usize::from(frame.get_len()).min(64)] {
+                    write!(format, "{byte:02X}")?;
+                }
+            },
+            CanAnyFrame::RawStd(frame) => {
+                let len = frame.get_len();
+                if canid & FilterMask::RTR_FLAG.bits() != 0 {
+                    format.write_str("R")?;
+                    if len > 0 {
+                        write!(format, "{len}")?;
+                    }
+                } else {
+                    for byte in &frame.get_data()[..usize::from(len).min(8)] {
+                        write!(format, "{byte:02X}")?;
+                    }
+                }
+                if len == 8 && (9..=15).contains(&frame.0.len8_dlc) {
+                    write!(format, "_{:X}", frame.0.len8_dlc)?;
+                }
+            },
+            _ => {},
+        }
+
+        match self.dir {
+            Some(CanLogDir::Rx) => format.write_str(" R"),
+            Some(CanLogDir::Tx) => format.write_str(" T"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Streams candump log records, malformed lines are reported with their line number.
+pub struct CanDumpReader<R: BufRead> {
+    reader: R,
+    line: usize,
+}
+
+impl CanDumpReader<BufReader<File>> {
+    /// Opens a candump log file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be opened.
+    pub fn open(path: &str) -> Result<Self, CanError> {
+        match File::open(path) {
+            Ok(file) => Ok(CanDumpReader::new(BufReader::new(file))),
+            Err(error) => Err(CanError::new("candump-open-fail", format!("{path}: {error}"))),
+        }
+    }
+}
+
+impl<R: BufRead> CanDumpReader<R> {
+    pub fn new(reader: R) -> Self {
+        CanDumpReader { reader, line: 0 }
+    }
+
+    /// Number of the last line read.
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    /// Reads next record, skipping empty and `#` comment lines. Returns `None` at end of file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` tagged with line number when a line cannot be parsed.
+    pub fn read_record(&mut self) -> Result<Option<CanLogRecord>, CanError> {
+        let mut buffer = String::new();
+        loop {
+            buffer.clear();
+            self.line += 1;
+            match self.reader.read_line(&mut buffer) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {},
+                Err(error) => {
+                    return Err(CanError::new(
+                        "candump-read-fail",
+                        format!("line {}: {error}", self.line),
+                    ))
+                },
+            }
+            let text = buffer.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            return match CanLogRecord::from_str(text) {
+                Ok(mut record) => {
+                    record.line = self.line;
+                    Ok(Some(record))
+                },
+                Err(error) => Err(CanError::new(
+                    "candump-invalid-line",
+                    format!("line {}: {}", self.line, error.get_info()),
+                )),
+            };
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CanDumpReader<R> {
+    type Item = Result<CanLogRecord, CanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Writes records in candump log format, readable back by `canplayer` or `CanDumpReader`.
+pub struct CanDumpWriter<W: Write> {
+    writer: W,
+}
+
+impl CanDumpWriter<BufWriter<File>> {
+    /// Creates (truncates) a candump log file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be created.
+    pub fn create(path: &str) -> Result<Self, CanError> {
+        match File::create(path) {
+            Ok(file) => Ok(CanDumpWriter::new(BufWriter::new(file))),
+            Err(error) => Err(CanError::new("candump-create-fail", format!("{path}: {error}"))),
+        }
+    }
+}
+
+impl<W: Write> CanDumpWriter<W> {
+    pub fn new(writer: W) -> Self {
+        CanDumpWriter { writer }
+    }
+
+    /// # Errors
+    /// Returns a `CanError` when record holds no frame or write fails.
+    pub fn write_record(&mut self, record: &CanLogRecord) -> Result<(), CanError> {
+        if candump_raw_canid(&record.frame).is_none() {
+            return Err(CanError::new("candump-invalid-frame", "record holds no CAN frame"));
+        }
+        writeln!(self.writer, "{record}")
+            .map_err(|error| CanError::new("candump-write-fail", error.to_string()))
+    }
+
+    /// Writes a frame received from a socket, `stamp` is in microseconds.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when frame is not a data frame or write fails.
+    pub fn write_frame(
+        &mut self,
+        stamp: u64,
+        iface: &str,
+        frame: &CanAnyFrame,
+    ) -> Result<(), CanError> {
+        self.write_record(&CanLogRecord::new(stamp, iface, frame.clone()))
+    }
+
+    /// # Errors
+    /// Returns a `CanError` when flush fails.
+    pub fn flush(&mut self) -> Result<(), CanError> {
+        self.writer
+            .flush()
+            .map_err(|error| CanError::new("candump-write-fail", error.to_string()))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CANDUMP_LOG: &str = "\
+(1621945396.763904) elmcan 301#0000 R
+(1683797713.200980) vcan0 1DF9050F#0027FFFF0200FFFF
+
+(1683797713.201000) vcan0 123#R
+(1683797713.201009) vcan0 456#R3 T
+(1683797713.201018) vcan0 7FF##1112233445566778899AABBCC
+(1683797713.201026) vcan0 20000080#0000000000000000
+(1683797713.201030) vcan0 321#1122334455667788_C
+(1683797713.2) vcan0 100#11.22.33
+";
+
+    #[test]
+    fn test_candump_roundtrip() {
+        let records: Vec<CanLogRecord> =
+            CanDumpReader::new(CANDUMP_LOG.as_bytes()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(records.len(), 8);
+
+        assert_eq!(records[0].get_stamp(), 1_621_945_396_763_904);
+        assert_eq!(records[0].get_iface(), "elmcan");
+        assert_eq!(records[0].get_dir(), Some(CanLogDir::Rx));
+        assert_eq!(records[0].get_frame().get_len().unwrap(), 2);
+
+        assert_eq!(records[1].get_canid(), 0x9DF9_050F);
+        assert_eq!(records[1].get_frame().get_id().unwrap(), 0x1DF9_050F);
+        assert!(records[2].is_rtr() && records[3].is_rtr());
+        assert_eq!(records[3].get_frame().get_len().unwrap(), 3);
+        assert_eq!(records[3].get_line(), 5);
+        assert!(records[4].is_fd());
+        assert_eq!(records[4].get_frame().get_len().unwrap(), 12);
+        assert!(records[5].is_error() && !records[5].is_rtr());
+        assert_eq!(records[7].get_stamp(), 1_683_797_713_200_000);
+
+        let mut writer = CanDumpWriter::new(Vec::new());
+        for record in &records {
+            writer.write_record(record).unwrap();
+        }
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let expected: Vec<&str> = CANDUMP_LOG
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| match line {
+                "(1683797713.2) vcan0 100#11.22.33" => "(1683797713.200000) vcan0 100#112233",
+                _ => line,
+            })
+            .collect();
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_candump_errors() {
+        let log = "(1.000001) can0 123#11\n(1.000002) can0 123#1G\n";
+        let mut reader = CanDumpReader::new(log.as_bytes());
+        assert!(reader.next().unwrap().is_ok());
+        let error = reader.next().unwrap().err().unwrap();
+        assert!(error.get_info().starts_with("line 2:"));
+        assert!(reader.next().is_none());
+
+        for line in [
+            "(1.0) can0 800#11",
+            "(1.0) can0 123#112233445566778899",
+            "(1.0) can0 123##1112233445566778899",
+            "(1.0) can0 123##1112233445566778899AABBCCDD",
+            "1.0 can0 123#11",
+            "(1.0) can0",
+        ] {
+            assert!(CanLogRecord::from_str(line).is_err(), "{line}");
+        }
+    }
+}
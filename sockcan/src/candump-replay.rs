@@ -0,0 +1,346 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    can-utils canplayer (-g/-l/-i options, iface1=iface2 remapping)
+ *
+*/
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Frame output used by replay engine, one call writes one recorded frame.
+pub trait CanReplaySink {
+    /// # Errors
+    /// Returns a `CanError` when the frame cannot be sent.
+    fn replay_frame(&mut self, frame: &CanAnyFrame) -> Result<(), CanError>;
+}
+
+// RAW socket output (see SockCanHandle::open_raw)
+impl CanReplaySink for SockCanHandle {
+    fn replay_frame(&mut self, frame: &CanAnyFrame) -> Result<(), CanError> {
+        self.write_frame(frame)
+    }
+}
+
+/// Replay counters returned by `CanReplay::play`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CanReplayStats {
+    pub sent: usize,
+    pub filtered: usize,
+    pub unmapped: usize,
+    pub loops: u32,
+}
+
+// filter keeps frame format as kernel can_filter, (id, mask) both carry EFF flag
+fn replay_filter(canid: u32, mask: u32) -> (u32, u32) {
+    let eff = FilterMask::EFF_FLAG.bits();
+    let mask = (mask & FilterMask::EFF_MASK.bits()) | eff;
+    let extended =
+        canid & eff != 0 || canid & FilterMask::EFF_MASK.bits() > FilterMask::SFF_MASK.bits();
+    ((canid | if extended { eff } else { 0 }) & mask, mask)
+}
+
+// delay from first record for an `offset` in us, None when frames are sent without delay
+fn replay_delay(offset: u64, speed: f64) -> Option<Duration> {
+    #[allow(clippy::cast_precision_loss)]
+    (speed > 0.0).then(|| Duration::from_secs_f64(offset as f64 / 1_000_000.0 / speed))
+}
+
+/// Replays recorded frames keeping original inter-frame timing, scaled by `speed`.
+pub struct CanReplay<T: CanReplaySink> {
+    outputs: HashMap<String, T>,
+    ifmap: HashMap<String, String>,
+    includes: Vec<(u32, u32)>,
+    excludes: Vec<(u32, u32)>,
+    speed: f64,
+    loops: u32,
+    stop: Arc<AtomicBool>,
+}
+
+impl CanReplay<SockCanHandle> {
+    /// Opens a RAW socket on `iface` and registers it as replay output.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the interface cannot be opened.
+    pub fn open_output(&mut self, iface: &str) -> Result<&mut Self, CanError> {
+        let sock = SockCanHandle::open_raw(iface, CanTimeStamp::NONE)?;
+        Ok(self.add_output(iface, sock))
+    }
+}
+
+impl<T: CanReplaySink> Default for CanReplay<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: CanReplaySink> CanReplay<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        CanReplay {
+            outputs: HashMap::new(),
+            ifmap: HashMap::new(),
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            speed: 1.0,
+            loops: 1,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Registers an output under interface name `iface`.
+    pub fn add_output(&mut self, iface: &str, output: T) -> &mut Self {
+        self.outputs.insert(iface.to_owned(), output);
+        self
+    }
+
+    /// Sends frames recorded on `source` ("*" for any other) to output `target`. As with
+    /// canplayer, once one mapping is defined frames from unmapped interfaces are dropped.
+    pub fn map_iface(&mut self, source: &str, target: &str) -> &mut Self {
+        self.ifmap.insert(source.to_owned(), target.to_owned());
+        self
+    }
+
+    /// Only replays canids matching `canid & mask`, may be called more than once. As with
+    /// kernel `can_filter` frame format is compared, `canid` above 0x7FF or carrying
+    /// `EFF_FLAG` only matches extended frames, others only standard frames.
+    pub fn add_include(&mut self, canid: u32, mask: u32) -> &mut Self {
+        self.includes.push(replay_filter(canid, mask));
+        self
+    }
+
+    /// Never replays canids matching `canid & mask`, excludes win over includes.
+    pub fn add_exclude(&mut self, canid: u32, mask: u32) -> &mut Self {
+        self.excludes.push(replay_filter(canid, mask));
+        self
+    }
+
+    /// Speed factor (2.0 twice faster), 0 sends frames as fast as possible.
+    pub fn set_speed(&mut self, speed: f64) -> &mut Self {
+        self.speed = if speed.is_finite() && speed > 0.0 { speed } else { 0.0 };
+        self
+    }
+
+    /// Number of passes over recorded frames, 0 loops until stopped.
+    pub fn set_loops(&mut self, loops: u32) -> &mut Self {
+        self.loops = loops;
+        self
+    }
+
+    /// Shared flag aborting `play` from another thread or a signal handler. Flag is cleared
+    /// here, a stop requested before `play` starts is kept.
+    #[must_use]
+    pub fn get_stopper(&self) -> Arc<AtomicBool> {
+        self.stop.store(false, Ordering::Relaxed);
+        self.stop.clone()
+    }
+
+    #[must_use]
+    pub fn get_output(&self, iface: &str) -> Option<&T> {
+        self.outputs.get(iface)
+    }
+
+    // `canid` is the raw identifier with its EFF flag
+    fn is_selected(&self, canid: u32) -> bool {
+        let canid = canid & (FilterMask::EFF_FLAG.bits() | FilterMask::EFF_MASK.bits());
+        let matching =
+            |filters: &[(u32, u32)]| filters.iter().any(|(id, mask)| canid & mask == *id);
+        (self.includes.is_empty() || matching(&self.includes)) && !matching(&self.excludes)
+    }
+
+    fn target<'a>(&'a self, iface: &'a str) -> Option<&'a str> {
+        if self.ifmap.is_empty() {
+            Some(iface)
+        } else {
+            self.ifmap.get(iface).or_else(|| self.ifmap.get("*")).map(String::as_str)
+        }
+    }
+
+    // sleeps until frame offset (us) from first record is reached, false when stopped
+    fn wait_until(&self, start: Instant, offset: u64) -> bool {
+        if let Some(delay) = replay_delay(offset, self.speed) {
+            let deadline = start + delay;
+            loop {
+                let now = Instant::now();
+                if now >= deadline || self.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                // short slices keep stop flag responsive on long gaps
+                thread::sleep((deadline - now).min(Duration::from_millis(100)));
+            }
+        }
+        !self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Replays `records` in order. Timing is relative to first record of each pass;
+    /// records should be sorted by timestamp, earlier ones are sent without delay.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when an output fails or a mapped output is missing.
+    pub fn play(&mut self, records: &[CanLogRecord]) -> Result<CanReplayStats, CanError> {
+        let mut stats = CanReplayStats::default();
+        let Some(first) = records.first() else {
+            return Ok(stats);
+        };
+
+        while self.loops == 0 || stats.loops < self.loops {
+            let start = Instant::now();
+            for record in records {
+                // error frames are bus events, they cannot be written back
+                if record.get_frame().get_id().is_err() {
+                    stats.filtered += 1;
+                    continue;
+                }
+                if record.is_error() || !self.is_selected(record.get_canid()) {
+                    stats.filtered += 1;
+                    continue;
+                }
+                let Some(target) = self.target(&record.iface).map(str::to_owned) else {
+                    stats.unmapped += 1;
+                    continue;
+                };
+                if !self.outputs.contains_key(&target) {
+                    if !self.ifmap.is_empty() {
+                        return Err(CanError::new(
+                            "replay-missing-output",
+                            format!("no output for mapped iface:{target}"),
+                        ));
+                    }
+                    stats.unmapped += 1;
+                    continue;
+                }
+                if !self.wait_until(start, record.stamp.saturating_sub(first.stamp)) {
+                    return Ok(stats);
+                }
+                if let Some(output) = self.outputs.get_mut(&target) {
+                    output.replay_frame(record.get_frame())?;
+                    stats.sent += 1;
+                }
+            }
+            stats.loops += 1;
+            if self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Loads a candump log and replays it.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when log cannot be parsed or replay fails.
+    pub fn play_file(&mut self, path: &str) -> Result<CanReplayStats, CanError> {
+        let records = CanDumpReader::open(path)?.collect::<Result<Vec<_>, _>>()?;
+        self.play(&records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[derive(Default)]
+    struct FakeSink {
+        frames: Vec<(u32, Instant)>,
+    }
+
+    impl CanReplaySink for FakeSink {
+        fn replay_frame(&mut self, frame: &CanAnyFrame) -> Result<(), CanError> {
+            self.frames.push((frame.get_id()?, Instant::now()));
+            Ok(())
+        }
+    }
+
+    fn records(lines: &[&str]) -> Vec<CanLogRecord> {
+        lines.iter().map(|line| CanLogRecord::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_replay_filters() {
+        let records = records(&[
+            "(10.000000) can0 100#01",
+            "(10.000100) can1 200#02",
+            "(10.000200) can0 18FEF100#03",
+            "(10.000300) can0 101#04",
+            "(10.000400) can2 102#05",
+            "(10.000500) can0 20000080#0000000000000000",
+            "(10.000600) can0 00000105#06",
+        ]);
+
+        let mut replay = CanReplay::new();
+        replay
+            .add_output("vcan0", FakeSink::default())
+            .add_output("vcan1", FakeSink::default())
+            .map_iface("can0", "vcan0")
+            .map_iface("can1", "vcan1")
+            .add_include(0x100, 0x7F0)
+            .add_include(0x18FE_F100, 0x1FFF_FFFF)
+            .add_exclude(0x101, 0x7FF)
+            .set_speed(0.0)
+            .set_loops(2);
+
+        let stats = replay.play(&records).unwrap();
+        // standard filter 0x100/0x7F0 does not match extended 0x105
+        assert_eq!(stats, CanReplayStats { sent: 4, filtered: 8, unmapped: 2, loops: 2 });
+        let vcan0: Vec<u32> =
+            replay.get_output("vcan0").unwrap().frames.iter().map(|f| f.0).collect();
+        assert_eq!(vcan0, [0x100, 0x18FE_F100, 0x100, 0x18FE_F100]);
+        assert!(replay.get_output("vcan1").unwrap().frames.is_empty());
+
+        // mapped target without output is a configuration error
+        let mut replay = CanReplay::<FakeSink>::new();
+        replay.map_iface("can0", "vcan9");
+        assert!(replay.play(&records).is_err());
+
+        // wildcard mapping catches every other recorded interface
+        let mut replay = CanReplay::new();
+        replay
+            .add_output("vcan1", FakeSink::default())
+            .map_iface("*", "vcan1")
+            .set_speed(0.0);
+        let stats = replay.play(&records).unwrap();
+        assert_eq!(stats, CanReplayStats { sent: 6, filtered: 1, unmapped: 0, loops: 1 });
+
+        // stop requested before play is kept
+        let stop = replay.get_stopper();
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(replay.play(&records).unwrap().sent, 0);
+    }
+
+    #[test]
+    fn test_replay_timing() {
+        let records = records(&[
+            "(5.000000) can0 100#01",
+            "(5.040000) can0 101#02",
+            "(5.080000) can0 102#03",
+        ]);
+
+        let mut replay = CanReplay::new();
+        replay.add_output("can0", FakeSink::default()).set_speed(2.0);
+        let start = Instant::now();
+        let stats = replay.play(&records).unwrap();
+        assert_eq!(stats.sent, 3);
+
+        let frames = &replay.get_output("can0").unwrap().frames;
+        let last = frames[2].1.duration_since(start);
+        // only lower bound is reliable, a loaded host may delay frames by any amount
+        assert!(last >= Duration::from_millis(40), "{last:?}");
+        assert!(frames[1].1.duration_since(frames[0].1) >= Duration::from_millis(19));
+
+        // speed scaling is checked without wall clock
+        assert_eq!(replay_delay(80_000, 1.0), Some(Duration::from_millis(80)));
+        assert_eq!(replay_delay(80_000, 2.0), Some(Duration::from_millis(40)));
+        assert_eq!(replay_delay(80_000, 0.5), Some(Duration::from_millis(160)));
+        assert_eq!(replay_delay(0, 4.0), Some(Duration::ZERO));
+        assert_eq!(replay_delay(80_000, 0.0), None);
+    }
+}
@@ -0,0 +1,512 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    CiA 306 electronic data sheet (EDS/DCF) specification
+ *    CiA 301 CANopen application layer (object dictionary data types)
+ *
+ * Loads an EDS/DCF file as CANopen object dictionary, DCF ParameterValue takes precedence
+ * over EDS DefaultValue, "$NODEID" is replaced with dictionary node id:
+ *    let dict = CoObjectDict::from_file("servo.dcf")?;
+ *    let object = dict.get(0x6041, 0).unwrap(); // status word
+ *
+*/
+use crate::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+/// `CANopen` basic data types (`CiA` 301 object 0x0001-0x001B).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoDataType {
+    Boolean,
+    Integer8,
+    Integer16,
+    Integer32,
+    Unsigned8,
+    Unsigned16,
+    Unsigned32,
+    Real32,
+    VisibleString,
+    OctetString,
+    UnicodeString,
+    Domain,
+    Real64,
+    Integer64,
+    Unsigned64,
+    Unknown(u16),
+}
+
+impl CoDataType {
+    #[must_use]
+    pub fn from_u16(value: u16) -> Self {
+        match value {
+            0x01 => CoDataType::Boolean,
+            0x02 => CoDataType::Integer8,
+            0x03 => CoDataType::Integer16,
+            0x04 => CoDataType::Integer32,
+            0x05 => CoDataType::Unsigned8,
+            0x06 => CoDataType::Unsigned16,
+            0x07 => CoDataType::Unsigned32,
+            0x08 => CoDataType::Real32,
+            0x09 => CoDataType::VisibleString,
+            0x0A => CoDataType::OctetString,
+            0x0B => CoDataType::UnicodeString,
+            0x0F => CoDataType::Domain,
+            0x11 => CoDataType::Real64,
+            0x15 => CoDataType::Integer64,
+            0x1B => CoDataType::Unsigned64,
+            _ => CoDataType::Unknown(value),
+        }
+    }
+
+    /// Returns fixed size in bytes, None for strings and domain.
+    #[must_use]
+    pub fn get_size(&self) -> Option<usize> {
+        match self {
+            CoDataType::Boolean | CoDataType::Integer8 | CoDataType::Unsigned8 => Some(1),
+            CoDataType::Integer16 | CoDataType::Unsigned16 => Some(2),
+            CoDataType::Integer32 | CoDataType::Unsigned32 | CoDataType::Real32 => Some(4),
+            CoDataType::Integer64 | CoDataType::Unsigned64 | CoDataType::Real64 => Some(8),
+            _ => None,
+        }
+    }
+
+    /// Decodes little endian `data`, None for non numeric types or too short data.
+    #[must_use]
+    pub fn decode(&self, data: &[u8]) -> Option<CanDbcType> {
+        let size = self.get_size()?;
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(data.get(..size)?);
+        let value = match self {
+            CoDataType::Boolean => CanDbcType::Bool(bytes[0] != 0),
+            CoDataType::Integer8 => CanDbcType::I8(i8::from_le_bytes([bytes[0]])),
+            CoDataType::Integer16 => CanDbcType::I16(i16::from_le_bytes([bytes[0], bytes[1]])),
+            CoDataType::Integer32 => {
+                CanDbcType::I32(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            },
+            CoDataType::Integer64 => CanDbcType::I64(i64::from_le_bytes(bytes)),
+            CoDataType::Unsigned8 => CanDbcType::U8(bytes[0]),
+            CoDataType::Unsigned16 => CanDbcType::U16(u16::from_le_bytes([bytes[0], bytes[1]])),
+            CoDataType::Unsigned32 => {
+                CanDbcType::U32(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            },
+            CoDataType::Unsigned64 => CanDbcType::U64(u64::from_le_bytes(bytes)),
+            CoDataType::Real32 => {
+                CanDbcType::F32(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            },
+            CoDataType::Real64 => CanDbcType::F64(f64::from_le_bytes(bytes)),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Encodes `value` as little endian bytes of this type.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when type is not numeric or value does not fit.
+    pub fn encode(&self, value: CanDbcType) -> Result<Vec<u8>, CanError> {
+        let invalid =
+            || CanError::new("canopen-value-invalid", format!("{value:?} does not fit {self:?}"));
+        let float = match value {
+            CanDbcType::F32(val) => Some(f64::from(val)),
+            CanDbcType::F64(val) => Some(val),
+            _ => None,
+        };
+        let integer: Option<i128> = match value {
+            CanDbcType::U8(val) => Some(i128::from(val)),
+            CanDbcType::U16(val) => Some(i128::from(val)),
+            CanDbcType::U32(val) => Some(i128::from(val)),
+            CanDbcType::U64(val) => Some(i128::from(val)),
+            CanDbcType::I8(val) => Some(i128::from(val)),
+            CanDbcType::I16(val) => Some(i128::from(val)),
+            CanDbcType::I32(val) => Some(i128::from(val)),
+            CanDbcType::I64(val) => Some(i128::from(val)),
+            CanDbcType::Bool(val) => Some(i128::from(val)),
+            CanDbcType::F32(_) | CanDbcType::F64(_) => None,
+        };
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let bytes = match self {
+            CoDataType::Real32 => match (float, integer) {
+                (Some(val), _) => (val as f32).to_le_bytes().to_vec(),
+                (None, Some(val)) => (val as f32).to_le_bytes().to_vec(),
+                _ => return Err(invalid()),
+            },
+            CoDataType::Real64 => match (float, integer) {
+                (Some(val), _) => val.to_le_bytes().to_vec(),
+                (None, Some(val)) => (val as f64).to_le_bytes().to_vec(),
+                _ => return Err(invalid()),
+            },
+            _ => {
+                let val = integer.ok_or_else(invalid)?;
+                match self {
+                    CoDataType::Boolean => vec![u8::from(val != 0)],
+                    CoDataType::Integer8 => {
+                        i8::try_from(val).map_err(|_| invalid())?.to_le_bytes().to_vec()
+                    },
+                    CoDataType::Integer16 => {
+                        i16::try_from(val).map_err(|_| invalid())?.to_le_bytes().to_vec()
+                    },
+                    CoDataType::Integer32 => {
+                        i32::try_from(val).map_err(|_| invalid())?.to_le_bytes().to_vec()
+                    },
+                    CoDataType::Integer64 => {
+                        i64::try_from(val).map_err(|_| invalid())?.to_le_bytes().to_vec()
+                    },
+                    CoDataType::Unsigned8 => vec![u8::try_from(val).map_err(|_| invalid())?],
+                    CoDataType::Unsigned16 => {
+                        u16::try_from(val).map_err(|_| invalid())?.to_le_bytes().to_vec()
+                    },
+                    CoDataType::Unsigned32 => {
+                        u32::try_from(val).map_err(|_| invalid())?.to_le_bytes().to_vec()
+                    },
+                    CoDataType::Unsigned64 => {
+                        u64::try_from(val).map_err(|_| invalid())?.to_le_bytes().to_vec()
+                    },
+                    _ => return Err(invalid()),
+                }
+            },
+        };
+        Ok(bytes)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoAccess {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    Const,
+}
+
+impl CoAccess {
+    fn from_str(access: &str) -> Self {
+        match access.to_ascii_lowercase().as_str() {
+            "ro" => CoAccess::ReadOnly,
+            "wo" => CoAccess::WriteOnly,
+            "const" => CoAccess::Const,
+            _ => CoAccess::ReadWrite, // rw, rwr, rww
+        }
+    }
+
+    #[must_use]
+    pub fn is_readable(&self) -> bool {
+        *self != CoAccess::WriteOnly
+    }
+
+    #[must_use]
+    pub fn is_writable(&self) -> bool {
+        matches!(self, CoAccess::WriteOnly | CoAccess::ReadWrite)
+    }
+}
+
+/// One object dictionary entry (index/sub-index).
+#[derive(Clone, Debug)]
+pub struct CoObject {
+    pub index: u16,
+    pub sub: u8,
+    pub name: String,
+    pub data_type: CoDataType,
+    pub access: CoAccess,
+    pub pdo_mapping: bool,
+    pub default: Option<String>,
+    pub value: Option<String>, // DCF ParameterValue
+}
+
+impl CoObject {
+    /// Returns DCF value (or EDS default) as integer, `$NODEID` is replaced with `node_id`.
+    #[must_use]
+    pub fn get_raw(&self, node_id: u8) -> Option<u64> {
+        let text = self.value.as_ref().or(self.default.as_ref())?;
+        co_parse_int(text, node_id)
+    }
+
+    /// Returns DCF value (or EDS default) typed after object data type.
+    #[must_use]
+    pub fn get_value(&self, node_id: u8) -> Option<CanDbcType> {
+        let text = self.value.as_ref().or(self.default.as_ref())?;
+        match self.data_type {
+            CoDataType::Real32 => text.trim().parse::<f32>().ok().map(CanDbcType::F32),
+            CoDataType::Real64 => text.trim().parse::<f64>().ok().map(CanDbcType::F64),
+            data_type => data_type.decode(&co_parse_int(text, node_id)?.to_le_bytes()),
+        }
+    }
+}
+
+impl fmt::Display for CoObject {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(format, "{:04x}sub{} {} ({:?})", self.index, self.sub, self.name, self.data_type)
+    }
+}
+
+// EDS integer: decimal, 0x hexadecimal or 0 octal, optionally summed with $NODEID
+fn co_parse_int(text: &str, node_id: u8) -> Option<u64> {
+    let mut total: u64 = 0;
+    for term in text.split('+') {
+        let term = term.trim();
+        let value = if term.eq_ignore_ascii_case("$NODEID") {
+            u64::from(node_id)
+        } else if let Some(hexa) = term.strip_prefix("0x").or_else(|| term.strip_prefix("0X")) {
+            u64::from_str_radix(hexa, 16).ok()?
+        } else if let Some(negative) = term.strip_prefix('-') {
+            negative.parse::<i64>().ok()?.wrapping_neg().cast_unsigned()
+        } else if term.len() > 1 && term.starts_with('0') {
+            u64::from_str_radix(&term[1..], 8).ok()?
+        } else {
+            term.parse::<u64>().ok()?
+        };
+        total = total.wrapping_add(value);
+    }
+    Some(total)
+}
+
+/// `CANopen` object dictionary loaded from an EDS or DCF file.
+#[derive(Default)]
+pub struct CoObjectDict {
+    pub node_id: u8,
+    pub vendor: String,
+    pub product: String,
+    objects: BTreeMap<(u16, u8), CoObject>,
+}
+
+impl CoObjectDict {
+    /// Parses EDS/DCF `buffer`, node id comes from DCF `[DeviceComissioning]` section.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when an object section is invalid.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(buffer: &str) -> Result<Self, CanError> {
+        let mut sections: Vec<(String, Vec<(String, String)>)> = Vec::new();
+        for (count, line) in buffer.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|name| name.strip_suffix(']')) {
+                sections.push((name.trim().to_ascii_lowercase(), Vec::new()));
+                continue;
+            }
+            let (Some((key, value)), Some((_, entries))) =
+                (line.split_once('='), sections.last_mut())
+            else {
+                return Err(CanError::new(
+                    "canopen-eds-invalid",
+                    format!("line:{} unexpected '{line}'", count + 1),
+                ));
+            };
+            entries.push((key.trim().to_ascii_lowercase(), value.trim().to_owned()));
+        }
+
+        let mut dict = CoObjectDict::default();
+        for (name, entries) in &sections {
+            let lookup = |key: &str| {
+                entries.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+            };
+            match name.as_str() {
+                "deviceinfo" => {
+                    lookup("vendorname").unwrap_or_default().clone_into(&mut dict.vendor);
+                    lookup("productname").unwrap_or_default().clone_into(&mut dict.product);
+                    continue;
+                },
+                "devicecomissioning" | "devicecommissioning" => {
+                    let node_id = lookup("nodeid").and_then(|value| co_parse_int(value, 0));
+                    dict.node_id = node_id.and_then(|value| u8::try_from(value).ok()).unwrap_or(0);
+                    continue;
+                },
+                _ => {},
+            }
+
+            // object sections: "1018" or "1018sub1"
+            let (index, sub) = match name.split_once("sub") {
+                Some((index, sub)) => (index, sub),
+                None => (name.as_str(), "0"),
+            };
+            let (Ok(index), Ok(sub)) =
+                (u16::from_str_radix(index, 16), u8::from_str_radix(sub, 16))
+            else {
+                continue; // FileInfo, MandatoryObjects, Comments, ...
+            };
+
+            // ARRAY/RECORD top section only describes sub-objects count
+            let Some(data_type) = lookup("datatype") else {
+                continue;
+            };
+            let data_type = co_parse_int(data_type, 0).and_then(|value| u16::try_from(value).ok());
+            let Some(data_type) = data_type else {
+                return Err(CanError::new(
+                    "canopen-eds-invalid",
+                    format!("[{name}] invalid DataType"),
+                ));
+            };
+
+            dict.objects.insert(
+                (index, sub),
+                CoObject {
+                    index,
+                    sub,
+                    name: lookup("parametername").unwrap_or_default().to_owned(),
+                    data_type: CoDataType::from_u16(data_type),
+                    access: CoAccess::from_str(lookup("accesstype").unwrap_or("rw")),
+                    pdo_mapping: lookup("pdomapping").is_some_and(|value| value == "1"),
+                    default: lookup("defaultvalue")
+                        .filter(|value| !value.is_empty())
+                        .map(str::to_owned),
+                    value: lookup("parametervalue")
+                        .filter(|value| !value.is_empty())
+                        .map(str::to_owned),
+                },
+            );
+        }
+        Ok(dict)
+    }
+
+    /// Loads an EDS/DCF file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be read or parsed.
+    pub fn from_file(path: &str) -> Result<Self, CanError> {
+        match fs::read_to_string(path) {
+            Ok(buffer) => CoObjectDict::from_str(&buffer),
+            Err(error) => Err(CanError::new("canopen-eds-read", format!("{path}: {error}"))),
+        }
+    }
+
+    /// Overwrite node id used for `$NODEID` substitution.
+    pub fn set_node_id(&mut self, node_id: u8) -> &mut Self {
+        self.node_id = node_id;
+        self
+    }
+
+    #[must_use]
+    pub fn get(&self, index: u16, sub: u8) -> Option<&CoObject> {
+        self.objects.get(&(index, sub))
+    }
+
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<&CoObject> {
+        self.objects.values().find(|object| object.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns object value as integer, see [`CoObject::get_raw`].
+    #[must_use]
+    pub fn get_raw(&self, index: u16, sub: u8) -> Option<u64> {
+        self.get(index, sub)?.get_raw(self.node_id)
+    }
+
+    /// Returns objects sorted by index/sub-index.
+    pub fn iter(&self) -> impl Iterator<Item = &CoObject> + '_ {
+        self.objects.values()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Builds PDO mapping from communication (0x1400/0x1800) and mapping (0x1600/0x1A00)
+    /// parameters, `map_index` is mapping object index (eg: 0x1A00 for TPDO1).
+    #[must_use]
+    pub fn get_pdo_map(&self, map_index: u16) -> Option<CoPdoMap> {
+        let comm_index = map_index.checked_sub(0x200)?;
+        let cobid = u32::try_from(self.get_raw(comm_index, 1)?).ok()?;
+        let count = self.get_raw(map_index, 0)?;
+        let mut map = CoPdoMap::new(cobid);
+        if let Some(transmission) = self.get_raw(comm_index, 2) {
+            map.set_transmission(u8::try_from(transmission).unwrap_or(0xFF));
+        }
+        for sub in 1..=u8::try_from(count).ok()? {
+            let entry = u32::try_from(self.get_raw(map_index, sub)?).ok()?;
+            map.add_entry(CoPdoEntry::from_u32(entry));
+        }
+        Some(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DCF: &str = "[DeviceInfo]\n\
+VendorName=IoT.bzh\n\
+ProductName=Servo\n\
+\n\
+[DeviceComissioning]\n\
+NodeID=0x05\n\
+\n\
+[1018]\n\
+SubNumber=2\n\
+ObjectType=0x9\n\
+\n\
+[1018sub1]\n\
+ParameterName=Vendor-ID\n\
+DataType=0x0007\n\
+AccessType=ro\n\
+DefaultValue=0x1234\n\
+\n\
+[1800sub1]\n\
+ParameterName=COB-ID used by TPDO\n\
+DataType=0x0007\n\
+AccessType=rw\n\
+DefaultValue=$NODEID+0x180\n\
+\n\
+[1800sub2]\n\
+ParameterName=Transmission type\n\
+DataType=0x0005\n\
+DefaultValue=255\n\
+ParameterValue=1\n\
+\n\
+[1A00sub0]\n\
+ParameterName=Number of mapped objects\n\
+DataType=0x0005\n\
+DefaultValue=2\n\
+\n\
+[1A00sub1]\n\
+ParameterName=Mapped object 1\n\
+DataType=0x0007\n\
+DefaultValue=0x60410010\n\
+\n\
+[1A00sub2]\n\
+ParameterName=Mapped object 2\n\
+DataType=0x0007\n\
+DefaultValue=0x60640020\n\
+\n\
+[6064]\n\
+ParameterName=Position actual value\n\
+ObjectType=0x7\n\
+DataType=0x0004\n\
+AccessType=ro\n\
+PDOMapping=1\n";
+
+    #[test]
+    fn test_eds_load() {
+        let dict = CoObjectDict::from_str(DCF).unwrap();
+        assert_eq!(dict.node_id, 5);
+        assert_eq!(dict.product, "Servo");
+        assert_eq!(dict.get(0x1018, 1).unwrap().get_value(5), Some(CanDbcType::U32(0x1234)));
+        assert_eq!(dict.get_raw(0x1800, 1), Some(0x185));
+        assert!(dict.find("Position actual value").unwrap().pdo_mapping);
+        assert_eq!(co_parse_int("-2", 0).map(u64::cast_signed), Some(-2));
+        assert_eq!(co_parse_int("010", 0), Some(8));
+
+        let map = dict.get_pdo_map(0x1A00).unwrap();
+        assert_eq!(map.get_cobid(), 0x185);
+        assert_eq!(map.get_transmission(), 1);
+        assert_eq!(map.get_entries().len(), 2);
+        assert_eq!(map.get_entries()[1], CoPdoEntry::new(0x6064, 0, 32));
+
+        let data = [0x37, 0x06, 0xFE, 0xFF, 0xFF, 0xFF];
+        let values = map.decode_dict(&data, &dict);
+        assert_eq!(values.len(), 1); // 0x6041 is not described
+        assert_eq!(values[0].1, CanDbcType::I32(-2));
+    }
+}
@@ -0,0 +1,905 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    CiA 301 CANopen application layer and communication profile
+ *    https://www.can-cia.org/can-knowledge/canopen/
+ *
+ * CANopen master: NMT and SDO client on a RAW socket, heartbeat/node guarding consumer
+ * on a BCM socket, TPDO/RPDO mapping:
+ *    let sock = SockCanHandle::open_raw("can0", CanTimeStamp::CLASSIC)?;
+ *    let mut master = CoMaster::new(sock);
+ *    master.nmt(0x05, CoNmtCmd::Start)?;
+ *    let status = master.sdo_upload(0x05, 0x6041, 0)?;
+ *
+*/
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+pub const CO_COBID_NMT: u32 = 0x000;
+pub const CO_COBID_SYNC: u32 = 0x080;
+pub const CO_COBID_EMCY: u32 = 0x080; // + node id
+pub const CO_COBID_TPDO1: u32 = 0x180; // + node id, TPDO2..4 every 0x100
+pub const CO_COBID_RPDO1: u32 = 0x200; // + node id, RPDO2..4 every 0x100
+pub const CO_COBID_SDO_TX: u32 = 0x580; // server -> client
+pub const CO_COBID_SDO_RX: u32 = 0x600; // client -> server
+pub const CO_COBID_NMT_ERR: u32 = 0x700; // heartbeat, node guarding, boot-up
+
+const CO_COBID_INVALID: u32 = 0x8000_0000; // PDO disabled
+const CO_SDO_TIMEOUT: u64 = 500;
+const CO_SDO_CCS_DOWNLOAD_SEG: u8 = 0x00;
+const CO_SDO_CCS_DOWNLOAD: u8 = 0x20;
+const CO_SDO_CCS_UPLOAD: u8 = 0x40;
+const CO_SDO_CCS_UPLOAD_SEG: u8 = 0x60;
+const CO_SDO_SCS_DOWNLOAD_SEG: u8 = 0x20;
+const CO_SDO_SCS_DOWNLOAD: u8 = 0x60;
+const CO_SDO_CS_ABORT: u8 = 0x80;
+const CO_SDO_SEG_SZ: usize = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CoNmtCmd {
+    Start = 0x01,
+    Stop = 0x02,
+    PreOperational = 0x80,
+    ResetNode = 0x81,
+    ResetComm = 0x82,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoNmtState {
+    BootUp,
+    Stopped,
+    Operational,
+    PreOperational,
+    Unknown(u8),
+}
+
+impl From<u8> for CoNmtState {
+    fn from(state: u8) -> Self {
+        match state & 0x7F {
+            0x00 => CoNmtState::BootUp,
+            0x04 => CoNmtState::Stopped,
+            0x05 => CoNmtState::Operational,
+            0x7F => CoNmtState::PreOperational,
+            value => CoNmtState::Unknown(value),
+        }
+    }
+}
+
+impl fmt::Display for CoNmtState {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoNmtState::BootUp => write!(format, "BootUp"),
+            CoNmtState::Stopped => write!(format, "Stopped"),
+            CoNmtState::Operational => write!(format, "Operational"),
+            CoNmtState::PreOperational => write!(format, "PreOperational"),
+            CoNmtState::Unknown(value) => write!(format, "Unknown({value:#04x})"),
+        }
+    }
+}
+
+/// SDO abort code (`CiA` 301 table 22).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoSdoAbort(pub u32);
+
+impl fmt::Display for CoSdoAbort {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self.0 {
+            0x0503_0000 => "toggle bit not alternated",
+            0x0504_0000 => "SDO protocol timed out",
+            0x0504_0001 => "invalid command specifier",
+            0x0601_0000 => "unsupported access to an object",
+            0x0601_0001 => "attempt to read a write only object",
+            0x0601_0002 => "attempt to write a read only object",
+            0x0602_0000 => "object does not exist",
+            0x0604_0041 => "object cannot be mapped to the PDO",
+            0x0604_0042 => "PDO length exceeded",
+            0x0607_0010 => "data type does not match",
+            0x0609_0011 => "sub-index does not exist",
+            0x0609_0030 => "invalid value for parameter",
+            0x0800_0000 => "general error",
+            0x0800_0020 => "data cannot be transferred or stored",
+            0x0800_0022 => "data cannot be transferred in current device state",
+            _ => "unknown abort code",
+        };
+        write!(format, "{:#010x} {text}", self.0)
+    }
+}
+
+/// Raw CAN frame transport used by `CANopen` master, one call sends/receives one frame.
+pub trait CoTransport {
+    /// Sends one classic CAN frame.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the frame cannot be sent.
+    fn send_frame(&mut self, canid: u32, data: &[u8]) -> Result<(), CanError>;
+
+    /// Waits up to `timeout_ms` for next frame, returns `None` on timeout.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the transport fails to receive.
+    fn recv_frame(&mut self, timeout_ms: u64) -> Result<Option<(u32, Vec<u8>)>, CanError>;
+}
+
+// RAW socket transport (see SockCanHandle::open_raw)
+impl CoTransport for SockCanHandle {
+    fn send_frame(&mut self, canid: u32, data: &[u8]) -> Result<(), CanError> {
+        self.send_std(canid, data)
+    }
+
+    fn recv_frame(&mut self, timeout_ms: u64) -> Result<Option<(u32, Vec<u8>)>, CanError> {
+        let timeout = i64::try_from(timeout_ms.max(1)).unwrap_or(i64::MAX);
+        self.set_timeout(timeout, 0)?;
+
+        let msg = self.get_can_frame();
+        if let Some(error) = msg.get_os_error() {
+            return match error.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Ok(None),
+                _ => Err(CanError::new("canopen-recv-fail", error.to_string())),
+            };
+        }
+        let canid = msg.get_id()?;
+        let data = msg.get_data()?;
+        Ok(Some((canid, data.to_vec())))
+    }
+}
+
+/// One mapped object: index, sub-index and size in bits (mapping parameter layout).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoPdoEntry {
+    pub index: u16,
+    pub sub: u8,
+    pub bits: u8,
+}
+
+impl CoPdoEntry {
+    #[must_use]
+    pub fn new(index: u16, sub: u8, bits: u8) -> Self {
+        CoPdoEntry { index, sub, bits }
+    }
+
+    #[must_use]
+    pub fn from_u32(value: u32) -> Self {
+        let bytes = value.to_be_bytes();
+        CoPdoEntry {
+            index: u16::from_be_bytes([bytes[0], bytes[1]]),
+            sub: bytes[2],
+            bits: bytes[3],
+        }
+    }
+
+    #[must_use]
+    pub fn to_u32(&self) -> u32 {
+        (u32::from(self.index) << 16) | (u32::from(self.sub) << 8) | u32::from(self.bits)
+    }
+}
+
+/// TPDO/RPDO mapping: COB-ID, transmission type and mapped objects in frame order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoPdoMap {
+    cobid: u32,
+    transmission: u8,
+    entries: Vec<CoPdoEntry>,
+}
+
+impl CoPdoMap {
+    #[must_use]
+    pub fn new(cobid: u32) -> Self {
+        CoPdoMap { cobid, transmission: 0xFF, entries: Vec::new() }
+    }
+
+    /// Transmission type: 0 acyclic synchronous, 1-240 every n SYNC, 254/255 event driven.
+    pub fn set_transmission(&mut self, transmission: u8) -> &mut Self {
+        self.transmission = transmission;
+        self
+    }
+
+    pub fn add_entry(&mut self, entry: CoPdoEntry) -> &mut Self {
+        self.entries.push(entry);
+        self
+    }
+
+    #[must_use]
+    pub fn get_cobid(&self) -> u32 {
+        self.cobid & !CO_COBID_INVALID
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.cobid & CO_COBID_INVALID == 0
+    }
+
+    #[must_use]
+    pub fn get_transmission(&self) -> u8 {
+        self.transmission
+    }
+
+    #[must_use]
+    pub fn get_entries(&self) -> &[CoPdoEntry] {
+        &self.entries
+    }
+
+    /// Returns raw value of every mapped object present within `data`.
+    #[must_use]
+    pub fn decode(&self, data: &[u8]) -> Vec<(CoPdoEntry, u64)> {
+        let mut values = Vec::with_capacity(self.entries.len());
+        let mut offset = 0usize;
+        for entry in &self.entries {
+            let bits = usize::from(entry.bits);
+            if bits == 0 || bits > 64 || offset + bits > data.len() * 8 {
+                break;
+            }
+            let mut value = 0u64;
+            for bit in 0..bits {
+                let pos = offset + bit;
+                if data[pos / 8] & (1 << (pos % 8)) != 0 {
+                    value |= 1 << bit;
+                }
+            }
+            values.push((*entry, value));
+            offset += bits;
+        }
+        values
+    }
+
+    /// Returns typed value of every mapped object described within `dict`.
+    #[must_use]
+    pub fn decode_dict<'a>(
+        &self,
+        data: &[u8],
+        dict: &'a CoObjectDict,
+    ) -> Vec<(&'a CoObject, CanDbcType)> {
+        self.decode(data)
+            .into_iter()
+            .filter_map(|(entry, raw)| {
+                let object = dict.get(entry.index, entry.sub)?;
+                Some((object, object.data_type.decode(&raw.to_le_bytes())?))
+            })
+            .collect()
+    }
+
+    /// Packs raw `values` (one per mapped object) as PDO payload.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when values count does not match mapping or payload exceeds 8 bytes.
+    pub fn encode(&self, values: &[u64]) -> Result<Vec<u8>, CanError> {
+        if values.len() != self.entries.len() {
+            return Err(CanError::new(
+                "canopen-pdo-invalid",
+                format!("pdo:{:#x} expect {} values", self.get_cobid(), self.entries.len()),
+            ));
+        }
+        let size: usize = self.entries.iter().map(|entry| usize::from(entry.bits)).sum();
+        if size > 64 {
+            return Err(CanError::new(
+                "canopen-pdo-invalid",
+                format!("pdo:{:#x} mapping {size} bits exceeds 64", self.get_cobid()),
+            ));
+        }
+        let mut data = vec![0u8; size.div_ceil(8)];
+        let mut offset = 0usize;
+        for (entry, value) in self.entries.iter().zip(values) {
+            for bit in 0..usize::from(entry.bits) {
+                if value & (1 << bit) != 0 {
+                    let pos = offset + bit;
+                    data[pos / 8] |= 1 << (pos % 8);
+                }
+            }
+            offset += usize::from(entry.bits);
+        }
+        Ok(data)
+    }
+}
+
+/// `CANopen` master: NMT commands, SDO client and PDO configuration.
+pub struct CoMaster<T: CoTransport> {
+    transport: T,
+    timeout_ms: u64,
+}
+
+impl<T: CoTransport> CoMaster<T> {
+    pub fn new(transport: T) -> Self {
+        CoMaster { transport, timeout_ms: CO_SDO_TIMEOUT }
+    }
+
+    /// SDO response timeout (default 500ms)
+    pub fn set_timeout(&mut self, timeout_ms: u64) -> &mut Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    pub fn get_transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Sends NMT command to `node`, 0 addresses every node.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when frame cannot be sent.
+    pub fn nmt(&mut self, node: u8, cmd: CoNmtCmd) -> Result<(), CanError> {
+        self.transport.send_frame(CO_COBID_NMT, &[cmd as u8, node])
+    }
+
+    /// Sends a SYNC message.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when frame cannot be sent.
+    pub fn sync(&mut self) -> Result<(), CanError> {
+        self.transport.send_frame(CO_COBID_SYNC, &[])
+    }
+
+    /// Sends `data` as PDO payload of `map` (RPDO from master view).
+    ///
+    /// # Errors
+    /// Returns a `CanError` when values do not match mapping or frame cannot be sent.
+    pub fn send_pdo(&mut self, map: &CoPdoMap, values: &[u64]) -> Result<(), CanError> {
+        let data = map.encode(values)?;
+        self.transport.send_frame(map.get_cobid(), &data)
+    }
+
+    // sends one SDO request and waits for matching server response
+    fn sdo_request(&mut self, node: u8, request: [u8; 8]) -> Result<[u8; 8], CanError> {
+        self.transport.send_frame(CO_COBID_SDO_RX + u32::from(node), &request)?;
+        let deadline = Instant::now() + Duration::from_millis(self.timeout_ms);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                // tell server to drop current transfer
+                let mut abort = [CO_SDO_CS_ABORT, request[1], request[2], request[3], 0, 0, 0, 0];
+                abort[4..8].copy_from_slice(&0x0504_0000u32.to_le_bytes());
+                self.transport.send_frame(CO_COBID_SDO_RX + u32::from(node), &abort)?;
+                return Err(CanError::new(
+                    "canopen-sdo-timeout",
+                    format!("node:{node} no SDO response"),
+                ));
+            }
+            let timeout = u64::try_from(remaining.as_millis().max(1)).unwrap_or(u64::MAX);
+            let Some((canid, data)) = self.transport.recv_frame(timeout)? else {
+                continue;
+            };
+            if canid != CO_COBID_SDO_TX + u32::from(node) || data.len() < 8 {
+                continue;
+            }
+            let mut response = [0u8; 8];
+            response.copy_from_slice(&data[0..8]);
+            if response[0] == CO_SDO_CS_ABORT {
+                let abort = CoSdoAbort(u32::from_le_bytes([
+                    response[4],
+                    response[5],
+                    response[6],
+                    response[7],
+                ]));
+                let index = u16::from_le_bytes([response[1], response[2]]);
+                return Err(CanError::new(
+                    "canopen-sdo-abort",
+                    format!("node:{node} {index:04x}sub{} {abort}", response[3]),
+                ));
+            }
+            return Ok(response);
+        }
+    }
+
+    fn sdo_protocol_error(node: u8, response: [u8; 8]) -> CanError {
+        CanError::new(
+            "canopen-sdo-protocol",
+            format!("node:{node} unexpected SDO response {response:02x?}"),
+        )
+    }
+
+    /// Reads object `index`/`sub` from `node` (expedited or segmented upload).
+    ///
+    /// # Errors
+    /// Returns a `CanError` on timeout, SDO abort or protocol error.
+    pub fn sdo_upload(&mut self, node: u8, index: u16, sub: u8) -> Result<Vec<u8>, CanError> {
+        let idx = index.to_le_bytes();
+        let response =
+            self.sdo_request(node, [CO_SDO_CCS_UPLOAD, idx[0], idx[1], sub, 0, 0, 0, 0])?;
+        if response[0] & 0xE0 != CO_SDO_CCS_UPLOAD {
+            return Err(Self::sdo_protocol_error(node, response));
+        }
+
+        // expedited: data within initiate response
+        if response[0] & 0x02 != 0 {
+            let size = if response[0] & 0x01 != 0 {
+                4 - usize::from((response[0] >> 2) & 0x03)
+            } else {
+                4
+            };
+            return Ok(response[4..4 + size].to_vec());
+        }
+
+        let expected = if response[0] & 0x01 != 0 {
+            Some(u32::from_le_bytes([response[4], response[5], response[6], response[7]]))
+        } else {
+            None
+        };
+        let mut data = Vec::new();
+        let mut toggle = 0u8;
+        loop {
+            let response =
+                self.sdo_request(node, [CO_SDO_CCS_UPLOAD_SEG | toggle, 0, 0, 0, 0, 0, 0, 0])?;
+            if response[0] & 0xE0 != 0 || response[0] & 0x10 != toggle {
+                return Err(Self::sdo_protocol_error(node, response));
+            }
+            let size = CO_SDO_SEG_SZ - usize::from((response[0] >> 1) & 0x07);
+            data.extend_from_slice(&response[1..=size]);
+            if response[0] & 0x01 != 0 {
+                break;
+            }
+            toggle ^= 0x10;
+        }
+        if let Some(expected) = expected {
+            if usize::try_from(expected).ok() != Some(data.len()) {
+                return Err(CanError::new(
+                    "canopen-sdo-size",
+                    format!(
+                        "node:{node} {index:04x}sub{sub} len:{} expected:{expected}",
+                        data.len()
+                    ),
+                ));
+            }
+        }
+        Ok(data)
+    }
+
+    /// Writes `data` into object `index`/`sub` of `node`, up to 4 bytes use expedited transfer.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on timeout, SDO abort or protocol error.
+    pub fn sdo_download(
+        &mut self,
+        node: u8,
+        index: u16,
+        sub: u8,
+        data: &[u8],
+    ) -> Result<(), CanError> {
+        let idx = index.to_le_bytes();
+        let mut request = [0u8; 8];
+        request[1..4].copy_from_slice(&[idx[0], idx[1], sub]);
+
+        if (1..=4).contains(&data.len()) {
+            let unused = u8::try_from(4 - data.len()).unwrap_or(0);
+            request[0] = CO_SDO_CCS_DOWNLOAD | (unused << 2) | 0x03;
+            request[4..4 + data.len()].copy_from_slice(data);
+            let response = self.sdo_request(node, request)?;
+            if response[0] & 0xE0 != CO_SDO_SCS_DOWNLOAD {
+                return Err(Self::sdo_protocol_error(node, response));
+            }
+            return Ok(());
+        }
+
+        let size = u32::try_from(data.len())
+            .map_err(|_| CanError::new("canopen-sdo-size", "SDO download too big"))?;
+        request[0] = CO_SDO_CCS_DOWNLOAD | 0x01;
+        request[4..8].copy_from_slice(&size.to_le_bytes());
+        let response = self.sdo_request(node, request)?;
+        if response[0] & 0xE0 != CO_SDO_SCS_DOWNLOAD {
+            return Err(Self::sdo_protocol_error(node, response));
+        }
+
+        // an empty object still needs one (last) segment
+        let mut chunks: Vec<&[u8]> = data.chunks(CO_SDO_SEG_SZ).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        let mut toggle = 0u8;
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let last = idx + 1 == chunks.len();
+            let unused = u8::try_from(CO_SDO_SEG_SZ - chunk.len()).unwrap_or(0);
+            let mut segment = [0u8; 8];
+            segment[0] = CO_SDO_CCS_DOWNLOAD_SEG | toggle | (unused << 1) | u8::from(last);
+            segment[1..=chunk.len()].copy_from_slice(chunk);
+            let response = self.sdo_request(node, segment)?;
+            if response[0] & 0xE0 != CO_SDO_SCS_DOWNLOAD_SEG || response[0] & 0x10 != toggle {
+                return Err(Self::sdo_protocol_error(node, response));
+            }
+            toggle ^= 0x10;
+        }
+        Ok(())
+    }
+
+    /// Reads and decodes a numeric object after its `data_type`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on SDO failure or when response does not match `data_type`.
+    pub fn sdo_read(
+        &mut self,
+        node: u8,
+        index: u16,
+        sub: u8,
+        data_type: CoDataType,
+    ) -> Result<CanDbcType, CanError> {
+        let data = self.sdo_upload(node, index, sub)?;
+        data_type.decode(&data).ok_or_else(|| {
+            CanError::new(
+                "canopen-sdo-type",
+                format!("node:{node} {index:04x}sub{sub} is not {data_type:?}"),
+            )
+        })
+    }
+
+    /// Encodes `value` after `data_type` and writes it.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on SDO failure or when value does not fit `data_type`.
+    pub fn sdo_write(
+        &mut self,
+        node: u8,
+        index: u16,
+        sub: u8,
+        data_type: CoDataType,
+        value: CanDbcType,
+    ) -> Result<(), CanError> {
+        let data = data_type.encode(value)?;
+        self.sdo_download(node, index, sub, &data)
+    }
+
+    /// Reads PDO mapping from `node`, `map_index` is mapping object (0x1600.. RPDO, 0x1A00.. TPDO).
+    ///
+    /// # Errors
+    /// Returns a `CanError` on SDO failure.
+    pub fn read_pdo_map(&mut self, node: u8, map_index: u16) -> Result<CoPdoMap, CanError> {
+        let comm_index = map_index.wrapping_sub(0x200);
+        let cobid = self.sdo_read(node, comm_index, 1, CoDataType::Unsigned32)?;
+        let transmission = self.sdo_read(node, comm_index, 2, CoDataType::Unsigned8)?;
+        let count = self.sdo_read(node, map_index, 0, CoDataType::Unsigned8)?;
+        let (CanDbcType::U32(cobid), CanDbcType::U8(transmission), CanDbcType::U8(count)) =
+            (cobid, transmission, count)
+        else {
+            return Err(CanError::new("canopen-pdo-invalid", "unexpected PDO parameter type"));
+        };
+
+        let mut map = CoPdoMap::new(cobid);
+        map.set_transmission(transmission);
+        for sub in 1..=count {
+            if let CanDbcType::U32(entry) =
+                self.sdo_read(node, map_index, sub, CoDataType::Unsigned32)?
+            {
+                map.add_entry(CoPdoEntry::from_u32(entry));
+            }
+        }
+        Ok(map)
+    }
+
+    /// Writes PDO mapping into `node` following `CiA` 301 sequence: disable PDO, clear mapping,
+    /// write entries, set entries count then enable PDO with `map` COB-ID.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on SDO failure (eg: object cannot be mapped).
+    pub fn write_pdo_map(
+        &mut self,
+        node: u8,
+        map_index: u16,
+        map: &CoPdoMap,
+    ) -> Result<(), CanError> {
+        let comm_index = map_index.wrapping_sub(0x200);
+        let cobid = map.get_cobid();
+        self.sdo_download(node, comm_index, 1, &(cobid | CO_COBID_INVALID).to_le_bytes())?;
+        self.sdo_download(node, comm_index, 2, &[map.get_transmission()])?;
+        self.sdo_download(node, map_index, 0, &[0])?;
+        let mut count = 0u8;
+        for entry in map.get_entries() {
+            count += 1;
+            self.sdo_download(node, map_index, count, &entry.to_u32().to_le_bytes())?;
+        }
+        self.sdo_download(node, map_index, 0, &[count])?;
+        self.sdo_download(node, comm_index, 1, &cobid.to_le_bytes())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoNodeEvent {
+    BootUp,
+    State(CoNmtState),
+    Timeout,
+}
+
+pub trait CoNodeCtrl {
+    fn node_notification(&self, node: u8, event: &CoNodeEvent);
+}
+
+/// Heartbeat and node guarding consumer. Each node gets a BCM RX job on its NMT error
+/// control COB-ID whose watchdog reports missing heartbeats/guarding answers, node guarding
+/// also gets a BCM TX job sending the remote frame every guard time.
+pub struct CoNodeMonitor {
+    sock: SockCanHandle,
+    states: HashMap<u8, CoNmtState>,
+    expected: Vec<u8>,
+    guarded: Vec<u8>,
+    callback: Option<Box<dyn CoNodeCtrl>>,
+}
+
+impl CoNodeMonitor {
+    /// Opens a BCM socket on `candev`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when socket cannot be opened.
+    pub fn open<T>(candev: T) -> Result<Self, CanError>
+    where
+        SockCanHandle: CanIFaceFrom<T>,
+    {
+        let sock = SockCanHandle::open_bcm(candev, CanTimeStamp::CLASSIC)?;
+        Ok(CoNodeMonitor {
+            sock,
+            states: HashMap::new(),
+            expected: Vec::new(),
+            guarded: Vec::new(),
+            callback: None,
+        })
+    }
+
+    pub fn set_callback(&mut self, callback: Box<dyn CoNodeCtrl>) -> &mut Self {
+        self.callback = Some(callback);
+        self
+    }
+
+    pub fn get_socket(&self) -> &SockCanHandle {
+        &self.sock
+    }
+
+    #[must_use]
+    pub fn get_state(&self, node: u8) -> Option<CoNmtState> {
+        self.states.get(&node).copied()
+    }
+
+    fn rx_setup(&mut self, node: u8, timeout_ms: u64) -> Result<(), CanError> {
+        SockBcmCmd::new(
+            CanBcmOpCode::RxSetup,
+            CanBcmFlag::RX_FILTER_ID
+                | CanBcmFlag::SET_TIMER
+                | CanBcmFlag::START_TIMER
+                | CanBcmFlag::RX_ANNOUNCE_RESUME,
+            CO_COBID_NMT_ERR + u32::from(node),
+        )
+        .set_timers(0, timeout_ms)
+        .apply(&self.sock)?;
+        // timeout is notified even when node never sent any heartbeat
+        if !self.expected.contains(&node) {
+            self.expected.push(node);
+        }
+        Ok(())
+    }
+
+    /// Consumes `node` heartbeat, a timeout is notified when no heartbeat is received
+    /// within `timeout_ms` (usually 1.5 x producer heartbeat time).
+    ///
+    /// # Errors
+    /// Returns a `CanError` when BCM job cannot be created.
+    pub fn add_heartbeat(&mut self, node: u8, timeout_ms: u64) -> Result<&mut Self, CanError> {
+        self.rx_setup(node, timeout_ms)?;
+        Ok(self)
+    }
+
+    /// Guards `node` every `guard_ms`, a timeout is notified when node does not answer
+    /// within `guard_ms * life_factor`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when BCM jobs cannot be created.
+    pub fn add_guarding(
+        &mut self,
+        node: u8,
+        guard_ms: u64,
+        life_factor: u8,
+    ) -> Result<&mut Self, CanError> {
+        let canid = CO_COBID_NMT_ERR + u32::from(node);
+        let rtr = CanFrameRaw::new(canid | FilterMask::RTR_FLAG.bits(), 0, 0, 0, [0; 8]);
+        SockBcmTx::new(canid | FilterMask::RTR_FLAG.bits())
+            .set_cycle(guard_ms)
+            .set_announce(true)
+            .add_frame(CanAnyFrame::RawStd(rtr))
+            .start(&self.sock)?;
+        self.rx_setup(node, guard_ms * u64::from(life_factor.max(1)))?;
+        self.guarded.push(node);
+        Ok(self)
+    }
+
+    /// Stops monitoring `node`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when BCM jobs cannot be removed.
+    pub fn remove(&mut self, node: u8) -> Result<&mut Self, CanError> {
+        let canid = CO_COBID_NMT_ERR + u32::from(node);
+        if let Some(pos) = self.guarded.iter().position(|guarded| *guarded == node) {
+            self.guarded.remove(pos);
+            SockBcmTx::new(canid | FilterMask::RTR_FLAG.bits()).stop(&self.sock)?;
+        }
+        SockBcmCmd::new(CanBcmOpCode::RxDelete, CanBcmFlag::NONE, canid).apply(&self.sock)?;
+        self.expected.retain(|expected| *expected != node);
+        self.states.remove(&node);
+        Ok(self)
+    }
+
+    // applies one NMT error control event (frame content or timeout), returns event to notify
+    fn update(
+        &mut self,
+        canid: u32,
+        opcode: CanBcmOpCode,
+        data: &[u8],
+    ) -> Option<(u8, CoNodeEvent)> {
+        let node = u8::try_from(canid.checked_sub(CO_COBID_NMT_ERR)?)
+            .ok()
+            .filter(|node| *node < 0x80)?;
+        let event = match opcode {
+            CanBcmOpCode::RxTimeout => {
+                if !self.expected.contains(&node) {
+                    return None;
+                }
+                self.states.remove(&node);
+                CoNodeEvent::Timeout
+            },
+            CanBcmOpCode::RxChanged => {
+                let state = CoNmtState::from(*data.first()?);
+                if self.states.insert(node, state) == Some(state) {
+                    return None;
+                }
+                if state == CoNmtState::BootUp {
+                    CoNodeEvent::BootUp
+                } else {
+                    CoNodeEvent::State(state)
+                }
+            },
+            _ => return None,
+        };
+
+        if let Some(callback) = &self.callback {
+            callback.node_notification(node, &event);
+        }
+        Some((node, event))
+    }
+
+    /// Waits for next BCM message and returns node event if any (unchanged state is ignored).
+    pub fn read(&mut self) -> Option<(u8, CoNodeEvent)> {
+        let msg = self.sock.get_bcm_frame();
+        let canid = msg.get_id().ok()?;
+        let data = msg.get_data().map(<[u8]>::to_vec).unwrap_or_default();
+        self.update(canid, msg.get_opcode(), &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // minimal SDO server over a fake transport, serving one object dictionary entry
+    struct SdoServer {
+        node: u8,
+        object: Vec<u8>,
+        responses: VecDeque<(u32, Vec<u8>)>,
+        offset: usize,
+        toggle: u8,
+    }
+
+    impl CoTransport for SdoServer {
+        fn send_frame(&mut self, canid: u32, data: &[u8]) -> Result<(), CanError> {
+            if canid != CO_COBID_SDO_RX + u32::from(self.node) {
+                return Ok(()); // other node does not answer
+            }
+            let mut response = vec![0u8; 8];
+            response[1..4].copy_from_slice(&data[1..4]);
+            match data[0] & 0xE0 {
+                CO_SDO_CCS_UPLOAD => {
+                    response[0] = 0x41;
+                    response[4..8]
+                        .copy_from_slice(&u32::try_from(self.object.len()).unwrap().to_le_bytes());
+                    self.offset = 0;
+                },
+                CO_SDO_CCS_UPLOAD_SEG => {
+                    let chunk: Vec<u8> =
+                        self.object.iter().skip(self.offset).take(7).copied().collect();
+                    self.offset += chunk.len();
+                    let last = u8::from(self.offset >= self.object.len());
+                    response = vec![0u8; 8];
+                    response[0] =
+                        (data[0] & 0x10) | (u8::try_from(7 - chunk.len()).unwrap() << 1) | last;
+                    response[1..=chunk.len()].copy_from_slice(&chunk);
+                },
+                CO_SDO_CCS_DOWNLOAD => {
+                    if data[0] & 0x02 != 0 {
+                        let size = 4 - usize::from((data[0] >> 2) & 0x03);
+                        self.object = data[4..4 + size].to_vec();
+                    } else {
+                        self.object.clear();
+                    }
+                    self.toggle = 0;
+                    response[0] = CO_SDO_SCS_DOWNLOAD;
+                },
+                CO_SDO_CCS_DOWNLOAD_SEG => {
+                    assert_eq!(data[0] & 0x10, self.toggle);
+                    self.toggle ^= 0x10;
+                    let size = 7 - usize::from((data[0] >> 1) & 0x07);
+                    self.object.extend_from_slice(&data[1..=size]);
+                    response =
+                        vec![CO_SDO_SCS_DOWNLOAD_SEG | (data[0] & 0x10), 0, 0, 0, 0, 0, 0, 0];
+                },
+                _ => response[0] = CO_SDO_CS_ABORT,
+            }
+            self.responses.push_back((CO_COBID_SDO_TX + u32::from(self.node), response));
+            Ok(())
+        }
+
+        fn recv_frame(&mut self, _timeout_ms: u64) -> Result<Option<(u32, Vec<u8>)>, CanError> {
+            Ok(self.responses.pop_front())
+        }
+    }
+
+    #[test]
+    fn test_sdo_transfers() {
+        let server = SdoServer {
+            node: 5,
+            object: Vec::new(),
+            responses: VecDeque::new(),
+            offset: 0,
+            toggle: 0,
+        };
+        let mut master = CoMaster::new(server);
+
+        master
+            .sdo_write(5, 0x6060, 0, CoDataType::Integer8, CanDbcType::I8(-3))
+            .unwrap();
+        assert_eq!(master.get_transport().object, [0xFD]);
+
+        let name = b"servo-drive-42 rev B";
+        master.sdo_download(5, 0x1008, 0, name).unwrap();
+        assert_eq!(master.get_transport().object, name);
+        assert_eq!(master.sdo_upload(5, 0x1008, 0).unwrap(), name);
+        master.set_timeout(10);
+        assert!(master.sdo_upload(4, 0x1008, 0).is_err());
+    }
+
+    #[test]
+    fn test_pdo_mapping() {
+        let mut map = CoPdoMap::new(0x185);
+        map.add_entry(CoPdoEntry::new(0x6041, 0, 16))
+            .add_entry(CoPdoEntry::new(0x2000, 1, 4))
+            .add_entry(CoPdoEntry::new(0x2000, 2, 4));
+        assert_eq!(CoPdoEntry::from_u32(0x6041_0010), map.get_entries()[0]);
+
+        let data = map.encode(&[0x0637, 0x0A, 0x05]).unwrap();
+        assert_eq!(data, [0x37, 0x06, 0x5A]);
+        let values: Vec<u64> = map.decode(&data).into_iter().map(|(_, value)| value).collect();
+        assert_eq!(values, [0x0637, 0x0A, 0x05]);
+        assert!(map.encode(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_node_events() {
+        let mut monitor = CoNodeMonitor {
+            sock: SockCanHandle { sockfd: -1, mode: SockCanMod::BCM, callback: None },
+            states: HashMap::new(),
+            expected: vec![5, 6],
+            guarded: Vec::new(),
+            callback: None,
+        };
+        // expected node missing from boot is reported, unknown node is ignored
+        assert_eq!(
+            monitor.update(0x706, CanBcmOpCode::RxTimeout, &[]),
+            Some((6, CoNodeEvent::Timeout))
+        );
+        assert_eq!(monitor.update(0x707, CanBcmOpCode::RxTimeout, &[]), None);
+        assert_eq!(
+            monitor.update(0x705, CanBcmOpCode::RxChanged, &[0x00]),
+            Some((5, CoNodeEvent::BootUp))
+        );
+        assert_eq!(
+            monitor.update(0x705, CanBcmOpCode::RxChanged, &[0x7F]),
+            Some((5, CoNodeEvent::State(CoNmtState::PreOperational)))
+        );
+        // node guarding toggle bit does not change state
+        assert_eq!(monitor.update(0x705, CanBcmOpCode::RxChanged, &[0xFF]), None);
+        assert_eq!(
+            monitor.update(0x705, CanBcmOpCode::RxTimeout, &[]),
+            Some((5, CoNodeEvent::Timeout))
+        );
+        assert_eq!(monitor.get_state(5), None);
+        assert_eq!(monitor.update(0x185, CanBcmOpCode::RxChanged, &[0x05]), None);
+    }
+}
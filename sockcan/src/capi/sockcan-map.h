@@ -43,7 +43,7 @@
 
 #define MAX_BCM_CAN_FRAMES 128
 #define MAX_ISOTP_FRAMES 4096
-#define TIME_STAMP_CTRL_SZ 32
+#define TIME_STAMP_CTRL_SZ 64 // scm_timestamping holds 3 timespec
 
 // force include of few non can_ type
 typedef struct cmsghdr can_cmsghdr;
@@ -107,6 +107,8 @@ enum can_SOCK {
     x_SO_SNDTIMEO=SO_SNDTIMEO,
     x_SO_RCVTIMEO=SO_RCVTIMEO,
     x_MSG_EOR=MSG_EOR,
+    x_MSG_PEEK=MSG_PEEK,
+    x_MSG_DONTWAIT=MSG_DONTWAIT,
     x_MAX_BCM_CAN_FRAMES=MAX_BCM_CAN_FRAMES,
     x_MAX_ISOTP_FRAMES=MAX_ISOTP_FRAMES,
     x_SIOCGIFMTU=SIOCGIFMTU,
@@ -348,4 +350,6 @@ enum can_RAW {
     x_SO_TIMESTAMP_NEW=SO_TIMESTAMP_NEW,
     x_SOF_TIMESTAMPING_RX_HARDWARE=SOF_TIMESTAMPING_RX_HARDWARE,
     x_SOF_TIMESTAMPING_RX_SOFTWARE=SOF_TIMESTAMPING_RX_SOFTWARE,
+    x_SOF_TIMESTAMPING_SOFTWARE=SOF_TIMESTAMPING_SOFTWARE,
+    x_SOF_TIMESTAMPING_RAW_HARDWARE=SOF_TIMESTAMPING_RAW_HARDWARE,
 };
\ No newline at end of file
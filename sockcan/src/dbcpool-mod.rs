@@ -24,6 +24,7 @@ pub enum CanDataStatus {
     Unchanged,
     Error,
     Unset,
+    Inactive,
 }
 
 impl fmt::Display for CanDataStatus {
@@ -34,11 +35,13 @@ impl fmt::Display for CanDataStatus {
             CanDataStatus::Unchanged => "Unchanged",
             CanDataStatus::Error => "Error",
             CanDataStatus::Unset => "Unset",
+            CanDataStatus::Inactive => "Inactive",
         };
         write!(format, "{status}")
     }
 }
 
+#[derive(Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CanDbcType {
     U8(u8),
@@ -49,6 +52,7 @@ pub enum CanDbcType {
     I16(i16),
     I32(i32),
     I64(i64),
+    F32(f32),
     F64(f64),
     Bool(bool),
 }
@@ -56,7 +60,7 @@ pub enum CanDbcType {
 impl fmt::Display for CanDbcType {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         let text = match self {
-            CanDbcType::U8(val) => format!("{val})"),
+            CanDbcType::U8(val) => format!("{val}"),
             CanDbcType::U16(val) => format!("{val}"),
             CanDbcType::U32(val) => format!("{val}"),
             CanDbcType::U64(val) => format!("{val}"),
@@ -65,6 +69,7 @@ impl fmt::Display for CanDbcType {
             CanDbcType::I32(val) => format!("{val}"),
             CanDbcType::I64(val) => format!("{val}"),
             CanDbcType::Bool(val) => format!("{val}"),
+            CanDbcType::F32(val) => format!("{val}"),
             CanDbcType::F64(val) => format!("{val}"),
         };
         fmt.pad(&text)
@@ -83,6 +88,7 @@ impl fmt::Debug for CanDbcType {
             CanDbcType::I32(val) => format!("{val}(i32)"),
             CanDbcType::I64(val) => format!("{val}(i64)"),
             CanDbcType::Bool(val) => format!("{val}(bool)"),
+            CanDbcType::F32(val) => format!("{val}(f32)"),
             CanDbcType::F64(val) => format!("{val}(f64)"),
         };
         fmt.debug_struct(&text).finish()
@@ -144,6 +150,7 @@ to_can_type!(i16, I16);
 to_can_type!(i32, I32);
 to_can_type!(i64, I64);
 to_can_type!(bool, Bool);
+to_can_type!(f32, F32);
 to_can_type!(f64, F64);
 
 pub trait CanSigCtrl {
@@ -158,7 +165,7 @@ pub trait CanDbcSignal {
     /// Returns an error if `value` does not fit into the target layout or if
     /// the destination buffer is too small / misaligned.
     fn set_value(&mut self, value: CanDbcType, data: &mut [u8]) -> Result<(), CanError>;
-    fn get_name(&self) -> &'static str;
+    fn get_name(&self) -> &str;
     fn get_stamp(&self) -> u64;
     fn get_status(&self) -> CanDataStatus;
     fn update(&mut self, frame: &CanMsgData) -> i32;
@@ -189,7 +196,7 @@ pub trait CanDbcMessage {
     fn update(&mut self, data: &CanMsgData) -> Result<(), CanError>;
     fn get_stamp(&self) -> u64;
     fn get_status(&self) -> CanBcmOpCode;
-    fn get_name(&self) -> &'static str;
+    fn get_name(&self) -> &str;
     fn get_signals(&self) -> &[Rc<RefCell<Box<dyn CanDbcSignal>>>];
     fn as_any(&mut self) -> &mut dyn Any;
     /// Resets the internal state of the pool (counters, buffers, caches, etc.).
@@ -201,6 +208,31 @@ pub trait CanDbcMessage {
     fn reset(&mut self) -> Result<(), CanError>;
     fn set_callback(&mut self, callback: Box<dyn CanMsgCtrl>);
     fn get_listeners(&self) -> i32;
+    /// Returns DBC message size in bytes (up to 64 for CAN FD).
+    fn get_len(&self) -> u8;
+    /// Returns CAN FD flags to send this message with, `None` for classic CAN messages.
+    fn get_fd_flags(&self) -> Option<CanFdFlags>;
+    /// Returns J1939 PGN for messages flagged `J1939PG` within the DBC, `None` otherwise.
+    fn get_pgn(&self) -> Option<u32> {
+        None
+    }
+    /// Returns J1939 source address of the last received frame, `None` for non J1939 messages.
+    fn get_src(&self) -> Option<u8> {
+        None
+    }
+
+    /// Builds the frame to send `data` payload (as encoded by `set_values`), a CAN FD frame
+    /// is returned for CAN FD messages.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when payload does not fit within a classic or FD frame.
+    fn get_frame(&self, data: &[u8]) -> Result<CanAnyFrame, CanError> {
+        let len = usize::from(self.get_len()).min(data.len());
+        match self.get_fd_flags() {
+            Some(flags) => generate_fd_frame(self.get_id(), &data[0..len], flags),
+            None => generate_frame(self.get_id(), &data[0..len]),
+        }
+    }
 }
 
 pub trait CanDbcPool {
@@ -227,4 +259,63 @@ pub trait CanDbcPool {
     /// Returns an error if the frame ID is unknown, the payload size
     /// is invalid for the expected message, or decoding fails.
     fn update(&self, data: &CanMsgData) -> Result<RefMut<'_, Box<dyn CanDbcMessage>>, CanError>;
+
+    /// Updates the pool with a J1939 message, J1939 DBC messages match on PGN whatever
+    /// the sender source address is.
+    ///
+    /// # Errors
+    /// Same as [`CanDbcPool::update`].
+    fn update_j1939(
+        &self,
+        msg: &SockJ1939Msg,
+    ) -> Result<RefMut<'_, Box<dyn CanDbcMessage>>, CanError> {
+        self.update(&CanMsgData::try_from(msg)?)
+    }
+
+    /// Subscribes every pool message on a BCM socket with `RX_FILTER_ID` and `flags`
+    /// (timers, ...). CAN FD messages are registered with `FD_FRAME` flag.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when one BCM filter cannot be applied.
+    fn subscribe_bcm(
+        &self,
+        sock: &SockCanHandle,
+        flags: &CanBcmFlag,
+        rate_ms: u64,
+        watchdog_ms: u64,
+    ) -> Result<(), CanError> {
+        for msg in self.get_messages() {
+            let Ok(msg) = msg.try_borrow() else {
+                return Err(CanError::new("message-get", "internal msg pool error"));
+            };
+            bcm_rx_setup(
+                sock,
+                flags,
+                msg.get_id(),
+                msg.get_fd_flags().is_some(),
+                rate_ms,
+                watchdog_ms,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// register one pool message BCM filter, shared by Rc and thread safe pools
+pub(crate) fn bcm_rx_setup(
+    sock: &SockCanHandle,
+    flags: &CanBcmFlag,
+    canid: u32,
+    fd_frame: bool,
+    rate_ms: u64,
+    watchdog_ms: u64,
+) -> Result<(), CanError> {
+    let mut msg_flags = CanBcmFlag::from_bits_retain(flags.bits()) | CanBcmFlag::RX_FILTER_ID;
+    if fd_frame {
+        msg_flags |= CanBcmFlag::FD_FRAME;
+    }
+    SockBcmCmd::new(CanBcmOpCode::RxSetup, msg_flags, canid)
+        .set_timers(rate_ms, watchdog_ms)
+        .apply(sock)?;
+    Ok(())
 }
@@ -0,0 +1,159 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+*/
+
+// Thread safe (Send + Sync) variant of dbcpool traits, as generated by DbcParser::thread_safe(true).
+// Messages/signals are shared through Arc<Mutex<..>> and may be updated from a reader thread
+// while a consumer thread reads them.
+
+use crate::dbcpool::bcm_rx_setup;
+use crate::prelude::*;
+use std::any::Any;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::utils::CanError;
+
+pub trait CanSigCtrlSync: Send + Sync {
+    fn sig_notification(&self, sig: &dyn CanDbcSignalSync) -> i32;
+}
+
+pub trait CanDbcSignalSync: Send {
+    fn get_value(&self) -> CanDbcType;
+    /// Encodes `value` into `data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `value` does not fit into the target layout or if
+    /// the destination buffer is too small / misaligned.
+    fn set_value(&mut self, value: CanDbcType, data: &mut [u8]) -> Result<(), CanError>;
+    fn get_name(&self) -> &str;
+    fn get_stamp(&self) -> u64;
+    fn get_status(&self) -> CanDataStatus;
+    fn update(&mut self, frame: &CanMsgData) -> i32;
+    fn as_any(&mut self) -> &mut dyn Any;
+    fn to_json(&self) -> String;
+    fn reset(&mut self);
+    fn set_callback(&mut self, callback: Box<dyn CanSigCtrlSync>);
+}
+
+pub trait CanMsgCtrlSync: Send + Sync {
+    fn msg_notification(&self, msg: &dyn CanDbcMessageSync);
+}
+
+pub trait CanDbcMessageSync: Send {
+    fn get_id(&self) -> u32;
+    /// Updates message signals from a newly received CAN frame.
+    ///
+    /// # Errors
+    /// Returns a `CanError` if the frame length is invalid for the message or
+    /// when one signal lock is poisoned.
+    fn update(&mut self, data: &CanMsgData) -> Result<(), CanError>;
+    fn get_stamp(&self) -> u64;
+    fn get_status(&self) -> CanBcmOpCode;
+    fn get_name(&self) -> &str;
+    fn get_signals(&self) -> &[Arc<Mutex<Box<dyn CanDbcSignalSync>>>];
+    fn as_any(&mut self) -> &mut dyn Any;
+    /// Resets message and signals internal state.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when one signal lock is poisoned.
+    fn reset(&mut self) -> Result<(), CanError>;
+    fn set_callback(&mut self, callback: Box<dyn CanMsgCtrlSync>);
+    fn get_listeners(&self) -> i32;
+    /// Returns DBC message size in bytes (up to 64 for CAN FD).
+    fn get_len(&self) -> u8;
+    /// Returns CAN FD flags to send this message with, `None` for classic CAN messages.
+    fn get_fd_flags(&self) -> Option<CanFdFlags>;
+    /// Returns J1939 PGN for messages flagged `J1939PG` within the DBC, `None` otherwise.
+    fn get_pgn(&self) -> Option<u32> {
+        None
+    }
+    /// Returns J1939 source address of the last received frame, `None` for non J1939 messages.
+    fn get_src(&self) -> Option<u8> {
+        None
+    }
+
+    /// Builds the frame to send `data` payload, see [`CanDbcMessage::get_frame`].
+    ///
+    /// # Errors
+    /// Returns a `CanError` when payload does not fit within a classic or FD frame.
+    fn get_frame(&self, data: &[u8]) -> Result<CanAnyFrame, CanError> {
+        let len = usize::from(self.get_len()).min(data.len());
+        match self.get_fd_flags() {
+            Some(flags) => generate_fd_frame(self.get_id(), &data[0..len], flags),
+            None => generate_frame(self.get_id(), &data[0..len]),
+        }
+    }
+}
+
+pub trait CanDbcPoolSync: Send + Sync {
+    /// Returns the list of known CAN IDs handled by this pool.
+    fn get_ids(&self) -> &[u32];
+
+    /// Returns the decoded messages managed by this pool.
+    fn get_messages(&self) -> &[Arc<Mutex<Box<dyn CanDbcMessageSync>>>];
+
+    /// Returns a locked reference to the message for `canid`, waiting for an other
+    /// thread holding it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `canid` is not registered in this pool or
+    /// if the message lock is poisoned (a thread panicked while holding it).
+    fn get_mut(&self, canid: u32) -> Result<MutexGuard<'_, Box<dyn CanDbcMessageSync>>, CanError>;
+
+    /// Updates the pool with the provided raw CAN frame.
+    ///
+    /// On success, returns a locked reference to the updated message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame ID is unknown, the payload size
+    /// is invalid for the expected message, or decoding fails.
+    fn update(
+        &self,
+        data: &CanMsgData,
+    ) -> Result<MutexGuard<'_, Box<dyn CanDbcMessageSync>>, CanError>;
+
+    /// Updates the pool with a J1939 message, see [`CanDbcPool::update_j1939`].
+    ///
+    /// # Errors
+    /// Same as [`CanDbcPoolSync::update`].
+    fn update_j1939(
+        &self,
+        msg: &SockJ1939Msg,
+    ) -> Result<MutexGuard<'_, Box<dyn CanDbcMessageSync>>, CanError> {
+        self.update(&CanMsgData::try_from(msg)?)
+    }
+
+    /// Subscribes every pool message on a BCM socket, see [`CanDbcPool::subscribe_bcm`].
+    ///
+    /// # Errors
+    /// Returns a `CanError` when one BCM filter cannot be applied.
+    fn subscribe_bcm(
+        &self,
+        sock: &SockCanHandle,
+        flags: &CanBcmFlag,
+        rate_ms: u64,
+        watchdog_ms: u64,
+    ) -> Result<(), CanError> {
+        for msg in self.get_messages() {
+            let Ok(msg) = msg.lock() else {
+                return Err(CanError::new("message-get", "internal msg pool error"));
+            };
+            bcm_rx_setup(
+                sock,
+                flags,
+                msg.get_id(),
+                msg.get_fd_flags().is_some(),
+                rate_ms,
+                watchdog_ms,
+            )?;
+        }
+        Ok(())
+    }
+}
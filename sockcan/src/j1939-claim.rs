@@ -0,0 +1,469 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    https://www.kernel.org/doc/html/latest/networking/j1939.html (address claiming)
+ *    https://github.com/linux-can/can-utils/blob/master/j1939acd.c
+ *    SAE J1939-81 network management
+ *
+*/
+use super::cglue;
+use crate::prelude::*;
+use std::fmt;
+
+const J1939_CLAIM_TIMEOUT: u64 = 250_000; // 250ms in us (same unit as CanRecvInfo stamp)
+const J1939_DYNAMIC_ADDR_MIN: u8 = 128; // arbitrary address range (J1939-81)
+const J1939_DYNAMIC_ADDR_MAX: u8 = 247;
+
+/// J1939 64 bits NAME builder, values bigger than their bit field are truncated.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct J1939Name {
+    identity: u32,         // 21 bits
+    manufacturer: u16,     // 11 bits
+    ecu_instance: u8,      // 3 bits
+    function_instance: u8, // 5 bits
+    function: u8,          // 8 bits
+    vehicle_system: u8,    // 7 bits
+    vehicle_instance: u8,  // 4 bits
+    industry_group: u8,    // 3 bits
+    arbitrary: bool,       // arbitrary address capable
+}
+
+impl J1939Name {
+    #[must_use]
+    pub fn new() -> Self {
+        J1939Name::default()
+    }
+
+    pub fn set_identity(&mut self, identity: u32) -> &mut Self {
+        self.identity = identity & 0x1F_FFFF;
+        self
+    }
+
+    pub fn set_manufacturer(&mut self, code: u16) -> &mut Self {
+        self.manufacturer = code & 0x7FF;
+        self
+    }
+
+    pub fn set_ecu_instance(&mut self, instance: u8) -> &mut Self {
+        self.ecu_instance = instance & 0x07;
+        self
+    }
+
+    pub fn set_function_instance(&mut self, instance: u8) -> &mut Self {
+        self.function_instance = instance & 0x1F;
+        self
+    }
+
+    pub fn set_function(&mut self, function: u8) -> &mut Self {
+        self.function = function;
+        self
+    }
+
+    pub fn set_vehicle_system(&mut self, system: u8) -> &mut Self {
+        self.vehicle_system = system & 0x7F;
+        self
+    }
+
+    pub fn set_vehicle_instance(&mut self, instance: u8) -> &mut Self {
+        self.vehicle_instance = instance & 0x0F;
+        self
+    }
+
+    pub fn set_industry_group(&mut self, group: u8) -> &mut Self {
+        self.industry_group = group & 0x07;
+        self
+    }
+
+    pub fn set_arbitrary(&mut self, flag: bool) -> &mut Self {
+        self.arbitrary = flag;
+        self
+    }
+
+    #[must_use]
+    pub fn get_identity(&self) -> u32 {
+        self.identity
+    }
+
+    #[must_use]
+    pub fn get_manufacturer(&self) -> u16 {
+        self.manufacturer
+    }
+
+    #[must_use]
+    pub fn get_function(&self) -> u8 {
+        self.function
+    }
+
+    #[must_use]
+    pub fn get_industry_group(&self) -> u8 {
+        self.industry_group
+    }
+
+    #[must_use]
+    pub fn is_arbitrary(&self) -> bool {
+        self.arbitrary
+    }
+
+    /// Returns NAME as sent on the bus (little endian within address claim data)
+    #[must_use]
+    pub fn to_u64(&self) -> u64 {
+        u64::from(self.identity)
+            | u64::from(self.manufacturer) << 21
+            | u64::from(self.ecu_instance) << 32
+            | u64::from(self.function_instance) << 35
+            | u64::from(self.function) << 40
+            | u64::from(self.vehicle_system) << 49
+            | u64::from(self.vehicle_instance) << 56
+            | u64::from(self.industry_group) << 60
+            | u64::from(self.arbitrary) << 63
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn from_u64(name: u64) -> Self {
+        J1939Name {
+            identity: (name & 0x1F_FFFF) as u32,
+            manufacturer: ((name >> 21) & 0x7FF) as u16,
+            ecu_instance: ((name >> 32) & 0x07) as u8,
+            function_instance: ((name >> 35) & 0x1F) as u8,
+            function: ((name >> 40) & 0xFF) as u8,
+            vehicle_system: ((name >> 49) & 0x7F) as u8,
+            vehicle_instance: ((name >> 56) & 0x0F) as u8,
+            industry_group: ((name >> 60) & 0x07) as u8,
+            arbitrary: (name >> 63) == 1,
+        }
+    }
+}
+
+impl fmt::Debug for J1939Name {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format
+            .debug_struct("J1939Name")
+            .field("name", &format_args!("{:#018x}", self.to_u64()))
+            .field("identity", &self.identity)
+            .field("manufacturer", &self.manufacturer)
+            .field("function", &self.function)
+            .field("industry_group", &self.industry_group)
+            .field("arbitrary", &self.arbitrary)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Network address table: which NAME currently owns which source address.
+pub struct J1939AddrTable {
+    names: [Option<u64>; 256],
+}
+
+impl Default for J1939AddrTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl J1939AddrTable {
+    #[must_use]
+    pub fn new() -> Self {
+        J1939AddrTable { names: [None; 256] }
+    }
+
+    /// Registers a received address claim, an `addr` of `IDLE_ADDR` (cannot claim)
+    /// removes `name` from the table.
+    pub fn update(&mut self, addr: u8, name: u64) -> &mut Self {
+        // a NAME owns at most one address
+        for slot in &mut self.names {
+            if *slot == Some(name) {
+                *slot = None;
+            }
+        }
+        if u32::from(addr) <= cglue::can_J1939_x_MAX_UNICAST_ADDR {
+            self.names[usize::from(addr)] = Some(name);
+        }
+        self
+    }
+
+    pub fn remove(&mut self, addr: u8) -> &mut Self {
+        self.names[usize::from(addr)] = None;
+        self
+    }
+
+    #[must_use]
+    pub fn get_name(&self, addr: u8) -> Option<u64> {
+        self.names[usize::from(addr)]
+    }
+
+    #[must_use]
+    pub fn get_addr(&self, name: u64) -> Option<u8> {
+        let idx = self.names.iter().position(|slot| *slot == Some(name))?;
+        u8::try_from(idx).ok()
+    }
+
+    #[must_use]
+    pub fn is_free(&self, addr: u8) -> bool {
+        self.names[usize::from(addr)].is_none()
+    }
+
+    /// Returns (addr, name) of every known node
+    pub fn iter(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        self.names
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, slot)| Some((u8::try_from(idx).ok()?, (*slot)?)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum J1939ClaimState {
+    Idle,
+    Pending(u8),
+    Claimed(u8),
+    CannotClaim,
+}
+
+impl fmt::Display for J1939ClaimState {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            J1939ClaimState::Idle => write!(format, "Idle"),
+            J1939ClaimState::Pending(addr) => write!(format, "Pending({addr:#04x})"),
+            J1939ClaimState::Claimed(addr) => write!(format, "Claimed({addr:#04x})"),
+            J1939ClaimState::CannotClaim => write!(format, "CannotClaim"),
+        }
+    }
+}
+
+/// Address claim state machine (J1939-81). It does not do I/O by itself, every method
+/// returns the source address to (re)claim, that the caller sends with
+/// [`SockCanJ1939::send_addr_claim`], `IDLE_ADDR` meaning cannot claim.
+pub struct J1939AddrClaim {
+    name: J1939Name,
+    preferred: u8,
+    state: J1939ClaimState,
+    stamp: u64,
+    table: J1939AddrTable,
+}
+
+impl J1939AddrClaim {
+    #[must_use]
+    pub fn new(name: J1939Name, preferred: u8) -> Self {
+        J1939AddrClaim {
+            name,
+            preferred,
+            state: J1939ClaimState::Idle,
+            stamp: 0,
+            table: J1939AddrTable::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn get_name(&self) -> &J1939Name {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn get_state(&self) -> J1939ClaimState {
+        self.state
+    }
+
+    #[must_use]
+    pub fn get_table(&self) -> &J1939AddrTable {
+        &self.table
+    }
+
+    /// Returns claimed address once claim timeout elapsed without contention.
+    #[must_use]
+    pub fn get_addr(&self) -> Option<u8> {
+        match self.state {
+            J1939ClaimState::Claimed(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    fn idle_addr() -> u8 {
+        u8::try_from(cglue::can_J1939_x_IDLE_ADDR).unwrap_or(u8::MAX)
+    }
+
+    fn claim(&mut self, addr: u8, now: u64) -> u8 {
+        self.state = J1939ClaimState::Pending(addr);
+        self.stamp = now;
+        addr
+    }
+
+    fn cannot_claim(&mut self) -> u8 {
+        self.state = J1939ClaimState::CannotClaim;
+        Self::idle_addr()
+    }
+
+    // first free address within arbitrary range, excluding lost address
+    fn next_addr(&self, lost: u8) -> Option<u8> {
+        (J1939_DYNAMIC_ADDR_MIN..=J1939_DYNAMIC_ADDR_MAX)
+            .find(|addr| *addr != lost && self.table.is_free(*addr))
+    }
+
+    /// Starts claiming preferred address (or first free arbitrary address when
+    /// already taken by a higher priority NAME).
+    pub fn start(&mut self, now: u64) -> u8 {
+        let name = self.name.to_u64();
+        match self.table.get_name(self.preferred) {
+            Some(owner) if owner < name => match self.next_addr(self.preferred) {
+                Some(addr) if self.name.is_arbitrary() => self.claim(addr, now),
+                _ => self.cannot_claim(),
+            },
+            _ => self.claim(self.preferred, now),
+        }
+    }
+
+    /// Moves pending claim to claimed once 250ms elapsed without contention,
+    /// returns true when address is claimed.
+    pub fn check_timeout(&mut self, now: u64) -> bool {
+        if let J1939ClaimState::Pending(addr) = self.state {
+            if now.saturating_sub(self.stamp) >= J1939_CLAIM_TIMEOUT {
+                self.state = J1939ClaimState::Claimed(addr);
+            }
+        }
+        matches!(self.state, J1939ClaimState::Claimed(_))
+    }
+
+    /// Handles an address claim received from `addr`, returns the address to (re)claim
+    /// on contention. Lowest NAME value wins.
+    pub fn claim_received(&mut self, addr: u8, name: u64, now: u64) -> Option<u8> {
+        self.table.update(addr, name);
+
+        let ours = self.name.to_u64();
+        let current = match self.state {
+            J1939ClaimState::Pending(current) | J1939ClaimState::Claimed(current) => current,
+            J1939ClaimState::Idle | J1939ClaimState::CannotClaim => return None,
+        };
+        if addr != current || name == ours {
+            return None;
+        }
+
+        if ours < name {
+            // we win, defend our address and restore it within table. An already claimed
+            // address only needs address claimed to be sent again.
+            self.table.update(current, ours);
+            if let J1939ClaimState::Claimed(_) = self.state {
+                return Some(current);
+            }
+            return Some(self.claim(current, now));
+        }
+
+        match self.next_addr(current) {
+            Some(next) if self.name.is_arbitrary() => Some(self.claim(next, now)),
+            _ => Some(self.cannot_claim()),
+        }
+    }
+
+    /// Answers a request for address claimed, returns current address or `IDLE_ADDR`.
+    #[must_use]
+    pub fn request_received(&self) -> Option<u8> {
+        match self.state {
+            J1939ClaimState::Idle => None,
+            J1939ClaimState::Pending(addr) | J1939ClaimState::Claimed(addr) => Some(addr),
+            J1939ClaimState::CannotClaim => Some(Self::idle_addr()),
+        }
+    }
+
+    /// Feeds a received J1939 message (address claim or request PGN), returns the address
+    /// to (re)claim when an answer should be sent. `now` uses the same clock as `start` and
+    /// `check_timeout`, kernel receive stamp may be disabled or on another clock.
+    pub fn update(&mut self, msg: &SockJ1939Msg, now: u64) -> Option<u8> {
+        let info = msg.get_info().ok()?;
+        let data = msg.get_data();
+        if info.pgn == cglue::can_J1939_x_PGN_ADDRESS_CLAIMED {
+            let name = u64::from_le_bytes(data.get(0..8)?.try_into().ok()?);
+            self.claim_received(info.src.addr, name, now)
+        } else if info.pgn == cglue::can_J1939_x_PGN_REQUEST {
+            let pgn = u32::from_le_bytes([*data.first()?, *data.get(1)?, *data.get(2)?, 0]);
+            if pgn == cglue::can_J1939_x_PGN_ADDRESS_CLAIMED {
+                self.request_received()
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_encoding() {
+        let mut name = J1939Name::new();
+        name.set_identity(0x12345)
+            .set_manufacturer(0x7FF)
+            .set_function(130)
+            .set_industry_group(4)
+            .set_arbitrary(true);
+        let value = name.to_u64();
+        assert_eq!(value, 0xC000_8200_FFE1_2345);
+        assert_eq!(J1939Name::from_u64(value), name);
+    }
+
+    #[test]
+    fn test_addr_claim_contention() {
+        let mut name = J1939Name::new();
+        name.set_identity(10).set_arbitrary(true);
+        let mut claim = J1939AddrClaim::new(name, 0x80);
+        assert_eq!(claim.start(0), 0x80);
+
+        // higher NAME value loose against us
+        assert_eq!(claim.claim_received(0x80, name.to_u64() + 1, 1000), Some(0x80));
+        assert!(!claim.check_timeout(100_000));
+        assert!(claim.check_timeout(300_000));
+        assert_eq!(claim.get_addr(), Some(0x80));
+
+        // lower NAME value wins, move to next free arbitrary address
+        assert_eq!(claim.claim_received(0x80, 1, 400_000), Some(0x81));
+        assert_eq!(claim.get_table().get_name(0x80), Some(1));
+        assert_eq!(claim.get_state(), J1939ClaimState::Pending(0x81));
+
+        // winning a contention on a claimed address keeps it claimed
+        assert!(claim.check_timeout(700_000));
+        assert_eq!(claim.claim_received(0x81, name.to_u64() + 1, 800_000), Some(0x81));
+        assert_eq!(claim.get_state(), J1939ClaimState::Claimed(0x81));
+        assert_eq!(claim.get_addr(), Some(0x81));
+        assert_eq!(claim.get_table().get_name(0x81), Some(name.to_u64()));
+
+        // non arbitrary address capable ECU cannot claim
+        let mut fixed = J1939AddrClaim::new(*J1939Name::new().set_identity(10), 0x20);
+        fixed.start(0);
+        assert_eq!(fixed.claim_received(0x20, 1, 10), Some(0xFE));
+        assert_eq!(fixed.request_received(), Some(0xFE));
+    }
+
+    #[test]
+    fn test_addr_claim_update() {
+        let mut name = J1939Name::new();
+        name.set_identity(10).set_arbitrary(true);
+        let mut claim = J1939AddrClaim::new(name, 0x80);
+        claim.start(0);
+
+        // kernel stamp is 0 without timestamping, caller clock is used instead
+        let msg = SockJ1939Msg {
+            opcode: SockCanOpCode::RxRead(1u64.to_le_bytes().to_vec()),
+            info: CanRecvInfo {
+                proto: CanProtoInfo::J1939(CanJ1939Info {
+                    src: CanJ1939Header { name: 1, addr: 0x80 },
+                    dst: CanJ1939Header { name: 0, addr: 0xFF },
+                    pgn: cglue::can_J1939_x_PGN_ADDRESS_CLAIMED,
+                    priority: 6,
+                }),
+                stamp: 0,
+                stamp_ns: 0,
+                errno: 0,
+                count: 8,
+                iface: 0,
+            },
+        };
+        assert_eq!(claim.update(&msg, 400_000), Some(0x81));
+        assert!(!claim.check_timeout(500_000));
+        assert!(claim.check_timeout(650_000));
+    }
+}
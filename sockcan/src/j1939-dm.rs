@@ -0,0 +1,556 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    SAE J1939-73 application layer diagnostics (DM1, DM2, DM11)
+ *
+ * Decodes diagnostic messages received from a J1939 socket (kernel or user land transport,
+ * DM1 bigger than 8 bytes uses BAM) and tracks active DTCs per source address:
+ *    let mut table = J1939DtcTable::new();
+ *    table.set_callback(Box::new(MyDtcCtrl {}));
+ *    let msg = sock.get_j1939_frame();
+ *    table.update_j1939(&msg)?;
+ *
+*/
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+pub const PGN_J1939_REQUEST: u32 = 0xEA00;
+pub const PGN_J1939_DM1: u32 = 0xFECA; // active DTCs
+pub const PGN_J1939_DM2: u32 = 0xFECB; // previously active DTCs
+pub const PGN_J1939_DM11: u32 = 0xFED3; // clear/reset active DTCs
+
+const J1939_ADDR_BROADCAST: u8 = 0xFF;
+const J1939_DTC_SZ: usize = 4;
+const J1939_DM_MIN_SZ: usize = 8;
+
+/// Lamp status (2 bits), as reported by DM1/DM2 first byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum J1939LampStatus {
+    Off,
+    On,
+    Error,
+    NotAvailable,
+}
+
+impl J1939LampStatus {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => J1939LampStatus::Off,
+            1 => J1939LampStatus::On,
+            2 => J1939LampStatus::Error,
+            _ => J1939LampStatus::NotAvailable,
+        }
+    }
+
+    fn to_bits(self) -> u8 {
+        match self {
+            J1939LampStatus::Off => 0,
+            J1939LampStatus::On => 1,
+            J1939LampStatus::Error => 2,
+            J1939LampStatus::NotAvailable => 3,
+        }
+    }
+}
+
+/// Lamp flash status (2 bits), as reported by DM1/DM2 second byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum J1939LampFlash {
+    Slow,
+    Fast,
+    Reserved,
+    Off,
+}
+
+impl J1939LampFlash {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => J1939LampFlash::Slow,
+            1 => J1939LampFlash::Fast,
+            2 => J1939LampFlash::Reserved,
+            _ => J1939LampFlash::Off,
+        }
+    }
+
+    fn to_bits(self) -> u8 {
+        match self {
+            J1939LampFlash::Slow => 0,
+            J1939LampFlash::Fast => 1,
+            J1939LampFlash::Reserved => 2,
+            J1939LampFlash::Off => 3,
+        }
+    }
+}
+
+/// Malfunction indicator, red stop, amber warning and protect lamps with their flash status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct J1939Lamps {
+    pub mil: J1939LampStatus,
+    pub red_stop: J1939LampStatus,
+    pub amber_warning: J1939LampStatus,
+    pub protect: J1939LampStatus,
+    pub mil_flash: J1939LampFlash,
+    pub red_stop_flash: J1939LampFlash,
+    pub amber_warning_flash: J1939LampFlash,
+    pub protect_flash: J1939LampFlash,
+}
+
+impl Default for J1939Lamps {
+    fn default() -> Self {
+        J1939Lamps {
+            mil: J1939LampStatus::Off,
+            red_stop: J1939LampStatus::Off,
+            amber_warning: J1939LampStatus::Off,
+            protect: J1939LampStatus::Off,
+            mil_flash: J1939LampFlash::Off,
+            red_stop_flash: J1939LampFlash::Off,
+            amber_warning_flash: J1939LampFlash::Off,
+            protect_flash: J1939LampFlash::Off,
+        }
+    }
+}
+
+impl J1939Lamps {
+    #[must_use]
+    pub fn from_bytes(status: u8, flash: u8) -> Self {
+        J1939Lamps {
+            mil: J1939LampStatus::from_bits(status >> 6),
+            red_stop: J1939LampStatus::from_bits(status >> 4),
+            amber_warning: J1939LampStatus::from_bits(status >> 2),
+            protect: J1939LampStatus::from_bits(status),
+            mil_flash: J1939LampFlash::from_bits(flash >> 6),
+            red_stop_flash: J1939LampFlash::from_bits(flash >> 4),
+            amber_warning_flash: J1939LampFlash::from_bits(flash >> 2),
+            protect_flash: J1939LampFlash::from_bits(flash),
+        }
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; 2] {
+        [
+            (self.mil.to_bits() << 6)
+                | (self.red_stop.to_bits() << 4)
+                | (self.amber_warning.to_bits() << 2)
+                | self.protect.to_bits(),
+            (self.mil_flash.to_bits() << 6)
+                | (self.red_stop_flash.to_bits() << 4)
+                | (self.amber_warning_flash.to_bits() << 2)
+                | self.protect_flash.to_bits(),
+        ]
+    }
+}
+
+/// Diagnostic trouble code: suspect parameter number, failure mode identifier and
+/// occurrence count. `cm` is the SPN conversion method bit, when set SPN uses the
+/// legacy (version 1) most significant byte first layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct J1939Dtc {
+    pub spn: u32,
+    pub fmi: u8,
+    pub occurrence: u8,
+    pub cm: bool,
+}
+
+impl J1939Dtc {
+    #[must_use]
+    pub fn new(spn: u32, fmi: u8) -> Self {
+        J1939Dtc { spn: spn & 0x7_FFFF, fmi: fmi & 0x1F, occurrence: 0, cm: false }
+    }
+
+    pub fn set_occurrence(&mut self, count: u8) -> &mut Self {
+        self.occurrence = count & 0x7F;
+        self
+    }
+
+    /// Decodes one 4 bytes DTC, returns None for "no DTC" (all zero) and padding (all ones) slots.
+    #[must_use]
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let bytes: [u8; J1939_DTC_SZ] = data.get(0..J1939_DTC_SZ)?.try_into().ok()?;
+        if bytes == [0; 4] || bytes == [0xFF; 4] {
+            return None;
+        }
+        let cm = bytes[3] & 0x80 != 0;
+        let spn = if cm {
+            (u32::from(bytes[0]) << 11) | (u32::from(bytes[1]) << 3) | u32::from(bytes[2] >> 5)
+        } else {
+            u32::from(bytes[0]) | (u32::from(bytes[1]) << 8) | (u32::from(bytes[2] >> 5) << 16)
+        };
+        Some(J1939Dtc { spn, fmi: bytes[2] & 0x1F, occurrence: bytes[3] & 0x7F, cm })
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let spn = self.spn & 0x7_FFFF;
+        let (byte0, byte1, spn_high) = if self.cm {
+            (
+                (spn >> 11).to_le_bytes()[0],
+                (spn >> 3).to_le_bytes()[0],
+                (spn & 0x07).to_le_bytes()[0],
+            )
+        } else {
+            let bytes = spn.to_le_bytes();
+            (bytes[0], bytes[1], bytes[2])
+        };
+        [
+            byte0,
+            byte1,
+            (spn_high << 5) | (self.fmi & 0x1F),
+            (u8::from(self.cm) << 7) | (self.occurrence & 0x7F),
+        ]
+    }
+
+    // same fault whatever its occurrence count
+    fn is_same(self, other: J1939Dtc) -> bool {
+        self.spn == other.spn && self.fmi == other.fmi
+    }
+}
+
+impl fmt::Display for J1939Dtc {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(format, "SPN:{} FMI:{} OC:{}", self.spn, self.fmi, self.occurrence)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum J1939DmKind {
+    Dm1,
+    Dm2,
+    Dm11,
+}
+
+impl J1939DmKind {
+    #[must_use]
+    pub fn get_pgn(&self) -> u32 {
+        match self {
+            J1939DmKind::Dm1 => PGN_J1939_DM1,
+            J1939DmKind::Dm2 => PGN_J1939_DM2,
+            J1939DmKind::Dm11 => PGN_J1939_DM11,
+        }
+    }
+}
+
+/// Decoded DM1/DM2 (lamps + DTC list) or DM11 (clear active DTCs of `dst`) message.
+#[derive(Clone, Debug)]
+pub struct J1939DmMsg {
+    pub kind: J1939DmKind,
+    pub src: u8,
+    pub dst: u8,
+    pub stamp: u64,
+    pub lamps: J1939Lamps,
+    pub dtcs: Vec<J1939Dtc>,
+}
+
+impl J1939DmMsg {
+    #[must_use]
+    pub fn new(kind: J1939DmKind) -> Self {
+        J1939DmMsg {
+            kind,
+            src: J1939_ADDR_BROADCAST,
+            dst: J1939_ADDR_BROADCAST,
+            stamp: 0,
+            lamps: J1939Lamps::default(),
+            dtcs: Vec::new(),
+        }
+    }
+
+    /// Decodes a DM payload, DM11 is accepted either as its own PGN or as a request PGN
+    /// asking for DM11.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when PGN is not a supported DM or payload is too short.
+    pub fn decode(pgn: u32, data: &[u8]) -> Result<Self, CanError> {
+        let kind = match pgn {
+            PGN_J1939_DM1 => J1939DmKind::Dm1,
+            PGN_J1939_DM2 => J1939DmKind::Dm2,
+            PGN_J1939_DM11 => J1939DmKind::Dm11,
+            PGN_J1939_REQUEST if j1939_requested_pgn(data) == Some(PGN_J1939_DM11) => {
+                J1939DmKind::Dm11
+            },
+            _ => {
+                return Err(CanError::new(
+                    "j1939-dm-invalid-pgn",
+                    format!("pgn:{pgn:#06x} is not a supported DM"),
+                ))
+            },
+        };
+
+        let mut msg = J1939DmMsg::new(kind);
+        if kind == J1939DmKind::Dm11 {
+            return Ok(msg);
+        }
+        if data.len() < 2 {
+            return Err(CanError::new(
+                "j1939-dm-invalid-len",
+                format!("{kind:?} len:{} too short", data.len()),
+            ));
+        }
+        msg.lamps = J1939Lamps::from_bytes(data[0], data[1]);
+        msg.dtcs = data[2..].chunks_exact(J1939_DTC_SZ).filter_map(J1939Dtc::from_bytes).collect();
+        Ok(msg)
+    }
+
+    /// Decodes a received J1939 message, source/destination and stamp come from message info.
+    ///
+    /// # Errors
+    /// Same as [`J1939DmMsg::decode`], or when message has no J1939 info.
+    pub fn from_msg(msg: &SockJ1939Msg) -> Result<Self, CanError> {
+        let info = msg.get_info()?;
+        let mut dm = J1939DmMsg::decode(info.pgn, msg.get_data())?;
+        dm.src = info.src.addr;
+        dm.dst = info.dst.addr;
+        dm.stamp = msg.get_stamp();
+        Ok(dm)
+    }
+
+    /// Encodes DM1/DM2 payload (at least 8 bytes, 0xFF padded), payloads bigger than 8
+    /// bytes are sent through J1939 transport. DM11 payload is all 0xFF.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(2 + J1939_DTC_SZ * self.dtcs.len().max(1));
+        if self.kind != J1939DmKind::Dm11 {
+            data.extend_from_slice(&self.lamps.to_bytes());
+            if self.dtcs.is_empty() {
+                data.extend_from_slice(&[0; J1939_DTC_SZ]);
+            }
+            for dtc in &self.dtcs {
+                data.extend_from_slice(&dtc.to_bytes());
+            }
+        }
+        if data.len() < J1939_DM_MIN_SZ {
+            data.resize(J1939_DM_MIN_SZ, 0xFF);
+        }
+        data
+    }
+}
+
+/// Builds a request PGN (0xEA00) payload, eg: DM2 which is only sent on request, or DM11.
+#[must_use]
+pub fn j1939_request(pgn: u32) -> [u8; 3] {
+    let bytes = pgn.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+fn j1939_requested_pgn(data: &[u8]) -> Option<u32> {
+    let bytes = data.get(0..3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum J1939DtcEvent {
+    Raised(J1939Dtc),
+    Updated(J1939Dtc), // occurrence count or conversion method changed
+    Cleared(J1939Dtc),
+    Lamps(J1939Lamps),
+}
+
+pub trait J1939DtcCtrl {
+    fn dtc_notification(&self, src: u8, event: &J1939DtcEvent);
+}
+
+#[derive(Default)]
+struct J1939DtcNode {
+    lamps: J1939Lamps,
+    active: Vec<J1939Dtc>,
+    previous: Vec<J1939Dtc>,
+    stamp: u64,
+}
+
+/// Active (DM1) and previously active (DM2) DTCs per source address. Every DM1 is compared
+/// with the previous one from the same source, raised/cleared DTCs and lamp changes are
+/// notified. DM11 clears active DTCs of its destination (every source when global).
+#[derive(Default)]
+pub struct J1939DtcTable {
+    nodes: HashMap<u8, J1939DtcNode>,
+    callback: Option<Box<dyn J1939DtcCtrl>>,
+}
+
+impl J1939DtcTable {
+    #[must_use]
+    pub fn new() -> Self {
+        J1939DtcTable::default()
+    }
+
+    pub fn set_callback(&mut self, callback: Box<dyn J1939DtcCtrl>) -> &mut Self {
+        self.callback = Some(callback);
+        self
+    }
+
+    /// Applies one decoded DM, returns (source address, event) list also sent to callback.
+    pub fn update(&mut self, msg: &J1939DmMsg) -> Vec<(u8, J1939DtcEvent)> {
+        let mut events = Vec::new();
+        match msg.kind {
+            J1939DmKind::Dm1 => {
+                let node = self.nodes.entry(msg.src).or_default();
+                node.stamp = msg.stamp;
+                if node.lamps != msg.lamps {
+                    node.lamps = msg.lamps;
+                    events.push((msg.src, J1939DtcEvent::Lamps(msg.lamps)));
+                }
+                for dtc in &node.active {
+                    if !msg.dtcs.iter().any(|new| new.is_same(*dtc)) {
+                        events.push((msg.src, J1939DtcEvent::Cleared(*dtc)));
+                    }
+                }
+                for dtc in &msg.dtcs {
+                    match node.active.iter().find(|old| old.is_same(*dtc)) {
+                        None => events.push((msg.src, J1939DtcEvent::Raised(*dtc))),
+                        Some(old) if old != dtc => {
+                            events.push((msg.src, J1939DtcEvent::Updated(*dtc)));
+                        },
+                        Some(_) => {},
+                    }
+                }
+                node.active.clone_from(&msg.dtcs);
+            },
+            J1939DmKind::Dm2 => {
+                let node = self.nodes.entry(msg.src).or_default();
+                node.stamp = msg.stamp;
+                node.previous.clone_from(&msg.dtcs);
+            },
+            J1939DmKind::Dm11 => {
+                for (src, node) in &mut self.nodes {
+                    if msg.dst != J1939_ADDR_BROADCAST && msg.dst != *src {
+                        continue;
+                    }
+                    for dtc in node.active.drain(..) {
+                        events.push((*src, J1939DtcEvent::Cleared(dtc)));
+                    }
+                }
+            },
+        }
+
+        if let Some(callback) = &self.callback {
+            for (src, event) in &events {
+                callback.dtc_notification(*src, event);
+            }
+        }
+        events
+    }
+
+    /// Decodes and applies a received J1939 message, non DM messages are ignored.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when a DM payload is invalid.
+    pub fn update_j1939(
+        &mut self,
+        msg: &SockJ1939Msg,
+    ) -> Result<Vec<(u8, J1939DtcEvent)>, CanError> {
+        match msg.get_pgn() {
+            PGN_J1939_DM1 | PGN_J1939_DM2 | PGN_J1939_DM11 | PGN_J1939_REQUEST => {},
+            _ => return Ok(Vec::new()),
+        }
+        match J1939DmMsg::from_msg(msg) {
+            Ok(dm) => Ok(self.update(&dm)),
+            Err(error) if error.get_uid() == "j1939-dm-invalid-pgn" => Ok(Vec::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    #[must_use]
+    pub fn get_active(&self, src: u8) -> &[J1939Dtc] {
+        self.nodes.get(&src).map_or(&[], |node| node.active.as_slice())
+    }
+
+    #[must_use]
+    pub fn get_previous(&self, src: u8) -> &[J1939Dtc] {
+        self.nodes.get(&src).map_or(&[], |node| node.previous.as_slice())
+    }
+
+    #[must_use]
+    pub fn get_lamps(&self, src: u8) -> Option<J1939Lamps> {
+        self.nodes.get(&src).map(|node| node.lamps)
+    }
+
+    /// Returns stamp of last DM received from `src`
+    #[must_use]
+    pub fn get_stamp(&self, src: u8) -> Option<u64> {
+        self.nodes.get(&src).map(|node| node.stamp)
+    }
+
+    pub fn remove(&mut self, src: u8) -> &mut Self {
+        self.nodes.remove(&src);
+        self
+    }
+
+    /// Returns (source address, active DTCs) of every known node
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &[J1939Dtc])> + '_ {
+        self.nodes.iter().map(|(src, node)| (*src, node.active.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dm1_decode() {
+        // amber lamp on, 2 DTCs (multi-packet): SPN 100 FMI 1 OC 3, SPN 0x7FFFE FMI 31 OC 127
+        let data = [0x04, 0xFF, 0x64, 0x00, 0x01, 0x03, 0xFE, 0xFF, 0xFF, 0x7F, 0xFF, 0xFF];
+        let msg = J1939DmMsg::decode(PGN_J1939_DM1, &data).unwrap();
+        assert_eq!(msg.kind, J1939DmKind::Dm1);
+        assert_eq!(msg.lamps.amber_warning, J1939LampStatus::On);
+        assert_eq!(msg.lamps.mil, J1939LampStatus::Off);
+        assert_eq!(msg.lamps.mil_flash, J1939LampFlash::Off);
+        assert_eq!(msg.dtcs.len(), 2);
+        assert_eq!(msg.dtcs[0], *J1939Dtc::new(100, 1).set_occurrence(3));
+        assert_eq!(msg.dtcs[1], *J1939Dtc::new(0x7_FFFE, 31).set_occurrence(127));
+        assert_eq!(msg.encode()[..10], data[..10]);
+
+        // conversion method 1 layout
+        let mut dtc = J1939Dtc::new(0x1_2345, 4);
+        dtc.cm = true;
+        assert_eq!(J1939Dtc::from_bytes(&dtc.to_bytes()), Some(dtc));
+
+        // no active DTC
+        let empty = J1939DmMsg::decode(PGN_J1939_DM1, &[0, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF]).unwrap();
+        assert!(empty.dtcs.is_empty());
+        assert_eq!(empty.encode(), [0, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF]);
+
+        let dm11 = J1939DmMsg::decode(PGN_J1939_REQUEST, &j1939_request(PGN_J1939_DM11)).unwrap();
+        assert_eq!(dm11.kind, J1939DmKind::Dm11);
+        assert!(J1939DmMsg::decode(PGN_J1939_REQUEST, &j1939_request(PGN_J1939_DM2)).is_err());
+    }
+
+    #[test]
+    fn test_dtc_table() {
+        let mut table = J1939DtcTable::new();
+        let mut dm1 = J1939DmMsg::new(J1939DmKind::Dm1);
+        dm1.src = 0x00;
+        dm1.dtcs = vec![J1939Dtc::new(100, 1), J1939Dtc::new(110, 0)];
+        let events = table.update(&dm1);
+        assert_eq!(events.len(), 2);
+        assert!(events
+            .iter()
+            .all(|(src, event)| *src == 0 && matches!(event, J1939DtcEvent::Raised(_))));
+
+        // same DM1 from an other source does not interfere
+        dm1.src = 0x03;
+        assert_eq!(table.update(&dm1).len(), 2);
+
+        dm1.src = 0x00;
+        dm1.lamps.amber_warning = J1939LampStatus::On;
+        dm1.dtcs = vec![*J1939Dtc::new(110, 0).set_occurrence(2)];
+        let events = table.update(&dm1);
+        assert_eq!(
+            events,
+            vec![
+                (0, J1939DtcEvent::Lamps(dm1.lamps)),
+                (0, J1939DtcEvent::Cleared(J1939Dtc::new(100, 1))),
+                (0, J1939DtcEvent::Updated(dm1.dtcs[0])),
+            ]
+        );
+        assert_eq!(table.get_active(0), dm1.dtcs.as_slice());
+        assert!(table.update(&dm1).is_empty());
+
+        let mut dm11 = J1939DmMsg::new(J1939DmKind::Dm11);
+        dm11.dst = 0x03;
+        assert_eq!(table.update(&dm11).len(), 2);
+        assert!(table.get_active(3).is_empty());
+        assert_eq!(table.get_active(0).len(), 1);
+    }
+}
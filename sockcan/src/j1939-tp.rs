@@ -0,0 +1,491 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    SAE J1939-21 transport protocol (TP.CM/TP.DT) and extended transport protocol (ETP)
+ *    https://www.kernel.org/doc/html/latest/networking/j1939.html
+ *
+ * User land J1939 transport for kernels without can-j1939 module. Open a RAW socket,
+ * register SockJ1939Tp as callback and read messages with `get_j1939_frame`:
+ *    let mut sock = SockCanHandle::open_raw("can0", CanTimeStamp::CLASSIC)?;
+ *    let mut transport = SockJ1939Tp::new(&sock);
+ *    transport.set_addr(0x80); // answer RTS for our address, default passive
+ *    sock.set_callback(Box::new(transport));
+ *
+*/
+use super::cglue;
+use crate::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const PGN_J1939_TP_CM: u32 = 0xEC00;
+pub const PGN_J1939_TP_DT: u32 = 0xEB00;
+pub const PGN_J1939_ETP_CM: u32 = 0xC800;
+pub const PGN_J1939_ETP_DT: u32 = 0xC700;
+
+const TP_CM_RTS: u8 = 16;
+const TP_CM_CTS: u8 = 17;
+const TP_CM_EOMA: u8 = 19;
+const TP_CM_BAM: u8 = 32;
+const ETP_CM_RTS: u8 = 20;
+const ETP_CM_CTS: u8 = 21;
+const ETP_CM_DPO: u8 = 22;
+const ETP_CM_EOMA: u8 = 23;
+const TP_CM_ABORT: u8 = 255;
+const TP_ABORT_TIMEOUT: u8 = 3;
+const TP_ABORT_BAD_SEQUENCE: u8 = 7;
+const TP_ABORT_OTHER: u8 = 250;
+
+// TP carries 9..=1785 bytes (255 packets), larger messages use ETP
+const TP_MIN_SIZE: usize = 9;
+const TP_MAX_SIZE: usize = 1785;
+const ETP_MAX_SIZE: usize = 117_440_505;
+
+const J1939_TP_PRIO: u8 = 7;
+const J1939_TP_TIMEOUT: Duration = Duration::from_millis(1250); // T2/T3
+const J1939_DT_SZ: usize = 7;
+const J1939_ADDR_BROADCAST: u8 = 0xFF;
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+const CAN_FRAME_SZ: usize = 16; // struct can_frame: canid[4] len[1] pad[3] data[8]
+
+/// Decodes J1939 header (pgn, src, dst) from a 29 bits canid
+#[must_use]
+pub fn j1939_raw_info(canid: u32) -> CanJ1939Info {
+    let canid = canid & CAN_EFF_MASK;
+    let src = u8::try_from(canid & 0xFF).unwrap_or(J1939_ADDR_BROADCAST);
+    let mut pgn = (canid >> 8) & 0x3_FFFF;
+    let pdu_format = (pgn >> 8) & 0xFF;
+    let dst = if pdu_format < 240 {
+        // PDU1 destination specific, PS field is the destination address
+        let dst = u8::try_from(pgn & 0xFF).unwrap_or(J1939_ADDR_BROADCAST);
+        pgn &= 0x3_FF00;
+        dst
+    } else {
+        J1939_ADDR_BROADCAST
+    };
+    CanJ1939Info {
+        src: CanJ1939Header { name: 0, addr: src },
+        dst: CanJ1939Header { name: 0, addr: dst },
+        pgn,
+        priority: u8::try_from((canid >> 26) & 0x07).unwrap_or(J1939_TP_PRIO),
+    }
+}
+
+/// Returns J1939 PGN carried by a received canid, None for 11 bits standard ids
+#[must_use]
+pub fn j1939_canid_pgn(canid: u32) -> Option<u32> {
+    if canid & CAN_EFF_FLAG == 0 && canid <= 0x7FF {
+        return None;
+    }
+    Some(j1939_raw_info(canid).pgn)
+}
+
+/// Builds extended canid (with EFF flag) from J1939 priority/pgn/addresses
+#[must_use]
+pub fn j1939_raw_canid(priority: u8, pgn: u32, dst: u8, src: u8) -> u32 {
+    let mut pgn = pgn & 0x3_FFFF;
+    if (pgn >> 8) & 0xFF < 240 {
+        pgn = (pgn & 0x3_FF00) | u32::from(dst);
+    }
+    (u32::from(priority & 0x07) << 26) | (pgn << 8) | u32::from(src) | CAN_EFF_FLAG
+}
+
+// rewrites a RAW can_frame in place as J1939 payload + J1939 info, false when not an extended frame
+pub(crate) fn j1939_raw_frame(buffer: &mut [u8], info: &mut CanRecvInfo) -> bool {
+    if usize::try_from(info.count).unwrap_or(0) < CAN_FRAME_SZ || buffer.len() < CAN_FRAME_SZ {
+        return false;
+    }
+    let canid = u32::from_ne_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+    if canid & CAN_EFF_FLAG == 0 {
+        return false;
+    }
+    let len = usize::from(buffer[4]).min(8);
+    buffer.copy_within(8..8 + len, 0);
+    info.count = isize::try_from(len).unwrap_or(0);
+    info.proto = CanProtoInfo::J1939(j1939_raw_info(canid));
+    true
+}
+
+struct J1939TpSession {
+    pgn: u32,
+    size: usize,
+    packets: u32,
+    next: u32,    // next expected packet number (1..)
+    window: u32,  // last packet number of current CTS window
+    max_cts: u32, // sender max packets per CTS
+    offset: u32,  // ETP data packet offset
+    extended: bool,
+    priority: u8, // connection management priority, transported message has none on the wire
+    data: Vec<u8>,
+    stamp: Instant,
+}
+
+/// J1939 TP (BAM, RTS/CTS) and ETP reassembler for RAW sockets
+pub struct SockJ1939Tp {
+    sockfd: i32,
+    addr: Option<u8>,
+    sessions: RefCell<HashMap<(u8, u8), J1939TpSession>>,
+    completed: Cell<Option<CanJ1939Info>>,
+}
+
+impl SockJ1939Tp {
+    /// Passive reassembler, `sock` is only used to answer RTS once `set_addr` is called
+    #[must_use]
+    pub fn new(sock: &SockCanHandle) -> Self {
+        SockJ1939Tp {
+            sockfd: sock.sockfd,
+            addr: None,
+            sessions: RefCell::new(HashMap::new()),
+            completed: Cell::new(None),
+        }
+    }
+
+    /// Local address, RTS sent to this address are answered with CTS/EOMA
+    pub fn set_addr(&mut self, addr: u8) -> &mut Self {
+        self.addr = Some(addr);
+        self
+    }
+
+    #[must_use]
+    pub fn get_sessions(&self) -> usize {
+        self.sessions.borrow().len()
+    }
+
+    fn is_receiver(&self, dst: u8) -> bool {
+        self.addr == Some(dst)
+    }
+
+    fn send_cm(&self, extended: bool, dst: u8, data: [u8; 8]) {
+        let Some(src) = self.addr else {
+            return;
+        };
+        let pgn = if extended { PGN_J1939_ETP_CM } else { PGN_J1939_TP_CM };
+        let canid = j1939_raw_canid(J1939_TP_PRIO, pgn, dst, src);
+        let frame = CanFrameRaw::new(canid, 8, 0, 0, data);
+        let count = unsafe {
+            cglue::write(self.sockfd, frame.as_ptr(), std::mem::size_of::<CanFrameRaw>())
+        };
+        if count < 0 {
+            log::warn!("j1939-tp fail to send cm:{:#04x} to:{dst:#04x}", data[0]);
+        }
+    }
+
+    // TP.CM CTS or ETP.CM CTS for next window
+    fn send_cts(&self, src: u8, session: &mut J1939TpSession) {
+        let count = (session.packets + 1 - session.next).min(session.max_cts).min(255);
+        session.window = session.next + count - 1;
+        let pgn = session.pgn.to_le_bytes();
+        let count = u8::try_from(count).unwrap_or(255);
+        let data = if session.extended {
+            let next = session.next.to_le_bytes();
+            [ETP_CM_CTS, count, next[0], next[1], next[2], pgn[0], pgn[1], pgn[2]]
+        } else {
+            let next = u8::try_from(session.next).unwrap_or(255);
+            [TP_CM_CTS, count, next, 0xFF, 0xFF, pgn[0], pgn[1], pgn[2]]
+        };
+        self.send_cm(session.extended, src, data);
+    }
+
+    fn send_eoma(&self, src: u8, session: &J1939TpSession) {
+        let pgn = session.pgn.to_le_bytes();
+        let size = u32::try_from(session.size).unwrap_or(0).to_le_bytes();
+        let data = if session.extended {
+            [ETP_CM_EOMA, size[0], size[1], size[2], size[3], pgn[0], pgn[1], pgn[2]]
+        } else {
+            let packets = u8::try_from(session.packets).unwrap_or(255);
+            [TP_CM_EOMA, size[0], size[1], packets, 0xFF, pgn[0], pgn[1], pgn[2]]
+        };
+        self.send_cm(session.extended, src, data);
+    }
+
+    fn send_abort(&self, src: u8, extended: bool, pgn: u32, reason: u8) {
+        let pgn = pgn.to_le_bytes();
+        self.send_cm(
+            extended,
+            src,
+            [TP_CM_ABORT, reason, 0xFF, 0xFF, 0xFF, pgn[0], pgn[1], pgn[2]],
+        );
+    }
+
+    // drop sessions without traffic for more than T2/T3
+    fn expire(&self) {
+        let mut sessions = self.sessions.borrow_mut();
+        let expired: Vec<(u8, u8)> = sessions
+            .iter()
+            .filter(|(_, session)| session.stamp.elapsed() > J1939_TP_TIMEOUT)
+            .map(|(key, _)| *key)
+            .collect();
+        for (src, dst) in expired {
+            if let Some(session) = sessions.remove(&(src, dst)) {
+                log::warn!("j1939-tp pgn:{:#x} src:{src:#04x} timeout", session.pgn);
+                if self.is_receiver(dst) {
+                    self.send_abort(src, session.extended, session.pgn, TP_ABORT_TIMEOUT);
+                }
+            }
+        }
+    }
+
+    fn control(&self, extended: bool, info: &CanJ1939Info, data: &[u8]) -> SockCanOpCode {
+        let (src, dst) = (info.src.addr, info.dst.addr);
+        let pgn = u32::from_le_bytes([data[5], data[6], data[7], 0]);
+        let (size, packets, max_cts) = match (extended, data[0]) {
+            (false, TP_CM_RTS | TP_CM_BAM) => {
+                let size = usize::from(u16::from_le_bytes([data[1], data[2]]));
+                let max_cts = if data[0] == TP_CM_BAM { 255 } else { u32::from(data[4]) };
+                (size, u32::from(data[3]), max_cts)
+            },
+            (true, ETP_CM_RTS) => {
+                let size = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+                let size = usize::try_from(size).unwrap_or(usize::MAX);
+                let packets = u32::try_from(size.div_ceil(J1939_DT_SZ)).unwrap_or(u32::MAX);
+                (size, packets, 255)
+            },
+            (true, ETP_CM_DPO) => {
+                let mut sessions = self.sessions.borrow_mut();
+                if let Some(session) = sessions.get_mut(&(src, dst)) {
+                    session.offset = u32::from_le_bytes([data[2], data[3], data[4], 0]);
+                    session.stamp = Instant::now();
+                }
+                return SockCanOpCode::RxIgnore;
+            },
+            (_, TP_CM_ABORT) => {
+                let mut sessions = self.sessions.borrow_mut();
+                let removed = sessions.remove(&(src, dst)).is_some()
+                    || sessions.remove(&(dst, src)).is_some();
+                if !removed {
+                    return SockCanOpCode::RxIgnore;
+                }
+                return SockCanOpCode::RxError(CanError::new(
+                    "j1939-tp-abort",
+                    format!("pgn:{pgn:#x} src:{src:#04x} aborted reason:{}", data[1]),
+                ));
+            },
+            // CTS/EOMA are sent by receiver, nothing to reassemble
+            _ => return SockCanOpCode::RxIgnore,
+        };
+
+        let range =
+            if extended { TP_MAX_SIZE + 1..=ETP_MAX_SIZE } else { TP_MIN_SIZE..=TP_MAX_SIZE };
+        let expected = u32::try_from(size.div_ceil(J1939_DT_SZ)).unwrap_or(u32::MAX);
+        if !range.contains(&size) || packets != expected {
+            if data[0] != TP_CM_BAM && self.is_receiver(dst) {
+                self.send_abort(src, extended, pgn, TP_ABORT_OTHER);
+            }
+            return SockCanOpCode::RxError(CanError::new(
+                "j1939-tp-rts",
+                format!("pgn:{pgn:#x} invalid size:{size} packets:{packets}"),
+            ));
+        }
+
+        let mut session = J1939TpSession {
+            pgn,
+            size,
+            packets,
+            next: 1,
+            window: packets,
+            max_cts: max_cts.max(1),
+            offset: 0,
+            extended,
+            priority: info.priority,
+            // announced size is not trusted, buffer grows with received packets
+            data: Vec::with_capacity(size.min(TP_MAX_SIZE)),
+            stamp: Instant::now(),
+        };
+        if data[0] != TP_CM_BAM && self.is_receiver(dst) {
+            self.send_cts(src, &mut session);
+        }
+        if self.sessions.borrow_mut().insert((src, dst), session).is_some() {
+            log::warn!("j1939-tp pgn:{pgn:#x} src:{src:#04x} previous session dropped");
+        }
+        SockCanOpCode::RxPartial(0)
+    }
+
+    fn transfer(&self, extended: bool, src: u8, dst: u8, data: &[u8]) -> SockCanOpCode {
+        let mut sessions = self.sessions.borrow_mut();
+        let Some(session) = sessions.get_mut(&(src, dst)) else {
+            return SockCanOpCode::RxIgnore;
+        };
+        if session.extended != extended {
+            return SockCanOpCode::RxIgnore;
+        }
+
+        let seq = data[0];
+        let packet = if extended { session.offset + u32::from(seq) } else { u32::from(seq) };
+        if packet != session.next {
+            let pgn = session.pgn;
+            sessions.remove(&(src, dst));
+            if self.is_receiver(dst) {
+                self.send_abort(src, extended, pgn, TP_ABORT_BAD_SEQUENCE);
+            }
+            return SockCanOpCode::RxError(CanError::new(
+                "j1939-tp-sequence",
+                format!("pgn:{pgn:#x} src:{src:#04x} packet:{packet} out of sequence"),
+            ));
+        }
+
+        let take = (session.size - session.data.len()).min(J1939_DT_SZ).min(data.len() - 1);
+        session.data.extend_from_slice(&data[1..=take]);
+        session.next += 1;
+        session.stamp = Instant::now();
+
+        if session.data.len() >= session.size {
+            if let Some(session) = sessions.remove(&(src, dst)) {
+                if self.is_receiver(dst) {
+                    self.send_eoma(src, &session);
+                }
+                self.completed.set(Some(CanJ1939Info {
+                    src: CanJ1939Header { name: 0, addr: src },
+                    dst: CanJ1939Header { name: 0, addr: dst },
+                    pgn: session.pgn,
+                    priority: session.priority,
+                }));
+                return SockCanOpCode::RxRead(session.data);
+            }
+        } else if self.is_receiver(dst) && packet == session.window {
+            self.send_cts(src, session);
+        }
+        SockCanOpCode::RxPartial(seq)
+    }
+}
+
+impl SockCanCtrl for SockJ1939Tp {
+    fn check_frame(&self, data: &[u8], recv: &CanRecvInfo) -> SockCanOpCode {
+        let CanProtoInfo::J1939(info) = recv.proto else {
+            return SockCanOpCode::RxInvalid;
+        };
+        let count = usize::try_from(recv.count).unwrap_or(0).min(data.len());
+        let data = &data[..count];
+        self.completed.set(None);
+        self.expire();
+
+        let (src, dst) = (info.src.addr, info.dst.addr);
+        match info.pgn {
+            PGN_J1939_TP_CM | PGN_J1939_ETP_CM if data.len() == 8 => {
+                self.control(info.pgn == PGN_J1939_ETP_CM, &info, data)
+            },
+            PGN_J1939_TP_DT | PGN_J1939_ETP_DT if data.len() > 1 => {
+                self.transfer(info.pgn == PGN_J1939_ETP_DT, src, dst, data)
+            },
+            PGN_J1939_TP_CM | PGN_J1939_ETP_CM | PGN_J1939_TP_DT | PGN_J1939_ETP_DT => {
+                SockCanOpCode::RxInvalid
+            },
+            // single frame message
+            _ => SockCanOpCode::RxRead(data.to_vec()),
+        }
+    }
+
+    fn update_info(&self, info: &mut CanRecvInfo) {
+        if let Some(j1939) = self.completed.take() {
+            info.proto = CanProtoInfo::J1939(j1939);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(tp: &SockJ1939Tp, canid: u32, data: &[u8]) -> (SockCanOpCode, CanRecvInfo) {
+        let mut info = CanRecvInfo {
+            proto: CanProtoInfo::J1939(j1939_raw_info(canid)),
+            stamp: 0,
+            stamp_ns: 0,
+            errno: 0,
+            count: isize::try_from(data.len()).unwrap(),
+            iface: 0,
+        };
+        let opcode = tp.check_frame(data, &info);
+        if let SockCanOpCode::RxRead(_) = opcode {
+            tp.update_info(&mut info);
+        }
+        (opcode, info)
+    }
+
+    #[test]
+    fn j1939_raw_canid_info() {
+        let canid = j1939_raw_canid(6, 0xEA00, 0x20, 0x80);
+        assert_eq!(canid, 0x98EA_2080);
+        let info = j1939_raw_info(canid);
+        assert_eq!((info.pgn, info.src.addr, info.dst.addr), (0xEA00, 0x80, 0x20));
+        let info = j1939_raw_info(0x18FE_F100);
+        assert_eq!((info.pgn, info.dst.addr), (0xFEF1, J1939_ADDR_BROADCAST));
+    }
+
+    #[test]
+    fn j1939_tp_reassembly() {
+        let sock = SockCanHandle { sockfd: -1, mode: SockCanMod::RAW, callback: None };
+        let tp = SockJ1939Tp::new(&sock);
+
+        // BAM pgn 0xFECA (DM1) 10 bytes from 0x00
+        let cm = j1939_raw_canid(7, PGN_J1939_TP_CM, 0xFF, 0x00);
+        let dt = j1939_raw_canid(7, PGN_J1939_TP_DT, 0xFF, 0x00);
+        let (opcode, _) = frame(&tp, cm, &[TP_CM_BAM, 10, 0, 2, 0xFF, 0xCA, 0xFE, 0x00]);
+        assert!(matches!(opcode, SockCanOpCode::RxPartial(0)));
+        let (opcode, _) = frame(&tp, dt, &[1, 0, 1, 2, 3, 4, 5, 6]);
+        assert!(matches!(opcode, SockCanOpCode::RxPartial(1)));
+        let (opcode, info) = frame(&tp, dt, &[2, 7, 8, 9, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let SockCanOpCode::RxRead(data) = opcode else { panic!("bam not complete") };
+        assert_eq!(data, (0..10).collect::<Vec<u8>>());
+        let CanProtoInfo::J1939(info) = info.proto else { panic!("no j1939 info") };
+        assert_eq!((info.pgn, info.src.addr), (0xFECA, 0x00));
+
+        // passive ETP 1800 bytes (258 packets) 0x10->0x20 with DPO, bad sequence aborts session
+        let cm = j1939_raw_canid(7, PGN_J1939_ETP_CM, 0x20, 0x10);
+        let dt = j1939_raw_canid(7, PGN_J1939_ETP_DT, 0x20, 0x10);
+        frame(&tp, cm, &[ETP_CM_RTS, 0x08, 0x07, 0, 0, 0x00, 0xD7, 0x00]);
+        frame(&tp, cm, &[ETP_CM_DPO, 255, 0, 0, 0, 0x00, 0xD7, 0x00]);
+        for seq in 1..=255 {
+            frame(&tp, dt, &[seq; 8]);
+        }
+        frame(&tp, cm, &[ETP_CM_DPO, 3, 255, 0, 0, 0x00, 0xD7, 0x00]);
+        frame(&tp, dt, &[1; 8]);
+        frame(&tp, dt, &[2; 8]);
+        let (opcode, info) = frame(&tp, dt, &[3; 8]);
+        let SockCanOpCode::RxRead(data) = opcode else { panic!("etp not complete") };
+        assert_eq!(data.len(), 1800);
+        let CanProtoInfo::J1939(info) = info.proto else { panic!("no j1939 info") };
+        assert_eq!((info.pgn, info.dst.addr), (0xD700, 0x20));
+
+        frame(&tp, cm, &[ETP_CM_RTS, 0x08, 0x07, 0, 0, 0x00, 0xD7, 0x00]);
+        frame(&tp, cm, &[ETP_CM_DPO, 3, 0, 0, 0, 0x00, 0xD7, 0x00]);
+        let (opcode, _) = frame(&tp, dt, &[2; 8]);
+        assert!(matches!(opcode, SockCanOpCode::RxError(_)));
+        assert_eq!(tp.get_sessions(), 0);
+    }
+
+    #[test]
+    fn j1939_tp_invalid_rts() {
+        let sock = SockCanHandle { sockfd: -1, mode: SockCanMod::RAW, callback: None };
+        let tp = SockJ1939Tp::new(&sock);
+        let cm = j1939_raw_canid(7, PGN_J1939_TP_CM, 0x20, 0x10);
+        let etp = j1939_raw_canid(7, PGN_J1939_ETP_CM, 0x20, 0x10);
+
+        // oversized ETP is refused without allocating announced size
+        let (opcode, _) = frame(&tp, etp, &[ETP_CM_RTS, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0xD7, 0x00]);
+        assert!(matches!(opcode, SockCanOpCode::RxError(_)));
+        // ETP size fitting within TP
+        let (opcode, _) = frame(&tp, etp, &[ETP_CM_RTS, 16, 0, 0, 0, 0x00, 0xD7, 0x00]);
+        assert!(matches!(opcode, SockCanOpCode::RxError(_)));
+        // TP size/packet count mismatch, RTS and BAM
+        let (opcode, _) = frame(&tp, cm, &[TP_CM_RTS, 20, 0, 5, 0xFF, 0xCA, 0xFE, 0x00]);
+        assert!(matches!(opcode, SockCanOpCode::RxError(_)));
+        let (opcode, _) = frame(&tp, cm, &[TP_CM_BAM, 20, 0, 2, 0xFF, 0xCA, 0xFE, 0x00]);
+        assert!(matches!(opcode, SockCanOpCode::RxError(_)));
+        // TP size out of 9..=1785 range
+        let (opcode, _) = frame(&tp, cm, &[TP_CM_RTS, 8, 0, 2, 0xFF, 0xCA, 0xFE, 0x00]);
+        assert!(matches!(opcode, SockCanOpCode::RxError(_)));
+        let (opcode, _) = frame(&tp, cm, &[TP_CM_RTS, 0x00, 0x08, 255, 0xFF, 0xCA, 0xFE, 0x00]);
+        assert!(matches!(opcode, SockCanOpCode::RxError(_)));
+        assert_eq!(tp.get_sessions(), 0);
+
+        let (opcode, _) = frame(&tp, cm, &[TP_CM_RTS, 20, 0, 3, 0xFF, 0xCA, 0xFE, 0x00]);
+        assert!(matches!(opcode, SockCanOpCode::RxPartial(0)));
+        assert_eq!(tp.get_sessions(), 1);
+    }
+}
@@ -0,0 +1,451 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    SAE J1979 / ISO 15031-5 OBD-II services
+ *    ISO 15765-4 diagnostic on CAN (11 bits identifiers)
+ *    https://en.wikipedia.org/wiki/OBD-II_PIDs
+ *
+*/
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+pub const OBD_FUNCTIONAL_ID: u32 = 0x7DF;
+pub const OBD_RESPONSE_FIRST_ID: u32 = 0x7E8;
+pub const OBD_RESPONSE_LAST_ID: u32 = 0x7EF;
+pub const OBD_PHYSICAL_OFFSET: u32 = 8; // ecu request id = response id - 8
+
+const OBD_PADDING: u8 = 0x00;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum ObdMode {
+    CurrentData = 0x01,
+    FreezeFrame = 0x02,
+    StoredDtc = 0x03,
+    VehicleInfo = 0x09,
+}
+
+/// Standard PID definition, value = decode(A,B,...) in `unit`
+pub struct ObdPid {
+    pub pid: u8,
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub len: usize,
+    decode: fn(&[u8]) -> CanDbcType,
+}
+
+fn obd_word(data: &[u8]) -> u16 {
+    u16::from_be_bytes([data[0], data[1]])
+}
+
+fn obd_temperature(data: &[u8]) -> CanDbcType {
+    CanDbcType::I16(i16::from(data[0]) - 40)
+}
+
+fn obd_percent(data: &[u8]) -> CanDbcType {
+    CanDbcType::F64(f64::from(data[0]) * 100.0 / 255.0)
+}
+
+fn obd_fuel_trim(data: &[u8]) -> CanDbcType {
+    CanDbcType::F64((f64::from(data[0]) - 128.0) * 100.0 / 128.0)
+}
+
+fn obd_byte(data: &[u8]) -> CanDbcType {
+    CanDbcType::U8(data[0])
+}
+
+fn obd_u16(data: &[u8]) -> CanDbcType {
+    CanDbcType::U16(obd_word(data))
+}
+
+fn obd_bitmap(data: &[u8]) -> CanDbcType {
+    CanDbcType::U32(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+}
+
+static OBD_PIDS: &[ObdPid] = &[
+    ObdPid { pid: 0x00, name: "PidsSupported01_20", unit: "", len: 4, decode: obd_bitmap },
+    ObdPid { pid: 0x01, name: "MonitorStatus", unit: "", len: 4, decode: obd_bitmap },
+    ObdPid { pid: 0x04, name: "EngineLoad", unit: "%", len: 1, decode: obd_percent },
+    ObdPid { pid: 0x05, name: "CoolantTemp", unit: "°C", len: 1, decode: obd_temperature },
+    ObdPid { pid: 0x06, name: "ShortFuelTrim1", unit: "%", len: 1, decode: obd_fuel_trim },
+    ObdPid { pid: 0x07, name: "LongFuelTrim1", unit: "%", len: 1, decode: obd_fuel_trim },
+    ObdPid { pid: 0x08, name: "ShortFuelTrim2", unit: "%", len: 1, decode: obd_fuel_trim },
+    ObdPid { pid: 0x09, name: "LongFuelTrim2", unit: "%", len: 1, decode: obd_fuel_trim },
+    ObdPid {
+        pid: 0x0A,
+        name: "FuelPressure",
+        unit: "kPa",
+        len: 1,
+        decode: |data| CanDbcType::U16(u16::from(data[0]) * 3),
+    },
+    ObdPid { pid: 0x0B, name: "IntakePressure", unit: "kPa", len: 1, decode: obd_byte },
+    ObdPid {
+        pid: 0x0C,
+        name: "EngineSpeed",
+        unit: "rpm",
+        len: 2,
+        decode: |data| CanDbcType::F64(f64::from(obd_word(data)) / 4.0),
+    },
+    ObdPid { pid: 0x0D, name: "VehicleSpeed", unit: "km/h", len: 1, decode: obd_byte },
+    ObdPid {
+        pid: 0x0E,
+        name: "TimingAdvance",
+        unit: "°",
+        len: 1,
+        decode: |data| CanDbcType::F64(f64::from(data[0]) / 2.0 - 64.0),
+    },
+    ObdPid { pid: 0x0F, name: "IntakeTemp", unit: "°C", len: 1, decode: obd_temperature },
+    ObdPid {
+        pid: 0x10,
+        name: "MafRate",
+        unit: "g/s",
+        len: 2,
+        decode: |data| CanDbcType::F64(f64::from(obd_word(data)) / 100.0),
+    },
+    ObdPid { pid: 0x11, name: "ThrottlePosition", unit: "%", len: 1, decode: obd_percent },
+    ObdPid { pid: 0x1F, name: "RunTime", unit: "s", len: 2, decode: obd_u16 },
+    ObdPid { pid: 0x20, name: "PidsSupported21_40", unit: "", len: 4, decode: obd_bitmap },
+    ObdPid { pid: 0x21, name: "DistanceWithMil", unit: "km", len: 2, decode: obd_u16 },
+    ObdPid { pid: 0x2F, name: "FuelLevel", unit: "%", len: 1, decode: obd_percent },
+    ObdPid { pid: 0x31, name: "DistanceSinceClear", unit: "km", len: 2, decode: obd_u16 },
+    ObdPid { pid: 0x33, name: "BarometricPressure", unit: "kPa", len: 1, decode: obd_byte },
+    ObdPid { pid: 0x40, name: "PidsSupported41_60", unit: "", len: 4, decode: obd_bitmap },
+    ObdPid {
+        pid: 0x42,
+        name: "ModuleVoltage",
+        unit: "V",
+        len: 2,
+        decode: |data| CanDbcType::F64(f64::from(obd_word(data)) / 1000.0),
+    },
+    ObdPid { pid: 0x46, name: "AmbientTemp", unit: "°C", len: 1, decode: obd_temperature },
+    ObdPid { pid: 0x5C, name: "OilTemp", unit: "°C", len: 1, decode: obd_temperature },
+    ObdPid {
+        pid: 0x5E,
+        name: "FuelRate",
+        unit: "L/h",
+        len: 2,
+        decode: |data| CanDbcType::F64(f64::from(obd_word(data)) / 20.0),
+    },
+];
+
+impl ObdPid {
+    #[must_use]
+    pub fn find(pid: u8) -> Option<&'static ObdPid> {
+        OBD_PIDS.iter().find(|entry| entry.pid == pid)
+    }
+
+    /// Decodes PID `data` (A,B,... bytes following PID), short data returns an error status
+    #[must_use]
+    pub fn decode(&self, data: &[u8]) -> (CanDbcType, CanDataStatus) {
+        if data.len() < self.len {
+            return (CanDbcType::U8(0), CanDataStatus::Error);
+        }
+        ((self.decode)(data), CanDataStatus::Updated)
+    }
+}
+
+/// One decoded PID value as returned by one ECU
+pub struct ObdValue {
+    pub ecu: u32,
+    pub pid: u8,
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub value: CanDbcType,
+    pub status: CanDataStatus,
+}
+
+impl fmt::Display for ObdValue {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            format,
+            "ecu:{:#05x} {}={}{} ({})",
+            self.ecu, self.name, self.value, self.unit, self.status
+        )
+    }
+}
+
+/// Diagnostic trouble code as 2 raw bytes, displayed as `P0123`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObdDtc(pub u16);
+
+impl fmt::Display for ObdDtc {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let system = ['P', 'C', 'B', 'U'][usize::from(self.0 >> 14)];
+        write!(format, "{system}{:04X}", self.0 & 0x3FFF)
+    }
+}
+
+/// Complete response payload (service byte first) from one ECU
+#[derive(Debug, PartialEq)]
+pub struct ObdResponse {
+    pub ecu: u32,
+    pub data: Vec<u8>,
+}
+
+enum ObdStep {
+    Done(ObdResponse),
+    // first frame received, ECU waits for flow control on its request id
+    FlowControl(u32),
+    Pending,
+}
+
+struct ObdPartial {
+    len: usize,
+    seq: u8,
+    data: Vec<u8>,
+}
+
+// minimal ISO-TP receiver, functional requests get one answer per ECU
+#[derive(Default)]
+struct ObdCollector {
+    partials: HashMap<u32, ObdPartial>,
+}
+
+impl ObdCollector {
+    fn push(&mut self, ecu: u32, frame: &[u8]) -> ObdStep {
+        let Some(&pci) = frame.first() else {
+            return ObdStep::Pending;
+        };
+        match pci >> 4 {
+            // single frame
+            0 => {
+                let len = usize::from(pci & 0x0F);
+                if len == 0 || frame.len() <= len {
+                    return ObdStep::Pending;
+                }
+                ObdStep::Done(ObdResponse { ecu, data: frame[1..=len].to_vec() })
+            },
+            // first frame
+            1 if frame.len() == 8 => {
+                let len = usize::from(u16::from_be_bytes([pci & 0x0F, frame[1]]));
+                self.partials.insert(ecu, ObdPartial { len, seq: 1, data: frame[2..].to_vec() });
+                ObdStep::FlowControl(ecu - OBD_PHYSICAL_OFFSET)
+            },
+            // consecutive frame
+            2 => {
+                let Some(partial) = self.partials.get_mut(&ecu) else {
+                    return ObdStep::Pending;
+                };
+                if pci & 0x0F != partial.seq {
+                    log::warn!("obd ecu:{ecu:#05x} lost consecutive frame");
+                    self.partials.remove(&ecu);
+                    return ObdStep::Pending;
+                }
+                partial.seq = (partial.seq + 1) & 0x0F;
+                partial.data.extend_from_slice(&frame[1..]);
+                if partial.data.len() < partial.len {
+                    return ObdStep::Pending;
+                }
+                match self.partials.remove(&ecu) {
+                    Some(mut partial) => {
+                        partial.data.truncate(partial.len);
+                        ObdStep::Done(ObdResponse { ecu, data: partial.data })
+                    },
+                    None => ObdStep::Pending,
+                }
+            },
+            _ => ObdStep::Pending,
+        }
+    }
+}
+
+/// OBD-II client on a raw CAN socket, requests are sent on functional id 0x7DF
+/// and responses are collected from 0x7E8-0x7EF until timeout.
+pub struct ObdClient {
+    sock: SockCanHandle,
+    timeout_ms: u64,
+}
+
+impl ObdClient {
+    /// Opens a raw socket on `candev` filtering OBD-II response ids.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when socket cannot be opened or filter cannot be applied.
+    pub fn open<T>(candev: T) -> Result<Self, CanError>
+    where
+        SockCanHandle: CanIFaceFrom<T>,
+    {
+        let sock = SockCanHandle::open_raw(candev, CanTimeStamp::CLASSIC)?;
+        SockCanFilter::new(1)
+            .add_whitelist(OBD_RESPONSE_FIRST_ID, &FilterMask::from_bits_retain(0x7F8))
+            .apply(&sock)?;
+        Ok(ObdClient { sock, timeout_ms: 100 })
+    }
+
+    /// Time to wait for ECU responses after each request (default 100ms)
+    pub fn set_timeout(&mut self, timeout_ms: u64) -> &mut Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    pub fn get_socket(&self) -> &SockCanHandle {
+        &self.sock
+    }
+
+    /// Sends `mode` request with `args` and returns positive responses from every ECU.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when request or flow control cannot be sent.
+    pub fn request(&mut self, mode: ObdMode, args: &[u8]) -> Result<Vec<ObdResponse>, CanError> {
+        if args.len() > 6 {
+            return Err(CanError::new("obd-request-len", "obd request too long"));
+        }
+        let mut frame = [OBD_PADDING; 8];
+        frame[0] = u8::try_from(args.len() + 1).unwrap_or(0);
+        frame[1] = mode as u8;
+        frame[2..2 + args.len()].copy_from_slice(args);
+        self.sock.send_std(OBD_FUNCTIONAL_ID, &frame)?;
+
+        let mut collector = ObdCollector::default();
+        let mut responses = Vec::new();
+        let deadline = Instant::now() + Duration::from_millis(self.timeout_ms);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            let timeout = i64::try_from(remaining.as_millis().max(1)).unwrap_or(i64::MAX);
+            self.sock.set_timeout(timeout, 0)?;
+
+            let msg = self.sock.get_can_frame();
+            let (Ok(ecu), Ok(data)) = (msg.get_id(), msg.get_data()) else {
+                continue; // timeout or invalid frame
+            };
+            if !(OBD_RESPONSE_FIRST_ID..=OBD_RESPONSE_LAST_ID).contains(&ecu) {
+                continue;
+            }
+
+            match collector.push(ecu, data) {
+                ObdStep::Done(response) => match response.data.first() {
+                    Some(&sid) if sid == mode as u8 + 0x40 => responses.push(response),
+                    _ => log::debug!("obd ecu:{ecu:#05x} ignoring {:02x?}", response.data),
+                },
+                ObdStep::FlowControl(ecu_rqt) => {
+                    self.sock.send_std(ecu_rqt, &[0x30, 0, 0, 0, 0, 0, 0, 0])?;
+                },
+                ObdStep::Pending => {},
+            }
+        }
+        Ok(responses)
+    }
+
+    fn decode_values(pid: u8, responses: &[ObdResponse], skip: usize) -> Vec<ObdValue> {
+        responses
+            .iter()
+            .filter(|response| response.data.get(1) == Some(&pid))
+            .map(|response| {
+                let data = response.data.get(skip..).unwrap_or(&[]);
+                let (name, unit, (value, status)) = match ObdPid::find(pid) {
+                    Some(def) => (def.name, def.unit, def.decode(data)),
+                    None => ("Unknown", "", (CanDbcType::U8(0), CanDataStatus::Unset)),
+                };
+                ObdValue { ecu: response.ecu, pid, name, unit, value, status }
+            })
+            .collect()
+    }
+
+    /// Mode 01, reads current value of `pid` from every responding ECU.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on socket failure.
+    pub fn read_pid(&mut self, pid: u8) -> Result<Vec<ObdValue>, CanError> {
+        let responses = self.request(ObdMode::CurrentData, &[pid])?;
+        Ok(Self::decode_values(pid, &responses, 2))
+    }
+
+    /// Mode 02, reads `pid` value stored in freeze `frame`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on socket failure.
+    pub fn read_freeze_frame(&mut self, pid: u8, frame: u8) -> Result<Vec<ObdValue>, CanError> {
+        let responses = self.request(ObdMode::FreezeFrame, &[pid, frame])?;
+        Ok(Self::decode_values(pid, &responses, 3))
+    }
+
+    /// Mode 03, reads stored DTCs per ECU.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on socket failure.
+    pub fn read_dtcs(&mut self) -> Result<Vec<(u32, Vec<ObdDtc>)>, CanError> {
+        let responses = self.request(ObdMode::StoredDtc, &[])?;
+        Ok(responses
+            .iter()
+            .map(|response| (response.ecu, obd_parse_dtcs(&response.data)))
+            .collect())
+    }
+
+    /// Mode 09 PID 02, reads vehicle identification number (multi-frame response).
+    ///
+    /// # Errors
+    /// Returns a `CanError` on socket failure or when no ECU returns a VIN.
+    pub fn read_vin(&mut self) -> Result<String, CanError> {
+        let responses = self.request(ObdMode::VehicleInfo, &[0x02])?;
+        responses
+            .iter()
+            .find_map(|response| match response.data.as_slice() {
+                [_, 0x02, _count, vin @ ..] if !vin.is_empty() => {
+                    Some(String::from_utf8_lossy(vin).trim_matches('\0').to_string())
+                },
+                _ => None,
+            })
+            .ok_or_else(|| CanError::new("obd-vin-missing", "no ecu returned a VIN"))
+    }
+}
+
+/// Parses mode 03/07/0A response (service byte, count, 2 bytes per DTC), null codes are skipped
+#[must_use]
+pub fn obd_parse_dtcs(response: &[u8]) -> Vec<ObdDtc> {
+    response
+        .get(2..)
+        .unwrap_or(&[])
+        .chunks_exact(2)
+        .map(|code| ObdDtc(u16::from_be_bytes([code[0], code[1]])))
+        .filter(|dtc| dtc.0 != 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obd_decode_pids() {
+        let rpm = ObdPid::find(0x0C).unwrap();
+        assert!(
+            matches!(rpm.decode(&[0x1A, 0xF8]).0, CanDbcType::F64(val) if (val - 1726.0).abs() < f64::EPSILON)
+        );
+        assert!(matches!(rpm.decode(&[0x1A]).1, CanDataStatus::Error));
+        let coolant = ObdPid::find(0x05).unwrap();
+        assert!(matches!(coolant.decode(&[0x7B]).0, CanDbcType::I16(83)));
+
+        assert_eq!(ObdDtc(0x0133).to_string(), "P0133");
+        assert_eq!(ObdDtc(0xC158).to_string(), "U0158");
+        assert_eq!(obd_parse_dtcs(&[0x43, 0x02, 0x01, 0x33, 0x41, 0x23, 0, 0]).len(), 2);
+    }
+
+    #[test]
+    fn obd_collect_multi_frame() {
+        let mut collector = ObdCollector::default();
+        let step = collector.push(0x7E8, &[0x10, 0x13, 0x49, 0x02, 0x01, b'W', b'V', b'W']);
+        assert!(matches!(step, ObdStep::FlowControl(0x7E0)));
+        assert!(matches!(collector.push(0x7E8, b"\x21ZZZ1JZ"), ObdStep::Pending));
+        let ObdStep::Done(response) = collector.push(0x7E8, b"\x22XW00000") else {
+            panic!("vin not complete");
+        };
+        assert_eq!(response.data.len(), 0x13);
+        assert_eq!(&response.data[3..], b"WVWZZZ1JZXW00000");
+
+        let ObdStep::Done(response) = collector.push(0x7E9, &[0x03, 0x41, 0x0D, 0x32, 0, 0, 0, 0])
+        else {
+            panic!("single frame not decoded");
+        };
+        assert_eq!(response, ObdResponse { ecu: 0x7E9, data: vec![0x41, 0x0D, 0x32] });
+    }
+}
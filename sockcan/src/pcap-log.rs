@@ -0,0 +1,952 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    https://www.tcpdump.org/linktypes/LINKTYPE_CAN_SOCKETCAN.html
+ *    https://www.ietf.org/archive/id/draft-ietf-opsawg-pcap-03.html (pcap)
+ *    https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-01.html (pcapng)
+ *
+*/
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+
+const PCAP_MAGIC_USEC: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NSEC: u32 = 0xA1B2_3C4D;
+const PCAP_HEADER_SIZE: usize = 24;
+const PCAP_RECORD_SIZE: usize = 16;
+
+const PCAPNG_SHB: u32 = 0x0A0D_0D0A;
+const PCAPNG_IDB: u32 = 0x0000_0001;
+const PCAPNG_SPB: u32 = 0x0000_0003;
+const PCAPNG_EPB: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER: u32 = 0x1A2B_3C4D;
+
+// block options
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_SHB_USERAPPL: u16 = 4;
+const PCAPNG_IF_NAME: u16 = 2;
+const PCAPNG_IF_TSRESOL: u16 = 9;
+const PCAPNG_EPB_FLAGS: u16 = 2;
+const PCAPNG_EPB_INBOUND: u32 = 0x01;
+const PCAPNG_EPB_OUTBOUND: u32 = 0x02;
+
+// LINKTYPE_CAN_SOCKETCAN header, can_id is big endian, full can_frame/canfd_frame is captured
+const SOCKETCAN_HEADER_SIZE: usize = 8;
+const SOCKETCAN_MTU: usize = 16;
+const SOCKETCAN_FD_MTU: usize = 72;
+const SOCKETCAN_FDF: u8 = 0x04;
+
+// large enough for any CAN frame, bigger blocks are bogus files
+const PCAP_MAX_PACKET: usize = 0x10_0000;
+
+/// Capture file flavour: classic pcap has no interface names, pcapng keeps one per packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CanPcapFormat {
+    Pcap,
+    PcapNg,
+}
+
+// encodes frame as LINKTYPE_CAN_SOCKETCAN packet, None for non data frames
+fn pcap_encode(frame: &CanAnyFrame) -> Option<Vec<u8>> {
+    match frame {
+        CanAnyFrame::RawStd(frame) => {
+            let mut packet = Vec::with_capacity(SOCKETCAN_MTU);
+            packet.extend_from_slice(&frame.0.can_id.to_be_bytes());
+            packet.extend_from_slice(&[frame.get_len(), 0, 0, frame.0.len8_dlc]);
+            packet.extend_from_slice(frame.get_data());
+            Some(packet)
+        },
+        CanAnyFrame::RawFd(frame) => {
+            let mut packet = Vec::with_capacity(SOCKETCAN_FD_MTU);
+            packet.extend_from_slice(&frame.0.can_id.to_be_bytes());
+            packet.extend_from_slice(&[frame.get_len(), frame.get_flag() | SOCKETCAN_FDF, 0, 0]);
+            packet.extend_from_slice(frame.get_data());
+            Some(packet)
+        },
+        CanAnyFrame::Err(_) | CanAnyFrame::None(_) => None,
+    }
+}
+
+// decodes LINKTYPE_CAN_SOCKETCAN packet, FD frames use FD MTU or carry CANFD_FDF flag
+fn pcap_decode(packet: &[u8]) -> Option<CanAnyFrame> {
+    let canid = u32::from_be_bytes(packet.get(..4)?.try_into().ok()?);
+    let len = *packet.get(4)?;
+    let flags = *packet.get(5)?;
+    let payload = packet.get(SOCKETCAN_HEADER_SIZE..).unwrap_or_default();
+
+    if packet.len() == SOCKETCAN_FD_MTU || flags & SOCKETCAN_FDF != 0 {
+        let mut buffer = [0u8; 64];
+        let len = len.min(64);
+        let size = usize::from(len).min(payload.len());
+        buffer[..size].copy_from_slice(&payload[..size]);
+        let flags = flags & (CanFdFlags::BRS | CanFdFlags::ESI).bits();
+        Some(CanAnyFrame::RawFd(CanFdFrameRaw::new(canid, len, flags, 0, 0, buffer)))
+    } else {
+        let mut buffer = [0u8; 8];
+        let len = len.min(8);
+        let size = usize::from(len).min(payload.len());
+        buffer[..size].copy_from_slice(&payload[..size]);
+        let mut frame = CanFrameRaw::new(canid, len, 0, 0, buffer);
+        let len8_dlc = *packet.get(7)?;
+        if len == 8 && (9..=15).contains(&len8_dlc) {
+            frame.0.len8_dlc = len8_dlc;
+        }
+        Some(CanAnyFrame::RawStd(frame))
+    }
+}
+
+fn pcap_u16(data: &[u8], offset: usize, big: bool) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if big { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+}
+
+fn pcap_u32(data: &[u8], offset: usize, big: bool) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+}
+
+// pcapng option list: code, length, value padded to 32 bits
+fn pcapng_option(block: &mut Vec<u8>, code: u16, value: &[u8]) {
+    block.extend_from_slice(&code.to_le_bytes());
+    block.extend_from_slice(&u16::try_from(value.len()).unwrap_or(u16::MAX).to_le_bytes());
+    block.extend_from_slice(value);
+    block.resize(block.len().next_multiple_of(4), 0);
+}
+
+fn pcapng_options(data: &[u8], big: bool) -> Vec<(u16, &[u8])> {
+    let mut options = Vec::new();
+    let mut pos = 0;
+    while let (Some(code), Some(len)) = (pcap_u16(data, pos, big), pcap_u16(data, pos + 2, big)) {
+        if code == PCAPNG_OPT_END {
+            break;
+        }
+        let Some(value) = data.get(pos + 4..pos + 4 + usize::from(len)) else {
+            break;
+        };
+        options.push((code, value));
+        pos += 4 + usize::from(len).next_multiple_of(4);
+    }
+    options
+}
+
+// pcapng timestamp (units from if_tsresol) to nanoseconds
+fn pcapng_stamp_ns(stamp: u64, tsresol: u8) -> u64 {
+    let value = u128::from(stamp);
+    let nanos = if tsresol & 0x80 != 0 {
+        (value * 1_000_000_000) >> (tsresol & 0x7F).min(127)
+    } else if tsresol <= 9 {
+        value * 10u128.pow(9 - u32::from(tsresol))
+    } else {
+        value / 10u128.pow(u32::from(tsresol).min(38) - 9)
+    };
+    u64::try_from(nanos).unwrap_or(u64::MAX)
+}
+
+/// Writes frames as pcap/pcapng captures with `LINKTYPE_CAN_SOCKETCAN` link type,
+/// readable by Wireshark and tcpdump. Timestamps are kept with nanosecond resolution.
+pub struct CanPcapWriter<W: Write> {
+    writer: W,
+    format: CanPcapFormat,
+    ifaces: HashMap<String, u32>,
+    size: u64,
+}
+
+impl CanPcapWriter<BufWriter<File>> {
+    /// Creates (truncates) capture file `path`, format is pcapng unless path ends with `.pcap`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be created.
+    pub fn create(path: &str) -> Result<Self, CanError> {
+        let format =
+            if Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pcap")) {
+                CanPcapFormat::Pcap
+            } else {
+                CanPcapFormat::PcapNg
+            };
+        match File::create(path) {
+            Ok(file) => CanPcapWriter::new(BufWriter::new(file), format),
+            Err(error) => Err(CanError::new("pcap-create-fail", format!("{path}: {error}"))),
+        }
+    }
+}
+
+impl<W: Write> CanPcapWriter<W> {
+    /// Writes file header (pcapng section header) to `writer`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when header cannot be written.
+    pub fn new(writer: W, format: CanPcapFormat) -> Result<Self, CanError> {
+        let mut pcap = CanPcapWriter { writer, format, ifaces: HashMap::new(), size: 0 };
+        match format {
+            CanPcapFormat::Pcap => {
+                let mut header = Vec::with_capacity(PCAP_HEADER_SIZE);
+                header.extend_from_slice(&PCAP_MAGIC_NSEC.to_le_bytes());
+                header.extend_from_slice(&2u16.to_le_bytes());
+                header.extend_from_slice(&4u16.to_le_bytes());
+                header.extend_from_slice(&[0; 8]);
+                header
+                    .extend_from_slice(&u32::try_from(SOCKETCAN_FD_MTU).unwrap_or(0).to_le_bytes());
+                header.extend_from_slice(&u32::from(LINKTYPE_CAN_SOCKETCAN).to_le_bytes());
+                pcap.output(&header)?;
+            },
+            CanPcapFormat::PcapNg => {
+                let mut body = Vec::new();
+                body.extend_from_slice(&PCAPNG_BYTE_ORDER.to_le_bytes());
+                body.extend_from_slice(&1u16.to_le_bytes());
+                body.extend_from_slice(&0u16.to_le_bytes());
+                body.extend_from_slice(&(-1i64).to_le_bytes());
+                pcapng_option(&mut body, PCAPNG_SHB_USERAPPL, b"sockcan");
+                pcapng_option(&mut body, PCAPNG_OPT_END, &[]);
+                pcap.block(PCAPNG_SHB, &body)?;
+            },
+        }
+        Ok(pcap)
+    }
+
+    #[must_use]
+    pub fn get_format(&self) -> CanPcapFormat {
+        self.format
+    }
+
+    /// Bytes written so far, headers included.
+    #[must_use]
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    fn output(&mut self, data: &[u8]) -> Result<(), CanError> {
+        self.writer
+            .write_all(data)
+            .map_err(|error| CanError::new("pcap-write-fail", error.to_string()))?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    fn block(&mut self, kind: u32, body: &[u8]) -> Result<(), CanError> {
+        let total = u32::try_from(12 + body.len())
+            .map_err(|_| CanError::new("pcap-write-fail", "block too large"))?;
+        let mut block = Vec::with_capacity(12 + body.len());
+        block.extend_from_slice(&kind.to_le_bytes());
+        block.extend_from_slice(&total.to_le_bytes());
+        block.extend_from_slice(body);
+        block.extend_from_slice(&total.to_le_bytes());
+        self.output(&block)
+    }
+
+    // pcapng interface id for iface, description block is emitted on first use
+    fn interface(&mut self, iface: &str) -> Result<u32, CanError> {
+        if let Some(id) = self.ifaces.get(iface) {
+            return Ok(*id);
+        }
+        let id = u32::try_from(self.ifaces.len()).unwrap_or(u32::MAX);
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&u32::try_from(SOCKETCAN_FD_MTU).unwrap_or(0).to_le_bytes());
+        pcapng_option(&mut body, PCAPNG_IF_NAME, iface.as_bytes());
+        pcapng_option(&mut body, PCAPNG_IF_TSRESOL, &[9]);
+        pcapng_option(&mut body, PCAPNG_OPT_END, &[]);
+        self.block(PCAPNG_IDB, &body)?;
+        self.ifaces.insert(iface.to_owned(), id);
+        Ok(id)
+    }
+
+    /// Writes one packet, `stamp_ns` is epoch time in nanoseconds (see `SockCanMsg::get_stamp_ns`).
+    /// Classic pcap drops interface name and direction. Error/None frames are ignored.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on I/O error.
+    pub fn write_packet(
+        &mut self,
+        stamp_ns: u64,
+        iface: &str,
+        dir: Option<CanLogDir>,
+        frame: &CanAnyFrame,
+    ) -> Result<(), CanError> {
+        let Some(packet) = pcap_encode(frame) else {
+            return Ok(());
+        };
+        let caplen = u32::try_from(packet.len()).unwrap_or(0);
+
+        match self.format {
+            CanPcapFormat::Pcap => {
+                let secs = u32::try_from(stamp_ns / 1_000_000_000).unwrap_or(u32::MAX);
+                let nanos = u32::try_from(stamp_ns % 1_000_000_000).unwrap_or(0);
+                let mut record = Vec::with_capacity(PCAP_RECORD_SIZE + packet.len());
+                record.extend_from_slice(&secs.to_le_bytes());
+                record.extend_from_slice(&nanos.to_le_bytes());
+                record.extend_from_slice(&caplen.to_le_bytes());
+                record.extend_from_slice(&caplen.to_le_bytes());
+                record.extend_from_slice(&packet);
+                self.output(&record)
+            },
+            CanPcapFormat::PcapNg => {
+                let id = self.interface(iface)?;
+                let mut body = Vec::with_capacity(40 + packet.len());
+                body.extend_from_slice(&id.to_le_bytes());
+                body.extend_from_slice(&u32::try_from(stamp_ns >> 32).unwrap_or(0).to_le_bytes());
+                body.extend_from_slice(
+                    &u32::try_from(stamp_ns & 0xFFFF_FFFF).unwrap_or(0).to_le_bytes(),
+                );
+                body.extend_from_slice(&caplen.to_le_bytes());
+                body.extend_from_slice(&caplen.to_le_bytes());
+                body.extend_from_slice(&packet);
+                body.resize(body.len().next_multiple_of(4), 0);
+                if let Some(dir) = dir {
+                    let flags = match dir {
+                        CanLogDir::Rx => PCAPNG_EPB_INBOUND,
+                        CanLogDir::Tx => PCAPNG_EPB_OUTBOUND,
+                    };
+                    pcapng_option(&mut body, PCAPNG_EPB_FLAGS, &flags.to_le_bytes());
+                    pcapng_option(&mut body, PCAPNG_OPT_END, &[]);
+                }
+                self.block(PCAPNG_EPB, &body)
+            },
+        }
+    }
+
+    /// Writes a received message, interface index is resolved by caller.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on I/O error.
+    pub fn write_msg(&mut self, msg: &SockCanMsg, iface: &str) -> Result<(), CanError> {
+        self.write_packet(msg.get_stamp_ns(), iface, Some(CanLogDir::Rx), msg.get_raw())
+    }
+
+    /// Writes a log record, timestamp precision is limited to record microseconds.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on I/O error.
+    pub fn write_record(&mut self, record: &CanLogRecord) -> Result<(), CanError> {
+        self.write_packet(
+            record.stamp.saturating_mul(1_000),
+            &record.iface,
+            record.dir,
+            &record.frame,
+        )
+    }
+
+    /// # Errors
+    /// Returns a `CanError` on I/O error.
+    pub fn flush(&mut self) -> Result<(), CanError> {
+        self.writer
+            .flush()
+            .map_err(|error| CanError::new("pcap-write-fail", error.to_string()))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+// pcapng interface description
+struct PcapNgIface {
+    linktype: u16,
+    name: String,
+    tsresol: u8,
+}
+
+/// Streams `LINKTYPE_CAN_SOCKETCAN` packets from pcap or pcapng captures (format is
+/// auto-detected). Packets from other link types are skipped.
+pub struct CanPcapReader<R: Read> {
+    reader: R,
+    format: CanPcapFormat,
+    big: bool,
+    nanos: bool,
+    linktype: u32,
+    ifaces: Vec<PcapNgIface>,
+}
+
+impl CanPcapReader<BufReader<File>> {
+    /// Opens a pcap/pcapng file and checks its header.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be opened or is not a capture file.
+    pub fn open(path: &str) -> Result<Self, CanError> {
+        match File::open(path) {
+            Ok(file) => CanPcapReader::new(BufReader::new(file)),
+            Err(error) => Err(CanError::new("pcap-open-fail", format!("{path}: {error}"))),
+        }
+    }
+}
+
+impl<R: Read> CanPcapReader<R> {
+    /// Reads pcap file header or pcapng section header from `reader`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when header is truncated or magic number is unknown.
+    pub fn new(mut reader: R) -> Result<Self, CanError> {
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|error| CanError::new("pcap-invalid-header", error.to_string()))?;
+        let mut pcap = CanPcapReader {
+            reader,
+            format: CanPcapFormat::PcapNg,
+            big: false,
+            nanos: true,
+            linktype: u32::from(LINKTYPE_CAN_SOCKETCAN),
+            ifaces: Vec::new(),
+        };
+
+        if u32::from_le_bytes(magic) == PCAPNG_SHB {
+            pcap.section()?;
+            return Ok(pcap);
+        }
+        (pcap.big, pcap.nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC_USEC, _) => (false, false),
+            (PCAP_MAGIC_NSEC, _) => (false, true),
+            (_, PCAP_MAGIC_USEC) => (true, false),
+            (_, PCAP_MAGIC_NSEC) => (true, true),
+            _ => return Err(CanError::new("pcap-invalid-header", "unknown magic number")),
+        };
+        let mut header = [0u8; PCAP_HEADER_SIZE - 4];
+        pcap.reader
+            .read_exact(&mut header)
+            .map_err(|error| CanError::new("pcap-invalid-header", error.to_string()))?;
+        pcap.format = CanPcapFormat::Pcap;
+        pcap.linktype = pcap_u32(&header, 16, pcap.big).unwrap_or(0) & 0x0FFF_FFFF;
+        Ok(pcap)
+    }
+
+    #[must_use]
+    pub fn get_format(&self) -> CanPcapFormat {
+        self.format
+    }
+
+    // reads exactly buffer size, false on clean end of file
+    fn fetch(&mut self, buffer: &mut [u8]) -> Result<bool, CanError> {
+        match self.reader.read_exact(buffer) {
+            Ok(()) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(error) => Err(CanError::new("pcap-read-fail", error.to_string())),
+        }
+    }
+
+    fn body(&mut self, size: usize) -> Result<Vec<u8>, CanError> {
+        if size > PCAP_MAX_PACKET {
+            return Err(CanError::new("pcap-invalid-block", format!("block size {size}")));
+        }
+        let mut body = vec![0u8; size];
+        if !self.fetch(&mut body)? {
+            return Err(CanError::new("pcap-invalid-block", "truncated block"));
+        }
+        Ok(body)
+    }
+
+    // section header block, block type already consumed; byte order is per section
+    fn section(&mut self) -> Result<(), CanError> {
+        let mut header = [0u8; 8];
+        if !self.fetch(&mut header)? {
+            return Err(CanError::new("pcap-invalid-block", "truncated section header"));
+        }
+        self.big = match u32::from_le_bytes(header[4..8].try_into().unwrap_or_default()) {
+            PCAPNG_BYTE_ORDER => false,
+            magic if magic.swap_bytes() == PCAPNG_BYTE_ORDER => true,
+            _ => return Err(CanError::new("pcap-invalid-header", "unknown byte order magic")),
+        };
+        let total = pcap_u32(&header, 0, self.big)
+            .and_then(|value| usize::try_from(value).ok())
+            .unwrap_or(0);
+        let Some(size) = total.checked_sub(12) else {
+            return Err(CanError::new("pcap-invalid-block", format!("block size {total}")));
+        };
+        self.body(size)?;
+        self.format = CanPcapFormat::PcapNg;
+        self.ifaces.clear();
+        Ok(())
+    }
+
+    fn record(
+        stamp_ns: u64,
+        iface: &str,
+        dir: Option<CanLogDir>,
+        packet: &[u8],
+    ) -> Option<(u64, CanLogRecord)> {
+        let frame = pcap_decode(packet)?;
+        let mut record = CanLogRecord::new(stamp_ns / 1_000, iface, frame);
+        if let Some(dir) = dir {
+            record.set_dir(dir);
+        }
+        Some((stamp_ns, record))
+    }
+
+    fn read_pcap(&mut self) -> Result<Option<(u64, CanLogRecord)>, CanError> {
+        loop {
+            let mut header = [0u8; PCAP_RECORD_SIZE];
+            if !self.fetch(&mut header)? {
+                return Ok(None);
+            }
+            let secs = u64::from(pcap_u32(&header, 0, self.big).unwrap_or(0));
+            let frac = u64::from(pcap_u32(&header, 4, self.big).unwrap_or(0));
+            let caplen = pcap_u32(&header, 8, self.big)
+                .and_then(|value| usize::try_from(value).ok())
+                .unwrap_or(0);
+            let packet = self.body(caplen)?;
+            if self.linktype != u32::from(LINKTYPE_CAN_SOCKETCAN) {
+                continue;
+            }
+            let stamp_ns = secs * 1_000_000_000 + if self.nanos { frac } else { frac * 1_000 };
+            if let Some(packet) = Self::record(stamp_ns, "0", None, &packet) {
+                return Ok(Some(packet));
+            }
+        }
+    }
+
+    fn read_pcapng(&mut self) -> Result<Option<(u64, CanLogRecord)>, CanError> {
+        loop {
+            let mut header = [0u8; 8];
+            if !self.fetch(&mut header[..4])? {
+                return Ok(None);
+            }
+            let kind = pcap_u32(&header, 0, self.big).unwrap_or(0);
+            if kind == PCAPNG_SHB {
+                self.section()?;
+                continue;
+            }
+            if !self.fetch(&mut header[4..])? {
+                return Ok(None);
+            }
+            let total = pcap_u32(&header, 4, self.big)
+                .and_then(|value| usize::try_from(value).ok())
+                .unwrap_or(0);
+            if total < 12 || !total.is_multiple_of(4) {
+                return Err(CanError::new("pcap-invalid-block", format!("block size {total}")));
+            }
+            // body followed by trailing block length
+            let block = self.body(total - 8)?;
+            let body = &block[..block.len() - 4];
+
+            match kind {
+                PCAPNG_IDB => {
+                    let mut iface = PcapNgIface {
+                        linktype: pcap_u16(body, 0, self.big).unwrap_or(0),
+                        name: self.ifaces.len().to_string(),
+                        tsresol: 6,
+                    };
+                    for (code, value) in pcapng_options(body.get(8..).unwrap_or_default(), self.big)
+                    {
+                        match code {
+                            PCAPNG_IF_NAME => {
+                                String::from_utf8_lossy(value)
+                                    .trim_end_matches('\0')
+                                    .clone_into(&mut iface.name);
+                            },
+                            PCAPNG_IF_TSRESOL => {
+                                iface.tsresol = value.first().copied().unwrap_or(6);
+                            },
+                            _ => {},
+                        }
+                    }
+                    self.ifaces.push(iface);
+                },
+                PCAPNG_EPB => {
+                    let id = pcap_u32(body, 0, self.big)
+                        .and_then(|value| usize::try_from(value).ok())
+                        .unwrap_or(usize::MAX);
+                    let Some(iface) = self.ifaces.get(id) else {
+                        return Err(CanError::new(
+                            "pcap-invalid-block",
+                            format!("unknown interface {id}"),
+                        ));
+                    };
+                    if iface.linktype != LINKTYPE_CAN_SOCKETCAN {
+                        continue;
+                    }
+                    let high = u64::from(pcap_u32(body, 4, self.big).unwrap_or(0));
+                    let low = u64::from(pcap_u32(body, 8, self.big).unwrap_or(0));
+                    let caplen = pcap_u32(body, 12, self.big)
+                        .and_then(|value| usize::try_from(value).ok())
+                        .unwrap_or(0);
+                    let Some(packet) = body.get(20..20usize.saturating_add(caplen)) else {
+                        return Err(CanError::new("pcap-invalid-block", "truncated packet"));
+                    };
+                    let options = body.get(20 + caplen.next_multiple_of(4)..).unwrap_or_default();
+                    let dir = pcapng_options(options, self.big)
+                        .into_iter()
+                        .find(|(code, _)| *code == PCAPNG_EPB_FLAGS)
+                        .and_then(|(_, value)| pcap_u32(value, 0, self.big))
+                        .and_then(|flags| match flags & 0x03 {
+                            PCAPNG_EPB_INBOUND => Some(CanLogDir::Rx),
+                            PCAPNG_EPB_OUTBOUND => Some(CanLogDir::Tx),
+                            _ => None,
+                        });
+                    let stamp_ns = pcapng_stamp_ns(high << 32 | low, iface.tsresol);
+                    if let Some(packet) = Self::record(stamp_ns, &iface.name, dir, packet) {
+                        return Ok(Some(packet));
+                    }
+                },
+                PCAPNG_SPB => {
+                    // simple packets have no timestamp and belong to first interface
+                    let Some(iface) = self.ifaces.first() else {
+                        continue;
+                    };
+                    if iface.linktype != LINKTYPE_CAN_SOCKETCAN {
+                        continue;
+                    }
+                    let origlen = pcap_u32(body, 0, self.big)
+                        .and_then(|value| usize::try_from(value).ok())
+                        .unwrap_or(0);
+                    let packet = body.get(4..).unwrap_or_default();
+                    let packet = &packet[..origlen.min(packet.len())];
+                    if let Some(packet) = Self::record(0, &iface.name, None, packet) {
+                        return Ok(Some(packet));
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Reads next CAN packet with its nanosecond timestamp, returns `None` at end of file.
+    /// Record stamp is truncated to microseconds.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on I/O error or invalid block framing.
+    pub fn read_packet(&mut self) -> Result<Option<(u64, CanLogRecord)>, CanError> {
+        match self.format {
+            CanPcapFormat::Pcap => self.read_pcap(),
+            CanPcapFormat::PcapNg => self.read_pcapng(),
+        }
+    }
+
+    /// Reads next CAN record, returns `None` at end of file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on I/O error or invalid block framing.
+    pub fn read_record(&mut self) -> Result<Option<CanLogRecord>, CanError> {
+        Ok(self.read_packet()?.map(|(_, record)| record))
+    }
+}
+
+impl<R: Read> Iterator for CanPcapReader<R> {
+    type Item = Result<CanLogRecord, CanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+// pcapng output split into numbered files once size or duration limit is reached
+struct PcapRotate {
+    path: String,
+    max_size: u64,
+    max_time: Option<Duration>,
+    writer: Option<CanPcapWriter<BufWriter<File>>>,
+    opened: Instant,
+    index: u32,
+    files: Vec<String>,
+}
+
+impl PcapRotate {
+    fn new(path: &str) -> Self {
+        PcapRotate {
+            path: path.to_owned(),
+            max_size: 0,
+            max_time: None,
+            writer: None,
+            opened: Instant::now(),
+            index: 0,
+            files: Vec::new(),
+        }
+    }
+
+    // 'trace.pcapng' becomes 'trace-0001.pcapng' when rotation is enabled
+    fn filename(&self) -> String {
+        if self.max_size == 0 && self.max_time.is_none() {
+            return self.path.clone();
+        }
+        let path = Path::new(&self.path);
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("capture");
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("pcapng");
+        let name = format!("{stem}-{:04}.{ext}", self.index);
+        path.with_file_name(name).to_string_lossy().into_owned()
+    }
+
+    fn close(&mut self) -> Result<(), CanError> {
+        match self.writer.take() {
+            Some(mut writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn writer(&mut self) -> Result<&mut CanPcapWriter<BufWriter<File>>, CanError> {
+        let expired = self.max_time.is_some_and(|max| self.opened.elapsed() >= max);
+        let full = self.max_size > 0
+            && self.writer.as_ref().is_some_and(|writer| writer.get_size() >= self.max_size);
+        if expired || full {
+            self.close()?;
+        }
+        if self.writer.is_none() {
+            self.index += 1;
+            let filename = self.filename();
+            let file = File::create(&filename).map_err(|error| {
+                CanError::new("pcap-create-fail", format!("{filename}: {error}"))
+            })?;
+            self.writer = Some(CanPcapWriter::new(BufWriter::new(file), CanPcapFormat::PcapNg)?);
+            self.opened = Instant::now();
+            self.files.push(filename);
+        }
+        self.writer
+            .as_mut()
+            .ok_or_else(|| CanError::new("pcap-create-fail", "no output"))
+    }
+
+    fn write(&mut self, stamp_ns: u64, iface: &str, frame: &CanAnyFrame) -> Result<(), CanError> {
+        self.writer()?.write_packet(stamp_ns, iface, Some(CanLogDir::Rx), frame)
+    }
+}
+
+/// Captures frames received on a RAW socket into pcapng files, with optional rotation
+/// by file size or duration. Interface names are resolved for every packet.
+pub struct CanPcapRecorder {
+    sock: SockCanHandle,
+    output: PcapRotate,
+    names: HashMap<i32, String>,
+    stop: Arc<AtomicBool>,
+}
+
+impl CanPcapRecorder {
+    /// Opens a RAW socket on `iface` recording into `path`.
+    /// Use `CanTimeStamp::NANOSEC` or `HARDWARE` for nanosecond timestamps, frames received
+    /// without kernel timestamp (`CanTimeStamp::NONE`) are stamped with system time.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the interface cannot be opened.
+    pub fn open(iface: &str, stamp: CanTimeStamp, path: &str) -> Result<Self, CanError> {
+        let sock = SockCanHandle::open_raw(iface, stamp)?;
+        Ok(CanPcapRecorder::new(sock, path))
+    }
+
+    /// Records frames from an already opened (and filtered) RAW socket.
+    #[must_use]
+    pub fn new(sock: SockCanHandle, path: &str) -> Self {
+        CanPcapRecorder {
+            sock,
+            output: PcapRotate::new(path),
+            names: HashMap::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Starts a new file once current one reaches `bytes` (0 disables).
+    pub fn set_rotate_size(&mut self, bytes: u64) -> &mut Self {
+        self.output.max_size = bytes;
+        self
+    }
+
+    /// Starts a new file every `period` (None disables).
+    pub fn set_rotate_time(&mut self, period: Option<Duration>) -> &mut Self {
+        self.output.max_time = period;
+        self
+    }
+
+    /// Shared flag aborting `run` from another thread or a signal handler. Flag is cleared
+    /// here, a stop requested before `run` starts is kept.
+    #[must_use]
+    pub fn get_stopper(&self) -> Arc<AtomicBool> {
+        self.stop.store(false, Ordering::Relaxed);
+        self.stop.clone()
+    }
+
+    /// Files created so far, in creation order.
+    #[must_use]
+    pub fn get_files(&self) -> &[String] {
+        &self.output.files
+    }
+
+    fn ifname(&mut self, index: i32) -> String {
+        if let Some(name) = self.names.get(&index) {
+            return name.clone();
+        }
+        let name = self.sock.get_ifname(index).unwrap_or_else(|_| format!("can{index}"));
+        self.names.insert(index, name.clone());
+        name
+    }
+
+    /// Receives and records one frame, returns false on read timeout or invalid frame.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on socket or file error.
+    pub fn record(&mut self) -> Result<bool, CanError> {
+        let msg = self.sock.get_can_frame();
+        if let Some(error) = msg.get_os_error() {
+            return match error.kind() {
+                ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted => Ok(false),
+                _ => Err(CanError::new("pcap-recv-fail", error.to_string())),
+            };
+        }
+        if let CanAnyFrame::Err(_) | CanAnyFrame::None(_) = msg.get_raw() {
+            log::warn!("pcap-recorder: skipping invalid frame on iface:{}", msg.get_iface());
+            return Ok(false);
+        }
+        let iface = self.ifname(msg.get_iface());
+        let stamp_ns = match msg.get_stamp_ns() {
+            0 => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| u64::try_from(now.as_nanos()).unwrap_or(u64::MAX)),
+            stamp_ns => stamp_ns,
+        };
+        self.output.write(stamp_ns, &iface, msg.get_raw())?;
+        Ok(true)
+    }
+
+    /// Records until stopped, returns the number of captured frames. Socket read timeout
+    /// is set to 250ms to keep stop flag and time rotation responsive.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on socket or file error.
+    pub fn run(&mut self) -> Result<usize, CanError> {
+        self.sock.set_timeout(250, 0)?;
+        let mut count = 0;
+        while !self.stop.load(Ordering::Relaxed) {
+            if self.record()? {
+                count += 1;
+            } else if let Some(writer) = self.output.writer.as_mut() {
+                // idle bus, push pending packets to disk
+                writer.flush()?;
+            }
+        }
+        self.output.close()?;
+        Ok(count)
+    }
+
+    /// Flushes and closes current file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when pending data cannot be written.
+    pub fn close(&mut self) -> Result<(), CanError> {
+        self.output.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn records() -> Vec<(u64, CanLogRecord)> {
+        [
+            (1_700_000_000_123_456_789, "(0.0) can0 123#DEADBEEF R"),
+            (1_700_000_000_123_457_001, "(0.0) vcan1 18FEF100#0102030405060708_C T"),
+            (1_700_000_000_223_000_000, "(0.0) can0 7FF#R3 R"),
+            (1_700_000_001_000_000_999, "(0.0) vcan1 1A0##3000102030405060708090A0B R"),
+            (1_700_000_002_000_000_000, "(0.0) can0 20000080#0000000000000000 R"),
+        ]
+        .into_iter()
+        .map(|(stamp_ns, line)| {
+            let mut record = CanLogRecord::from_str(line).unwrap();
+            record.stamp = stamp_ns / 1_000;
+            (stamp_ns, record)
+        })
+        .collect()
+    }
+
+    fn write(format: CanPcapFormat, packets: &[(u64, CanLogRecord)]) -> Vec<u8> {
+        let mut writer = CanPcapWriter::new(Vec::new(), format).unwrap();
+        for (stamp_ns, record) in packets {
+            writer
+                .write_packet(*stamp_ns, &record.iface, record.dir, &record.frame)
+                .unwrap();
+        }
+        let size = writer.get_size();
+        let output = writer.into_inner();
+        assert_eq!(size, output.len() as u64);
+        output
+    }
+
+    #[test]
+    fn test_pcapng_roundtrip() {
+        let packets = records();
+        let output = write(CanPcapFormat::PcapNg, &packets);
+
+        let mut reader = CanPcapReader::new(output.as_slice()).unwrap();
+        assert_eq!(reader.get_format(), CanPcapFormat::PcapNg);
+        for (stamp_ns, record) in &packets {
+            let (stamp, read) = reader.read_packet().unwrap().unwrap();
+            assert_eq!(stamp, *stamp_ns);
+            assert_eq!(read.to_string(), record.to_string());
+        }
+        assert!(reader.read_packet().unwrap().is_none());
+
+        // one interface block per interface, BRS flag kept and FDF marker added
+        let idb = output.windows(4).filter(|word| *word == PCAPNG_IDB.to_le_bytes()).count();
+        assert!(idb >= 2);
+        let packet = pcap_encode(&packets[3].1.frame).unwrap();
+        assert_eq!(packet.len(), SOCKETCAN_FD_MTU);
+        assert_eq!(packet[..6], [0x00, 0x00, 0x01, 0xA0, 12, 0x07]);
+    }
+
+    #[test]
+    fn test_pcap_roundtrip() {
+        let packets = records();
+        let output = write(CanPcapFormat::Pcap, &packets);
+        assert_eq!(output[..4], PCAP_MAGIC_NSEC.to_le_bytes());
+
+        let mut reader = CanPcapReader::new(output.as_slice()).unwrap();
+        assert_eq!(reader.get_format(), CanPcapFormat::Pcap);
+        for (stamp_ns, record) in &packets {
+            let (stamp, read) = reader.read_packet().unwrap().unwrap();
+            assert_eq!(stamp, *stamp_ns);
+            assert_eq!(read.get_iface(), "0");
+            assert_eq!(read.get_dir(), None);
+            assert_eq!(
+                read.get_frame().get_data().unwrap(),
+                record.get_frame().get_data().unwrap()
+            );
+            assert_eq!(read.get_canid(), record.get_canid());
+        }
+        assert!(reader.next().is_none());
+
+        // big endian microsecond capture written by another tool
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&PCAP_MAGIC_USEC.to_be_bytes());
+        capture.extend_from_slice(&[0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 227]);
+        capture.extend_from_slice(&[0, 0, 0, 10, 0, 0, 0, 20, 0, 0, 0, 16, 0, 0, 0, 16]);
+        capture.extend_from_slice(&[0, 0, 0x01, 0x23, 2, 0, 0, 0, 0xAA, 0xBB, 0, 0, 0, 0, 0, 0]);
+        let mut reader = CanPcapReader::new(capture.as_slice()).unwrap();
+        let (stamp, record) = reader.read_packet().unwrap().unwrap();
+        assert_eq!(stamp, 10_000_020_000);
+        assert_eq!(record.to_string(), "(10.000020) 0 123#AABB");
+    }
+
+    #[test]
+    fn test_pcap_rotate() {
+        let dir = std::env::temp_dir().join(format!("sockcan-pcap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.pcapng").to_string_lossy().into_owned();
+
+        let mut output = PcapRotate::new(&path);
+        output.max_size = 200;
+        let packets = records();
+        for (stamp_ns, record) in &packets {
+            output.write(*stamp_ns, &record.iface, &record.frame).unwrap();
+        }
+        output.close().unwrap();
+        assert!(output.files.len() > 1);
+        assert!(output.files[0].ends_with("trace-0001.pcapng"));
+
+        // every file is a complete capture, nothing lost between files
+        let mut count = 0;
+        for file in &output.files {
+            for record in CanPcapReader::open(file).unwrap() {
+                assert_eq!(record.unwrap().get_dir(), Some(CanLogDir::Rx));
+                count += 1;
+            }
+        }
+        assert_eq!(count, packets.len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -32,13 +32,91 @@ mod sockbmc;
 #[path = "./socket-j1939.rs"]
 mod sockj1939;
 
+#[path = "./j1939-claim.rs"]
+mod j1939claim;
+
+#[path = "./j1939-tp.rs"]
+mod j1939tp;
+
+#[path = "./j1939-dm.rs"]
+mod j1939dm;
+
+#[path = "./socket-isotp.rs"]
+mod sockisotp;
+
+#[path = "./uds-mod.rs"]
+mod uds;
+
+#[path = "./uds-client.rs"]
+mod udsclient;
+
+#[path = "./uds-server.rs"]
+mod udsserver;
+
+#[path = "./obd-mod.rs"]
+mod obd;
+
+#[path = "./candump-log.rs"]
+mod candump;
+
+#[path = "./candump-replay.rs"]
+mod canreplay;
+
+#[path = "./canopen-mod.rs"]
+mod canopen;
+
+#[path = "./canopen-eds.rs"]
+mod canopeneds;
+
+#[path = "./vector-asc.rs"]
+mod vectorasc;
+
+#[cfg(feature = "blf")]
+#[path = "./vector-blf.rs"]
+mod vectorblf;
+
+#[path = "./pcap-log.rs"]
+mod pcaplog;
+
 #[path = "./dbcpool-mod.rs"]
 mod dbcpool;
 
+#[path = "./dbcpool-sync.rs"]
+mod dbcsync;
+
+#[cfg(feature = "tokio")]
+#[path = "./socket-async.rs"]
+mod sockasync;
+
+#[cfg(all(feature = "serde", feature = "serde_json"))]
+#[path = "./canboat-mod.rs"]
+mod canboat;
+
 pub mod prelude {
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    pub use crate::canboat::*;
+    pub use crate::candump::*;
+    pub use crate::canopen::*;
+    pub use crate::canopeneds::*;
+    pub use crate::canreplay::*;
     pub use crate::dbcpool::*;
+    pub use crate::dbcsync::*;
+    pub use crate::j1939claim::*;
+    pub use crate::j1939dm::*;
+    pub use crate::j1939tp::*;
+    pub use crate::obd::*;
+    pub use crate::pcaplog::*;
+    #[cfg(feature = "tokio")]
+    pub use crate::sockasync::*;
     pub use crate::sockbmc::*;
     pub use crate::sockcan::*;
+    pub use crate::sockisotp::*;
     pub use crate::sockj1939::*;
+    pub use crate::uds::*;
+    pub use crate::udsclient::*;
+    pub use crate::udsserver::*;
     pub use crate::utils::*;
+    pub use crate::vectorasc::*;
+    #[cfg(feature = "blf")]
+    pub use crate::vectorblf::*;
 }
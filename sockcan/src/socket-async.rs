@@ -0,0 +1,299 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    https://docs.rs/tokio/latest/tokio/io/unix/struct.AsyncFd.html
+ *
+*/
+use super::cglue;
+use crate::prelude::*;
+
+use futures_core::Stream;
+use std::future::poll_fn;
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::unix::AsyncFd;
+
+impl AsRawFd for SockCanHandle {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sockfd
+    }
+}
+
+// check without consuming it that a message is pending on a non blocking socket
+fn sock_peek(sock: &SockCanHandle) -> io::Result<()> {
+    let mut byte = 0u8;
+    let flags = i32::try_from(cglue::can_SOCK_x_MSG_PEEK | cglue::can_SOCK_x_MSG_DONTWAIT)
+        .unwrap_or(i32::MAX);
+    let count = unsafe {
+        cglue::recvfrom(
+            sock.sockfd,
+            (&raw mut byte).cast::<std::ffi::c_void>(),
+            1,
+            flags,
+            cglue::__SOCKADDR_ARG { __sockaddr__: std::ptr::null_mut() },
+            std::ptr::null_mut(),
+        )
+    };
+    if count < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Message returned by async readers. Messages consumed without payload (protocol
+/// callback waiting for next frames) are skipped, a read failing with `EAGAIN` waits
+/// for readiness again.
+pub trait SockCanAsyncMsg {
+    fn get_recv_error(&self) -> Option<io::Error>;
+
+    fn is_ignored(&self) -> bool {
+        false
+    }
+}
+
+impl SockCanAsyncMsg for SockCanMsg {
+    fn get_recv_error(&self) -> Option<io::Error> {
+        self.get_os_error()
+    }
+}
+
+impl SockCanAsyncMsg for SockBcmMsg {
+    fn get_recv_error(&self) -> Option<io::Error> {
+        self.get_os_error()
+    }
+}
+
+impl SockCanAsyncMsg for SockJ1939Msg {
+    fn get_recv_error(&self) -> Option<io::Error> {
+        self.get_os_error()
+    }
+
+    fn is_ignored(&self) -> bool {
+        matches!(self.opcode, SockCanOpCode::RxIgnore)
+    }
+}
+
+/// Tokio wrapper around a RAW, BCM or J1939 `SockCanHandle`.
+///
+/// The socket is switched to non-blocking mode and registered within tokio reactor.
+/// As `SockCanHandle` callback is not `Send`, futures should run on a current-thread
+/// runtime or within a `tokio::task::LocalSet`.
+pub struct SockCanAsync {
+    inner: AsyncFd<SockCanHandle>,
+}
+
+impl SockCanAsync {
+    /// Registers an opened socket within tokio reactor.
+    ///
+    /// # Errors
+    /// Returns a `CanError` if the socket cannot be set non-blocking or when
+    /// registration fails (no running tokio runtime, invalid fd, ...).
+    pub fn new(mut sock: SockCanHandle) -> Result<Self, CanError> {
+        sock.set_blocking(false)?;
+        let inner = AsyncFd::new(sock)?;
+        Ok(SockCanAsync { inner })
+    }
+
+    #[must_use]
+    pub fn get_ref(&self) -> &SockCanHandle {
+        self.inner.get_ref()
+    }
+
+    /// Deregisters the socket from tokio and returns it (still in non-blocking mode).
+    #[must_use]
+    pub fn into_inner(self) -> SockCanHandle {
+        self.inner.into_inner()
+    }
+
+    fn poll_read_with<M: SockCanAsyncMsg>(
+        &self,
+        ctx: &mut Context<'_>,
+        reader: fn(&SockCanHandle) -> M,
+    ) -> Poll<Result<M, CanError>> {
+        loop {
+            let mut guard = match self.inner.poll_read_ready(ctx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(CanError::from(error))),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            // readiness may be spurious, only call reader when a message is effectively pending
+            match guard.try_io(|inner| {
+                sock_peek(inner.get_ref())?;
+                let msg = reader(inner.get_ref());
+                // try_io only clears readiness on WouldBlock, other errors are returned
+                match msg.get_recv_error() {
+                    Some(error) => Err(error),
+                    None if msg.is_ignored() => Ok(None),
+                    None => Ok(Some(msg)),
+                }
+            }) {
+                Ok(Ok(Some(msg))) => return Poll::Ready(Ok(msg)),
+                // consumed by protocol callback, readiness is kept for next frames
+                Ok(Ok(None)) => {},
+                Ok(Err(error)) => return Poll::Ready(Err(CanError::from(error))),
+                Err(_would_block) => {},
+            }
+        }
+    }
+
+    // only a failed write syscall reporting EAGAIN waits for writability again,
+    // validation errors are returned as they are
+    async fn write_with<F>(&self, mut writer: F) -> Result<(), CanError>
+    where
+        F: FnMut(&SockCanHandle) -> Result<(), SockWriteError>,
+    {
+        loop {
+            let mut guard = self.inner.writable().await?;
+            let status = guard.try_io(|inner| match writer(inner.get_ref()) {
+                Ok(()) => Ok(Ok(())),
+                Err(SockWriteError::Os(_, error)) if error.kind() == io::ErrorKind::WouldBlock => {
+                    Err(error)
+                },
+                Err(error) => Ok(Err(CanError::from(error))),
+            });
+            match status {
+                Ok(Ok(result)) => return result,
+                Ok(Err(error)) => return Err(CanError::from(error)),
+                Err(_would_block) => {},
+            }
+        }
+    }
+
+    /// Waits for the next frame on a RAW socket.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when tokio reactor or socket read fails. Invalid frames are
+    /// returned as `CanAnyFrame::Err` within `SockCanMsg` as with `get_can_frame`.
+    pub async fn recv(&self) -> Result<SockCanMsg, CanError> {
+        poll_fn(|ctx| self.poll_read_with(ctx, SockCanHandle::get_can_frame)).await
+    }
+
+    /// Waits for the next message on a BCM socket.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when tokio reactor fails.
+    pub async fn recv_bcm(&self) -> Result<SockBcmMsg, CanError> {
+        poll_fn(|ctx| self.poll_read_with(ctx, <SockCanHandle as SockCanBmc>::get_bcm_frame)).await
+    }
+
+    /// Waits for the next message on a J1939 socket.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when tokio reactor fails.
+    pub async fn recv_j1939(&self) -> Result<SockJ1939Msg, CanError> {
+        poll_fn(|ctx| self.poll_read_with(ctx, <SockCanHandle as SockCanJ1939>::get_j1939_frame))
+            .await
+    }
+
+    /// Sends a frame on a RAW socket, waiting for buffer space when needed.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when frame is not sendable or when the write fails.
+    pub async fn send(&self, frame: &CanAnyFrame) -> Result<(), CanError> {
+        self.write_with(|sock| sock.try_write_frame(frame)).await
+    }
+
+    /// Applies a BCM command (filter or cyclic job) on a BCM socket.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the command is invalid or when the write fails.
+    pub async fn send_bcm(&self, cmd: &mut SockBcmCmd) -> Result<(), CanError> {
+        self.write_with(|sock| cmd.try_apply(sock)).await
+    }
+
+    /// Returns an endless stream of RAW frames.
+    #[must_use]
+    pub fn stream(&self) -> SockCanStream<'_, SockCanMsg> {
+        SockCanStream { sock: self, reader: SockCanHandle::get_can_frame }
+    }
+
+    /// Returns an endless stream of BCM messages.
+    #[must_use]
+    pub fn stream_bcm(&self) -> SockCanStream<'_, SockBcmMsg> {
+        SockCanStream { sock: self, reader: <SockCanHandle as SockCanBmc>::get_bcm_frame }
+    }
+
+    /// Returns an endless stream of J1939 messages.
+    #[must_use]
+    pub fn stream_j1939(&self) -> SockCanStream<'_, SockJ1939Msg> {
+        SockCanStream { sock: self, reader: <SockCanHandle as SockCanJ1939>::get_j1939_frame }
+    }
+}
+
+pub struct SockCanStream<'a, M> {
+    sock: &'a SockCanAsync,
+    reader: fn(&SockCanHandle) -> M,
+}
+
+impl<M: SockCanAsyncMsg> Stream for SockCanStream<'_, M> {
+    type Item = Result<M, CanError>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.sock.poll_read_with(ctx, self.reader).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::fd::IntoRawFd;
+    use std::os::unix::net::UnixDatagram;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_send_invalid_frame() {
+        // any pollable fd stands for the CAN socket, no vcan needed
+        let (sock, peer) = UnixDatagram::pair().unwrap();
+        peer.set_nonblocking(true).unwrap();
+        let sock =
+            SockCanHandle { sockfd: sock.into_raw_fd(), mode: SockCanMod::RAW, callback: None };
+        let sock = SockCanAsync::new(sock).unwrap();
+
+        // leaves a stale EAGAIN in errno, invalid frame fails without any syscall
+        let mut byte = [0u8; 1];
+        assert_eq!(peer.recv(&mut byte).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        let frame = CanAnyFrame::None(0x123);
+        let status = tokio::time::timeout(Duration::from_secs(1), sock.send(&frame)).await;
+        let error = status.expect("invalid frame should not wait for writability").unwrap_err();
+        assert_eq!(error.get_uid(), "can-send-invalid");
+    }
+
+    // protocol callback keeping only datagrams starting with 0xAA
+    struct KeepAa;
+    impl SockCanCtrl for KeepAa {
+        fn check_frame(&self, data: &[u8], _info: &CanRecvInfo) -> SockCanOpCode {
+            match data[0] {
+                0xAA => SockCanOpCode::RxRead(data[..2].to_vec()),
+                _ => SockCanOpCode::RxIgnore,
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_recv_skip_ignored() {
+        let (sock, peer) = UnixDatagram::pair().unwrap();
+        let mut sock =
+            SockCanHandle { sockfd: sock.into_raw_fd(), mode: SockCanMod::J1939, callback: None };
+        sock.set_callback(Box::new(KeepAa));
+        let sock = SockCanAsync::new(sock).unwrap();
+
+        // ignored message is skipped, next one is returned
+        peer.send(&[0x55, 1]).unwrap();
+        peer.send(&[0xAA, 2]).unwrap();
+        let msg = tokio::time::timeout(Duration::from_secs(1), sock.recv_j1939()).await;
+        assert_eq!(msg.unwrap().unwrap().get_data(), [0xAA, 2]);
+
+        // an ignored message alone does not wake reader up
+        peer.send(&[0x55, 3]).unwrap();
+        let msg = tokio::time::timeout(Duration::from_millis(50), sock.recv_j1939()).await;
+        assert!(msg.is_err());
+    }
+}
@@ -14,6 +14,7 @@ use bitflags::bitflags;
 
 use super::cglue;
 use crate::prelude::*;
+use std::io;
 use std::mem::{self};
 
 bitflags! {
@@ -94,17 +95,26 @@ impl CanBcmOpCode {
     }
 }
 
-struct CanBcmOneMsg(cglue::can_bcm_one_msg);
-struct CanFdBcmOneMsg(cglue::canfd_bcm_one_msg);
 struct CanBcmHeader(cglue::bcm_msg_head);
 
 pub struct SockBcmMsg {
     opcode: CanBcmOpCode,
     info: CanRecvInfo,
     frame: CanAnyFrame,
+    frames: Vec<CanAnyFrame>,
+    flags: u32,
+    count: u32,
+    ival1: u64,
+    ival2: u64,
 }
 
 impl SockBcmMsg {
+    /// Returns errno captured right after a failed read, `None` when read succeeded.
+    #[must_use]
+    pub fn get_os_error(&self) -> Option<io::Error> {
+        (self.info.errno != 0).then(|| io::Error::from_raw_os_error(self.info.errno))
+    }
+
     #[must_use]
     pub fn get_iface(&self) -> i32 {
         self.info.iface
@@ -115,6 +125,31 @@ impl SockBcmMsg {
         self.opcode
     }
 
+    /// Returns BCM flags as reported by the kernel within message header.
+    #[must_use]
+    pub fn get_flags(&self) -> CanBcmFlag {
+        CanBcmFlag::from_bits_retain(self.flags)
+    }
+
+    /// Returns remaining ival1 frame count (`TX_STATUS` only).
+    #[must_use]
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns (ival1, ival2) timers in milliseconds (`TX_STATUS`/`RX_STATUS` only).
+    #[must_use]
+    pub fn get_timers(&self) -> (u64, u64) {
+        (self.ival1, self.ival2)
+    }
+
+    /// Returns every frame carried by the message. `TX_STATUS` returns the full
+    /// sequence of a cyclic job, others opcodes return at most one frame.
+    #[must_use]
+    pub fn get_frames(&self) -> &[CanAnyFrame] {
+        &self.frames
+    }
+
     #[must_use]
     pub fn get_stamp(&self) -> u64 {
         self.info.stamp
@@ -245,72 +280,86 @@ impl SockCanBmc for SockCanHandle {
     }
 
     fn get_bcm_frame(&self) -> SockBcmMsg {
-        // 1) Use a zero-initialized byte buffer; avoid `assume_init()` UB on uninitialized arrays.
-        let mut buffer: [u8; core::mem::size_of::<CanFdBcmOneMsg>()] =
-            [0u8; core::mem::size_of::<CanFdBcmOneMsg>()];
+        // Use a zero-initialized byte buffer big enough for a full TX_STATUS sequence.
+        let mut buffer: [u8; core::mem::size_of::<cglue::canfd_bcm_msg>()] =
+            [0u8; core::mem::size_of::<cglue::canfd_bcm_msg>()];
 
         // Read raw bytes from the socket into `buffer`
         let info = self.get_raw_frame(&mut buffer);
+        bcm_parse(&buffer, info)
+    }
+}
 
-        // Precompute expected sizes and convert to `isize` safely (avoid direct casts)
-        let sz_hdr = isize::try_from(core::mem::size_of::<CanBcmHeader>()).unwrap_or(isize::MAX);
-        let sz_std = isize::try_from(core::mem::size_of::<CanBcmOneMsg>()).unwrap_or(isize::MAX);
-        let sz_fd = isize::try_from(core::mem::size_of::<CanFdBcmOneMsg>()).unwrap_or(isize::MAX);
-
-        // 2) Safely read the header (for opcode) from an unaligned byte buffer.
-        //    `read_unaligned` avoids undefined behavior due to potential misalignment.
-        let header_for_opcode: CanBcmHeader =
-            unsafe { core::ptr::read_unaligned((&raw const buffer).cast::<CanBcmHeader>()) };
-        let opcode = match CanBcmOpCode::from(header_for_opcode.0.opcode) {
-            Ok(v) => v,
-            Err(_) => CanBcmOpCode::Unknown,
-        };
+// decodes header and std/fd frames sequence received from a BCM socket
+fn bcm_parse(buffer: &[u8], info: CanRecvInfo) -> SockBcmMsg {
+    let sz_hdr = core::mem::size_of::<CanBcmHeader>();
+    let sz_std = core::mem::size_of::<cglue::can_frame>();
+    let sz_fd = core::mem::size_of::<cglue::canfd_frame>();
 
-        // 3) Decode the payload without violating alignment rules.
-        //    We either:
-        //    - copy the exact number of bytes into a `MaybeUninit<T>` with proper alignment,
-        //      then `assume_init()` (safe because we just wrote all bytes), OR
-        //    - read unaligned when we only need a small header field.
-        let can_any_frame = if info.count == sz_std {
-            // Classic BCM one-message (non-FD). Copy into properly aligned storage.
-            let mut tmp = core::mem::MaybeUninit::<CanBcmOneMsg>::uninit();
-            unsafe {
-                core::ptr::copy_nonoverlapping(
-                    (&raw const buffer).cast::<u8>(),
-                    tmp.as_mut_ptr().cast::<u8>(),
-                    core::mem::size_of::<CanBcmOneMsg>(),
-                );
-                // Now it is initialized with valid bytes from the socket.
-                let one_msg = tmp.assume_init();
-                // Get a pointer to the embedded CAN frame in the aligned struct
-                CanAnyFrame::from((&raw const one_msg.0.frame).cast::<CanFrameRaw>())
-            }
-        } else if info.count == sz_fd {
-            // FD BCM one-message. Same strategy as above.
-            let mut tmp = core::mem::MaybeUninit::<CanFdBcmOneMsg>::uninit();
-            unsafe {
-                core::ptr::copy_nonoverlapping(
-                    (&raw const buffer).cast::<u8>(),
-                    tmp.as_mut_ptr().cast::<u8>(),
-                    core::mem::size_of::<CanFdBcmOneMsg>(),
-                );
-                let one_msg = tmp.assume_init();
-                CanAnyFrame::from((&raw const one_msg.0.frame).cast::<CanFdFrameRaw>())
-            }
-        } else if info.count == sz_hdr {
-            // Only a header received — read unaligned to extract `can_id`.
-            let header: CanBcmHeader =
-                unsafe { core::ptr::read_unaligned((&raw const buffer).cast::<CanBcmHeader>()) };
-            CanAnyFrame::None(header.0.can_id)
-        } else {
-            // Unexpected size — return an error frame.
-            CanAnyFrame::Err(CanError::new("bcm-invalid-frame", cglue::get_perror()))
-        };
+    // Safely read the header from an unaligned byte buffer.
+    let header: CanBcmHeader =
+        unsafe { core::ptr::read_unaligned(buffer.as_ptr().cast::<CanBcmHeader>()) };
+    let opcode = match CanBcmOpCode::from(header.0.opcode) {
+        Ok(v) => v,
+        Err(_) => CanBcmOpCode::Unknown,
+    };
 
-        SockBcmMsg { opcode, frame: can_any_frame, info }
+    // Decode the payload as a list of std or fd frames. FD_FRAME flag is set by
+    // the kernel on FD jobs, size is only used as a fallback.
+    let count = usize::try_from(info.count).unwrap_or(0);
+    let payload = count.saturating_sub(sz_hdr);
+    let is_fd = CanBcmFlag::check(&CanBcmFlag::FD_FRAME, header.0.flags)
+        || (payload % sz_std != 0 && payload % sz_fd == 0);
+    let sz_frame = if is_fd { sz_fd } else { sz_std };
+
+    let mut frames = Vec::new();
+    let frame = if info.count < 0 || count < sz_hdr || payload % sz_frame != 0 {
+        // Unexpected size — return an error frame.
+        CanAnyFrame::Err(CanError::new("bcm-invalid-frame", cglue::get_perror()))
+    } else if payload == 0 {
+        // Only a header received (timeout, expired, ...)
+        CanAnyFrame::None(header.0.can_id)
+    } else {
+        for chunk in buffer[sz_hdr..count].chunks_exact(sz_frame) {
+            // read_unaligned copies frame into properly aligned storage
+            let frame = if is_fd {
+                CanAnyFrame::RawFd(CanFdFrameRaw(unsafe {
+                    core::ptr::read_unaligned(chunk.as_ptr().cast::<cglue::canfd_frame>())
+                }))
+            } else {
+                CanAnyFrame::RawStd(CanFrameRaw(unsafe {
+                    core::ptr::read_unaligned(chunk.as_ptr().cast::<cglue::can_frame>())
+                }))
+            };
+            frames.push(frame);
+        }
+        frames[0].clone()
+    };
+
+    SockBcmMsg {
+        opcode,
+        frame,
+        frames,
+        info,
+        flags: header.0.flags,
+        count: header.0.count,
+        ival1: bcm_timeval_to_ms(&header.0.ival1),
+        ival2: bcm_timeval_to_ms(&header.0.ival2),
     }
 }
 
+fn bcm_timeval_from_ms(value: u64) -> cglue::bcm_timeval {
+    let seconds = i64::try_from(value / 1000).unwrap_or(i64::MAX);
+    let microsec = i64::try_from((value % 1000) * 1000).unwrap_or(i64::MAX);
+    cglue::bcm_timeval { tv_sec: seconds, tv_usec: microsec }
+}
+
+fn bcm_timeval_to_ms(value: &cglue::bcm_timeval) -> u64 {
+    let seconds = u64::try_from(value.tv_sec).unwrap_or(0);
+    let microsec = u64::try_from(value.tv_usec).unwrap_or(0);
+    seconds.saturating_mul(1000).saturating_add(microsec / 1000)
+}
+
 pub struct CanBcmMsg(cglue::can_bcm_msg);
 impl CanBcmMsg {
     #[must_use]
@@ -369,6 +418,9 @@ pub struct SockBcmCmd {
     flags: CanBcmFlag,
     rx_watchdog: u64,
     rx_maxrate: u64,
+    tx_count: u32,
+    tx_ival1: u64,
+    tx_ival2: u64,
     canid: SockCanId,
     frames: Vec<CanFrameRaw>,
     fdframes: Vec<CanFdFrameRaw>,
@@ -400,6 +452,19 @@ impl CanBcmAddFilter<CanFdFrameRaw> for SockBcmCmd {
     }
 }
 
+// message buffer must outlive the write call, callers keep it on their stack
+fn bcm_write(
+    sock: &SockCanHandle,
+    buffer: *const ::std::os::raw::c_void,
+    len: usize,
+) -> Result<(), SockWriteError> {
+    let count = unsafe { cglue::write(sock.sockfd, buffer, len) };
+    if usize::try_from(count).unwrap_or(usize::MAX) != len {
+        return Err(SockWriteError::Os("fail-socketbcm-write", io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
 impl SockBcmCmd {
     #[must_use]
     pub fn new(opcode: CanBcmOpCode, flags: CanBcmFlag, canid: SockCanId) -> Self {
@@ -413,6 +478,9 @@ impl SockBcmCmd {
             muxid: Vec::new(),
             rx_maxrate: 0,
             rx_watchdog: 0,
+            tx_count: 0,
+            tx_ival1: 0,
+            tx_ival2: 0,
         }
     }
 
@@ -422,6 +490,15 @@ impl SockBcmCmd {
         self
     }
 
+    /// Set TX cyclic timers: `count` frames are sent every `ival1` ms, then
+    /// transmission continues forever every `ival2` ms (0 stops after count).
+    pub fn set_tx_timers(&mut self, count: u32, ival1: u64, ival2: u64) -> &mut Self {
+        self.tx_count = count;
+        self.tx_ival1 = ival1;
+        self.tx_ival2 = ival2;
+        self
+    }
+
     pub fn add_multiplex<T>(&mut self, filter: T) -> &mut Self
     where
         SockBcmCmd: CanBcmAddFilter<T>,
@@ -436,48 +513,37 @@ impl SockBcmCmd {
         head.flags = self.flags.bits();
         head.nframes = 0;
 
-        if self.rx_watchdog > 0 {
-            // Use clearer names to avoid similar-names lint and improve readability
-            let wd_seconds = i64::try_from(self.rx_watchdog / 1000).unwrap_or(i64::MAX);
-            let wd_microsec =
-                i64::try_from((self.rx_watchdog * 1000) % 1_000_000).unwrap_or(i64::MAX);
-
-            head.ival1 = cglue::bcm_timeval { tv_sec: wd_seconds, tv_usec: wd_microsec };
+        if let CanBcmOpCode::TxSetup = self.opcode {
+            // ival1 is only used while count>0, ival2 runs forever afterward
+            head.count = self.tx_count;
+            head.ival1 = bcm_timeval_from_ms(self.tx_ival1);
+            head.ival2 = bcm_timeval_from_ms(self.tx_ival2);
+        } else {
+            if self.rx_watchdog > 0 {
+                head.ival1 = bcm_timeval_from_ms(self.rx_watchdog);
+            }
+            if self.rx_maxrate > 0 {
+                head.ival2 = bcm_timeval_from_ms(self.rx_maxrate);
+            }
         }
+    }
 
-        if self.rx_maxrate > 0 {
-            // Same here: distinct, descriptive names
-            let mr_seconds = i64::try_from(self.rx_maxrate / 1000).unwrap_or(i64::MAX);
-            let mr_microsec =
-                i64::try_from((self.rx_maxrate * 1000) % 1_000_000).unwrap_or(i64::MAX);
-
-            head.ival2 = cglue::bcm_timeval { tv_sec: mr_seconds, tv_usec: mr_microsec };
+    fn check_nframes(&self) -> Result<(), CanError> {
+        let count = self.muxid.len() + self.frames.len() + self.fdframes.len();
+        if count > cglue::can_SOCK_x_MAX_BCM_CAN_FRAMES as usize {
+            return Err(CanError::new(
+                "invalid-socketcan-filter",
+                format!(
+                    "BCM:too many frames count={count} max={}",
+                    cglue::can_SOCK_x_MAX_BCM_CAN_FRAMES
+                ),
+            ));
         }
+        Ok(())
     }
-    /// Applies the current BCM (Broadcast Manager) filter configuration to the given socket.
-    ///
-    /// This configures the underlying CAN BCM socket with the prepared filter set
-    /// (standard/FD frames, masks, timers, and flags), replacing any previous configuration.
-    ///
-    /// # Parameters
-    /// - `sock`: The target socket handle to configure.
-    ///
-    /// # Returns
-    /// `Ok(())` on success.
-    ///
-    /// # Errors
-    /// Returns a `CanError` if:
-    /// - a system call fails (e.g., `socket`, `bind`, `setsockopt`, or `send`/`recvmsg`);
-    /// - the filter set is empty or internally inconsistent (e.g., frame/mask count mismatch);
-    /// - requested options are invalid for the platform or protocol (e.g., FD flags on a non-FD socket);
-    /// - numeric conversions of sizes/lengths fail validation (truncation/overflow);
-    /// - an internal borrow/state conflict prevents applying the configuration.
-    pub fn apply(&mut self, sock: &SockCanHandle) -> Result<(), CanError> {
-        match sock.mode {
-            SockCanMod::BCM => {},
-            _ => return Err(CanError::new("invalid-socketcan-mod", "not a BCM socketcan")),
-        }
 
+    // opcode specific frame/flag/timer consistency checks
+    fn check_opcode(&self) -> Result<(), CanError> {
         match self.opcode {
             CanBcmOpCode::RxSetup => {
                 if CanBcmFlag::check(&CanBcmFlag::RX_FILTER_ID, self.flags.bits()) {
@@ -516,65 +582,491 @@ impl SockBcmCmd {
                     ));
                 }
             },
+
+            CanBcmOpCode::TxSetup => {
+                if !self.muxid.is_empty() {
+                    return Err(CanError::new(
+                        "invalid-socketcan-filter",
+                        "BCM:TxSetup does not accept multiplex ids",
+                    ));
+                }
+                if CanBcmFlag::check(&CanBcmFlag::FD_FRAME, self.flags.bits()) {
+                    if !self.frames.is_empty() || self.fdframes.is_empty() {
+                        return Err(CanError::new(
+                            "invalid-socketcan-frame",
+                            "BCM:TxSetup FdFrame requires fd frames only",
+                        ));
+                    }
+                } else if !self.fdframes.is_empty() || self.frames.is_empty() {
+                    return Err(CanError::new(
+                        "invalid-socketcan-frame",
+                        "BCM:TxSetup StdFrame requires std frames only",
+                    ));
+                }
+                if self.tx_count > 0 && self.tx_ival1 == 0 {
+                    return Err(CanError::new(
+                        "invalid-socketcan-timer",
+                        "BCM:TxSetup count requires a non null ival1",
+                    ));
+                }
+            },
+
+            CanBcmOpCode::TxSend => {
+                if self.muxid.len() + self.frames.len() + self.fdframes.len() != 1 {
+                    return Err(CanError::new(
+                        "invalid-socketcan-frame",
+                        "BCM:TxSend requires exactly one frame",
+                    ));
+                }
+                if CanBcmFlag::check(&CanBcmFlag::FD_FRAME, self.flags.bits())
+                    != self.frames.is_empty()
+                {
+                    return Err(CanError::new(
+                        "invalid-socketcan-frame",
+                        "BCM:TxSend frame type does not match FD_FRAME flag",
+                    ));
+                }
+            },
+
+            CanBcmOpCode::TxDelete | CanBcmOpCode::TxRead | CanBcmOpCode::RxRead => {
+                if !self.frames.is_empty() || !self.fdframes.is_empty() {
+                    return Err(CanError::new(
+                        "invalid-socketcan-filter",
+                        "BCM:Delete/Read does not accept frames",
+                    ));
+                }
+            },
+
             _ => {
                 return Err(CanError::new(
                     "invalid-bcm-operation",
-                    "bcm operation not yet implemented",
+                    "bcm operation not supported from userspace",
                 ));
             },
         }
+        Ok(())
+    }
 
-        let (buffer_addr, buffer_len) =
-            if CanBcmFlag::check(&CanBcmFlag::FD_FRAME, self.flags.bits()) {
-                // fdcan can messages
-                // SAFETY: canfd_bcm_msg est un POD C ; une init à zéro est valide pour cet appel.
-                let mut bcm_msg: cglue::canfd_bcm_msg = unsafe { std::mem::zeroed() };
-                self.msg_head(&mut bcm_msg.head);
+    // multiplex ids follow user frames, nframes counts both
+    fn fd_msg(&self) -> cglue::canfd_bcm_msg {
+        // fdcan can messages
+        // SAFETY: canfd_bcm_msg est un POD C ; une init à zéro est valide pour cet appel.
+        let mut bcm_msg: cglue::canfd_bcm_msg = unsafe { std::mem::zeroed() };
+        self.msg_head(&mut bcm_msg.head);
 
-                for idx in 0..self.fdframes.len() {
-                    bcm_msg.fdframes[idx] = self.fdframes[idx].0;
-                }
+        for idx in 0..self.fdframes.len() {
+            bcm_msg.fdframes[idx] = self.fdframes[idx].0;
+        }
 
-                for idx in 0..self.muxid.len() {
-                    bcm_msg.fdframes[idx] = CanFdFrameRaw::empty(self.muxid[idx]).0;
-                }
+        for idx in 0..self.muxid.len() {
+            bcm_msg.fdframes[self.fdframes.len() + idx] = CanFdFrameRaw::empty(self.muxid[idx]).0;
+        }
 
-                bcm_msg.head.nframes =
-                    u32::try_from(self.muxid.len().saturating_add(self.frames.len()))
-                        .unwrap_or(u32::MAX);
-                let buffer = (&raw const bcm_msg).cast::<::std::os::raw::c_void>();
-                let len = mem::size_of::<cglue::bcm_msg_head>()
-                    + bcm_msg.head.nframes as usize * mem::size_of::<cglue::can_frame>();
-                (buffer, len)
-            } else {
-                // standard can messages
-                // Initialize the struct to all zeros (safe, no UB)
-                let mut bcm_msg: cglue::can_bcm_msg = unsafe { std::mem::zeroed() };
-                // haed is common to std and fd frames
-                self.msg_head(&mut bcm_msg.head);
-
-                for idx in 0..self.frames.len() {
-                    bcm_msg.frames[idx] = self.frames[idx].0;
-                }
+        bcm_msg.head.nframes =
+            u32::try_from(self.muxid.len().saturating_add(self.fdframes.len())).unwrap_or(u32::MAX);
+        bcm_msg
+    }
 
-                for idx in 0..self.muxid.len() {
-                    bcm_msg.frames[idx] = CanFrameRaw::empty(self.muxid[idx]).0;
-                }
+    fn std_msg(&self) -> cglue::can_bcm_msg {
+        // standard can messages
+        // Initialize the struct to all zeros (safe, no UB)
+        let mut bcm_msg: cglue::can_bcm_msg = unsafe { std::mem::zeroed() };
+        // haed is common to std and fd frames
+        self.msg_head(&mut bcm_msg.head);
 
-                bcm_msg.head.nframes =
-                    u32::try_from(self.muxid.len() + self.frames.len()).unwrap_or(u32::MAX);
-                let buffer = (&raw const bcm_msg).cast::<::std::os::raw::c_void>();
-                let len = mem::size_of::<cglue::bcm_msg_head>()
-                    + bcm_msg.head.nframes as usize * mem::size_of::<cglue::can_frame>();
+        for idx in 0..self.frames.len() {
+            bcm_msg.frames[idx] = self.frames[idx].0;
+        }
 
-                (buffer, len)
-            };
+        for idx in 0..self.muxid.len() {
+            bcm_msg.frames[self.frames.len() + idx] = CanFrameRaw::empty(self.muxid[idx]).0;
+        }
+
+        bcm_msg.head.nframes =
+            u32::try_from(self.muxid.len() + self.frames.len()).unwrap_or(u32::MAX);
+        bcm_msg
+    }
+
+    fn write_fd_msg(&self, sock: &SockCanHandle) -> Result<(), SockWriteError> {
+        let bcm_msg = self.fd_msg();
+        let buffer = (&raw const bcm_msg).cast::<::std::os::raw::c_void>();
+        let len = mem::size_of::<cglue::bcm_msg_head>()
+            + bcm_msg.head.nframes as usize * mem::size_of::<cglue::canfd_frame>();
+        bcm_write(sock, buffer, len)
+    }
+
+    fn write_std_msg(&self, sock: &SockCanHandle) -> Result<(), SockWriteError> {
+        let bcm_msg = self.std_msg();
+        let buffer = (&raw const bcm_msg).cast::<::std::os::raw::c_void>();
+        let len = mem::size_of::<cglue::bcm_msg_head>()
+            + bcm_msg.head.nframes as usize * mem::size_of::<cglue::can_frame>();
+
+        bcm_write(sock, buffer, len)
+    }
 
-        let count = unsafe { cglue::write(sock.sockfd, buffer_addr, buffer_len) };
-        let count_usize = usize::try_from(count).unwrap_or(usize::MAX);
-        if count_usize != buffer_len {
-            return Err(CanError::new("fail-socketbcm-write", cglue::get_perror()));
+    /// Applies the current BCM (Broadcast Manager) filter configuration to the given socket.
+    ///
+    /// This configures the underlying CAN BCM socket with the prepared filter set
+    /// (standard/FD frames, masks, timers, and flags), replacing any previous configuration.
+    ///
+    /// # Parameters
+    /// - `sock`: The target socket handle to configure.
+    ///
+    /// # Returns
+    /// `Ok(())` on success.
+    ///
+    /// # Errors
+    /// Returns a `CanError` if:
+    /// - a system call fails (e.g., `socket`, `bind`, `setsockopt`, or `send`/`recvmsg`);
+    /// - the filter set is empty or internally inconsistent (e.g., frame/mask count mismatch);
+    /// - requested options are invalid for the platform or protocol (e.g., FD flags on a non-FD socket);
+    /// - numeric conversions of sizes/lengths fail validation (truncation/overflow);
+    /// - an internal borrow/state conflict prevents applying the configuration.
+    pub fn apply(&mut self, sock: &SockCanHandle) -> Result<(), CanError> {
+        self.try_apply(sock).map_err(CanError::from)
+    }
+
+    // errno is read right after write(), see SockWriteError
+    pub(crate) fn try_apply(&mut self, sock: &SockCanHandle) -> Result<(), SockWriteError> {
+        match sock.mode {
+            SockCanMod::BCM => {},
+            _ => return Err(CanError::new("invalid-socketcan-mod", "not a BCM socketcan").into()),
         }
-        Ok(())
+        self.check_opcode()?;
+        self.check_nframes()?;
+
+        if CanBcmFlag::check(&CanBcmFlag::FD_FRAME, self.flags.bits()) {
+            self.write_fd_msg(sock)
+        } else {
+            self.write_std_msg(sock)
+        }
+    }
+}
+
+/// Typed helper to manage a BCM cyclic transmission job for one CAN id.
+///
+/// ```ignore
+/// let sock = SockCanHandle::open_bcm("vcan0", CanTimeStamp::NONE)?;
+/// SockBcmTx::new(0x123).set_cycle(100).add_frame(frame).start(&sock)?;
+/// ```
+pub struct SockBcmTx {
+    canid: SockCanId,
+    flags: CanBcmFlag,
+    count: u32,
+    ival1: u64,
+    ival2: u64,
+    frames: Vec<CanAnyFrame>,
+}
+
+impl SockBcmTx {
+    #[must_use]
+    pub fn new(canid: SockCanId) -> Self {
+        SockBcmTx {
+            canid,
+            flags: CanBcmFlag::NONE,
+            count: 0,
+            ival1: 0,
+            ival2: 0,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Send frames forever every `period` ms.
+    pub fn set_cycle(&mut self, period: u64) -> &mut Self {
+        self.ival2 = period;
+        self
+    }
+
+    /// Send `count` frames every `ival1` ms before switching to cycle period.
+    pub fn set_count(&mut self, count: u32, ival1: u64) -> &mut Self {
+        self.count = count;
+        self.ival1 = ival1;
+        self
+    }
+
+    /// Request a `TxExpired` notification when count reaches zero.
+    pub fn set_notify_expired(&mut self, value: bool) -> &mut Self {
+        self.flags.set(CanBcmFlag::TX_COUNTEVT, value);
+        self
+    }
+
+    /// Send the first frame immediately when the job is updated.
+    pub fn set_announce(&mut self, value: bool) -> &mut Self {
+        self.flags.set(CanBcmFlag::TX_ANNOUNCE, value);
+        self
+    }
+
+    /// Overwrite frames canid with job canid.
+    pub fn copy_canid(&mut self, value: bool) -> &mut Self {
+        self.flags.set(CanBcmFlag::TX_CP_CAN_ID, value);
+        self
+    }
+
+    /// Append a frame to the sequence, frames are sent in turn at each cycle.
+    pub fn add_frame(&mut self, frame: CanAnyFrame) -> &mut Self {
+        self.frames.push(frame);
+        self
+    }
+
+    fn command(&self, opcode: CanBcmOpCode, flags: CanBcmFlag) -> Result<SockBcmCmd, CanError> {
+        let mut is_fd = false;
+        let mut cmd = SockBcmCmd::new(opcode, CanBcmFlag::NONE, self.canid);
+        for frame in &self.frames {
+            match frame {
+                CanAnyFrame::RawStd(frame) => {
+                    cmd.add_multiplex(*frame);
+                },
+                CanAnyFrame::RawFd(frame) => {
+                    is_fd = true;
+                    cmd.add_multiplex(*frame);
+                },
+                _ => {
+                    return Err(CanError::new(
+                        "invalid-socketcan-frame",
+                        "BCM:Tx accepts only std or fd frames",
+                    ));
+                },
+            }
+        }
+        cmd.flags = if is_fd { flags | CanBcmFlag::FD_FRAME } else { flags };
+        cmd.set_tx_timers(self.count, self.ival1, self.ival2);
+        Ok(cmd)
+    }
+
+    /// Create or restart the cyclic job with current timers and frames.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when frames are missing/mixed or when the BCM write fails.
+    pub fn start(&self, sock: &SockCanHandle) -> Result<(), CanError> {
+        let flags = CanBcmFlag::from_bits_retain(self.flags.bits())
+            | CanBcmFlag::SET_TIMER
+            | CanBcmFlag::START_TIMER;
+        self.command(CanBcmOpCode::TxSetup, flags)?.apply(sock)
+    }
+
+    /// Update frames content of a running job without touching its timers.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when frames are missing/mixed or when the BCM write fails.
+    pub fn update(&self, sock: &SockCanHandle) -> Result<(), CanError> {
+        let flags = CanBcmFlag::from_bits_retain(self.flags.bits());
+        self.command(CanBcmOpCode::TxSetup, flags)?.apply(sock)
+    }
+
+    /// Remove the cyclic job.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the job does not exist or when the BCM write fails.
+    pub fn stop(&self, sock: &SockCanHandle) -> Result<(), CanError> {
+        SockBcmCmd::new(CanBcmOpCode::TxDelete, CanBcmFlag::NONE, self.canid).apply(sock)
+    }
+
+    /// Send the first frame once, independently of any cyclic job.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when no frame is defined or when the BCM write fails.
+    pub fn send_once(&self, sock: &SockCanHandle) -> Result<(), CanError> {
+        let Some(frame) = self.frames.first() else {
+            return Err(CanError::new("invalid-socketcan-frame", "BCM:TxSend no frame defined"));
+        };
+        let mut job = SockBcmTx::new(self.canid);
+        job.add_frame(frame.clone());
+        job.command(CanBcmOpCode::TxSend, CanBcmFlag::NONE)?.apply(sock)
+    }
+
+    /// Request job status, kernel answers with a `TxStatus` message readable
+    /// with `get_bcm_frame`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the job does not exist or when the BCM write fails.
+    pub fn read(&self, sock: &SockCanHandle) -> Result<(), CanError> {
+        SockBcmCmd::new(CanBcmOpCode::TxRead, CanBcmFlag::NONE, self.canid).apply(sock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bcm_mux_layout() {
+        let mut cmd = SockBcmCmd::new(CanBcmOpCode::RxSetup, CanBcmFlag::NONE, 0x100);
+        cmd.add_multiplex(CanFrameRaw::new(0x100, 8, 0, 0, [0xFF, 0, 0, 0, 0, 0, 0, 0]))
+            .add_multiplex(0x101)
+            .add_multiplex(0x102);
+        let msg = cmd.std_msg();
+        assert_eq!(msg.head.nframes, 3);
+        let ids: Vec<u32> = msg.frames[..4].iter().map(|frame| frame.can_id).collect();
+        assert_eq!(ids, [0x100, 0x101, 0x102, 0]);
+        assert_eq!(msg.frames[0].data[0], 0xFF);
+
+        let mut cmd = SockBcmCmd::new(CanBcmOpCode::RxSetup, CanBcmFlag::FD_FRAME, 0x200);
+        cmd.add_multiplex(CanFdFrameRaw::empty(0x200)).add_multiplex(0x201);
+        let msg = cmd.fd_msg();
+        assert_eq!(msg.head.nframes, 2);
+        let ids: Vec<u32> = msg.fdframes[..3].iter().map(|frame| frame.can_id).collect();
+        assert_eq!(ids, [0x200, 0x201, 0]);
+    }
+
+    fn std_frame(canid: u32) -> CanFrameRaw {
+        CanFrameRaw::new(canid, 2, 0, 0, [0x11, 0x22, 0, 0, 0, 0, 0, 0])
+    }
+
+    #[test]
+    fn test_bcm_check_opcode() {
+        let check = |opcode: CanBcmOpCode, flags: CanBcmFlag, setup: &dyn Fn(&mut SockBcmCmd)| {
+            let mut cmd = SockBcmCmd::new(opcode, flags, 0x123);
+            setup(&mut cmd);
+            cmd.check_opcode().and_then(|()| cmd.check_nframes()).is_ok()
+        };
+        let std = |cmd: &mut SockBcmCmd| {
+            cmd.add_multiplex(std_frame(0x123));
+        };
+        let fd = |cmd: &mut SockBcmCmd| {
+            cmd.add_multiplex(CanFdFrameRaw::empty(0x123));
+        };
+        let none = |_: &mut SockBcmCmd| {};
+
+        // RX_SETUP: filter id excludes mux frames, otherwise a filter of matching type
+        assert!(check(CanBcmOpCode::RxSetup, CanBcmFlag::RX_FILTER_ID, &none));
+        assert!(!check(CanBcmOpCode::RxSetup, CanBcmFlag::RX_FILTER_ID, &std));
+        assert!(check(CanBcmOpCode::RxSetup, CanBcmFlag::NONE, &std));
+        assert!(!check(CanBcmOpCode::RxSetup, CanBcmFlag::NONE, &none));
+        assert!(check(CanBcmOpCode::RxSetup, CanBcmFlag::FD_FRAME, &fd));
+        assert!(!check(CanBcmOpCode::RxSetup, CanBcmFlag::FD_FRAME, &std));
+        assert!(!check(CanBcmOpCode::RxSetup, CanBcmFlag::FD_FRAME, &none));
+
+        assert!(check(CanBcmOpCode::RxDelete, CanBcmFlag::NONE, &none));
+        assert!(!check(CanBcmOpCode::RxDelete, CanBcmFlag::NONE, &std));
+
+        // TX_SETUP: frames must match FD_FRAME flag, no mux ids, count needs ival1
+        assert!(check(CanBcmOpCode::TxSetup, CanBcmFlag::NONE, &std));
+        assert!(check(CanBcmOpCode::TxSetup, CanBcmFlag::FD_FRAME, &fd));
+        assert!(!check(CanBcmOpCode::TxSetup, CanBcmFlag::NONE, &none));
+        assert!(!check(CanBcmOpCode::TxSetup, CanBcmFlag::NONE, &fd));
+        assert!(!check(CanBcmOpCode::TxSetup, CanBcmFlag::FD_FRAME, &std));
+        assert!(!check(CanBcmOpCode::TxSetup, CanBcmFlag::NONE, &|cmd| {
+            cmd.add_multiplex(std_frame(0x123)).add_multiplex(0x124);
+        }));
+        assert!(!check(CanBcmOpCode::TxSetup, CanBcmFlag::NONE, &|cmd| {
+            cmd.add_multiplex(std_frame(0x123)).set_tx_timers(3, 0, 100);
+        }));
+
+        // TX_SEND: exactly one frame of FD_FRAME type
+        assert!(check(CanBcmOpCode::TxSend, CanBcmFlag::NONE, &std));
+        assert!(check(CanBcmOpCode::TxSend, CanBcmFlag::FD_FRAME, &fd));
+        assert!(!check(CanBcmOpCode::TxSend, CanBcmFlag::NONE, &none));
+        assert!(!check(CanBcmOpCode::TxSend, CanBcmFlag::NONE, &fd));
+        assert!(!check(CanBcmOpCode::TxSend, CanBcmFlag::FD_FRAME, &std));
+        assert!(!check(CanBcmOpCode::TxSend, CanBcmFlag::NONE, &|cmd| {
+            cmd.add_multiplex(std_frame(0x123)).add_multiplex(std_frame(0x123));
+        }));
+
+        for opcode in [CanBcmOpCode::TxDelete, CanBcmOpCode::TxRead, CanBcmOpCode::RxRead] {
+            assert!(check(opcode, CanBcmFlag::NONE, &none));
+            assert!(!check(opcode, CanBcmFlag::NONE, &std));
+            assert!(!check(opcode, CanBcmFlag::FD_FRAME, &fd));
+        }
+
+        // kernel to user opcodes are refused
+        for opcode in [
+            CanBcmOpCode::TxStatus,
+            CanBcmOpCode::TxExpired,
+            CanBcmOpCode::RxStatus,
+            CanBcmOpCode::RxTimeout,
+            CanBcmOpCode::RxChanged,
+            CanBcmOpCode::Unknown,
+        ] {
+            assert!(!check(opcode, CanBcmFlag::NONE, &none));
+        }
+
+        // frame count is bounded by kernel buffer
+        let max = cglue::can_SOCK_x_MAX_BCM_CAN_FRAMES;
+        assert!(check(CanBcmOpCode::RxSetup, CanBcmFlag::NONE, &|cmd| {
+            (0..max).for_each(|idx| {
+                cmd.add_multiplex(std_frame(idx));
+            });
+        }));
+        assert!(!check(CanBcmOpCode::RxSetup, CanBcmFlag::NONE, &|cmd| {
+            (0..=max).for_each(|idx| {
+                cmd.add_multiplex(std_frame(idx));
+            });
+        }));
+    }
+
+    #[test]
+    fn test_bcm_msg_head() {
+        let mut cmd = SockBcmCmd::new(CanBcmOpCode::TxSetup, CanBcmFlag::SET_TIMER, 0x123);
+        cmd.add_multiplex(std_frame(0x123)).add_multiplex(std_frame(0x123));
+        cmd.set_tx_timers(3, 10, 1_500).set_timers(7, 9);
+        let msg = cmd.std_msg();
+        assert_eq!(msg.head.opcode, cglue::can_BCM_OPE_x_TX_SETUP);
+        assert_eq!(msg.head.flags, CanBcmFlag::SET_TIMER.bits());
+        assert_eq!((msg.head.can_id, msg.head.nframes, msg.head.count), (0x123, 2, 3));
+        // TX timers ignore RX watchdog/maxrate
+        assert_eq!((msg.head.ival1.tv_sec, msg.head.ival1.tv_usec), (0, 10_000));
+        assert_eq!((msg.head.ival2.tv_sec, msg.head.ival2.tv_usec), (1, 500_000));
+        assert_eq!(msg.frames[1].data[..2], [0x11, 0x22]);
+
+        // RX watchdog goes in ival1, max rate in ival2
+        let mut cmd = SockBcmCmd::new(CanBcmOpCode::RxSetup, CanBcmFlag::RX_FILTER_ID, 0x321);
+        cmd.set_timers(200, 2_500).set_tx_timers(3, 10, 1_500);
+        let msg = cmd.std_msg();
+        assert_eq!((msg.head.count, msg.head.nframes), (0, 0));
+        assert_eq!((msg.head.ival1.tv_sec, msg.head.ival1.tv_usec), (2, 500_000));
+        assert_eq!((msg.head.ival2.tv_sec, msg.head.ival2.tv_usec), (0, 200_000));
+
+        // cyclic job helper sets FD_FRAME from its frames and refuses mixed sequences
+        let mut job = SockBcmTx::new(0x456);
+        job.set_cycle(100).add_frame(CanAnyFrame::RawFd(CanFdFrameRaw::empty(0x456)));
+        let cmd = job.command(CanBcmOpCode::TxSetup, CanBcmFlag::SET_TIMER).unwrap();
+        assert!(CanBcmFlag::check(&CanBcmFlag::FD_FRAME, cmd.flags.bits()));
+        assert_eq!(cmd.fd_msg().head.nframes, 1);
+        job.add_frame(CanAnyFrame::RawStd(std_frame(0x456)));
+        let cmd = job.command(CanBcmOpCode::TxSetup, CanBcmFlag::SET_TIMER).unwrap();
+        assert!(cmd.check_opcode().is_err());
+    }
+
+    #[test]
+    fn test_bcm_tx_status() {
+        let mut cmd = SockBcmCmd::new(CanBcmOpCode::TxSetup, CanBcmFlag::NONE, 0x123);
+        cmd.add_multiplex(std_frame(0x123)).add_multiplex(std_frame(0x124));
+        cmd.set_tx_timers(3, 10, 1_500);
+        let mut msg = cmd.std_msg();
+        msg.head.opcode = cglue::can_BCM_OPE_x_TX_STATUS;
+
+        let mut buffer = [0u8; mem::size_of::<cglue::canfd_bcm_msg>()];
+        let raw = unsafe {
+            std::slice::from_raw_parts(
+                (&raw const msg).cast::<u8>(),
+                mem::size_of::<cglue::can_bcm_msg>(),
+            )
+        };
+        buffer[..raw.len()].copy_from_slice(raw);
+
+        let sz_status =
+            mem::size_of::<cglue::bcm_msg_head>() + 2 * mem::size_of::<cglue::can_frame>();
+        let info = |count: usize| CanRecvInfo {
+            proto: CanProtoInfo::None,
+            stamp: 0,
+            stamp_ns: 0,
+            count: isize::try_from(count).unwrap(),
+            iface: 0,
+            errno: 0,
+        };
+        let status = bcm_parse(&buffer, info(sz_status));
+        assert!(matches!(status.get_opcode(), CanBcmOpCode::TxStatus));
+        assert_eq!(status.get_count(), 3);
+        assert_eq!(status.get_timers(), (10, 1_500));
+        let ids: Vec<u32> =
+            status.get_frames().iter().map(|frame| frame.get_id().unwrap()).collect();
+        assert_eq!(ids, [0x123, 0x124]);
+        assert_eq!(status.get_data().unwrap()[..2], [0x11, 0x22]);
+
+        // header only answer carries the job canid, truncated frame is an error
+        let status = bcm_parse(&buffer, info(mem::size_of::<cglue::bcm_msg_head>()));
+        assert!(matches!(status.get_raw(), CanAnyFrame::None(0x123)));
+        let status = bcm_parse(&buffer, info(sz_status - 1));
+        assert!(matches!(status.get_raw(), CanAnyFrame::Err(_)));
     }
 }
@@ -14,6 +14,7 @@
 use bitflags::bitflags;
 use std::cell::RefCell;
 use std::ffi::CStr;
+use std::io;
 use std::os::raw::c_char;
 
 use super::cglue;
@@ -31,7 +32,7 @@ const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
 /// - if `id <= 0x7FF`, return 11-bit Standard ID (no flags)
 /// - else, return 29-bit Extended ID with CAN_EFF_FLAG set
 #[inline]
-fn normalize_can_id(id: u32) -> u32 {
+pub(crate) fn normalize_can_id(id: u32) -> u32 {
     if id <= CAN_SFF_MASK {
         id & CAN_SFF_MASK
     } else {
@@ -84,12 +85,34 @@ pub fn generate_frame(id: u32, data: &[u8]) -> Result<CanAnyFrame, CanError> {
         let raw = CanFrameRaw(f);
         Ok(CanAnyFrame::RawStd(raw))
     } else {
-        let f = build_fd_frame(id, data, 0)?;
-        let raw = CanFdFrameRaw(f);
-        Ok(CanAnyFrame::RawFd(raw))
+        generate_fd_frame(id, data, CanFdFlags::empty())
     }
 }
 
+/// Valid CAN FD payload lengths (DLC 0..=15)
+const CANFD_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Returns the smallest valid CAN FD payload length able to hold `len` bytes.
+#[must_use]
+pub fn can_fd_len(len: usize) -> Option<usize> {
+    CANFD_LENGTHS.iter().copied().find(|valid| *valid >= len)
+}
+
+/// Build a CAN FD frame whatever the payload size (0..=64 bytes) with BRS/ESI flags.
+///
+/// Payload is zero padded up to the next valid CAN FD length (e.g. 10 → 12 bytes).
+///
+/// # Errors
+/// Returns a `CanError` when payload is bigger than 64 bytes.
+pub fn generate_fd_frame(id: u32, data: &[u8], flags: CanFdFlags) -> Result<CanAnyFrame, CanError> {
+    let Some(len) = can_fd_len(data.len()) else {
+        return Err(CanError::new("can-build-fd", "payload > 64 bytes"));
+    };
+    let mut f = build_fd_frame(id, data, flags.bits())?;
+    f.len = u8::try_from(len).unwrap_or(64);
+    Ok(CanAnyFrame::RawFd(CanFdFrameRaw(f)))
+}
+
 bitflags! {
     #[derive(PartialEq, Eq, Debug)]
     pub struct FilterMask: cglue::canid_t {
@@ -108,6 +131,18 @@ bitflags! {
     }
 }
 
+bitflags! {
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+    pub struct CanFdFlags: u8 {
+        /// BRS bit rate switch, payload is sent with data bitrate
+        #[allow(clippy::cast_possible_truncation)]
+        const BRS = cglue::can_FLAGS_x_FD_BRS as u8;
+        /// ESI error state indicator, transmitting node is error passive
+        #[allow(clippy::cast_possible_truncation)]
+        const ESI = cglue::can_FLAGS_x_FD_ESI as u8;
+    }
+}
+
 bitflags! {
     #[derive(PartialEq, Eq, Debug)]
     pub struct CanErrorMask: cglue::canid_t {
@@ -138,6 +173,7 @@ pub enum CanTimeStamp {
 ///            8 bytes but the DLC value (see ISO 11898-1) is greater then 8.
 ///            `CAN_CTRLMODE_CC_LEN8_DLC` flag has to be enabled in CAN driver.
 /// @data:     CAN frame payload (up to 8 byte)
+#[derive(Clone, Copy)]
 pub struct CanFrameRaw(pub cglue::can_frame);
 
 impl CanFrameRaw {
@@ -186,7 +222,7 @@ impl CanFrameRaw {
     ///
     /// # Example
     /// ```rust
-    /// use crate::sockcan::CanFrameRaw;
+    /// use sockcan::prelude::CanFrameRaw;
     /// const CAN_EFF_FLAG: u32 = 0x8000_0000;
     ///
     /// // Standard ID 0x118, 8-byte payload
@@ -216,7 +252,7 @@ impl CanFrameRaw {
     ///
     /// # Example
     /// ```rust
-    /// use crate::sockcan::CanFrameRaw;
+    /// use sockcan::prelude::CanFrameRaw;
     /// const CAN_EFF_FLAG: u32 = 0x8000_0000;
     ///
     /// // Standard ID 0x118, 8-byte payload
@@ -263,6 +299,7 @@ impl CanFrameRaw {
         &self.0.data
     }
 }
+#[derive(Clone, Copy)]
 pub struct CanFdFrameRaw(pub cglue::canfd_frame);
 impl CanFdFrameRaw {
     /// Constructs a **CAN FD** frame wrapper from raw fields.
@@ -299,13 +336,14 @@ impl CanFdFrameRaw {
     ///
     /// # Examples
     /// ```rust
+    /// use sockcan::prelude::{CanFdFlags, CanFdFrameRaw};
+    ///
     /// // Extended FD frame with BRS, 12-byte payload
     /// const CAN_EFF_FLAG: u32 = 0x8000_0000;
     /// let ext_id = (0x18DAF110 & 0x1FFF_FFFF) | CAN_EFF_FLAG;
-    /// let data = [0x01,0x02,0x03,0x04,0x05,0x06,0x07,0x08, 0x09,0x0A,0x0B,0x0C,
-    ///             0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,
-    ///             0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0];
-    /// let flags = cglue::CANFD_BRS; // or 0 if no BRS/ESI
+    /// let mut data = [0u8; 64];
+    /// data[..12].copy_from_slice(&[0x01,0x02,0x03,0x04,0x05,0x06,0x07,0x08,0x09,0x0A,0x0B,0x0C]);
+    /// let flags = CanFdFlags::BRS.bits(); // or 0 if no BRS/ESI
     /// let f_fd = CanFdFrameRaw::new(ext_id, 12, flags, 0, 0, data);
     ///
     /// // Standard FD frame (no EFF flag), 3-byte payload, no BRS
@@ -401,6 +439,8 @@ impl CanFdFrameRaw {
 ///
 /// # Example
 /// ```rust
+/// # use sockcan::prelude::*;
+/// # fn show(msg: &SockCanMsg) {
 /// match msg.get_raw() {
 ///     CanAnyFrame::RawStd(f) => {
 ///         let id = f.get_id();           // 11b or 29b (EFF cleared)
@@ -421,7 +461,9 @@ impl CanFdFrameRaw {
 ///         log::debug!("timeout/announce for id {:08X}", canid);
 ///     }
 /// }
+/// # }
 /// ```
+#[derive(Clone)]
 pub enum CanAnyFrame {
     /// Classical CAN frame (CAN 2.0B).
     RawFd(CanFdFrameRaw),
@@ -512,6 +554,8 @@ impl From<*const CanFdFrameRaw> for CanAnyFrame {
 pub struct SockCanMsg {
     iface: i32,
     stamp: u64,
+    stamp_ns: u64,
+    errno: i32,
     frame: CanAnyFrame,
 }
 
@@ -526,6 +570,19 @@ impl SockCanMsg {
         self.stamp
     }
 
+    /// Receive time in nanoseconds, full precision with `CanTimeStamp::NANOSEC/HARDWARE`.
+    #[must_use]
+    pub fn get_stamp_ns(&self) -> u64 {
+        self.stamp_ns
+    }
+
+    /// OS error of the failed read (`WouldBlock` on timeout), None when a message was
+    /// received, even if it is not a valid CAN frame.
+    #[must_use]
+    pub fn get_os_error(&self) -> Option<io::Error> {
+        (self.errno != 0).then(|| io::Error::from_raw_os_error(self.errno))
+    }
+
     #[must_use]
     pub fn get_raw(&self) -> &CanAnyFrame {
         &self.frame
@@ -595,7 +652,7 @@ pub enum SockCanMod {
     RAW,
     BCM,
     J1939,
-    _ISOTP,
+    ISOTP,
 }
 
 #[derive(Clone, Copy)]
@@ -608,6 +665,7 @@ pub struct CanJ1939Info {
     pub src: CanJ1939Header,
     pub dst: CanJ1939Header,
     pub pgn: u32,
+    pub priority: u8,
 }
 
 #[derive(Clone, Copy)]
@@ -630,8 +688,33 @@ pub enum CanProtoInfo {
 pub struct CanRecvInfo {
     pub proto: CanProtoInfo,
     pub stamp: u64,
+    pub stamp_ns: u64,
     pub count: isize,
     pub iface: i32,
+    /// errno captured right after a failed `recvmsg` (0 when read succeeded).
+    pub errno: i32,
+}
+
+/// Write outcome keeping the failed syscall errno apart from validation errors,
+/// so callers can retry on `EAGAIN` without reading a stale errno.
+pub(crate) enum SockWriteError {
+    Os(&'static str, io::Error),
+    Can(CanError),
+}
+
+impl From<CanError> for SockWriteError {
+    fn from(error: CanError) -> Self {
+        SockWriteError::Can(error)
+    }
+}
+
+impl From<SockWriteError> for CanError {
+    fn from(error: SockWriteError) -> Self {
+        match error {
+            SockWriteError::Os(uid, error) => CanError::new(uid, error.to_string()),
+            SockWriteError::Can(error) => error,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -644,6 +727,9 @@ pub enum SockCanOpCode {
 }
 pub trait SockCanCtrl {
     fn check_frame(&self, data: &[u8], info: &CanRecvInfo) -> SockCanOpCode;
+
+    // user land transport may rewrite info of reassembled message (pgn, src, ...)
+    fn update_info(&self, _info: &mut CanRecvInfo) {}
 }
 
 pub struct SockCanHandle {
@@ -822,9 +908,8 @@ impl SockCanHandle {
         if rc < 0 {
             Err(CanError::new("can-ifname-fail", cglue::get_perror()))
         } else {
-            let name_ptr = unsafe {
-                core::ptr::addr_of!(ifreq.ifr_ifrn.ifrn_name).cast::<std::os::raw::c_char>()
-            };
+            let name_ptr =
+                core::ptr::addr_of!(ifreq.ifr_ifrn.ifrn_name).cast::<std::os::raw::c_char>();
             let cstring = unsafe { CStr::from_ptr(name_ptr) };
             match cstring.to_str() {
                 Err(error) => Err(CanError::new("can-ifname-invalid", error.to_string())),
@@ -985,7 +1070,9 @@ impl SockCanHandle {
     pub fn set_timestamp(&mut self, timestamp: CanTimeStamp) -> Result<&mut Self, CanError> {
         let status = match timestamp {
             CanTimeStamp::SOFTWARE => {
-                let flag = cglue::can_RAW_x_SOF_TIMESTAMPING_RX_SOFTWARE;
+                // generate and report software receive stamps
+                let flag = cglue::can_RAW_x_SOF_TIMESTAMPING_RX_SOFTWARE
+                    | cglue::can_RAW_x_SOF_TIMESTAMPING_SOFTWARE;
                 unsafe {
                     cglue::setsockopt(
                         self.sockfd,
@@ -997,7 +1084,9 @@ impl SockCanHandle {
                 }
             },
             CanTimeStamp::HARDWARE => {
-                let flag = cglue::can_RAW_x_SOF_TIMESTAMPING_RX_HARDWARE;
+                // generate and report raw hardware receive stamps
+                let flag = cglue::can_RAW_x_SOF_TIMESTAMPING_RX_HARDWARE
+                    | cglue::can_RAW_x_SOF_TIMESTAMPING_RAW_HARDWARE;
                 unsafe {
                     cglue::setsockopt(
                         self.sockfd,
@@ -1130,13 +1219,16 @@ impl SockCanHandle {
         }
 
         if info.count < 0 {
-            info.proto = CanProtoInfo::Error(CanError::new("can_read_frame", cglue::get_perror()));
+            let error = io::Error::last_os_error();
+            info.errno = error.raw_os_error().unwrap_or(0);
+            info.proto = CanProtoInfo::Error(CanError::new("can_read_frame", error.to_string()));
             return info;
         }
 
         // ref: https://github.com/torvalds/linux/blob/master/tools/testing/selftests/net/timestamping.c
         //let mut cmsg = unsafe { cglue::CMSG_FIRSTHDR(&raw const msg_hdr) };
         let mut safe_msg = cglue::CMSG_FIRSTHDR(&raw const msg_hdr);
+        let mut j1939: Option<CanJ1939Info> = None;
 
         while !safe_msg.is_null() {
             let c_msg = unsafe { &*safe_msg };
@@ -1158,22 +1250,39 @@ impl SockCanHandle {
                 i32::try_from(cglue::can_J1939_x_SCM_DEST_ADDR).unwrap_or(i32::MAX);
             let scm_dest_name: i32 =
                 i32::try_from(cglue::can_J1939_x_SCM_DEST_NAME).unwrap_or(i32::MAX);
+            let scm_prio: i32 = i32::try_from(cglue::can_J1939_x_SCM_PRIO).unwrap_or(i32::MAX);
 
             if c_msg.cmsg_level == sol_socket {
                 let ctype = c_msg.cmsg_type;
 
-                // Trois bras identiques fusionnés avec ||
-                if ctype == so_timestamping || ctype == so_timestamp_new || ctype == so_timestampns
-                {
+                if ctype == so_timestamping {
+                    // scm_timestamping: [0] software, [1] deprecated, [2] raw hardware
+                    let count = (c_msg.cmsg_len.saturating_sub(mem::size_of::<cglue::cmsghdr>()))
+                        / mem::size_of::<cglue::timespec>();
+                    let data = cglue::CMSG_DATA(c_msg).cast::<cglue::timespec>();
+                    let soft = unsafe { core::ptr::read_unaligned(data) };
+                    let hard = if count >= 3 {
+                        unsafe { core::ptr::read_unaligned(data.add(2)) }
+                    } else {
+                        soft
+                    };
+                    let ts = if hard.tv_sec != 0 || hard.tv_nsec != 0 { hard } else { soft };
+                    let sec = u64::try_from(ts.tv_sec).unwrap_or(0);
+                    let nsec = u64::try_from(ts.tv_nsec).unwrap_or(0);
+                    info.stamp_ns = sec.saturating_mul(1_000_000_000).saturating_add(nsec);
+                    info.stamp = info.stamp_ns / 1_000;
+                    break;
+                } else if ctype == so_timestampns {
                     // lire timespec sans exigence d’alignement strict
                     let ts = unsafe {
                         core::ptr::read_unaligned(cglue::CMSG_DATA(c_msg).cast::<cglue::timespec>())
                     };
                     let sec = u64::try_from(ts.tv_sec).unwrap_or(0);
                     let nsec = u64::try_from(ts.tv_nsec).unwrap_or(0);
-                    info.stamp = sec.saturating_mul(1_000_000).saturating_add(nsec);
+                    info.stamp_ns = sec.saturating_mul(1_000_000_000).saturating_add(nsec);
+                    info.stamp = info.stamp_ns / 1_000;
                     break;
-                } else if ctype == so_timestamp {
+                } else if ctype == so_timestamp || ctype == so_timestamp_new {
                     // lire timeval sans exigence d’alignement strict
                     let tv = unsafe {
                         core::ptr::read_unaligned(cglue::CMSG_DATA(c_msg).cast::<cglue::timeval>())
@@ -1181,38 +1290,46 @@ impl SockCanHandle {
                     let sec = u64::try_from(tv.tv_sec).unwrap_or(0);
                     let usec = u64::try_from(tv.tv_usec).unwrap_or(0);
                     info.stamp = sec.saturating_mul(1_000_000).saturating_add(usec);
+                    info.stamp_ns = info.stamp.saturating_mul(1_000);
                     break;
                 }
             } else if c_msg.cmsg_level == sol_can_j1939 {
                 info.iface = canaddr.can_ifindex;
 
-                let j1939_src = unsafe {
-                    CanJ1939Header {
-                        name: canaddr.can_addr.j1939.name,
-                        addr: canaddr.can_addr.j1939.addr,
+                // destination address/name and priority come as separate control messages,
+                // kernel omits destination address for broadcast
+                let j1939 = j1939.get_or_insert(unsafe {
+                    CanJ1939Info {
+                        src: CanJ1939Header {
+                            name: canaddr.can_addr.j1939.name,
+                            addr: canaddr.can_addr.j1939.addr,
+                        },
+                        dst: CanJ1939Header { name: 0, addr: 0xFF },
+                        pgn: canaddr.can_addr.j1939.pgn,
+                        priority: 6,
                     }
-                };
-                let mut j1939_dst = CanJ1939Header { name: 0, addr: 0 };
+                });
 
                 if c_msg.cmsg_type == scm_dest_addr {
                     let addr =
                         unsafe { core::ptr::read_unaligned(cglue::CMSG_DATA(c_msg).cast::<u8>()) };
-                    j1939_dst.addr = addr;
+                    j1939.dst.addr = addr;
                 } else if c_msg.cmsg_type == scm_dest_name {
                     let name =
                         unsafe { core::ptr::read_unaligned(cglue::CMSG_DATA(c_msg).cast::<u64>()) };
-                    j1939_dst.name = name;
+                    j1939.dst.name = name;
+                } else if c_msg.cmsg_type == scm_prio {
+                    let priority =
+                        unsafe { core::ptr::read_unaligned(cglue::CMSG_DATA(c_msg).cast::<u8>()) };
+                    j1939.priority = priority;
                 }
-
-                info.proto = CanProtoInfo::J1939(CanJ1939Info {
-                    src: j1939_src,
-                    dst: j1939_dst,
-                    pgn: unsafe { canaddr.can_addr.j1939.pgn },
-                });
             }
 
             safe_msg = cglue::CMSG_NXTHDR(&raw const msg_hdr, core::ptr::from_ref(c_msg));
         }
+        if let Some(j1939) = j1939 {
+            info.proto = CanProtoInfo::J1939(j1939);
+        }
         info
     }
 
@@ -1243,11 +1360,23 @@ impl SockCanHandle {
                 );
                 CanAnyFrame::RawFd(tmp.assume_init())
             }
+        } else if info.errno != 0 {
+            let error = io::Error::from_raw_os_error(info.errno);
+            CanAnyFrame::Err(CanError::new("can-invalid-frame", error.to_string()))
         } else {
-            CanAnyFrame::Err(CanError::new("can-invalid-frame", cglue::get_perror()))
+            CanAnyFrame::Err(CanError::new(
+                "can-invalid-frame",
+                format!("unexpected frame size {}", info.count),
+            ))
         };
 
-        SockCanMsg { frame: can_any_frame, iface: info.iface, stamp: info.stamp }
+        SockCanMsg {
+            frame: can_any_frame,
+            iface: info.iface,
+            stamp: info.stamp,
+            stamp_ns: info.stamp_ns,
+            errno: info.errno,
+        }
     }
     /// Low-level send for Classical CAN
     pub fn send_std(&self, id: u32, data: &[u8]) -> Result<(), CanError> {
@@ -1283,13 +1412,18 @@ impl SockCanHandle {
 
     /// Generic writer that accepts `CanAnyFrame` (what your reader returns).
     pub fn write_frame(&self, frame: &CanAnyFrame) -> Result<(), CanError> {
+        self.try_write_frame(frame).map_err(CanError::from)
+    }
+
+    // errno is read right after write(), see SockWriteError
+    pub(crate) fn try_write_frame(&self, frame: &CanAnyFrame) -> Result<(), SockWriteError> {
         match frame {
             CanAnyFrame::RawStd(f) => {
                 let n = unsafe {
                     cglue::write(self.sockfd, f.as_ptr(), core::mem::size_of::<cglue::can_frame>())
                 };
                 if n < 0 {
-                    return Err(CanError::new("can-send-std", cglue::get_perror()));
+                    return Err(SockWriteError::Os("can-send-std", io::Error::last_os_error()));
                 }
                 Ok(())
             },
@@ -1302,15 +1436,18 @@ impl SockCanHandle {
                     )
                 };
                 if n < 0 {
-                    return Err(CanError::new("can-send-fd", cglue::get_perror()));
+                    return Err(SockWriteError::Os("can-send-fd", io::Error::last_os_error()));
                 }
                 Ok(())
             },
-            CanAnyFrame::Err(e) => Err(CanError::new("can-send-invalid", format!("Err: {e}"))),
+            CanAnyFrame::Err(e) => {
+                Err(CanError::new("can-send-invalid", format!("Err: {e}")).into())
+            },
             CanAnyFrame::None(id) => Err(CanError::new(
                 "can-send-invalid",
                 format!("None/timeout frame cannot be sent (id={id:08X})"),
-            )),
+            )
+            .into()),
         }
     }
 }
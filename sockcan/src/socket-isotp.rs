@@ -0,0 +1,368 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    https://www.kernel.org/doc/html/latest/networking/iso15765-2.html
+ *    https://github.com/linux-can/can-utils/blob/master/isotpsend.c
+ *    https://github.com/linux-can/can-utils/blob/master/isotprecv.c
+ *
+*/
+use bitflags::bitflags;
+
+use super::cglue;
+use crate::prelude::*;
+use crate::sockcan::normalize_can_id;
+use std::mem::{self};
+
+const MAX_ISOTP_PDU_SZ: usize = cglue::can_SOCK_x_MAX_ISOTP_FRAMES as usize;
+
+bitflags! {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct IsoTpFlag: u32 {
+        const LISTEN_MODE   = cglue::can_ISOTP_x_LISTEN_MODE;
+        const EXTEND_ADDR   = cglue::can_ISOTP_x_EXTEND_ADDR;
+        const TX_PADDING    = cglue::can_ISOTP_x_TX_PADDING;
+        const RX_PADDING    = cglue::can_ISOTP_x_RX_PADDING;
+        const CHK_PAD_LEN   = cglue::can_ISOTP_x_CHK_PAD_LEN;
+        const CHK_PAD_DATA  = cglue::can_ISOTP_x_CHK_PAD_DATA;
+        const HALF_DUPLEX   = cglue::can_ISOTP_x_HALF_DUPLEX;
+        const FORCE_TXSTMIN = cglue::can_ISOTP_x_FORCE_TXSTMIN;
+        const FORCE_RXSTMIN = cglue::can_ISOTP_x_FORCE_RXSTMIN;
+        const RX_EXT_ADDR   = cglue::can_ISOTP_x_RX_EXT_ADDR;
+        const WAIT_TX_DONE  = cglue::can_ISOTP_x_WAIT_TX_DONE;
+        const SF_BROADCAST  = cglue::can_ISOTP_x_SF_BROADCAST;
+        const NONE =0;
+    }
+}
+
+/// ISO-TP socket options, applied by `open_isotp` before bind.
+pub struct SockIsoTpOpts {
+    opts: cglue::can_isotp_options,
+    fc: Option<cglue::can_isotp_fc_options>,
+    ll: Option<cglue::can_isotp_ll_options>,
+    tx_stmin: Option<u32>,
+    rx_stmin: Option<u32>,
+}
+
+impl Default for SockIsoTpOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SockIsoTpOpts {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new() -> Self {
+        SockIsoTpOpts {
+            opts: cglue::can_isotp_options {
+                flags: cglue::can_ISOTP_x_DEFAULT_FLAGS,
+                frame_txtime: cglue::can_ISOTP_x_DEFAULT_FRAME_TXTIME,
+                ext_address: cglue::can_ISOTP_x_DEFAULT_EXT_ADDRESS as u8,
+                txpad_content: cglue::can_ISOTP_x_DEFAULT_PAD_CONTENT as u8,
+                rxpad_content: cglue::can_ISOTP_x_DEFAULT_PAD_CONTENT as u8,
+                rx_ext_address: cglue::can_ISOTP_x_DEFAULT_EXT_ADDRESS as u8,
+            },
+            fc: None,
+            ll: None,
+            tx_stmin: None,
+            rx_stmin: None,
+        }
+    }
+
+    pub fn set_flags(&mut self, flags: IsoTpFlag) -> &mut Self {
+        self.opts.flags |= flags.bits();
+        self
+    }
+
+    /// Extended (or mixed) addressing, `rx_address` defaults to `tx_address` when None.
+    pub fn set_ext_address(&mut self, tx_address: u8, rx_address: Option<u8>) -> &mut Self {
+        self.opts.flags |= IsoTpFlag::EXTEND_ADDR.bits();
+        self.opts.ext_address = tx_address;
+        if let Some(address) = rx_address {
+            self.opts.flags |= IsoTpFlag::RX_EXT_ADDR.bits();
+            self.opts.rx_ext_address = address;
+        }
+        self
+    }
+
+    /// Pads transmitted/received frames to 8 bytes (or next CAN FD length)
+    pub fn set_padding(&mut self, tx_content: u8, rx_content: u8) -> &mut Self {
+        self.opts.flags |= (IsoTpFlag::TX_PADDING | IsoTpFlag::RX_PADDING).bits();
+        self.opts.txpad_content = tx_content;
+        self.opts.rxpad_content = rx_content;
+        self
+    }
+
+    /// Time between two consecutive frames in nanoseconds
+    pub fn set_frame_txtime(&mut self, nanosec: u32) -> &mut Self {
+        self.opts.frame_txtime = nanosec;
+        self
+    }
+
+    /// Flow control sent to peer: block size, separation time (ST min) and max wait frames
+    pub fn set_recv_fc(&mut self, block_size: u8, stmin: u8, wftmax: u8) -> &mut Self {
+        self.fc = Some(cglue::can_isotp_fc_options { bs: block_size, stmin, wftmax });
+        self
+    }
+
+    /// Overrides peer ST min for transmission (nanoseconds)
+    pub fn set_tx_stmin(&mut self, nanosec: u32) -> &mut Self {
+        self.tx_stmin = Some(nanosec);
+        self
+    }
+
+    /// Ignores received frames that arrive faster than ST min (nanoseconds)
+    pub fn set_rx_stmin(&mut self, nanosec: u32) -> &mut Self {
+        self.rx_stmin = Some(nanosec);
+        self
+    }
+
+    /// Uses CAN FD link layer with `tx_dl` payload length (8,12,..,64)
+    ///
+    /// # Errors
+    /// Returns a `CanError` when `tx_dl` is not a valid CAN FD data length.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn set_fd(&mut self, tx_dl: u8, flags: CanFdFlags) -> Result<&mut Self, CanError> {
+        let len = usize::from(tx_dl);
+        if len < 8 || can_fd_len(len) != Some(len) {
+            return Err(CanError::new("isotp-invalid-txdl", format!("invalid tx_dl:{tx_dl}")));
+        }
+        self.ll = Some(cglue::can_isotp_ll_options {
+            mtu: cglue::can_MTU_x_FD_MTU as u8,
+            tx_dl,
+            tx_flags: flags.bits(),
+        });
+        Ok(self)
+    }
+}
+
+// set one SOL_CAN_ISOTP option from a C structure
+fn isotp_setsockopt<T>(sockfd: i32, optname: u32, value: &T) -> i32 {
+    let optlen = cglue::socklen_t::try_from(mem::size_of::<T>()).unwrap_or(u32::MAX);
+    unsafe {
+        cglue::setsockopt(
+            sockfd,
+            i32::try_from(cglue::can_ISOTP_x_SOL_ISOTP).unwrap_or(i32::MAX),
+            i32::try_from(optname).unwrap_or(i32::MAX),
+            core::ptr::from_ref(value).cast::<std::ffi::c_void>(),
+            optlen,
+        )
+    }
+}
+
+pub struct SockIsoTpMsg {
+    pub opcode: SockCanOpCode,
+    pub info: CanRecvInfo,
+}
+
+impl SockIsoTpMsg {
+    #[must_use]
+    pub fn get_iface(&self) -> i32 {
+        self.info.iface
+    }
+
+    #[must_use]
+    pub fn get_opcode(&self) -> SockCanOpCode {
+        self.opcode.clone()
+    }
+
+    #[must_use]
+    pub fn get_stamp(&self) -> u64 {
+        self.info.stamp
+    }
+
+    #[must_use]
+    pub fn get_len(&self) -> usize {
+        match &self.opcode {
+            SockCanOpCode::RxRead(data) => data.len(),
+            _ => 0,
+        }
+    }
+
+    #[must_use]
+    pub fn get_data(&self) -> &[u8] {
+        match &self.opcode {
+            SockCanOpCode::RxRead(data) => data.as_slice(),
+            _ => &[],
+        }
+    }
+}
+
+pub trait SockCanIsoTp {
+    /// Opens an ISO-TP (ISO 15765-2) socket sending with `tx_id` and receiving on `rx_id`,
+    /// ids bigger than 0x7FF are used as 29 bits extended ids. Kernel handles segmentation
+    /// and flow control, every read/write is a whole PDU.
+    ///
+    /// # Errors
+    /// Returns a `CanError` if:
+    /// - the socket cannot be created (e.g., `can-isotp` kernel module not loaded);
+    /// - `candev` cannot be mapped to an interface index;
+    /// - one of `opts` cannot be applied (`setsockopt` failure);
+    /// - binding the socket to the interface/ids fails or timestamp cannot be set.
+    fn open_isotp<T>(
+        candev: T,
+        tx_id: u32,
+        rx_id: u32,
+        opts: &SockIsoTpOpts,
+        timestamp: CanTimeStamp,
+    ) -> Result<SockCanHandle, CanError>
+    where
+        SockCanHandle: CanIFaceFrom<T>;
+
+    /// Sends a whole PDU (up to `MAX_ISOTP_FRAMES` bytes).
+    ///
+    /// # Errors
+    /// Returns a `CanError` when PDU is empty, too big or when `write` fails
+    /// (e.g., no flow control received from peer).
+    fn send_isotp(&self, data: &[u8]) -> Result<(), CanError>;
+
+    /// Reads next received PDU, blocks unless socket is non-blocking.
+    fn get_isotp_frame(&self) -> SockIsoTpMsg;
+}
+
+impl SockCanIsoTp for SockCanHandle {
+    fn open_isotp<T>(
+        candev: T,
+        tx_id: u32,
+        rx_id: u32,
+        opts: &SockIsoTpOpts,
+        timestamp: CanTimeStamp,
+    ) -> Result<SockCanHandle, CanError>
+    where
+        SockCanHandle: CanIFaceFrom<T>,
+    {
+        let pf_can = i32::try_from(cglue::can_SOCK_x_PF_CAN).unwrap_or(i32::MAX);
+        let dgram = i32::try_from(cglue::can_SOCK_x_DGRAM).unwrap_or(i32::MAX);
+        let isotp = i32::try_from(cglue::can_SOCK_x_ISOTP).unwrap_or(i32::MAX);
+        let sockfd = unsafe { cglue::socket(pf_can, dgram, isotp) };
+
+        if sockfd < 0 {
+            return Err(CanError::new("fail-socketcan-open", cglue::get_perror()));
+        }
+
+        let mut sockcan = SockCanHandle { sockfd, mode: SockCanMod::ISOTP, callback: None };
+
+        // handle has no Drop, socket is closed by hand when setup fails
+        let setup = |sockcan: &mut SockCanHandle| -> Result<(), CanError> {
+            let iface = SockCanHandle::map_can_iface(sockfd, candev);
+            if iface < 0 {
+                return Err(CanError::new("fail-socketcan-iface", cglue::get_perror()));
+            }
+
+            // options should be set before bind
+            if isotp_setsockopt(sockfd, cglue::can_ISOTP_x_OPTS, &opts.opts) < 0 {
+                return Err(CanError::new("fail-isotp-opts", cglue::get_perror()));
+            }
+            if let Some(fc) = &opts.fc {
+                if isotp_setsockopt(sockfd, cglue::can_ISOTP_x_RECV_FC, fc) < 0 {
+                    return Err(CanError::new("fail-isotp-recv-fc", cglue::get_perror()));
+                }
+            }
+            if let Some(stmin) = &opts.tx_stmin {
+                if isotp_setsockopt(sockfd, cglue::can_ISOTP_x_TX_STMIN, stmin) < 0 {
+                    return Err(CanError::new("fail-isotp-tx-stmin", cglue::get_perror()));
+                }
+            }
+            if let Some(stmin) = &opts.rx_stmin {
+                if isotp_setsockopt(sockfd, cglue::can_ISOTP_x_RX_STMIN, stmin) < 0 {
+                    return Err(CanError::new("fail-isotp-rx-stmin", cglue::get_perror()));
+                }
+            }
+            if let Some(ll) = &opts.ll {
+                if isotp_setsockopt(sockfd, cglue::can_ISOTP_x_LL_OPTS, ll) < 0 {
+                    return Err(CanError::new("fail-isotp-ll-opts", cglue::get_perror()));
+                }
+            }
+
+            #[allow(invalid_value)]
+            let mut canaddr: cglue::sockaddr_can = unsafe { std::mem::zeroed() };
+            canaddr.can_family = u16::try_from(cglue::can_SOCK_x_AF_CAN).unwrap_or(u16::MAX);
+            canaddr.can_ifindex = iface;
+            canaddr.can_addr.tp = cglue::sockaddr_can__bindgen_ty_1__bindgen_ty_1 {
+                rx_id: normalize_can_id(rx_id),
+                tx_id: normalize_can_id(tx_id),
+            };
+
+            let sockaddr = cglue::__CONST_SOCKADDR_ARG {
+                __sockaddr__: (&raw const canaddr).cast::<cglue::sockaddr>(),
+            };
+            let namelen = cglue::socklen_t::try_from(mem::size_of::<cglue::sockaddr_can>())
+                .unwrap_or(u32::MAX);
+            let status = unsafe { cglue::bind(sockfd, sockaddr, namelen) };
+            if status < 0 {
+                return Err(CanError::new("fail-isotp-bind", cglue::get_perror()));
+            }
+
+            sockcan.set_timestamp(timestamp)?;
+            Ok(())
+        };
+        if let Err(error) = setup(&mut sockcan) {
+            sockcan.close();
+            return Err(error);
+        }
+        Ok(sockcan)
+    }
+
+    fn send_isotp(&self, data: &[u8]) -> Result<(), CanError> {
+        if data.is_empty() || data.len() > MAX_ISOTP_PDU_SZ {
+            return Err(CanError::new(
+                "isotp-send-len",
+                format!("invalid pdu len:{} (max:{MAX_ISOTP_PDU_SZ})", data.len()),
+            ));
+        }
+
+        let count = unsafe {
+            cglue::write(self.sockfd, data.as_ptr().cast::<std::ffi::c_void>(), data.len())
+        };
+        if count < 0 {
+            return Err(CanError::new("fail-isotp-send", cglue::get_perror()));
+        }
+        if usize::try_from(count).unwrap_or(0) != data.len() {
+            return Err(CanError::new(
+                "fail-isotp-send",
+                format!("partial write {count}/{}", data.len()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn get_isotp_frame(&self) -> SockIsoTpMsg {
+        let mut buffer = vec![0u8; MAX_ISOTP_PDU_SZ];
+
+        let info = self.get_raw_frame(&mut buffer);
+        if info.count < 0 {
+            return SockIsoTpMsg {
+                info,
+                opcode: SockCanOpCode::RxError(CanError::new(
+                    "isotp-read-fail",
+                    "fail to read pdu from canbus",
+                )),
+            };
+        }
+
+        buffer.truncate(usize::try_from(info.count).unwrap_or(0));
+        SockIsoTpMsg { info, opcode: SockCanOpCode::RxRead(buffer) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_isotp_opts() {
+        let mut opts = SockIsoTpOpts::new();
+        opts.set_padding(0xCC, 0xAA).set_ext_address(0x10, None);
+        assert!(IsoTpFlag::from_bits_retain(opts.opts.flags)
+            .contains(IsoTpFlag::TX_PADDING | IsoTpFlag::RX_PADDING | IsoTpFlag::EXTEND_ADDR));
+        assert!(!IsoTpFlag::from_bits_retain(opts.opts.flags).contains(IsoTpFlag::RX_EXT_ADDR));
+
+        assert!(opts.set_fd(64, CanFdFlags::BRS).is_ok());
+        assert!(opts.set_fd(13, CanFdFlags::BRS).is_err());
+        assert!(opts.set_fd(4, CanFdFlags::BRS).is_err());
+    }
+}
@@ -18,11 +18,19 @@ use log::{debug, warn};
 use super::cglue;
 use crate::prelude::*;
 use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::io;
 use std::mem::{self};
+use std::time::{Duration, Instant};
 
 const MAX_N2K_FAST_SZ: u16 = 223; // Max N2K data with 32 packets
 const MAX_N2K_PACK_SZ: isize = 8; // Individual packet are 8 bytes
+const MAX_N2K_FAST_TIMEOUT: Duration = Duration::from_millis(750); // stale partial message
+const N2K_FIRST_PAYLOAD_SZ: usize = 6; // first frame carries 6 bytes (2..8)
+const N2K_NEXT_PAYLOAD_SZ: usize = 7; // next frames carry 7 bytes (1..8)
 const MAX_J1939_PKG_SZ: u32 = cglue::can_J1939_x_MAX_TP_PACKET_SIZE;
+const MAX_J1939_ETP_SZ: u32 = cglue::can_J1939_x_MAX_ETP_PACKET_SIZE;
+const MAX_J1939_PRIO: u8 = 7;
 
 pub struct SockJ1939Ecu {
     name: u64,
@@ -56,12 +64,69 @@ pub enum SockJ1939Addr {
     Promiscuous,
 }
 
+/// J1939 transmit destination, kernel handles TP (up to 1785 bytes) and
+/// peer-to-peer ETP (up to 117440505 bytes) segmentation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SockJ1939Dst {
+    Broadcast,
+    Addr(u8),
+    Name(u64),
+}
+
+impl SockJ1939Dst {
+    /// Checks `pgn` and payload `len` can be sent to this destination.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when pgn is out of range or payload is too big
+    /// (broadcast only supports TP/BAM, peer-to-peer supports ETP).
+    pub fn check(&self, pgn: u32, len: usize) -> Result<(), CanError> {
+        if pgn > cglue::can_J1939_x_PGN_MAX {
+            return Err(CanError::new("j1939-send-pgn", format!("invalid pgn:{pgn:#x}")));
+        }
+        let max = match self {
+            SockJ1939Dst::Broadcast => MAX_J1939_PKG_SZ,
+            SockJ1939Dst::Addr(_) | SockJ1939Dst::Name(_) => MAX_J1939_ETP_SZ,
+        };
+        if len > max as usize {
+            return Err(CanError::new(
+                "j1939-send-len",
+                format!("pgn:{pgn:#x} len:{len} bigger than max:{max} for {self:?}"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn get_sockaddr(&self, pgn: u32) -> cglue::sockaddr_can {
+        let no_addr = u8::try_from(cglue::can_J1939_x_NO_ADDR).unwrap_or(u8::MAX);
+        let no_name = u64::from(cglue::can_J1939_x_NO_NAME);
+        let (name, addr) = match *self {
+            SockJ1939Dst::Broadcast => (no_name, no_addr),
+            SockJ1939Dst::Addr(addr) => (no_name, addr),
+            SockJ1939Dst::Name(name) => (name, no_addr),
+        };
+
+        // can_ifindex=0 use socket bound interface
+        #[allow(invalid_value)]
+        let mut canaddr: cglue::sockaddr_can = unsafe { std::mem::zeroed() };
+        canaddr.can_family = u16::try_from(cglue::can_SOCK_x_AF_CAN).unwrap_or(u16::MAX);
+        canaddr.can_addr.j1939 =
+            cglue::sockaddr_can__bindgen_ty_1__bindgen_ty_2 { name, pgn, addr };
+        canaddr
+    }
+}
+
 pub struct SockJ1939Msg {
     pub opcode: SockCanOpCode,
     pub info: CanRecvInfo,
 }
 
 impl SockJ1939Msg {
+    /// Returns errno captured right after a failed read, `None` when read succeeded.
+    #[must_use]
+    pub fn get_os_error(&self) -> Option<io::Error> {
+        (self.info.errno != 0).then(|| io::Error::from_raw_os_error(self.info.errno))
+    }
+
     #[must_use]
     pub fn get_iface(&self) -> i32 {
         self.info.iface
@@ -121,6 +186,29 @@ impl SockJ1939Msg {
     }
 }
 
+// J1939 messages feed dbc pools as extended frames rebuilt from received header
+impl<'a> TryFrom<&'a SockJ1939Msg> for CanMsgData<'a> {
+    type Error = CanError;
+    fn try_from(msg: &'a SockJ1939Msg) -> Result<Self, CanError> {
+        let info = msg.get_info()?;
+        let data = msg.get_data();
+        // transport protocol payloads (up to 117MB) do not fit CanMsgData u8 length
+        let Ok(len) = u8::try_from(data.len()) else {
+            return Err(CanError::new(
+                "sockj1939-msg-len",
+                format!("pgn:{:#x} len:{} does not fit a dbc message", info.pgn, data.len()),
+            ));
+        };
+        Ok(CanMsgData {
+            canid: j1939_raw_canid(info.priority, info.pgn, info.dst.addr, info.src.addr),
+            len,
+            stamp: msg.get_stamp(),
+            opcode: CanBcmOpCode::RxChanged,
+            data,
+        })
+    }
+}
+
 pub trait SockCanJ1939 {
     /// Opens a J1939 socket on the given CAN interface with the requested addressing mode
     /// and timestamp configuration.
@@ -149,6 +237,64 @@ pub trait SockCanJ1939 {
         SockCanHandle: CanIFaceFrom<T>;
 
     fn get_j1939_frame(&self) -> SockJ1939Msg;
+
+    /// Sets priority (0=highest..7, kernel default 6) of every following sent message.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when `priority` is invalid or `setsockopt` fails.
+    fn set_send_prio(&self, priority: u8) -> Result<(), CanError>;
+
+    /// Allows sending to broadcast destination, already set when socket was opened
+    /// without a source address.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when `setsockopt` fails.
+    fn set_broadcast(&self, value: bool) -> Result<(), CanError>;
+
+    /// Sends `data` as `pgn` to a broadcast, address or NAME destination with socket
+    /// send priority (see `set_send_prio`). Payloads bigger than 8 bytes use kernel TP/ETP.
+    ///
+    /// # Errors
+    /// Returns a `CanError` if:
+    /// - `pgn` or payload length are invalid for `dst` (see [`SockJ1939Dst::check`]);
+    /// - `sendto` fails (no claimed source address, broadcast not allowed, busy bus, ...).
+    fn send_pgn(&self, pgn: u32, dst: SockJ1939Dst, data: &[u8]) -> Result<(), CanError>;
+
+    /// Sends NMEA2000 `pgn` as fast packet frames with sequence counter `seq` (0..7),
+    /// see [`SockJ1939Fast::fragment`].
+    ///
+    /// # Errors
+    /// Returns a `CanError` when `data` does not fit in a fast packet or one frame cannot be sent.
+    fn send_fast(&self, pgn: u32, dst: SockJ1939Dst, seq: u8, data: &[u8]) -> Result<(), CanError>;
+
+    /// Rebinds an already bound J1939 socket with a new source `name` and `addr`
+    /// (e.g. after address claim), interface is preserved.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when current socket address cannot be read or `bind` fails.
+    fn bind_j1939(&self, name: u64, addr: u8) -> Result<(), CanError>;
+
+    /// Rebinds socket with `addr` and broadcasts `name` address claim (PGN 60928),
+    /// `IDLE_ADDR` sends a cannot claim message. See [`J1939AddrClaim`]. Claim uses
+    /// socket send priority, J1939-81 expects the default 6.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when socket cannot be rebound or claim cannot be sent.
+    fn send_addr_claim(&self, name: u64, addr: u8) -> Result<(), CanError>;
+}
+
+// set an integer socket option (send priority, broadcast, ...)
+fn j1939_setsockopt(sockfd: i32, level: u32, optname: u32, value: i32) -> i32 {
+    let optlen = cglue::socklen_t::try_from(mem::size_of::<i32>()).unwrap_or(u32::MAX);
+    unsafe {
+        cglue::setsockopt(
+            sockfd,
+            i32::try_from(level).unwrap_or(i32::MAX),
+            i32::try_from(optname).unwrap_or(i32::MAX),
+            (&raw const value).cast::<std::ffi::c_void>(),
+            optlen,
+        )
+    }
 }
 
 impl SockCanJ1939 for SockCanHandle {
@@ -236,6 +382,7 @@ impl SockCanJ1939 for SockCanHandle {
                     }
                 }
 
+                // static source address is set afterward with bind_j1939
                 canaddr.can_addr.j1939 = cglue::sockaddr_can__bindgen_ty_1__bindgen_ty_2 {
                     name: ecu.name,
                     pgn: ecu.pgn,
@@ -268,7 +415,7 @@ impl SockCanJ1939 for SockCanHandle {
         let mut buffer: [u8; MAX_J1939_PKG_SZ as usize] = [0u8; MAX_J1939_PKG_SZ as usize];
 
         // read raw frame from canbus
-        let info = self.get_raw_frame(&mut buffer);
+        let mut info = self.get_raw_frame(&mut buffer);
         if info.count < 0 {
             return SockJ1939Msg {
                 info,
@@ -279,6 +426,13 @@ impl SockCanJ1939 for SockCanHandle {
             };
         }
 
+        // raw socket (no can-j1939 kernel module) decode J1939 header from extended canid
+        if let SockCanMod::RAW = self.mode {
+            if !j1939_raw_frame(&mut buffer, &mut info) {
+                return SockJ1939Msg { info, opcode: SockCanOpCode::RxIgnore };
+            }
+        }
+
         // if fast-packet or any other user land protocol is register let's call it now
         if let Some(callback) = &self.callback {
             let opcode = match callback.try_borrow() {
@@ -286,7 +440,13 @@ impl SockCanJ1939 for SockCanHandle {
                     "can-recv-callback",
                     "(internal) Fail to fet ref_mut".to_string(),
                 )),
-                Ok(callback) => callback.check_frame(&buffer, &info),
+                Ok(callback) => {
+                    let opcode = callback.check_frame(&buffer, &info);
+                    if let SockCanOpCode::RxRead(_) = opcode {
+                        callback.update_info(&mut info);
+                    }
+                    opcode
+                },
             };
             SockJ1939Msg { opcode, info }
         } else {
@@ -297,15 +457,125 @@ impl SockCanJ1939 for SockCanHandle {
             SockJ1939Msg { info, opcode: SockCanOpCode::RxRead(data) }
         }
     }
+
+    fn set_send_prio(&self, priority: u8) -> Result<(), CanError> {
+        if priority > MAX_J1939_PRIO {
+            return Err(CanError::new("j1939-send-prio", format!("invalid priority:{priority}")));
+        }
+        let status = j1939_setsockopt(
+            self.sockfd,
+            cglue::can_J1939_x_SOL_CAN_J1939,
+            cglue::can_J1939_x_SO_SEND_PRIO,
+            i32::from(priority),
+        );
+        if status < 0 {
+            return Err(CanError::new("fail-sockj1939-prio", cglue::get_perror()));
+        }
+        Ok(())
+    }
+
+    fn set_broadcast(&self, value: bool) -> Result<(), CanError> {
+        let status = j1939_setsockopt(
+            self.sockfd,
+            cglue::can_SOCK_x_SOL_SOCKET,
+            cglue::can_SOCK_x_SO_BROADCAST,
+            i32::from(value),
+        );
+        if status < 0 {
+            return Err(CanError::new("fail-sockj1939-broadcast", cglue::get_perror()));
+        }
+        Ok(())
+    }
+
+    fn send_pgn(&self, pgn: u32, dst: SockJ1939Dst, data: &[u8]) -> Result<(), CanError> {
+        dst.check(pgn, data.len())?;
+
+        let canaddr = dst.get_sockaddr(pgn);
+        let sockaddr = cglue::__CONST_SOCKADDR_ARG {
+            __sockaddr__: (&raw const canaddr).cast::<cglue::sockaddr>(),
+        };
+        let namelen =
+            cglue::socklen_t::try_from(mem::size_of::<cglue::sockaddr_can>()).unwrap_or(u32::MAX);
+
+        let count = unsafe {
+            cglue::sendto(
+                self.sockfd,
+                data.as_ptr().cast::<std::ffi::c_void>(),
+                data.len(),
+                0,
+                sockaddr,
+                namelen,
+            )
+        };
+        if count < 0 {
+            return Err(CanError::new("fail-sockj1939-send", cglue::get_perror()));
+        }
+        if usize::try_from(count).unwrap_or(0) != data.len() {
+            return Err(CanError::new(
+                "fail-sockj1939-send",
+                format!("pgn:{pgn:#x} partial write {count}/{}", data.len()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn send_fast(&self, pgn: u32, dst: SockJ1939Dst, seq: u8, data: &[u8]) -> Result<(), CanError> {
+        for frame in SockJ1939Fast::fragment(seq, data)? {
+            self.send_pgn(pgn, dst, &frame)?;
+        }
+        Ok(())
+    }
+
+    fn bind_j1939(&self, name: u64, addr: u8) -> Result<(), CanError> {
+        #[allow(invalid_value)]
+        let mut canaddr: cglue::sockaddr_can = unsafe { std::mem::zeroed() };
+        let mut namelen =
+            cglue::socklen_t::try_from(mem::size_of::<cglue::sockaddr_can>()).unwrap_or(u32::MAX);
+
+        // kernel only accepts rebinding on the same interface
+        let sockaddr = cglue::__SOCKADDR_ARG { __sockaddr__: (&raw mut canaddr).cast() };
+        let status = unsafe { cglue::getsockname(self.sockfd, sockaddr, &raw mut namelen) };
+        if status < 0 {
+            return Err(CanError::new("fail-sockj1939-getname", cglue::get_perror()));
+        }
+
+        canaddr.can_addr.j1939 = cglue::sockaddr_can__bindgen_ty_1__bindgen_ty_2 {
+            name,
+            pgn: cglue::can_J1939_x_NO_PGN,
+            addr,
+        };
+        let sockaddr = cglue::__CONST_SOCKADDR_ARG {
+            __sockaddr__: (&raw const canaddr).cast::<cglue::sockaddr>(),
+        };
+        let status = unsafe { cglue::bind(self.sockfd, sockaddr, namelen) };
+        if status < 0 {
+            return Err(CanError::new("fail-sockj1939-bind", cglue::get_perror()));
+        }
+        Ok(())
+    }
+
+    fn send_addr_claim(&self, name: u64, addr: u8) -> Result<(), CanError> {
+        self.bind_j1939(name, addr)?;
+        self.send_pgn(
+            cglue::can_J1939_x_PGN_ADDRESS_CLAIMED,
+            SockJ1939Dst::Broadcast,
+            &name.to_le_bytes(),
+        )
+    }
+}
+
+// one fast packet reassembly in progress for a (source address, sequence counter)
+struct SockJ1939FastPartial {
+    frame_idx: u8,  // next frame index expected
+    frame_len: u16, // data to be read
+    data: Vec<u8>,  // data buffer
+    stamp: Instant, // last received frame
 }
 
 pub struct SockJ1939Fast {
-    pgn: u32,        // msg PGN
-    frame_idx: u8,   // current chunk index
-    frame_count: u8, // current frame frame_count number
-    frame_len: u16,  // data to be read
-    data: Vec<u8>,   // data buffer
-    capacity: u16,   // data maximum capacity
+    pgn: u32,      // msg PGN
+    capacity: u16, // data maximum capacity
+    partials: HashMap<(u8, u8), SockJ1939FastPartial>,
 }
 
 impl SockJ1939Fast {
@@ -313,29 +583,62 @@ impl SockJ1939Fast {
     pub fn new(pgn: u32, dbc_len: u16, mut capacity: u16) -> Self {
         if capacity == 0 {
             capacity = dbc_len;
-        } else if u32::from(capacity) > MAX_J1939_PKG_SZ {
+        }
+        if capacity > MAX_N2K_FAST_SZ {
             capacity = MAX_N2K_FAST_SZ;
         }
 
-        SockJ1939Fast {
-            pgn,
-            frame_idx: 0,
-            frame_count: 0,
-            frame_len: 0,
-            capacity,
-            data: Vec::with_capacity(capacity as usize),
-        }
+        SockJ1939Fast { pgn, capacity, partials: HashMap::new() }
     }
 
     pub fn reset(&mut self) {
-        self.frame_idx = 0;
-        self.data.clear();
+        self.partials.clear();
     }
 
-    pub fn push(&mut self, buffer: &[u8], _len: isize) -> SockCanOpCode {
+    #[must_use]
+    pub fn get_pending(&self) -> usize {
+        self.partials.len()
+    }
+
+    /// Splits `data` into 8 bytes NMEA2000 fast packet frames using sequence counter `seq` (0..7),
+    /// the sender should increment `seq` for each new message of the same PGN.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when `data` is empty or bigger than 223 bytes.
+    pub fn fragment(seq: u8, data: &[u8]) -> Result<Vec<[u8; 8]>, CanError> {
+        let len = u8::try_from(data.len()).unwrap_or(u8::MAX);
+        if data.is_empty() || u16::from(len) > MAX_N2K_FAST_SZ {
+            return Err(CanError::new(
+                "j1939-fastpkg-len",
+                format!("invalid fast packet len:{}", data.len()),
+            ));
+        }
+
+        let serial = (seq & 0x07) << 5;
+        let mut frames = Vec::new();
+        let mut frame = [0xFF; 8];
+        frame[0] = serial;
+        frame[1] = len;
+        let first = data.len().min(N2K_FIRST_PAYLOAD_SZ);
+        frame[2..2 + first].copy_from_slice(&data[..first]);
+        frames.push(frame);
+
+        for (idx, chunk) in data[first..].chunks(N2K_NEXT_PAYLOAD_SZ).enumerate() {
+            let mut frame = [0xFF; 8];
+            frame[0] = serial | u8::try_from(idx + 1).unwrap_or(0x1F);
+            frame[1..=chunk.len()].copy_from_slice(chunk);
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+
+    /// Pushes one fast packet frame received from `src`, interleaved messages from different
+    /// sources or sequence counters are reassembled independently.
+    pub fn push(&mut self, src: u8, buffer: &[u8], _len: isize) -> SockCanOpCode {
         #[cfg(debug_assertions)]
         debug!(
-            "buffer: {:#02x?}:{:#02x?}  len:{}",
+            "src:{:#02x} buffer: {:#02x?}:{:#02x?}  len:{}",
+            src,
             buffer.first().copied().unwrap_or(0),
             buffer.get(1).copied().unwrap_or(0),
             _len
@@ -349,97 +652,82 @@ impl SockJ1939Fast {
             ));
         }
 
-        const HEADER_IDX: usize = 0;
-        const LEN_IDX: usize = 1;
-        const FIRST_FRAME_PAYLOAD_START: usize = 2; // first frame carries 6 bytes (2..8)
-        const NEXT_FRAME_PAYLOAD_START: usize = 1; // next frames carry 7 bytes (1..8)
-        const FIRST_FRAME_PAYLOAD_MAX: usize = 6;
-        const NEXT_FRAME_PAYLOAD_MAX: usize = 7;
-
-        let hdr = buffer[HEADER_IDX];
-        let frame_serial = hdr >> 4; // upper nibble: serial number for the whole message
-        let frame_index = hdr & 0x0F; // lower nibble: index within the message
-
-        // first frame when lower nibble == 0 (multiple of 16)
-        let is_first_frame = frame_index == 0;
-
-        if is_first_frame {
-            // previous message we uncompleted
-            if !self.data.is_empty() {
-                warn!("data lost: frame_len:{} data_len:{}", self.frame_len, self.data.len());
-                self.reset();
+        // drop stale partial messages (sender stopped or frames lost)
+        self.partials.retain(|(src, serial), partial| {
+            let alive = partial.stamp.elapsed() < MAX_N2K_FAST_TIMEOUT;
+            if !alive {
+                warn!("fastpkg src:{src:#02x} serial:{serial} timeout len:{}", partial.data.len());
+            }
+            alive
+        });
+
+        let hdr = buffer[0];
+        let frame_serial = hdr >> 5; // upper 3 bits: sequence counter for the whole message
+        let frame_index = hdr & 0x1F; // lower 5 bits: frame index within the message
+        let key = (src, frame_serial);
+
+        // first frame when frame index == 0
+        if frame_index == 0 {
+            // previous message with same sequence counter was not completed
+            if let Some(partial) = self.partials.remove(&key) {
+                warn!("data lost: frame_len:{} data_len:{}", partial.frame_len, partial.data.len());
             }
             // read total message length from byte[1] of the first frame
-            self.frame_len = u16::from(buffer[LEN_IDX]);
+            let frame_len = u16::from(buffer[1]);
 
             // capacity check
-            if self.frame_len > self.capacity {
+            if frame_len > self.capacity {
                 return SockCanOpCode::RxError(CanError::new(
                     "j1939-fastpkg-pgnlen",
                     format!(
                         "message pgn:{} len:{} bigger than capacity:{}",
-                        self.pgn, self.frame_len, self.capacity
+                        self.pgn, frame_len, self.capacity
                     ),
                 ));
             }
 
-            self.frame_count = frame_serial;
-            self.frame_idx = 1; // next frame expected has index 1
-
             // append up to 6 bytes (2..8), but only what we still need
-            let need = self.frame_len as usize;
-            let avail = buffer.len().saturating_sub(FIRST_FRAME_PAYLOAD_START);
-            let take = FIRST_FRAME_PAYLOAD_MAX.min(avail).min(need);
-            if take > 0 {
-                self.data.extend_from_slice(
-                    &buffer[FIRST_FRAME_PAYLOAD_START..FIRST_FRAME_PAYLOAD_START + take],
-                );
+            let take = N2K_FIRST_PAYLOAD_SZ.min(buffer.len() - 2).min(usize::from(frame_len));
+            let data = buffer[2..2 + take].to_vec();
+            if data.len() == usize::from(frame_len) {
+                return SockCanOpCode::RxRead(data);
             }
 
-            if self.data.len() == need {
-                let response = SockCanOpCode::RxRead(self.data.clone());
-                self.reset();
-                return response;
-            }
-
-            return SockCanOpCode::RxPartial(self.frame_idx);
+            self.partials.insert(
+                key,
+                SockJ1939FastPartial { frame_idx: 1, frame_len, data, stamp: Instant::now() },
+            );
+            return SockCanOpCode::RxPartial(1);
         }
 
-        // subsequent frames: check continuity (both serial and index)
-        if self.frame_count != frame_serial || self.frame_idx != frame_index {
-            self.reset();
+        // subsequent frames: check continuity
+        let Some(partial) = self.partials.get_mut(&key) else {
+            return SockCanOpCode::RxIgnore; // first frame was missed
+        };
+        if partial.frame_idx != frame_index {
+            self.partials.remove(&key);
             return SockCanOpCode::RxError(CanError::new(
                 "j1939-fastpkg-sequence",
-                format!("pgn:{} message sequence ordering broken", self.pgn),
+                format!("pgn:{} src:{src:#02x} message sequence ordering broken", self.pgn),
             ));
         }
 
         // append up to 7 bytes (1..8), but only what we still need
-        let need = (self.frame_len as usize).saturating_sub(self.data.len());
-        if need == 0 {
-            // should not happen, but guard anyway
-            let response = SockCanOpCode::RxRead(self.data.clone());
-            self.reset();
-            return response;
-        }
-
-        let avail = buffer.len().saturating_sub(NEXT_FRAME_PAYLOAD_START);
-        let take = NEXT_FRAME_PAYLOAD_MAX.min(avail).min(need);
-        if take > 0 {
-            self.data.extend_from_slice(
-                &buffer[NEXT_FRAME_PAYLOAD_START..NEXT_FRAME_PAYLOAD_START + take],
-            );
-        }
-
-        if self.data.len() == self.frame_len as usize {
-            let response = SockCanOpCode::RxRead(self.data.clone());
-            self.reset();
-            return response;
+        let need = usize::from(partial.frame_len).saturating_sub(partial.data.len());
+        let take = N2K_NEXT_PAYLOAD_SZ.min(buffer.len() - 1).min(need);
+        partial.data.extend_from_slice(&buffer[1..=take]);
+        partial.stamp = Instant::now();
+
+        if partial.data.len() == usize::from(partial.frame_len) {
+            return match self.partials.remove(&key) {
+                Some(partial) => SockCanOpCode::RxRead(partial.data),
+                None => SockCanOpCode::RxInvalid,
+            };
         }
 
         // expect next fragment
-        self.frame_idx = self.frame_idx.saturating_add(1);
-        SockCanOpCode::RxPartial(self.frame_idx)
+        partial.frame_idx = partial.frame_idx.saturating_add(1);
+        SockCanOpCode::RxPartial(partial.frame_idx)
     }
 }
 pub struct SockJ1939Filters {
@@ -466,7 +754,7 @@ impl SockCanCtrl for SockJ1939Filters {
         match self.search_pgn(info.pgn) {
             // if fast packet process partial data, else return msg data as it
             Err(_error) => SockCanOpCode::RxRead(data.to_vec()),
-            Ok(mut fast) => fast.push(data, recv.count),
+            Ok(mut fast) => fast.push(info.src.addr, data, recv.count),
         }
     }
 }
@@ -553,7 +841,7 @@ impl SockJ1939Filters {
     ///   configuration.
     pub fn apply(&mut self, sock: &SockCanHandle) -> Result<(), CanError> {
         // sort fast packet vector list
-        self.fastpkg.sort_by(|a, b| a.borrow().pgn.cmp(&b.borrow().pgn));
+        self.fastpkg.sort_by_key(|a| a.borrow().pgn);
 
         // build filter list
         let filter_len = self.filter.len();
@@ -596,4 +884,72 @@ mod tests {
         assert_eq!(mask_eff_flag(0x98A8_4444), 0x18A8_4444);
         assert_eq!(mask_eff_flag(0x18A8_4444), 0x18A8_4444);
     }
+
+    #[test]
+    fn test_send_dst_check() {
+        assert!(SockJ1939Dst::Broadcast.check(0xFEF1, 8).is_ok());
+        assert!(SockJ1939Dst::Broadcast.check(0xFEF1, 1785).is_ok());
+        assert!(SockJ1939Dst::Broadcast.check(0xFEF1, 1786).is_err());
+        assert!(SockJ1939Dst::Addr(0x20).check(0xEF00, 1786).is_ok());
+        assert!(SockJ1939Dst::Name(0x1234).check(0xEF00, 117_440_506).is_err());
+        assert!(SockJ1939Dst::Addr(0x20).check(0x4_0000, 8).is_err());
+
+        let canaddr = SockJ1939Dst::Addr(0x20).get_sockaddr(0xEF00);
+        let j1939 = unsafe { canaddr.can_addr.j1939 };
+        assert_eq!((j1939.pgn, j1939.addr), (0xEF00, 0x20));
+    }
+
+    #[test]
+    fn test_msg_data_header() {
+        let msg = |priority: u8, len: usize| SockJ1939Msg {
+            opcode: SockCanOpCode::RxRead(vec![0; len]),
+            info: CanRecvInfo {
+                proto: CanProtoInfo::J1939(CanJ1939Info {
+                    src: CanJ1939Header { name: 0, addr: 0x17 },
+                    dst: CanJ1939Header { name: 0, addr: 0xFF },
+                    pgn: 0xF004,
+                    priority,
+                }),
+                stamp: 0,
+                stamp_ns: 0,
+                errno: 0,
+                count: 0,
+                iface: 0,
+            },
+        };
+        let eec1 = msg(3, 8);
+        let data = CanMsgData::try_from(&eec1).unwrap();
+        assert_eq!((data.canid, data.len), (0x8CF0_0417, 8));
+        let dm1 = msg(6, 1785);
+        assert!(CanMsgData::try_from(&dm1).is_err());
+    }
+
+    #[test]
+    fn test_fast_interleaved() {
+        let msg_a: Vec<u8> = (0..20).collect();
+        let msg_b: Vec<u8> = (100..110).collect();
+        let frames_a = SockJ1939Fast::fragment(1, &msg_a).unwrap();
+        let frames_b = SockJ1939Fast::fragment(1, &msg_b).unwrap();
+        assert_eq!(frames_a.len(), 3);
+        assert_eq!(frames_a[2], [0x22, 13, 14, 15, 16, 17, 18, 19]);
+        assert!(SockJ1939Fast::fragment(0, &[0; 224]).is_err());
+
+        // same pgn and sequence counter from two sources
+        let mut fast = SockJ1939Fast::new(129_285, 0, 223);
+        let mut received = Vec::new();
+        let frames = [
+            (0x10, frames_a[0]),
+            (0x20, frames_b[0]),
+            (0x10, frames_a[1]),
+            (0x20, frames_b[1]),
+            (0x10, frames_a[2]),
+        ];
+        for (src, frame) in frames {
+            if let SockCanOpCode::RxRead(data) = fast.push(src, &frame, 8) {
+                received.push((src, data));
+            }
+        }
+        assert_eq!(received, vec![(0x20, msg_b), (0x10, msg_a)]);
+        assert_eq!(fast.get_pending(), 0);
+    }
 }
@@ -0,0 +1,366 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    ISO 14229-1 Unified diagnostic services (UDS)
+ *
+*/
+use crate::prelude::*;
+use std::time::Instant;
+
+const UDS_MAX_PENDING: u32 = 10; // max consecutive NRC 0x78 before giving up
+
+/// Computes `SecurityAccess` key from ECU seed (OEM specific algorithm)
+pub trait UdsSecurityCtrl {
+    /// Returns key for `level` (odd request-seed level) from ECU `seed`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when no key can be computed for this level/seed.
+    fn compute_key(&self, level: u8, seed: &[u8]) -> Result<Vec<u8>, CanError>;
+}
+
+pub enum UdsReply {
+    Positive(Vec<u8>),
+    Negative(UdsNrc),
+}
+
+/// UDS client, sends typed requests over any `UdsTransport` (ISO-TP socket, ...)
+pub struct UdsClient<T: UdsTransport> {
+    transport: T,
+    timing: UdsTiming,
+    max_pending: u32,
+    security: Option<Box<dyn UdsSecurityCtrl>>,
+    last_nrc: Option<UdsNrc>,
+    last_request: Instant,
+}
+
+impl<T: UdsTransport> UdsClient<T> {
+    pub fn new(transport: T) -> Self {
+        UdsClient {
+            transport,
+            timing: UdsTiming::default(),
+            max_pending: UDS_MAX_PENDING,
+            security: None,
+            last_nrc: None,
+            last_request: Instant::now(),
+        }
+    }
+
+    /// Overrides P2 (response) and P2* (after response pending) timeouts
+    pub fn set_timing(&mut self, p2_ms: u64, p2_star_ms: u64) -> &mut Self {
+        self.timing = UdsTiming { p2_ms, p2_star_ms };
+        self
+    }
+
+    /// Maximum number of consecutive response pending (NRC 0x78) accepted per request
+    pub fn set_max_pending(&mut self, count: u32) -> &mut Self {
+        self.max_pending = count;
+        self
+    }
+
+    pub fn set_security(&mut self, callback: Box<dyn UdsSecurityCtrl>) -> &mut Self {
+        self.security = Some(callback);
+        self
+    }
+
+    pub fn get_timing(&self) -> UdsTiming {
+        self.timing
+    }
+
+    /// Returns negative response code from last failed request, if any
+    pub fn get_last_nrc(&self) -> Option<UdsNrc> {
+        self.last_nrc
+    }
+
+    pub fn get_transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Sends a raw request and waits for its response, response pending (NRC 0x78)
+    /// extends timeout to P2* until final response.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on transport failure, timeout, too many pending responses or
+    /// when response does not match request service.
+    pub fn request(&mut self, request: &[u8]) -> Result<UdsReply, CanError> {
+        let Some(&sid) = request.first() else {
+            return Err(CanError::new("uds-request-empty", "empty uds request"));
+        };
+
+        self.last_request = Instant::now();
+        self.transport.send_pdu(request)?;
+
+        let mut timeout = self.timing.p2_ms;
+        let mut pending = 0;
+        loop {
+            let Some(response) = self.transport.recv_pdu(timeout)? else {
+                return Err(CanError::new(
+                    "uds-response-timeout",
+                    format!("sid:{sid:#04x} no response within {timeout}ms"),
+                ));
+            };
+
+            match response.as_slice() {
+                [UDS_NEGATIVE_RESPONSE, rsid, code, ..] if *rsid == sid => {
+                    let nrc = UdsNrc::from(*code);
+                    if nrc != UdsNrc::ResponsePending {
+                        return Ok(UdsReply::Negative(nrc));
+                    }
+                    pending += 1;
+                    if pending > self.max_pending {
+                        return Err(CanError::new(
+                            "uds-response-pending",
+                            format!("sid:{sid:#04x} still pending after {pending} retries"),
+                        ));
+                    }
+                    timeout = self.timing.p2_star_ms;
+                },
+                [rsid, ..] if *rsid == sid.wrapping_add(UDS_POSITIVE_OFFSET) => {
+                    return Ok(UdsReply::Positive(response));
+                },
+                // late response from a previous request
+                _ => log::debug!("uds sid:{sid:#04x} ignoring response {response:02x?}"),
+            }
+        }
+    }
+
+    // send request and return positive response, negative one become an error
+    fn request_positive(&mut self, request: &[u8]) -> Result<Vec<u8>, CanError> {
+        match self.request(request)? {
+            UdsReply::Positive(response) => {
+                self.last_nrc = None;
+                Ok(response)
+            },
+            UdsReply::Negative(nrc) => {
+                self.last_nrc = Some(nrc);
+                Err(CanError::new(
+                    "uds-negative-response",
+                    format!("sid:{:#04x} nrc:{nrc}", request[0]),
+                ))
+            },
+        }
+    }
+
+    fn check_len(response: &[u8], len: usize) -> Result<(), CanError> {
+        if response.len() < len {
+            return Err(CanError::new(
+                "uds-response-len",
+                format!("response:{response:02x?} shorter than {len} bytes"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Switches diagnostic session, P2/P2* timings are updated from ECU response.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on transport failure or negative response.
+    pub fn session_control(&mut self, session: UdsSession) -> Result<UdsTiming, CanError> {
+        let response =
+            self.request_positive(&[UdsSid::DiagnosticSessionControl as u8, u8::from(session)])?;
+        if response.len() >= 6 {
+            self.timing = UdsTiming {
+                p2_ms: u64::from(u16::from_be_bytes([response[2], response[3]])),
+                p2_star_ms: u64::from(u16::from_be_bytes([response[4], response[5]])) * 10,
+            };
+        }
+        Ok(self.timing)
+    }
+
+    /// Reads data identifier `did` value.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on transport failure, negative response or did mismatch.
+    pub fn read_data(&mut self, did: u16) -> Result<Vec<u8>, CanError> {
+        let did_be = did.to_be_bytes();
+        let response =
+            self.request_positive(&[UdsSid::ReadDataByIdentifier as u8, did_be[0], did_be[1]])?;
+        Self::check_len(&response, 3)?;
+        if response[1..3] != did_be {
+            return Err(CanError::new("uds-did-mismatch", format!("expected did:{did:#06x}")));
+        }
+        Ok(response[3..].to_vec())
+    }
+
+    /// Writes `data` into data identifier `did`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on transport failure or negative response.
+    pub fn write_data(&mut self, did: u16, data: &[u8]) -> Result<(), CanError> {
+        let mut request = vec![UdsSid::WriteDataByIdentifier as u8];
+        request.extend_from_slice(&did.to_be_bytes());
+        request.extend_from_slice(data);
+        self.request_positive(&request)?;
+        Ok(())
+    }
+
+    /// Unlocks security `level` (odd request-seed level) using `set_security` callback
+    /// to compute key. A null seed means ECU is already unlocked.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when no security callback is set, seed is refused or key is invalid.
+    pub fn security_access(&mut self, level: u8) -> Result<(), CanError> {
+        // request seed levels are odd within 0x01..=0x7D, send key uses level + 1
+        if !(0x01..=0x7E).contains(&level) || level.is_multiple_of(2) {
+            return Err(CanError::new(
+                "uds-security-level",
+                format!("request seed level:{level:#04x} should be odd within 0x01..=0x7D"),
+            ));
+        }
+        let response = self.request_positive(&[UdsSid::SecurityAccess as u8, level])?;
+        Self::check_len(&response, 2)?;
+        let seed = &response[2..];
+        if seed.iter().all(|byte| *byte == 0) {
+            return Ok(());
+        }
+
+        let key = match &self.security {
+            None => return Err(CanError::new("uds-security-callback", "no security callback")),
+            Some(callback) => callback.compute_key(level, seed)?,
+        };
+        let mut request = vec![UdsSid::SecurityAccess as u8, level + 1];
+        request.extend_from_slice(&key);
+        self.request_positive(&request)?;
+        Ok(())
+    }
+
+    /// Starts/stops routine `rid` or requests its results, returns routine status record.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on transport failure or negative response.
+    pub fn routine_control(
+        &mut self,
+        ctrl: UdsRoutineCtrl,
+        rid: u16,
+        option: &[u8],
+    ) -> Result<Vec<u8>, CanError> {
+        let mut request = vec![UdsSid::RoutineControl as u8, ctrl as u8];
+        request.extend_from_slice(&rid.to_be_bytes());
+        request.extend_from_slice(option);
+        let response = self.request_positive(&request)?;
+        Self::check_len(&response, 4)?;
+        Ok(response[4..].to_vec())
+    }
+
+    /// Reads DTCs matching `status_mask` (`ReadDTCInformation` 0x02), returns
+    /// ECU status availability mask and DTC list.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on transport failure or negative response.
+    pub fn read_dtc_by_status(&mut self, status_mask: u8) -> Result<(u8, Vec<UdsDtc>), CanError> {
+        let response =
+            self.request_positive(&[UdsSid::ReadDtcInformation as u8, 0x02, status_mask])?;
+        Self::check_len(&response, 3)?;
+        Ok((response[2], UdsDtc::from_records(&response[3..])))
+    }
+
+    /// Sends `TesterPresent`, with `suppress` ECU does not answer.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on transport failure or negative response.
+    pub fn tester_present(&mut self, suppress: bool) -> Result<(), CanError> {
+        if suppress {
+            self.last_request = Instant::now();
+            return self.transport.send_pdu(&[UdsSid::TesterPresent as u8, UDS_SUPPRESS_RESPONSE]);
+        }
+        self.request_positive(&[UdsSid::TesterPresent as u8, 0x00])?;
+        Ok(())
+    }
+
+    /// Keeps non default session alive, sends a suppressed `TesterPresent` when no request
+    /// was sent for `period_ms` (typically 2000ms). Call it from application main loop,
+    /// returns true when a `TesterPresent` was sent.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when `TesterPresent` cannot be sent.
+    pub fn keep_alive(&mut self, period_ms: u64) -> Result<bool, CanError> {
+        let elapsed = u64::try_from(self.last_request.elapsed().as_millis()).unwrap_or(u64::MAX);
+        if elapsed < period_ms {
+            return Ok(false);
+        }
+        self.tester_present(true)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // scripted ECU, returns queued responses in order
+    struct FakeEcu {
+        sent: Vec<Vec<u8>>,
+        replies: VecDeque<Vec<u8>>,
+    }
+
+    impl UdsTransport for FakeEcu {
+        fn send_pdu(&mut self, pdu: &[u8]) -> Result<(), CanError> {
+            self.sent.push(pdu.to_vec());
+            Ok(())
+        }
+        fn recv_pdu(&mut self, _timeout_ms: u64) -> Result<Option<Vec<u8>>, CanError> {
+            Ok(self.replies.pop_front())
+        }
+    }
+
+    struct XorKey;
+    impl UdsSecurityCtrl for XorKey {
+        fn compute_key(&self, _level: u8, seed: &[u8]) -> Result<Vec<u8>, CanError> {
+            Ok(seed.iter().map(|byte| byte ^ 0xFF).collect())
+        }
+    }
+
+    fn client(replies: &[&[u8]]) -> UdsClient<FakeEcu> {
+        UdsClient::new(FakeEcu {
+            sent: Vec::new(),
+            replies: replies.iter().map(|reply| reply.to_vec()).collect(),
+        })
+    }
+
+    #[test]
+    fn uds_response_pending() {
+        let mut uds =
+            client(&[&[0x7F, 0x22, 0x78], &[0x7F, 0x22, 0x78], &[0x62, 0xF1, 0x90, 1, 2]]);
+        assert_eq!(uds.read_data(0xF190).unwrap(), vec![1, 2]);
+        assert_eq!(uds.get_transport().sent, vec![vec![0x22, 0xF1, 0x90]]);
+
+        let mut uds = client(&[&[0x7F, 0x2E, 0x31]]);
+        assert!(uds.write_data(0xF190, &[1]).is_err());
+        assert_eq!(uds.get_last_nrc(), Some(UdsNrc::RequestOutOfRange));
+
+        let mut uds = client(&[&[0x7F, 0x22, 0x78], &[0x7F, 0x22, 0x78]]);
+        uds.set_max_pending(1);
+        assert!(uds.read_data(0xF190).is_err());
+    }
+
+    #[test]
+    fn uds_typed_services() {
+        let mut uds = client(&[
+            &[0x50, 0x03, 0x00, 0x32, 0x01, 0xF4],
+            &[0x67, 0x01, 0x12, 0x34],
+            &[0x67, 0x02],
+            &[0x59, 0x02, 0xFF, 0x12, 0x34, 0x56, 0x09],
+            &[0x71, 0x01, 0xFF, 0x00, 0x02],
+        ]);
+        uds.set_security(Box::new(XorKey));
+        let timing = uds.session_control(UdsSession::Extended).unwrap();
+        assert_eq!(timing, UdsTiming { p2_ms: 50, p2_star_ms: 5000 });
+        uds.security_access(1).unwrap();
+        let (mask, dtcs) = uds.read_dtc_by_status(0x08).unwrap();
+        assert_eq!((mask, dtcs), (0xFF, vec![UdsDtc { code: 0x12_3456, status: 0x09 }]));
+        let status = uds.routine_control(UdsRoutineCtrl::Start, 0xFF00, &[]).unwrap();
+        assert_eq!(status, vec![0x02]);
+        assert_eq!(uds.get_transport().sent[2], vec![0x27, 0x02, 0xED, 0xCB]);
+
+        // invalid levels are refused before any request is sent
+        assert!(uds.security_access(0x02).is_err());
+        assert!(uds.security_access(0x7F).is_err());
+        assert!(uds.security_access(0xFF).is_err());
+        assert_eq!(uds.get_transport().sent.len(), 5);
+    }
+}
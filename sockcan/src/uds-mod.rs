@@ -0,0 +1,275 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    ISO 14229-1 Unified diagnostic services (UDS)
+ *    https://en.wikipedia.org/wiki/Unified_Diagnostic_Services
+ *
+*/
+use crate::prelude::*;
+use std::fmt;
+use std::io;
+
+pub const UDS_NEGATIVE_RESPONSE: u8 = 0x7F;
+pub const UDS_POSITIVE_OFFSET: u8 = 0x40;
+pub const UDS_SUPPRESS_RESPONSE: u8 = 0x80;
+
+/// UDS service identifiers (request SID, positive response is SID + 0x40)
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum UdsSid {
+    DiagnosticSessionControl = 0x10,
+    EcuReset = 0x11,
+    ClearDiagnosticInformation = 0x14,
+    ReadDtcInformation = 0x19,
+    ReadDataByIdentifier = 0x22,
+    SecurityAccess = 0x27,
+    WriteDataByIdentifier = 0x2E,
+    RoutineControl = 0x31,
+    TesterPresent = 0x3E,
+}
+
+impl UdsSid {
+    /// Returns true for services whose first byte is a sub-function accepting the
+    /// suppress positive response bit.
+    #[must_use]
+    pub fn has_subfunction(self) -> bool {
+        matches!(
+            self,
+            UdsSid::DiagnosticSessionControl
+                | UdsSid::EcuReset
+                | UdsSid::SecurityAccess
+                | UdsSid::RoutineControl
+                | UdsSid::TesterPresent
+        )
+    }
+}
+
+impl TryFrom<u8> for UdsSid {
+    type Error = UdsNrc;
+    fn try_from(sid: u8) -> Result<Self, UdsNrc> {
+        let sid = match sid {
+            0x10 => UdsSid::DiagnosticSessionControl,
+            0x11 => UdsSid::EcuReset,
+            0x14 => UdsSid::ClearDiagnosticInformation,
+            0x19 => UdsSid::ReadDtcInformation,
+            0x22 => UdsSid::ReadDataByIdentifier,
+            0x27 => UdsSid::SecurityAccess,
+            0x2E => UdsSid::WriteDataByIdentifier,
+            0x31 => UdsSid::RoutineControl,
+            0x3E => UdsSid::TesterPresent,
+            _ => return Err(UdsNrc::ServiceNotSupported),
+        };
+        Ok(sid)
+    }
+}
+
+/// UDS negative response codes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UdsNrc {
+    GeneralReject,
+    ServiceNotSupported,
+    SubFunctionNotSupported,
+    IncorrectMessageLength,
+    ConditionsNotCorrect,
+    RequestSequenceError,
+    RequestOutOfRange,
+    SecurityAccessDenied,
+    InvalidKey,
+    ExceededNumberOfAttempts,
+    RequiredTimeDelayNotExpired,
+    ResponsePending,
+    ServiceNotSupportedInActiveSession,
+    Other(u8),
+}
+
+impl From<u8> for UdsNrc {
+    fn from(code: u8) -> Self {
+        match code {
+            0x10 => UdsNrc::GeneralReject,
+            0x11 => UdsNrc::ServiceNotSupported,
+            0x12 => UdsNrc::SubFunctionNotSupported,
+            0x13 => UdsNrc::IncorrectMessageLength,
+            0x22 => UdsNrc::ConditionsNotCorrect,
+            0x24 => UdsNrc::RequestSequenceError,
+            0x31 => UdsNrc::RequestOutOfRange,
+            0x33 => UdsNrc::SecurityAccessDenied,
+            0x35 => UdsNrc::InvalidKey,
+            0x36 => UdsNrc::ExceededNumberOfAttempts,
+            0x37 => UdsNrc::RequiredTimeDelayNotExpired,
+            0x78 => UdsNrc::ResponsePending,
+            0x7F => UdsNrc::ServiceNotSupportedInActiveSession,
+            _ => UdsNrc::Other(code),
+        }
+    }
+}
+
+impl From<UdsNrc> for u8 {
+    fn from(nrc: UdsNrc) -> u8 {
+        match nrc {
+            UdsNrc::GeneralReject => 0x10,
+            UdsNrc::ServiceNotSupported => 0x11,
+            UdsNrc::SubFunctionNotSupported => 0x12,
+            UdsNrc::IncorrectMessageLength => 0x13,
+            UdsNrc::ConditionsNotCorrect => 0x22,
+            UdsNrc::RequestSequenceError => 0x24,
+            UdsNrc::RequestOutOfRange => 0x31,
+            UdsNrc::SecurityAccessDenied => 0x33,
+            UdsNrc::InvalidKey => 0x35,
+            UdsNrc::ExceededNumberOfAttempts => 0x36,
+            UdsNrc::RequiredTimeDelayNotExpired => 0x37,
+            UdsNrc::ResponsePending => 0x78,
+            UdsNrc::ServiceNotSupportedInActiveSession => 0x7F,
+            UdsNrc::Other(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for UdsNrc {
+    fn fmt(&self, format: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(format, "{self:?}({:#04x})", u8::from(*self))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UdsSession {
+    Default,
+    Programming,
+    Extended,
+    Other(u8),
+}
+
+impl From<u8> for UdsSession {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => UdsSession::Default,
+            0x02 => UdsSession::Programming,
+            0x03 => UdsSession::Extended,
+            _ => UdsSession::Other(code),
+        }
+    }
+}
+
+impl From<UdsSession> for u8 {
+    fn from(session: UdsSession) -> u8 {
+        match session {
+            UdsSession::Default => 0x01,
+            UdsSession::Programming => 0x02,
+            UdsSession::Extended => 0x03,
+            UdsSession::Other(code) => code,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum UdsRoutineCtrl {
+    Start = 0x01,
+    Stop = 0x02,
+    Results = 0x03,
+}
+
+/// Session timing returned by `DiagnosticSessionControl` (P2 and P2* in ms)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UdsTiming {
+    pub p2_ms: u64,
+    pub p2_star_ms: u64,
+}
+
+impl Default for UdsTiming {
+    fn default() -> Self {
+        UdsTiming { p2_ms: 50, p2_star_ms: 5000 }
+    }
+}
+
+/// Diagnostic trouble code (24 bits) with its status byte
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UdsDtc {
+    pub code: u32,
+    pub status: u8,
+}
+
+impl UdsDtc {
+    pub const STATUS_TEST_FAILED: u8 = 0x01;
+    pub const STATUS_PENDING: u8 = 0x04;
+    pub const STATUS_CONFIRMED: u8 = 0x08;
+
+    /// Parses `DTC[3]+STATUS` records, trailing incomplete record is ignored
+    #[must_use]
+    pub fn from_records(data: &[u8]) -> Vec<UdsDtc> {
+        data.chunks_exact(4)
+            .map(|rec| UdsDtc {
+                code: u32::from_be_bytes([0, rec[0], rec[1], rec[2]]),
+                status: rec[3],
+            })
+            .collect()
+    }
+
+    pub fn push_record(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.code.to_be_bytes()[1..4]);
+        data.push(self.status);
+    }
+}
+
+/// Transport used by UDS client/server, one call send/receive a whole PDU.
+pub trait UdsTransport {
+    /// Sends a whole UDS PDU.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the transport fails to send the PDU.
+    fn send_pdu(&mut self, pdu: &[u8]) -> Result<(), CanError>;
+
+    /// Waits up to `timeout_ms` for next PDU, returns `None` on timeout.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when the transport fails to receive.
+    fn recv_pdu(&mut self, timeout_ms: u64) -> Result<Option<Vec<u8>>, CanError>;
+}
+
+/// ISO-TP socket transport (see `SockCanIsoTp::open_isotp`), socket read timeout is only
+/// updated when the requested one changes.
+pub struct UdsIsoTp {
+    sock: SockCanHandle,
+    timeout_ms: u64,
+}
+
+impl UdsIsoTp {
+    #[must_use]
+    pub fn new(sock: SockCanHandle) -> Self {
+        UdsIsoTp { sock, timeout_ms: 0 }
+    }
+
+    #[must_use]
+    pub fn get_socket(&self) -> &SockCanHandle {
+        &self.sock
+    }
+}
+
+impl UdsTransport for UdsIsoTp {
+    fn send_pdu(&mut self, pdu: &[u8]) -> Result<(), CanError> {
+        self.sock.send_isotp(pdu)
+    }
+
+    fn recv_pdu(&mut self, timeout_ms: u64) -> Result<Option<Vec<u8>>, CanError> {
+        let timeout_ms = timeout_ms.max(1);
+        if timeout_ms != self.timeout_ms {
+            self.sock.set_timeout(i64::try_from(timeout_ms).unwrap_or(i64::MAX), 0)?;
+            self.timeout_ms = timeout_ms;
+        }
+
+        let msg = self.sock.get_isotp_frame();
+        if msg.info.count < 0 {
+            // errno captured with the frame, later calls may overwrite the global one
+            let error = io::Error::from_raw_os_error(msg.info.errno);
+            return match error.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Ok(None),
+                _ => Err(CanError::new("uds-recv-fail", error.to_string())),
+            };
+        }
+        Ok(Some(msg.get_data().to_vec()))
+    }
+}
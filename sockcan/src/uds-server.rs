@@ -0,0 +1,555 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    ISO 14229-1 Unified diagnostic services (UDS)
+ *
+*/
+use crate::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use std::time::Duration;
+
+const UDS_MAX_KEY_ATTEMPTS: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UdsDidAccess {
+    ReadOnly,
+    ReadWrite,
+    // writable once security level is unlocked
+    Secured(u8),
+}
+
+struct UdsDid {
+    data: Vec<u8>,
+    access: UdsDidAccess,
+}
+
+struct UdsRoutine {
+    status: Vec<u8>,
+    started: bool,
+}
+
+/// Simulated ECU, answers UDS requests from declarative DID/DTC/routine tables.
+pub struct UdsServer<T: UdsTransport> {
+    transport: T,
+    timing: UdsTiming,
+    session: UdsSession,
+    dids: HashMap<u16, UdsDid>,
+    dtcs: Vec<UdsDtc>,
+    routines: HashMap<u16, UdsRoutine>,
+    security: Option<Box<dyn UdsSecurityCtrl>>,
+    seed: Vec<u8>,
+    seed_level: Option<u8>,
+    unlocked: HashSet<u8>,
+    key_attempts: u8,
+    nrcs: HashMap<u8, UdsNrc>,
+    delays: HashMap<u8, u64>,
+}
+
+impl<T: UdsTransport> UdsServer<T> {
+    pub fn new(transport: T) -> Self {
+        UdsServer {
+            transport,
+            timing: UdsTiming::default(),
+            session: UdsSession::Default,
+            dids: HashMap::new(),
+            dtcs: Vec::new(),
+            routines: HashMap::new(),
+            security: None,
+            seed: vec![0x12, 0x34, 0x56, 0x78],
+            seed_level: None,
+            unlocked: HashSet::new(),
+            key_attempts: 0,
+            nrcs: HashMap::new(),
+            delays: HashMap::new(),
+        }
+    }
+
+    /// P2/P2* timings returned on `DiagnosticSessionControl`
+    pub fn set_timing(&mut self, p2_ms: u64, p2_star_ms: u64) -> &mut Self {
+        self.timing = UdsTiming { p2_ms, p2_star_ms };
+        self
+    }
+
+    pub fn add_did(&mut self, did: u16, data: &[u8], access: UdsDidAccess) -> &mut Self {
+        self.dids.insert(did, UdsDid { data: data.to_vec(), access });
+        self
+    }
+
+    pub fn add_dtc(&mut self, code: u32, status: u8) -> &mut Self {
+        self.dtcs.push(UdsDtc { code: code & 0x00FF_FFFF, status });
+        self
+    }
+
+    /// Routine `rid` returns `status` record on start/stop/results
+    pub fn add_routine(&mut self, rid: u16, status: &[u8]) -> &mut Self {
+        self.routines
+            .insert(rid, UdsRoutine { status: status.to_vec(), started: false });
+        self
+    }
+
+    /// Key algorithm, same callback as client side; without it `SecurityAccess` is not supported
+    pub fn set_security(&mut self, callback: Box<dyn UdsSecurityCtrl>) -> &mut Self {
+        self.security = Some(callback);
+        self
+    }
+
+    pub fn set_seed(&mut self, seed: &[u8]) -> &mut Self {
+        self.seed = seed.to_vec();
+        self
+    }
+
+    /// Forces service `sid` to answer with `nrc`, None restores normal behavior
+    pub fn set_nrc(&mut self, sid: u8, nrc: Option<UdsNrc>) -> &mut Self {
+        match nrc {
+            Some(nrc) => self.nrcs.insert(sid, nrc),
+            None => self.nrcs.remove(&sid),
+        };
+        self
+    }
+
+    /// Delays service `sid` positive response, a delay longer than P2 sends response pending
+    /// before each P2* period
+    pub fn set_delay(&mut self, sid: u8, delay_ms: u64) -> &mut Self {
+        self.delays.insert(sid, delay_ms);
+        self
+    }
+
+    pub fn get_session(&self) -> UdsSession {
+        self.session
+    }
+
+    pub fn get_did(&self, did: u16) -> Option<&[u8]> {
+        self.dids.get(&did).map(|entry| entry.data.as_slice())
+    }
+
+    pub fn get_dtcs(&self) -> &[UdsDtc] {
+        &self.dtcs
+    }
+
+    pub fn get_transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Waits up to `timeout_ms` for one request and sends its response (if any),
+    /// returns false when no request was received.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on transport failure.
+    pub fn process(&mut self, timeout_ms: u64) -> Result<bool, CanError> {
+        let Some(request) = self.transport.recv_pdu(timeout_ms)? else {
+            return Ok(false);
+        };
+        let Some(&sid) = request.first() else {
+            return Ok(true);
+        };
+
+        // request is validated first, only positive responses are delayed
+        let Some(response) = self.handle_request(&request) else {
+            return Ok(true);
+        };
+        if response[0] != UDS_NEGATIVE_RESPONSE {
+            if let Some(&delay) = self.delays.get(&sid) {
+                self.pending(sid, delay)?;
+            }
+        }
+        self.transport.send_pdu(&response)?;
+        Ok(true)
+    }
+
+    // waits `delay_ms`, past P2 response pending is repeated before each P2* expires
+    fn pending(&mut self, sid: u8, delay_ms: u64) -> Result<(), CanError> {
+        if delay_ms <= self.timing.p2_ms {
+            thread::sleep(Duration::from_millis(delay_ms));
+            return Ok(());
+        }
+        let slice = (self.timing.p2_star_ms - self.timing.p2_star_ms / 10).max(1);
+        let mut remaining = delay_ms;
+        while remaining > 0 {
+            self.transport.send_pdu(&[
+                UDS_NEGATIVE_RESPONSE,
+                sid,
+                u8::from(UdsNrc::ResponsePending),
+            ])?;
+            let sleep = remaining.min(slice);
+            thread::sleep(Duration::from_millis(sleep));
+            remaining -= sleep;
+        }
+        Ok(())
+    }
+
+    /// Serves requests until transport fails.
+    ///
+    /// # Errors
+    /// Returns the `CanError` that stopped the server.
+    pub fn serve(&mut self) -> Result<(), CanError> {
+        loop {
+            self.process(1000)?;
+        }
+    }
+
+    /// Builds response to `request`, None when response is suppressed
+    pub fn handle_request(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        let &sid = request.first()?;
+        let result = match self.nrcs.get(&sid) {
+            Some(nrc) => Err(*nrc),
+            None => self.dispatch(sid, request),
+        };
+
+        match result {
+            Ok(response) => response,
+            Err(nrc) => Some(vec![UDS_NEGATIVE_RESPONSE, sid, u8::from(nrc)]),
+        }
+    }
+
+    fn dispatch(&mut self, sid: u8, request: &[u8]) -> Result<Option<Vec<u8>>, UdsNrc> {
+        let positive = sid.wrapping_add(UDS_POSITIVE_OFFSET);
+        let service = UdsSid::try_from(sid)?;
+
+        // only sub-function services carry suppress positive response bit (bit 7)
+        let mut args = request[1..].to_vec();
+        let suppress = match args.first_mut() {
+            Some(byte) if service.has_subfunction() => {
+                let suppress = *byte & UDS_SUPPRESS_RESPONSE != 0;
+                *byte &= !UDS_SUPPRESS_RESPONSE;
+                suppress
+            },
+            _ => false,
+        };
+
+        let response = match service {
+            UdsSid::DiagnosticSessionControl => {
+                check_args(&args, 1)?;
+                self.set_session(UdsSession::from(args[0]));
+                let p2 = u16::try_from(self.timing.p2_ms).unwrap_or(u16::MAX).to_be_bytes();
+                let p2_star =
+                    u16::try_from(self.timing.p2_star_ms / 10).unwrap_or(u16::MAX).to_be_bytes();
+                vec![positive, args[0], p2[0], p2[1], p2_star[0], p2_star[1]]
+            },
+            UdsSid::EcuReset => {
+                check_args(&args, 1)?;
+                self.set_session(UdsSession::Default);
+                vec![positive, args[0]]
+            },
+            UdsSid::TesterPresent => {
+                check_args(&args, 1)?;
+                if args[0] != 0 {
+                    return Err(UdsNrc::SubFunctionNotSupported);
+                }
+                vec![positive, args[0]]
+            },
+            UdsSid::ReadDataByIdentifier => self.read_data(positive, &args)?,
+            UdsSid::WriteDataByIdentifier => self.write_data(positive, &args)?,
+            UdsSid::SecurityAccess => self.security_access(positive, &args)?,
+            UdsSid::RoutineControl => self.routine_control(positive, &args)?,
+            UdsSid::ReadDtcInformation => self.read_dtc(positive, &args)?,
+            UdsSid::ClearDiagnosticInformation => {
+                check_args(&args, 3)?;
+                let group = u32::from_be_bytes([0, args[0], args[1], args[2]]);
+                if group == 0x00FF_FFFF {
+                    self.dtcs.clear();
+                } else {
+                    self.dtcs.retain(|dtc| dtc.code != group);
+                }
+                vec![positive]
+            },
+        };
+
+        if suppress {
+            Ok(None)
+        } else {
+            Ok(Some(response))
+        }
+    }
+
+    fn set_session(&mut self, session: UdsSession) {
+        self.session = session;
+        self.seed_level = None;
+        self.unlocked.clear();
+        self.key_attempts = 0;
+    }
+
+    fn read_data(&self, positive: u8, args: &[u8]) -> Result<Vec<u8>, UdsNrc> {
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(UdsNrc::IncorrectMessageLength);
+        }
+        let mut response = vec![positive];
+        for did_be in args.chunks_exact(2) {
+            let did = u16::from_be_bytes([did_be[0], did_be[1]]);
+            let entry = self.dids.get(&did).ok_or(UdsNrc::RequestOutOfRange)?;
+            response.extend_from_slice(did_be);
+            response.extend_from_slice(&entry.data);
+        }
+        Ok(response)
+    }
+
+    fn write_data(&mut self, positive: u8, args: &[u8]) -> Result<Vec<u8>, UdsNrc> {
+        check_args(args, 3)?;
+        let did = u16::from_be_bytes([args[0], args[1]]);
+        let entry = self.dids.get_mut(&did).ok_or(UdsNrc::RequestOutOfRange)?;
+        match entry.access {
+            UdsDidAccess::ReadOnly => return Err(UdsNrc::RequestOutOfRange),
+            UdsDidAccess::Secured(level) if !self.unlocked.contains(&level) => {
+                return Err(UdsNrc::SecurityAccessDenied)
+            },
+            _ => {},
+        }
+        if args.len() - 2 != entry.data.len() {
+            return Err(UdsNrc::IncorrectMessageLength);
+        }
+        entry.data = args[2..].to_vec();
+        Ok(vec![positive, args[0], args[1]])
+    }
+
+    fn security_access(&mut self, positive: u8, args: &[u8]) -> Result<Vec<u8>, UdsNrc> {
+        check_args(args, 1)?;
+        let Some(security) = &self.security else {
+            return Err(UdsNrc::ServiceNotSupported);
+        };
+        if self.session == UdsSession::Default {
+            return Err(UdsNrc::ServiceNotSupportedInActiveSession);
+        }
+        if self.key_attempts >= UDS_MAX_KEY_ATTEMPTS {
+            return Err(UdsNrc::ExceededNumberOfAttempts);
+        }
+
+        let level = args[0];
+        if level == 0 || level == 0x7F {
+            return Err(UdsNrc::SubFunctionNotSupported);
+        }
+        if !level.is_multiple_of(2) {
+            // request seed, a null seed tells level is already unlocked
+            let mut response = vec![positive, level];
+            if self.unlocked.contains(&level) {
+                response.resize(2 + self.seed.len(), 0);
+            } else {
+                response.extend_from_slice(&self.seed);
+                self.seed_level = Some(level);
+            }
+            return Ok(response);
+        }
+
+        // send key
+        let seed_level = level - 1;
+        if self.seed_level != Some(seed_level) {
+            return Err(UdsNrc::RequestSequenceError);
+        }
+        self.seed_level = None;
+        let expected =
+            security.compute_key(seed_level, &self.seed).map_err(|_| UdsNrc::InvalidKey)?;
+        if expected != args[1..] {
+            self.key_attempts += 1;
+            return Err(if self.key_attempts >= UDS_MAX_KEY_ATTEMPTS {
+                UdsNrc::ExceededNumberOfAttempts
+            } else {
+                UdsNrc::InvalidKey
+            });
+        }
+        self.key_attempts = 0;
+        self.unlocked.insert(seed_level);
+        Ok(vec![positive, level])
+    }
+
+    fn routine_control(&mut self, positive: u8, args: &[u8]) -> Result<Vec<u8>, UdsNrc> {
+        check_args(args, 3)?;
+        let rid = u16::from_be_bytes([args[1], args[2]]);
+        let routine = self.routines.get_mut(&rid).ok_or(UdsNrc::RequestOutOfRange)?;
+        match args[0] {
+            ctrl if ctrl == UdsRoutineCtrl::Start as u8 => routine.started = true,
+            ctrl if ctrl == UdsRoutineCtrl::Stop as u8 || ctrl == UdsRoutineCtrl::Results as u8 => {
+                if !routine.started {
+                    return Err(UdsNrc::RequestSequenceError);
+                }
+                if ctrl == UdsRoutineCtrl::Stop as u8 {
+                    routine.started = false;
+                }
+            },
+            _ => return Err(UdsNrc::SubFunctionNotSupported),
+        }
+        let mut response = vec![positive, args[0], args[1], args[2]];
+        response.extend_from_slice(&routine.status);
+        Ok(response)
+    }
+
+    fn read_dtc(&self, positive: u8, args: &[u8]) -> Result<Vec<u8>, UdsNrc> {
+        check_args(args, 1)?;
+        match args[0] {
+            // number of DTC by status mask
+            0x01 => {
+                check_args(args, 2)?;
+                let count = self.dtcs.iter().filter(|dtc| dtc.status & args[1] != 0).count();
+                let count = u16::try_from(count).unwrap_or(u16::MAX).to_be_bytes();
+                Ok(vec![positive, 0x01, 0xFF, 0x01, count[0], count[1]])
+            },
+            // DTC by status mask
+            0x02 => {
+                check_args(args, 2)?;
+                let mut response = vec![positive, 0x02, 0xFF];
+                for dtc in self.dtcs.iter().filter(|dtc| dtc.status & args[1] != 0) {
+                    dtc.push_record(&mut response);
+                }
+                Ok(response)
+            },
+            _ => Err(UdsNrc::SubFunctionNotSupported),
+        }
+    }
+}
+
+fn check_args(args: &[u8], len: usize) -> Result<(), UdsNrc> {
+    if args.len() < len {
+        return Err(UdsNrc::IncorrectMessageLength);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    // client transport calling server directly, no socket needed
+    struct Loopback {
+        server: Rc<RefCell<UdsServer<Idle>>>,
+        responses: VecDeque<Vec<u8>>,
+    }
+
+    struct Idle;
+    impl UdsTransport for Idle {
+        fn send_pdu(&mut self, _pdu: &[u8]) -> Result<(), CanError> {
+            Ok(())
+        }
+        fn recv_pdu(&mut self, _timeout_ms: u64) -> Result<Option<Vec<u8>>, CanError> {
+            Ok(None)
+        }
+    }
+
+    impl UdsTransport for Loopback {
+        fn send_pdu(&mut self, pdu: &[u8]) -> Result<(), CanError> {
+            if let Some(response) = self.server.borrow_mut().handle_request(pdu) {
+                self.responses.push_back(response);
+            }
+            Ok(())
+        }
+        fn recv_pdu(&mut self, _timeout_ms: u64) -> Result<Option<Vec<u8>>, CanError> {
+            Ok(self.responses.pop_front())
+        }
+    }
+
+    struct XorKey;
+    impl UdsSecurityCtrl for XorKey {
+        fn compute_key(&self, _level: u8, seed: &[u8]) -> Result<Vec<u8>, CanError> {
+            Ok(seed.iter().map(|byte| byte ^ 0xFF).collect())
+        }
+    }
+
+    #[test]
+    fn uds_server_flow() {
+        let mut ecu = UdsServer::new(Idle);
+        ecu.add_did(0xF190, b"VIN0123", UdsDidAccess::ReadOnly)
+            .add_did(0x0100, &[0, 0], UdsDidAccess::Secured(1))
+            .add_dtc(0x12_3456, UdsDtc::STATUS_CONFIRMED)
+            .add_dtc(0x65_4321, UdsDtc::STATUS_PENDING)
+            .add_routine(0xFF00, &[0x02])
+            .set_security(Box::new(XorKey));
+        let server = Rc::new(RefCell::new(ecu));
+
+        let mut uds =
+            UdsClient::new(Loopback { server: server.clone(), responses: VecDeque::new() });
+        uds.set_security(Box::new(XorKey));
+
+        assert_eq!(uds.read_data(0xF190).unwrap(), b"VIN0123".to_vec());
+        assert!(uds.write_data(0x0100, &[1, 2]).is_err());
+        assert_eq!(uds.get_last_nrc(), Some(UdsNrc::SecurityAccessDenied));
+        assert!(uds.security_access(1).is_err());
+        assert_eq!(uds.get_last_nrc(), Some(UdsNrc::ServiceNotSupportedInActiveSession));
+
+        uds.session_control(UdsSession::Extended).unwrap();
+        uds.security_access(1).unwrap();
+        uds.write_data(0x0100, &[1, 2]).unwrap();
+        assert_eq!(server.borrow().get_did(0x0100), Some([1u8, 2].as_slice()));
+
+        let (_, dtcs) = uds.read_dtc_by_status(UdsDtc::STATUS_CONFIRMED).unwrap();
+        assert_eq!(dtcs, vec![UdsDtc { code: 0x12_3456, status: UdsDtc::STATUS_CONFIRMED }]);
+        assert!(uds.routine_control(UdsRoutineCtrl::Results, 0xFF00, &[]).is_err());
+        uds.routine_control(UdsRoutineCtrl::Start, 0xFF00, &[]).unwrap();
+        uds.tester_present(true).unwrap();
+
+        // suppress bit only applies to sub-function services
+        let mut ecu = server.borrow_mut();
+        assert_eq!(ecu.handle_request(&[0x3E, 0x80]), None);
+        assert_eq!(ecu.handle_request(&[0x14, 0xFF, 0xFF, 0xFF]), Some(vec![0x54]));
+        assert!(ecu.get_dtcs().is_empty());
+
+        // invalid security levels, attempts counter reset on session change
+        assert_eq!(ecu.handle_request(&[0x27, 0x00]), Some(vec![0x7F, 0x27, 0x12]));
+        assert_eq!(ecu.handle_request(&[0x27, 0x7F]), Some(vec![0x7F, 0x27, 0x12]));
+        assert_eq!(ecu.handle_request(&[0x27, 0x83]), None);
+        for nrc in [0x35, 0x35, 0x36, 0x36] {
+            ecu.handle_request(&[0x27, 0x03]);
+            assert_eq!(ecu.handle_request(&[0x27, 0x04, 0, 0, 0, 0]), Some(vec![0x7F, 0x27, nrc]));
+        }
+        ecu.handle_request(&[0x10, 0x03]);
+        assert_eq!(ecu.handle_request(&[0x27, 0x03]).map(|rsp| rsp[0]), Some(0x67));
+        drop(ecu);
+
+        server.borrow_mut().set_nrc(0x22, Some(UdsNrc::ConditionsNotCorrect));
+        assert!(uds.read_data(0xF190).is_err());
+        assert_eq!(uds.get_last_nrc(), Some(UdsNrc::ConditionsNotCorrect));
+    }
+
+    // replays queued requests and keeps every sent PDU
+    struct Scripted {
+        requests: VecDeque<Vec<u8>>,
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl UdsTransport for Scripted {
+        fn send_pdu(&mut self, pdu: &[u8]) -> Result<(), CanError> {
+            self.sent.push(pdu.to_vec());
+            Ok(())
+        }
+        fn recv_pdu(&mut self, _timeout_ms: u64) -> Result<Option<Vec<u8>>, CanError> {
+            Ok(self.requests.pop_front())
+        }
+    }
+
+    #[test]
+    fn uds_server_pending() {
+        let requests = [&[0x3E, 0x00][..], &[0x3E, 0x80], &[0x22, 0x12, 0x34], &[0x10, 0x01]];
+        let mut ecu = UdsServer::new(Scripted {
+            requests: requests.iter().map(|request| request.to_vec()).collect(),
+            sent: Vec::new(),
+        });
+        ecu.set_timing(10, 50)
+            .set_delay(0x3E, 120)
+            .set_delay(0x22, 120)
+            .set_delay(0x10, 5);
+
+        // 120ms with P2*=50ms needs a response pending every 45ms
+        assert!(ecu.process(0).unwrap());
+        let pending = vec![0x7F, 0x3E, 0x78];
+        assert_eq!(
+            ecu.get_transport().sent.drain(..).collect::<Vec<_>>(),
+            [pending.clone(), pending.clone(), pending, vec![0x7E, 0x00]]
+        );
+
+        // suppressed and negative responses are neither delayed nor pending
+        assert!(ecu.process(0).unwrap());
+        assert!(ecu.get_transport().sent.is_empty());
+        assert!(ecu.process(0).unwrap());
+        assert_eq!(ecu.get_transport().sent.drain(..).collect::<Vec<_>>(), [[0x7F, 0x22, 0x31]]);
+
+        // delay within P2 answers directly
+        assert!(ecu.process(0).unwrap());
+        assert_eq!(ecu.get_transport().sent[0][..2], [0x50, 0x01]);
+        assert_eq!(ecu.get_transport().sent.len(), 1);
+        assert!(!ecu.process(0).unwrap());
+    }
+}
@@ -0,0 +1,655 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    Vector CANalyzer/CANoe ASCII logging format (.asc)
+ *    python-can can/io/asc.py reader/writer
+ *
+*/
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+const ASC_MONTHS: [&str; 12] =
+    ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+const ASC_WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+// CANFD line flags column (EDL/BRS/ESI bits)
+const ASC_FD_EDL: u32 = 1 << 12;
+const ASC_FD_BRS: u32 = 1 << 13;
+const ASC_FD_ESI: u32 = 1 << 14;
+
+// days since 1970-01-01 for a proleptic gregorian date
+pub(crate) fn asc_days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn asc_civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+/// Parses ASC header date `Wed Jun 15 10:22:33.123 am 2022` into epoch microseconds.
+/// Time zone is not logged, date is taken as UTC.
+#[must_use]
+pub fn asc_parse_date(text: &str) -> Option<u64> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let (month, day, time, meridiem, year) = match tokens.as_slice() {
+        [_, month, day, time, meridiem, year] => (*month, *day, *time, Some(*meridiem), *year),
+        [_, month, day, time, year] => (*month, *day, *time, None, *year),
+        _ => return None,
+    };
+    let month = ASC_MONTHS.iter().position(|name| name.eq_ignore_ascii_case(month))?;
+    let day = day.parse::<i64>().ok()?;
+    let year = year.parse::<i64>().ok()?;
+
+    let mut fields = time.splitn(3, ':');
+    let mut hour = fields.next()?.parse::<i64>().ok()?;
+    let minute = fields.next()?.parse::<i64>().ok()?;
+    let seconds = log_parse_stamp(fields.next()?)?;
+    match meridiem.map(str::to_ascii_lowercase).as_deref() {
+        None => {},
+        Some("am") => hour %= 12,
+        Some("pm") => hour = hour % 12 + 12,
+        Some(_) => return None,
+    }
+
+    let month = i64::try_from(month).ok()? + 1;
+    let days = asc_days_from_civil(year, month, day);
+    let secs = u64::try_from(days * 86_400 + hour * 3_600 + minute * 60).ok()?;
+    Some(secs * 1_000_000 + seconds)
+}
+
+/// Formats epoch microseconds as ASC header date (UTC, millisecond precision).
+#[must_use]
+pub fn asc_format_date(stamp: u64) -> String {
+    let secs = i64::try_from(stamp / 1_000_000).unwrap_or(0);
+    let millis = (stamp % 1_000_000) / 1_000;
+    let days = secs.div_euclid(86_400);
+    let daysec = secs.rem_euclid(86_400);
+    let (year, month, day) = asc_civil_from_days(days);
+    let weekday = usize::try_from((days + 4).rem_euclid(7)).unwrap_or(0);
+    let month = usize::try_from(month - 1).unwrap_or(0);
+    let hour = daysec / 3_600;
+    let meridiem = if hour < 12 { "am" } else { "pm" };
+    let hour12 = match hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    format!(
+        "{} {} {:02} {:02}:{:02}:{:02}.{:03} {} {}",
+        ASC_WEEKDAYS[weekday],
+        ASC_MONTHS[month],
+        day,
+        hour12,
+        (daysec % 3_600) / 60,
+        daysec % 60,
+        millis,
+        meridiem,
+        year
+    )
+}
+
+// 'x' suffix marks 29 bits identifiers
+fn asc_canid(text: &str, radix: u32) -> Option<u32> {
+    if let Some(canid) = text.strip_suffix(['x', 'X']) {
+        let canid = u32::from_str_radix(canid, radix).ok()?;
+        Some((canid & FilterMask::EFF_MASK.bits()) | FilterMask::EFF_FLAG.bits())
+    } else {
+        let canid = u32::from_str_radix(text, radix).ok()?;
+        (canid <= FilterMask::SFF_MASK.bits()).then_some(canid)
+    }
+}
+
+fn asc_dir(text: &str) -> Result<Option<CanLogDir>, String> {
+    match text {
+        "Rx" => Ok(Some(CanLogDir::Rx)),
+        "Tx" => Ok(Some(CanLogDir::Tx)),
+        // transmission requests are not frames on the bus
+        "TxRq" => Ok(None),
+        _ => Err(format!("invalid direction '{text}'")),
+    }
+}
+
+fn asc_data(tokens: &[&str], len: usize, radix: u32) -> Result<Vec<u8>, String> {
+    if tokens.len() < len {
+        return Err(format!("expected {len} data bytes, got {}", tokens.len()));
+    }
+    tokens[..len]
+        .iter()
+        .map(|byte| {
+            u8::from_str_radix(byte, radix).map_err(|_| format!("invalid data byte '{byte}'"))
+        })
+        .collect()
+}
+
+fn asc_error_frame() -> CanAnyFrame {
+    CanAnyFrame::RawStd(CanFrameRaw::new(FilterMask::ERR_FLAG.bits(), 8, 0, 0, [0; 8]))
+}
+
+/// Streams Vector ASC traces as `CanLogRecord`, channel numbers become interface names.
+pub struct CanAscReader<R: BufRead> {
+    reader: R,
+    line: usize,
+    radix: u32,
+    relative: bool,
+    start: u64,
+    offset: u64,
+    channels: HashMap<u8, String>,
+}
+
+impl CanAscReader<BufReader<File>> {
+    /// Opens a Vector ASC log file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be opened.
+    pub fn open(path: &str) -> Result<Self, CanError> {
+        match File::open(path) {
+            Ok(file) => Ok(CanAscReader::new(BufReader::new(file))),
+            Err(error) => Err(CanError::new("asc-open-fail", format!("{path}: {error}"))),
+        }
+    }
+}
+
+impl<R: BufRead> CanAscReader<R> {
+    pub fn new(reader: R) -> Self {
+        CanAscReader {
+            reader,
+            line: 0,
+            radix: 16,
+            relative: false,
+            start: 0,
+            offset: 0,
+            channels: HashMap::new(),
+        }
+    }
+
+    /// Names records from ASC `channel` as `iface` (default is channel number).
+    pub fn set_iface(&mut self, channel: u8, iface: &str) -> &mut Self {
+        self.channels.insert(channel, iface.to_owned());
+        self
+    }
+
+    /// Number of the last line read.
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    /// Measurement start from `Begin Triggerblock`/`date` header (epoch us, 0 when unknown).
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    fn header(&mut self, keyword: &str, tokens: &[&str]) {
+        match (keyword, tokens) {
+            ("date", date) | ("Begin", [_, date @ ..]) => {
+                if let Some(start) = asc_parse_date(&date.join(" ")) {
+                    self.start = start;
+                }
+                self.offset = 0;
+            },
+            ("base", [base, _, mode, ..]) => {
+                self.radix = if base.eq_ignore_ascii_case("dec") { 10 } else { 16 };
+                self.relative = mode.eq_ignore_ascii_case("relative");
+            },
+            _ => {},
+        }
+    }
+
+    fn record(&self, channel: u8, dir: Option<CanLogDir>, frame: CanAnyFrame) -> CanLogRecord {
+        let iface = self.channels.get(&channel).cloned().unwrap_or_else(|| channel.to_string());
+        let mut record = CanLogRecord::new(self.start + self.offset, &iface, frame);
+        record.dir = dir;
+        record
+    }
+
+    // <channel> <id> <dir> d <dlc> <data...> | <channel> <id> <dir> r [dlc] | <channel> ErrorFrame
+    fn classic(&self, channel: u8, tokens: &[&str]) -> Result<Option<CanLogRecord>, String> {
+        match tokens.first() {
+            Some(text) if text.eq_ignore_ascii_case("ErrorFrame") => {
+                return Ok(Some(self.record(channel, Some(CanLogDir::Rx), asc_error_frame())));
+            },
+            Some(_) => {},
+            None => return Ok(None),
+        }
+        // anything else than a canid is a non frame event (Statistic:, ...)
+        let Some(mut canid) = asc_canid(tokens[0], self.radix) else {
+            return Ok(None);
+        };
+        let Some(dir) = asc_dir(tokens.get(1).copied().unwrap_or_default())? else {
+            return Ok(None);
+        };
+
+        let mut buffer = [0u8; 8];
+        let dlc = tokens.get(3).and_then(|dlc| u8::from_str_radix(dlc, self.radix).ok());
+        let len = match tokens.get(2).copied() {
+            Some("d" | "D") => {
+                let Some(dlc) = dlc.filter(|dlc| *dlc <= 15) else {
+                    return Err(format!("invalid dlc in '{}'", tokens.join(" ")));
+                };
+                let data = asc_data(&tokens[4..], usize::from(dlc.min(8)), self.radix)?;
+                buffer[..data.len()].copy_from_slice(&data);
+                dlc
+            },
+            Some("r" | "R") => {
+                canid |= FilterMask::RTR_FLAG.bits();
+                dlc.filter(|dlc| *dlc <= 15).unwrap_or(0)
+            },
+            _ => return Err(format!("invalid frame type in '{}'", tokens.join(" "))),
+        };
+
+        let mut frame = CanFrameRaw::new(canid, len.min(8), 0, 0, buffer);
+        if len > 8 {
+            frame.0.len8_dlc = len;
+        }
+        Ok(Some(self.record(channel, Some(dir), CanAnyFrame::RawStd(frame))))
+    }
+
+    // CANFD <channel> <dir> <id> [name] <brs> <esi> <dlc> <len> <data...> <duration> ...
+    fn canfd(&self, tokens: &[&str]) -> Result<Option<CanLogRecord>, String> {
+        let (Some(channel), Some(dir), Some(ident)) =
+            (tokens.first(), tokens.get(1), tokens.get(2))
+        else {
+            return Err("truncated CANFD event".to_owned());
+        };
+        let Ok(channel) = channel.parse::<u8>() else {
+            return Err(format!("invalid channel '{channel}'"));
+        };
+        let Some(dir) = asc_dir(dir)? else {
+            return Ok(None);
+        };
+        if ident.eq_ignore_ascii_case("ErrorFrame") {
+            return Ok(Some(self.record(channel, Some(dir), asc_error_frame())));
+        }
+        let Some(canid) = asc_canid(ident, self.radix) else {
+            return Err(format!("invalid canid '{ident}'"));
+        };
+
+        // optional symbolic message name sits before brs flag
+        let skip = match tokens.get(3) {
+            Some(token) if token.bytes().all(|byte| byte.is_ascii_digit()) => 3,
+            _ => 4,
+        };
+        let fields = &tokens[skip.min(tokens.len())..];
+        let [brs, esi, _dlc, len, data @ ..] = fields else {
+            return Err(format!("truncated CANFD event '{}'", tokens.join(" ")));
+        };
+        let Some(len) = len.parse::<usize>().ok().filter(|len| *len <= 64) else {
+            return Err(format!("invalid CANFD length '{len}'"));
+        };
+        let data = asc_data(data, len, self.radix)?;
+
+        let mut flags = CanFdFlags::empty();
+        if *brs == "1" {
+            flags |= CanFdFlags::BRS;
+        }
+        if *esi == "1" {
+            flags |= CanFdFlags::ESI;
+        }
+        let mut buffer = [0u8; 64];
+        buffer[..len].copy_from_slice(&data);
+        let len = u8::try_from(len).unwrap_or(64);
+        let frame = CanFdFrameRaw::new(canid, len, flags.bits(), 0, 0, buffer);
+        Ok(Some(self.record(channel, Some(dir), CanAnyFrame::RawFd(frame))))
+    }
+
+    fn event(&mut self, stamp: u64, tokens: &[&str]) -> Result<Option<CanLogRecord>, String> {
+        self.offset = if self.relative { self.offset + stamp } else { stamp };
+        match tokens.first() {
+            Some(&"CANFD") => self.canfd(&tokens[1..]),
+            Some(channel) => match channel.parse::<u8>() {
+                Ok(channel) => self.classic(channel, &tokens[1..]),
+                Err(_) => Ok(None),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Reads next CAN record, skipping headers and non frame events. Returns `None` at end of file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` tagged with line number when a frame line cannot be parsed.
+    pub fn read_record(&mut self) -> Result<Option<CanLogRecord>, CanError> {
+        let mut buffer = String::new();
+        loop {
+            buffer.clear();
+            self.line += 1;
+            match self.reader.read_line(&mut buffer) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {},
+                Err(error) => {
+                    return Err(CanError::new(
+                        "asc-read-fail",
+                        format!("line {}: {error}", self.line),
+                    ))
+                },
+            }
+            let tokens: Vec<&str> = buffer.split_whitespace().collect();
+            let Some((keyword, tokens)) = tokens.split_first() else {
+                continue;
+            };
+            let Some(stamp) = log_parse_stamp(keyword) else {
+                self.header(keyword, tokens);
+                continue;
+            };
+            match self.event(stamp, tokens) {
+                Ok(Some(mut record)) => {
+                    record.set_line(self.line);
+                    return Ok(Some(record));
+                },
+                Ok(None) => {},
+                Err(error) => {
+                    return Err(CanError::new(
+                        "asc-invalid-line",
+                        format!("line {}: {error}", self.line),
+                    ))
+                },
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CanAscReader<R> {
+    type Item = Result<CanLogRecord, CanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Writes records as Vector ASC trace (hex base, absolute timestamps from first record).
+pub struct CanAscWriter<W: Write> {
+    writer: W,
+    start: Option<u64>,
+    channels: HashMap<String, u8>,
+}
+
+impl CanAscWriter<BufWriter<File>> {
+    /// Creates (truncates) a Vector ASC log file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be created.
+    pub fn create(path: &str) -> Result<Self, CanError> {
+        match File::create(path) {
+            Ok(file) => Ok(CanAscWriter::new(BufWriter::new(file))),
+            Err(error) => Err(CanError::new("asc-create-fail", format!("{path}: {error}"))),
+        }
+    }
+}
+
+impl<W: Write> CanAscWriter<W> {
+    pub fn new(writer: W) -> Self {
+        CanAscWriter { writer, start: None, channels: HashMap::new() }
+    }
+
+    /// Logs records from `iface` on ASC `channel`. Numeric interface names are used as is,
+    /// others get next free channel in order of appearance.
+    pub fn set_channel(&mut self, iface: &str, channel: u8) -> &mut Self {
+        self.channels.insert(iface.to_owned(), channel);
+        self
+    }
+
+    fn get_channel(&mut self, iface: &str) -> u8 {
+        if let Some(channel) = self.channels.get(iface) {
+            return *channel;
+        }
+        let channel = iface.parse::<u8>().unwrap_or_else(|_| {
+            self.channels.values().max().map_or(1, |channel| channel.saturating_add(1))
+        });
+        self.channels.insert(iface.to_owned(), channel);
+        channel
+    }
+
+    fn header(&mut self, start: u64) -> Result<(), std::io::Error> {
+        let date = asc_format_date(start);
+        writeln!(self.writer, "date {date}")?;
+        writeln!(self.writer, "base hex  timestamps absolute")?;
+        writeln!(self.writer, "internal events logged")?;
+        writeln!(self.writer, "// version 9.0.0")?;
+        writeln!(self.writer, "Begin Triggerblock {date}")?;
+        writeln!(self.writer, "   0.000000 Start of measurement")
+    }
+
+    fn event(&mut self, record: &CanLogRecord) -> Result<(), std::io::Error> {
+        // header date only holds milliseconds, keep offsets exact against it
+        let start = if let Some(start) = self.start {
+            start
+        } else {
+            let start = record.stamp - record.stamp % 1_000;
+            self.header(start)?;
+            self.start = Some(start);
+            start
+        };
+        let offset = record.stamp.saturating_sub(start);
+        let channel = self.get_channel(&record.iface);
+        let dir = match record.dir {
+            Some(CanLogDir::Tx) => "Tx",
+            _ => "Rx",
+        };
+        let canid = record.get_canid();
+        let ident = if canid & FilterMask::EFF_FLAG.bits() != 0 {
+            format!("{:X}x", canid & FilterMask::EFF_MASK.bits())
+        } else {
+            format!("{:X}", canid & FilterMask::SFF_MASK.bits())
+        };
+        write!(self.writer, "{:>4}.{:06} ", offset / 1_000_000, offset % 1_000_000)?;
+
+        match &record.frame {
+            _ if record.is_error() => writeln!(self.writer, "{channel}  ErrorFrame"),
+            CanAnyFrame::RawFd(frame) => {
+                let len = usize::from(frame.get_len()).min(64);
+                let dlc = match len {
+                    0..=8 => len,
+                    9..=12 => 9,
+                    13..=16 => 10,
+                    17..=20 => 11,
+                    21..=24 => 12,
+                    25..=32 => 13,
+                    33..=48 => 14,
+                    _ => 15,
+                };
+                let fdflags = CanFdFlags::from_bits_truncate(frame.get_flag());
+                let mut flags = ASC_FD_EDL;
+                if fdflags.contains(CanFdFlags::BRS) {
+                    flags |= ASC_FD_BRS;
+                }
+                if fdflags.contains(CanFdFlags::ESI) {
+                    flags |= ASC_FD_ESI;
+                }
+                write!(
+                    self.writer,
+                    "CANFD {channel:>3} {dir:<4} {ident:>8} {} {} {dlc:x} {len:>2}",
+                    u8::from(fdflags.contains(CanFdFlags::BRS)),
+                    u8::from(fdflags.contains(CanFdFlags::ESI)),
+                )?;
+                for byte in &frame.get_data()[..len] {
+                    write!(self.writer, " {byte:02X}")?;
+                }
+                writeln!(
+                    self.writer,
+                    " {:>8} {:>4} {flags:>8X} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                    0, 0, 0, 0, 0, 0, 0
+                )
+            },
+            CanAnyFrame::RawStd(frame) => {
+                let len = frame.get_len().min(8);
+                let dlc = if len == 8 && (9..=15).contains(&frame.0.len8_dlc) {
+                    frame.0.len8_dlc
+                } else {
+                    len
+                };
+                write!(self.writer, "{channel}  {ident:<15} {dir:<4} ")?;
+                if record.is_rtr() {
+                    return writeln!(self.writer, "r {dlc:x}");
+                }
+                write!(self.writer, "d {dlc:x}")?;
+                for byte in &frame.get_data()[..usize::from(len)] {
+                    write!(self.writer, " {byte:02X}")?;
+                }
+                writeln!(self.writer)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// # Errors
+    /// Returns a `CanError` when record holds no frame or write fails.
+    pub fn write_record(&mut self, record: &CanLogRecord) -> Result<(), CanError> {
+        if !matches!(record.frame, CanAnyFrame::RawStd(_) | CanAnyFrame::RawFd(_)) {
+            return Err(CanError::new("asc-invalid-frame", "record holds no CAN frame"));
+        }
+        self.event(record)
+            .map_err(|error| CanError::new("asc-write-fail", error.to_string()))
+    }
+
+    /// Writes a frame received from a socket, `stamp` is in microseconds.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when frame is not a data frame or write fails.
+    pub fn write_frame(
+        &mut self,
+        stamp: u64,
+        iface: &str,
+        frame: &CanAnyFrame,
+    ) -> Result<(), CanError> {
+        self.write_record(&CanLogRecord::new(stamp, iface, frame.clone()))
+    }
+
+    /// Closes trigger block and flushes output.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when write fails.
+    pub fn finish(mut self) -> Result<W, CanError> {
+        let mut close = || -> Result<(), std::io::Error> {
+            if self.start.is_none() {
+                self.header(0)?;
+            }
+            writeln!(self.writer, "End TriggerBlock")?;
+            self.writer.flush()
+        };
+        close().map_err(|error| CanError::new("asc-write-fail", error.to_string()))?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const ASC_LOG: &str = "\
+date Wed Jun 15 10:22:33.123 am 2022
+base dec  timestamps relative
+internal events logged
+// version 13.0.0
+Begin Triggerblock Wed Jun 15 10:22:33.123 am 2022
+   0.000000 Start of measurement
+   0.001000 1  Statistic: D 0 R 0 XD 0 XR 0 E 0 O 0 B 0.00%
+   0.001000 2  291             Rx   d 3 1 2 255  Length = 0 BitCount = 0 ID = 291
+   0.000500 1  419361024x      Tx   r 8
+   0.000500 1  291             TxRq d 1 0
+   0.000500 1  ErrorFrame
+   0.001000 CANFD   1 Rx        31x  EngineData                    1 0 9 12 1 2 3 4 5 6 7 8 9 10 11 12   0   0   3000   0   0   0   0   0
+End TriggerBlock
+";
+
+    #[test]
+    fn test_asc_date() {
+        assert_eq!(asc_parse_date("Wed Jun 15 10:22:33.123 am 2022"), Some(1_655_288_553_123_000));
+        assert_eq!(asc_parse_date("Wed Jun 15 10:22:33.123 pm 2022"), Some(1_655_331_753_123_000));
+        assert_eq!(asc_format_date(1_655_288_553_123_000), "Wed Jun 15 10:22:33.123 am 2022");
+        assert_eq!(asc_format_date(1_683_840_913_200_980), "Thu May 11 09:35:13.200 pm 2023");
+        assert_eq!(asc_parse_date("Thu May 11 09:35:13.200 pm 2023"), Some(1_683_840_913_200_000));
+        assert!(asc_parse_date("Wed Foo 15 10:22:33.123 am 2022").is_none());
+    }
+
+    #[test]
+    fn test_asc_reader() {
+        let mut reader = CanAscReader::new(ASC_LOG.as_bytes());
+        reader.set_iface(1, "vcan0");
+        let records: Vec<CanLogRecord> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(reader.get_start(), 1_655_288_553_123_000);
+
+        let start: u64 = 1_655_288_553_123_000;
+        let lines: Vec<String> = records.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                format!("({}.{:06}) 2 123#0102FF R", start / 1_000_000, start % 1_000_000 + 2_000),
+                format!(
+                    "({}.{:06}) vcan0 18FEF100#R8 T",
+                    start / 1_000_000,
+                    start % 1_000_000 + 2_500
+                ),
+                format!(
+                    "({}.{:06}) vcan0 20000000#0000000000000000 R",
+                    start / 1_000_000,
+                    start % 1_000_000 + 3_500
+                ),
+                format!(
+                    "({}.{:06}) vcan0 0000001F##10102030405060708090A0B0C R",
+                    start / 1_000_000,
+                    start % 1_000_000 + 4_500
+                ),
+            ]
+        );
+        assert_eq!(records[0].get_line(), 8);
+        assert!(records[2].is_error());
+
+        let msg = CanMsgData::from(&records[1]);
+        assert_eq!((msg.canid, msg.len), (0x98FE_F100, 8));
+
+        let mut reader =
+            CanAscReader::new("base hex  timestamps absolute\n 0.1 1 123 Rx d 2 11\n".as_bytes());
+        let error = reader.next().unwrap().err().unwrap();
+        assert!(error.get_info().starts_with("line 2:"));
+    }
+
+    #[test]
+    fn test_asc_roundtrip() {
+        let candump = [
+            "(1683797713.200980) can0 1DF9050F#0027FFFF0200FFFF",
+            "(1683797713.201000) can0 123#R3 T",
+            "(1683797713.201018) can1 7FF##3112233445566778899AABBCC",
+            "(1683797713.201026) can0 20000000#0000000000000000",
+            "(1683797713.201030) can1 321#1122334455667788_C",
+            "(1683797715.000000) can0 000#",
+        ];
+        let mut writer = CanAscWriter::new(Vec::new());
+        for line in candump {
+            writer.write_record(&CanLogRecord::from_str(line).unwrap()).unwrap();
+        }
+        let output = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert!(output.starts_with("date Thu May 11 09:35:13.200 am 2023\n"));
+        assert!(output.ends_with("End TriggerBlock\n"));
+
+        let mut reader = CanAscReader::new(output.as_bytes());
+        reader.set_iface(1, "can0").set_iface(2, "can1");
+        let records: Vec<String> = reader.map(|record| record.unwrap().to_string()).collect();
+        let expected: Vec<String> = candump
+            .iter()
+            .map(|line| match line.split_whitespace().last() {
+                Some("T") => (*line).to_owned(),
+                _ => format!("{line} R"),
+            })
+            .collect();
+        assert_eq!(records, expected);
+    }
+}
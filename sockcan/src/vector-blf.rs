@@ -0,0 +1,509 @@
+/*
+ * Copyright (C) 2015-2023 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Redpesk interface code/config use MIT License and can be freely copy/modified even within proprietary code
+ * License: $RP_BEGIN_LICENSE$ SPDX:MIT https://opensource.org/licenses/MIT $RP_END_LICENSE$
+ *
+ * References:
+ *    Vector Binary Logging Format (.blf), Vector binlog object definitions
+ *    python-can can/io/blf.py reader
+ *
+*/
+use crate::prelude::*;
+use flate2::read::ZlibDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+
+const BLF_FILE_SIGNATURE: &[u8; 4] = b"LOGG";
+const BLF_OBJ_SIGNATURE: &[u8; 4] = b"LOBJ";
+const BLF_FILE_HEADER_SIZE: usize = 144;
+const BLF_OBJ_BASE_SIZE: usize = 16;
+const BLF_CONTAINER_SIZE: usize = 16;
+// writers use 128KiB containers, bigger objects are bogus files
+const BLF_MAX_OBJECT: usize = 0x10_0000;
+
+pub const BLF_CAN_MESSAGE: u32 = 1;
+pub const BLF_CAN_ERROR: u32 = 2;
+pub const BLF_LOG_CONTAINER: u32 = 10;
+pub const BLF_CAN_ERROR_EXT: u32 = 73;
+pub const BLF_CAN_MESSAGE2: u32 = 86;
+pub const BLF_CAN_FD_MESSAGE: u32 = 100;
+pub const BLF_CAN_FD_MESSAGE_64: u32 = 101;
+
+const BLF_NO_COMPRESSION: u16 = 0;
+const BLF_ZLIB_DEFLATE: u16 = 2;
+
+// object header flags, timestamp unit
+const BLF_TIME_TEN_MICS: u32 = 0x01;
+
+// CAN_MESSAGE/CAN_FD_MESSAGE flags and id extended bit
+const BLF_MSG_TX: u8 = 0x01;
+const BLF_MSG_RTR: u8 = 0x80;
+const BLF_MSG_EXT: u32 = 0x8000_0000;
+const BLF_FD_EDL: u8 = 0x01;
+const BLF_FD_BRS: u8 = 0x02;
+const BLF_FD_ESI: u8 = 0x04;
+const BLF_FD64_RTR: u32 = 0x0010;
+const BLF_FD64_EDL: u32 = 0x1000;
+const BLF_FD64_BRS: u32 = 0x2000;
+const BLF_FD64_ESI: u32 = 0x4000;
+
+const BLF_FD_LENGTHS: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+fn blf_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn blf_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn blf_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+// SYSTEMTIME (year, month, weekday, day, hour, minute, second, millis) to epoch us
+fn blf_systemtime(data: &[u8]) -> Option<u64> {
+    let field = |idx: usize| blf_u16(data, idx * 2).map(i64::from);
+    let days = asc_days_from_civil(field(0)?, field(1)?, field(3)?);
+    let secs = days * 86_400 + field(4)? * 3_600 + field(5)? * 60 + field(6)?;
+    let secs = u64::try_from(secs).ok()?;
+    Some(secs * 1_000_000 + u64::try_from(field(7)?).ok()? * 1_000)
+}
+
+fn blf_canid(canid: u32) -> u32 {
+    if canid & BLF_MSG_EXT != 0 {
+        (canid & FilterMask::EFF_MASK.bits()) | FilterMask::EFF_FLAG.bits()
+    } else {
+        canid & FilterMask::SFF_MASK.bits()
+    }
+}
+
+fn blf_classic(canid: u32, dlc: u8, data: &[u8]) -> CanAnyFrame {
+    let mut buffer = [0u8; 8];
+    let len = usize::from(dlc.min(8)).min(data.len());
+    buffer[..len].copy_from_slice(&data[..len]);
+    let mut frame = CanFrameRaw::new(canid, dlc.min(8), 0, 0, buffer);
+    if (9..=15).contains(&dlc) {
+        frame.0.len8_dlc = dlc;
+    }
+    CanAnyFrame::RawStd(frame)
+}
+
+fn blf_fd(canid: u32, flags: CanFdFlags, data: &[u8]) -> CanAnyFrame {
+    let mut buffer = [0u8; 64];
+    let len = data.len().min(64);
+    buffer[..len].copy_from_slice(&data[..len]);
+    let len = u8::try_from(len).unwrap_or(64);
+    CanAnyFrame::RawFd(CanFdFrameRaw::new(canid, len, flags.bits(), 0, 0, buffer))
+}
+
+fn blf_dir(tx: bool) -> CanLogDir {
+    if tx {
+        CanLogDir::Tx
+    } else {
+        CanLogDir::Rx
+    }
+}
+
+/// Streams CAN objects from Vector BLF logs as `CanLogRecord`, channel numbers become
+/// interface names. Other object types (ethernet, lin, markers, ...) are skipped.
+pub struct CanBlfReader<R: Read> {
+    reader: R,
+    start: u64,
+    objects: u32,
+    buffer: Vec<u8>,
+    pos: usize,
+    channels: HashMap<u16, String>,
+}
+
+impl CanBlfReader<BufReader<File>> {
+    /// Opens a BLF file and checks its header.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when file cannot be opened or is not a BLF log.
+    pub fn open(path: &str) -> Result<Self, CanError> {
+        match File::open(path) {
+            Ok(file) => CanBlfReader::new(BufReader::new(file)),
+            Err(error) => Err(CanError::new("blf-open-fail", format!("{path}: {error}"))),
+        }
+    }
+}
+
+impl<R: Read> CanBlfReader<R> {
+    /// Reads file header from `reader`.
+    ///
+    /// # Errors
+    /// Returns a `CanError` when header is truncated or signature is not `LOGG`.
+    pub fn new(mut reader: R) -> Result<Self, CanError> {
+        let mut header = [0u8; BLF_FILE_HEADER_SIZE];
+        reader
+            .read_exact(&mut header[..8])
+            .map_err(|error| CanError::new("blf-invalid-header", error.to_string()))?;
+        if &header[..4] != BLF_FILE_SIGNATURE {
+            return Err(CanError::new("blf-invalid-header", "missing LOGG signature"));
+        }
+        // header size is logged, skip any extra bytes from newer writers
+        let size = blf_u32(&header, 4).and_then(|size| usize::try_from(size).ok()).unwrap_or(0);
+        if !(72..=BLF_MAX_OBJECT).contains(&size) {
+            return Err(CanError::new("blf-invalid-header", format!("header size {size}")));
+        }
+        let mut extra = vec![0u8; size - 8];
+        reader
+            .read_exact(&mut extra)
+            .map_err(|error| CanError::new("blf-invalid-header", error.to_string()))?;
+        header[8..size.min(BLF_FILE_HEADER_SIZE)]
+            .copy_from_slice(&extra[..size.min(BLF_FILE_HEADER_SIZE) - 8]);
+
+        Ok(CanBlfReader {
+            reader,
+            start: blf_systemtime(&header[40..56]).unwrap_or(0),
+            objects: blf_u32(&header, 32).unwrap_or(0),
+            buffer: Vec::new(),
+            pos: 0,
+            channels: HashMap::new(),
+        })
+    }
+
+    /// Names records from BLF `channel` as `iface` (default is channel number).
+    pub fn set_iface(&mut self, channel: u16, iface: &str) -> &mut Self {
+        self.channels.insert(channel, iface.to_owned());
+        self
+    }
+
+    /// Measurement start from file header (epoch us, 0 when unknown).
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    /// Object count announced by file header.
+    pub fn get_count(&self) -> u32 {
+        self.objects
+    }
+
+    // appends next top level object payload to buffer, false at end of file
+    fn fill(&mut self) -> Result<bool, CanError> {
+        let mut header = [0u8; BLF_OBJ_BASE_SIZE];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {},
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(error) => return Err(CanError::new("blf-read-fail", error.to_string())),
+        }
+        if &header[..4] != BLF_OBJ_SIGNATURE {
+            return Err(CanError::new("blf-invalid-object", "missing LOBJ signature"));
+        }
+        let size = blf_u32(&header, 8).and_then(|size| usize::try_from(size).ok()).unwrap_or(0);
+        let Some(body) = size.checked_sub(BLF_OBJ_BASE_SIZE).filter(|_| size <= BLF_MAX_OBJECT)
+        else {
+            return Err(CanError::new("blf-invalid-object", format!("object size {size}")));
+        };
+        // top level objects are followed by size % 4 padding bytes
+        let mut data = vec![0u8; body + size % 4];
+        match self.reader.read_exact(&mut data) {
+            Ok(()) => {},
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(error) => return Err(CanError::new("blf-read-fail", error.to_string())),
+        }
+        data.truncate(body);
+
+        self.buffer.drain(..self.pos.min(self.buffer.len()));
+        self.pos = 0;
+        if blf_u32(&header, 12) != Some(BLF_LOG_CONTAINER) {
+            // uncompressed object outside of any container
+            self.buffer.extend_from_slice(&header);
+            self.buffer.extend_from_slice(&data);
+            return Ok(true);
+        }
+
+        let payload = data.get(BLF_CONTAINER_SIZE..).unwrap_or_default();
+        match blf_u16(&data, 0) {
+            Some(BLF_NO_COMPRESSION) => self.buffer.extend_from_slice(payload),
+            Some(BLF_ZLIB_DEFLATE) => {
+                let limit = u64::try_from(BLF_MAX_OBJECT).unwrap_or(u64::MAX);
+                let mut decoder = ZlibDecoder::new(payload).take(limit + 1);
+                match decoder.read_to_end(&mut self.buffer) {
+                    Ok(count) if count > BLF_MAX_OBJECT => {
+                        return Err(CanError::new(
+                            "blf-invalid-container",
+                            format!("uncompressed size over {BLF_MAX_OBJECT}"),
+                        ))
+                    },
+                    Ok(_) => {},
+                    Err(error) => {
+                        return Err(CanError::new("blf-invalid-container", error.to_string()))
+                    },
+                }
+            },
+            method => {
+                return Err(CanError::new(
+                    "blf-invalid-container",
+                    format!("unsupported compression {method:?}"),
+                ))
+            },
+        }
+        Ok(true)
+    }
+
+    fn record(&self, channel: u16, stamp: u64, dir: CanLogDir, frame: CanAnyFrame) -> CanLogRecord {
+        let iface = self.channels.get(&channel).cloned().unwrap_or_else(|| channel.to_string());
+        let mut record = CanLogRecord::new(stamp, &iface, frame);
+        record.set_dir(dir);
+        record
+    }
+
+    fn object(&self, kind: u32, stamp: u64, data: &[u8]) -> Option<CanLogRecord> {
+        match kind {
+            BLF_CAN_MESSAGE | BLF_CAN_MESSAGE2 => {
+                let flags = *data.get(2)?;
+                let dlc = *data.get(3)?;
+                let mut canid = blf_canid(blf_u32(data, 4)?);
+                if flags & BLF_MSG_RTR != 0 {
+                    canid |= FilterMask::RTR_FLAG.bits();
+                }
+                let frame = blf_classic(canid, dlc, data.get(8..16)?);
+                Some(self.record(blf_u16(data, 0)?, stamp, blf_dir(flags & BLF_MSG_TX != 0), frame))
+            },
+            BLF_CAN_FD_MESSAGE => {
+                let flags = *data.get(2)?;
+                let dlc = *data.get(3)?;
+                let mut canid = blf_canid(blf_u32(data, 4)?);
+                let fdflags = *data.get(13)?;
+                let valid = usize::from(*data.get(14)?);
+                let payload = data.get(20..20 + valid.min(64))?;
+                let frame = if fdflags & BLF_FD_EDL == 0 {
+                    if flags & BLF_MSG_RTR != 0 {
+                        canid |= FilterMask::RTR_FLAG.bits();
+                    }
+                    blf_classic(canid, dlc, payload)
+                } else {
+                    let mut fd = CanFdFlags::empty();
+                    fd.set(CanFdFlags::BRS, fdflags & BLF_FD_BRS != 0);
+                    fd.set(CanFdFlags::ESI, fdflags & BLF_FD_ESI != 0);
+                    blf_fd(canid, fd, payload)
+                };
+                Some(self.record(blf_u16(data, 0)?, stamp, blf_dir(flags & BLF_MSG_TX != 0), frame))
+            },
+            BLF_CAN_FD_MESSAGE_64 => {
+                let channel = u16::from(*data.first()?);
+                let dlc = *data.get(1)?;
+                let valid = usize::from(*data.get(2)?);
+                let mut canid = blf_canid(blf_u32(data, 4)?);
+                let flags = blf_u32(data, 12)?;
+                let tx = *data.get(34)? != 0;
+                let payload = data.get(40..40 + valid.min(64))?;
+                let frame = if flags & BLF_FD64_EDL == 0 {
+                    if flags & BLF_FD64_RTR != 0 {
+                        canid |= FilterMask::RTR_FLAG.bits();
+                    }
+                    blf_classic(canid, dlc, payload)
+                } else {
+                    let mut fd = CanFdFlags::empty();
+                    fd.set(CanFdFlags::BRS, flags & BLF_FD64_BRS != 0);
+                    fd.set(CanFdFlags::ESI, flags & BLF_FD64_ESI != 0);
+                    let len = usize::from(BLF_FD_LENGTHS[usize::from(dlc & 0x0F)]);
+                    blf_fd(canid, fd, &payload[..len.min(payload.len())])
+                };
+                Some(self.record(channel, stamp, blf_dir(tx), frame))
+            },
+            BLF_CAN_ERROR | BLF_CAN_ERROR_EXT => {
+                let frame = CanFrameRaw::new(FilterMask::ERR_FLAG.bits(), 8, 0, 0, [0; 8]);
+                Some(self.record(
+                    blf_u16(data, 0)?,
+                    stamp,
+                    CanLogDir::Rx,
+                    CanAnyFrame::RawStd(frame),
+                ))
+            },
+            _ => None,
+        }
+    }
+
+    /// Reads next CAN record, reassembling objects split across containers.
+    /// Returns `None` at end of file.
+    ///
+    /// # Errors
+    /// Returns a `CanError` on I/O, decompression or object framing errors.
+    pub fn read_record(&mut self) -> Result<Option<CanLogRecord>, CanError> {
+        loop {
+            // objects inside containers may be followed by up to 3 padding bytes
+            let window = &self.buffer[self.pos..self.buffer.len().min(self.pos + 8)];
+            let found = window.windows(4).position(|sig| sig == BLF_OBJ_SIGNATURE);
+            let header = found.map(|skip| self.pos + skip);
+            let size = header
+                .and_then(|start| blf_u32(&self.buffer, start + 8))
+                .and_then(|size| usize::try_from(size).ok());
+
+            let (Some(start), Some(size)) = (header, size) else {
+                if self.buffer.len() - self.pos >= 8 && found.is_none() {
+                    return Err(CanError::new("blf-invalid-object", "missing LOBJ signature"));
+                }
+                if !self.fill()? {
+                    return Ok(None);
+                }
+                continue;
+            };
+            if !(BLF_OBJ_BASE_SIZE..=BLF_MAX_OBJECT).contains(&size) {
+                return Err(CanError::new("blf-invalid-object", format!("object size {size}")));
+            }
+            if start + size > self.buffer.len() {
+                // object continues in next container
+                if !self.fill()? {
+                    return Ok(None);
+                }
+                continue;
+            }
+            self.pos = start + size;
+
+            let object = &self.buffer[start..start + size];
+            let kind = blf_u32(object, 12).unwrap_or(0);
+            let (flags, stamp, offset) = match blf_u16(object, 6) {
+                Some(1) => (blf_u32(object, 16), blf_u64(object, 24), 32),
+                Some(2) => (blf_u32(object, 16), blf_u64(object, 24), 40),
+                _ => continue,
+            };
+            let (Some(flags), Some(stamp)) = (flags, stamp) else {
+                continue;
+            };
+            let stamp = if flags == BLF_TIME_TEN_MICS { stamp * 10 } else { stamp / 1_000 };
+            let body = object.get(offset..).unwrap_or_default();
+            if let Some(record) = self.object(kind, self.start + stamp, body) {
+                return Ok(Some(record));
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for CanBlfReader<R> {
+    type Item = Result<CanLogRecord, CanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    // object with v1 header, timestamp in ns
+    fn blf_object(kind: u32, stamp_ns: u64, body: &[u8]) -> Vec<u8> {
+        let size = u32::try_from(32 + body.len()).unwrap();
+        let mut object = Vec::new();
+        object.extend_from_slice(BLF_OBJ_SIGNATURE);
+        object.extend_from_slice(&32u16.to_le_bytes());
+        object.extend_from_slice(&1u16.to_le_bytes());
+        object.extend_from_slice(&size.to_le_bytes());
+        object.extend_from_slice(&kind.to_le_bytes());
+        object.extend_from_slice(&2u32.to_le_bytes());
+        object.extend_from_slice(&[0; 4]);
+        object.extend_from_slice(&stamp_ns.to_le_bytes());
+        object.extend_from_slice(body);
+        object.resize(object.len() + object.len() % 4, 0);
+        object
+    }
+
+    fn blf_container(payload: &[u8], zlib: bool) -> Vec<u8> {
+        let mut body = vec![0u8; BLF_CONTAINER_SIZE];
+        let method = if zlib { BLF_ZLIB_DEFLATE } else { BLF_NO_COMPRESSION };
+        body[..2].copy_from_slice(&method.to_le_bytes());
+        body[8..12].copy_from_slice(&u32::try_from(payload.len()).unwrap().to_le_bytes());
+        if zlib {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(payload).unwrap();
+            body.extend_from_slice(&encoder.finish().unwrap());
+        } else {
+            body.extend_from_slice(payload);
+        }
+        let size = u32::try_from(BLF_OBJ_BASE_SIZE + body.len()).unwrap();
+        let mut object = Vec::new();
+        object.extend_from_slice(BLF_OBJ_SIGNATURE);
+        object.extend_from_slice(&16u16.to_le_bytes());
+        object.extend_from_slice(&1u16.to_le_bytes());
+        object.extend_from_slice(&size.to_le_bytes());
+        object.extend_from_slice(&BLF_LOG_CONTAINER.to_le_bytes());
+        object.extend_from_slice(&body);
+        object.resize(object.len() + object.len() % 4, 0);
+        object
+    }
+
+    #[test]
+    fn test_blf_reader() {
+        // 2023-05-11 09:35:13.200 (Thursday)
+        let mut file = vec![0u8; BLF_FILE_HEADER_SIZE];
+        file[..4].copy_from_slice(BLF_FILE_SIGNATURE);
+        file[4..8].copy_from_slice(&144u32.to_le_bytes());
+        file[32..36].copy_from_slice(&6u32.to_le_bytes());
+        for (idx, value) in [2023u16, 5, 4, 11, 9, 35, 13, 200].iter().enumerate() {
+            file[40 + idx * 2..42 + idx * 2].copy_from_slice(&value.to_le_bytes());
+        }
+
+        let mut objects = Vec::new();
+        let mut msg = vec![2, 0, BLF_MSG_TX, 3, 0x23, 0x01, 0, 0];
+        msg.extend_from_slice(&[0xAA, 0xBB, 0xCC, 0, 0, 0, 0, 0]);
+        objects.extend(blf_object(BLF_CAN_MESSAGE, 1_000, &msg));
+
+        let mut rtr = vec![1, 0, BLF_MSG_RTR, 8];
+        rtr.extend_from_slice(&(0x18FE_F100 | BLF_MSG_EXT).to_le_bytes());
+        rtr.extend_from_slice(&[0; 16]);
+        objects.extend(blf_object(BLF_CAN_MESSAGE2, 2_000, &rtr));
+
+        let mut fd = vec![1, 0, 0, 9];
+        fd.extend_from_slice(&0x7FFu32.to_le_bytes());
+        fd.extend_from_slice(&[0, 0, 0, 0, 0, BLF_FD_EDL | BLF_FD_BRS, 12, 0, 0, 0, 0, 0]);
+        fd.extend((1..=64).collect::<Vec<u8>>());
+        objects.extend(blf_object(BLF_CAN_FD_MESSAGE, 3_000, &fd));
+
+        objects.extend(blf_object(96, 3_500, &[0; 12]));
+
+        let mut fd64 = vec![1, 10, 16, 0];
+        fd64.extend_from_slice(&(0x1DF9_050F | BLF_MSG_EXT).to_le_bytes());
+        fd64.extend_from_slice(&0u32.to_le_bytes());
+        fd64.extend_from_slice(&(BLF_FD64_EDL | BLF_FD64_ESI).to_le_bytes());
+        fd64.extend_from_slice(&[0; 18]);
+        fd64.extend_from_slice(&[1, 0, 0, 0, 0, 0]);
+        fd64.extend((0..16).collect::<Vec<u8>>());
+        objects.extend(blf_object(BLF_CAN_FD_MESSAGE_64, 4_000_000, &fd64));
+
+        let mut error = vec![1, 0];
+        error.extend_from_slice(&[0; 30]);
+        objects.extend(blf_object(BLF_CAN_ERROR_EXT, 5_000_000, &error));
+
+        // split object stream over one plain and one compressed container
+        let split = objects.len() / 2;
+        file.extend(blf_container(&objects[..split], false));
+        file.extend(blf_container(&objects[split..], true));
+
+        let mut reader = CanBlfReader::new(file.as_slice()).unwrap();
+        reader.set_iface(1, "vcan0");
+        assert_eq!(reader.get_count(), 6);
+        assert_eq!(reader.get_start(), 1_683_797_713_200_000);
+
+        let lines: Vec<String> = reader.map(|record| record.unwrap().to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "(1683797713.200001) 2 123#AABBCC T",
+                "(1683797713.200002) vcan0 18FEF100#R8 R",
+                "(1683797713.200003) vcan0 7FF##10102030405060708090A0B0C R",
+                "(1683797713.204000) vcan0 1DF9050F##2000102030405060708090A0B0C0D0E0F T",
+                "(1683797713.205000) vcan0 20000000#0000000000000000 R",
+            ]
+        );
+
+        assert!(CanBlfReader::new(&b"LOBJ\x90\0\0\0"[..]).is_err());
+
+        // bogus object size is rejected before allocating its body
+        let mut bogus = file[..BLF_FILE_HEADER_SIZE].to_vec();
+        bogus.extend_from_slice(BLF_OBJ_SIGNATURE);
+        bogus.extend_from_slice(&[16, 0, 1, 0]);
+        bogus.extend_from_slice(&0x8000_0000u32.to_le_bytes());
+        bogus.extend_from_slice(&BLF_LOG_CONTAINER.to_le_bytes());
+        let mut reader = CanBlfReader::new(bogus.as_slice()).unwrap();
+        assert!(reader.read_record().is_err());
+    }
+}